@@ -0,0 +1,162 @@
+//! GDExtension exposing vitrail grammars to GDScript, as the `VitrailGrammar` class:
+//!
+//! ```gdscript
+//! var grammar := VitrailGrammar.new()
+//! if not grammar.load_json_file("res://grammars/tavern.json"):
+//!     push_error(grammar.get_last_error())
+//! grammar.set_seed("tavern 12")
+//! grammar.add_standard_modifiers()
+//! grammar.add_modifier("shout", func(text): return text.to_upper() + "!")
+//! print(grammar.flatten())
+//! ```
+
+use godot::classes::FileAccess;
+use godot::prelude::*;
+
+use vitrail::config::GrammarSyntax;
+use vitrail::error::VitrailError;
+use vitrail::grammar::Grammar;
+use vitrail::modifier::{self, Modifier};
+
+struct VitrailExtension;
+
+#[gdextension]
+unsafe impl ExtensionLibrary for VitrailExtension {}
+
+/// A grammar, expanded from GDScript. Errors are reported by `get_last_error`, rather than interrupting the game.
+#[derive(GodotClass)]
+#[class(init, base=RefCounted)]
+pub struct VitrailGrammar {
+    grammar: Grammar<'static>,
+    /// The message of the last failure, empty if the last call succeeded
+    last_error: GString,
+}
+
+#[godot_api]
+impl VitrailGrammar {
+    /// Replace the symbols of the grammar with the ones of a JSON grammar, keeping its seed and modifiers.
+    /// Returns whether the grammar is valid.
+    #[func]
+    fn load_json(&mut self, json: GString) -> bool {
+        let loaded = Grammar::from_json_str(
+            &json.to_string(),
+            &self.grammar.seed,
+            GrammarSyntax::default(),
+        );
+        match loaded {
+            Ok(mut grammar) => {
+                grammar.modifiers = std::mem::take(&mut self.grammar.modifiers);
+                self.grammar = grammar;
+                self.succeed();
+                true
+            }
+            Err(error) => self.fail(error),
+        }
+    }
+
+    /// Same as `load_json`, reading the grammar from a file, e.g. under `res://`
+    #[func]
+    fn load_json_file(&mut self, path: GString) -> bool {
+        if !FileAccess::file_exists(&path) {
+            return self.fail(format!("Could not read grammar file at {}", path));
+        }
+        self.load_json(FileAccess::get_file_as_string(&path))
+    }
+
+    /// Set the rules of a symbol, replacing its previous rules if any, even when they were read from a file
+    #[func]
+    fn set_symbol(&mut self, symbol: GString, rules: PackedStringArray) {
+        let rules = rules.as_slice().iter().map(GString::to_string).collect();
+        self.grammar.set_symbol(symbol.to_string(), rules);
+    }
+
+    /// Restart the random number generator from the given seed, so the following expansions are reproducible
+    #[func]
+    fn set_seed(&mut self, seed: GString) {
+        self.grammar.reseed(&seed.to_string());
+    }
+
+    #[func]
+    fn get_seed(&self) -> GString {
+        GString::from(self.grammar.seed.as_str())
+    }
+
+    /// Register the standard modifiers (`capitalize`, `s`, `a`, ...)
+    #[func]
+    fn add_standard_modifiers(&mut self) {
+        self.grammar.modifiers.extend(modifier::standard_modifiers());
+    }
+
+    /// Register a modifier implemented in GDScript, called with the expanded text and returning the modified one
+    #[func]
+    fn add_modifier(&mut self, name: GString, callable: Callable) {
+        self.grammar
+            .modifiers
+            .insert(name.to_string(), Box::new(CallableModifier { callable }));
+    }
+
+    /// Expand the grammar from its root symbol (`root`, or `origin` for Tracery grammars).
+    /// Returns an empty text if the expansion fails, or the grammar has no root symbol.
+    #[func]
+    fn flatten(&mut self) -> GString {
+        let result = self.grammar.flatten();
+        self.expanded(result)
+    }
+
+    /// Expand the grammar from the given root symbol. Returns an empty text if the expansion fails.
+    #[func]
+    fn flatten_from(&mut self, root: GString) -> GString {
+        let result = self.grammar.flatten_from_root(&root.to_string());
+        self.expanded(result)
+    }
+
+    /// Returns the message of the last failure, or an empty text if the last load or expansion succeeded
+    #[func]
+    fn get_last_error(&self) -> GString {
+        self.last_error.clone()
+    }
+
+    /// Returns the output of an expansion, or an empty text after recording its failure
+    fn expanded(&mut self, result: Result<String, VitrailError>) -> GString {
+        match result {
+            Ok(output) => {
+                self.succeed();
+                GString::from(output.as_str())
+            }
+            Err(error) => {
+                self.fail(error);
+                GString::new()
+            }
+        }
+    }
+
+    fn succeed(&mut self) {
+        self.last_error = GString::new();
+    }
+
+    /// Record a failure, report it in the Godot console, and return `false`
+    fn fail(&mut self, error: impl ToString) -> bool {
+        let message = error.to_string();
+        godot_error!("{}", message);
+        self.last_error = GString::from(message.as_str());
+        false
+    }
+}
+
+/// A modifier calling a GDScript callable
+struct CallableModifier {
+    callable: Callable,
+}
+
+impl Modifier for CallableModifier {
+    fn apply(&self, source: &str) -> String {
+        let result = self.callable.call(&[GString::from(source).to_variant()]);
+        result
+            .try_to::<GString>()
+            .map(|text| text.to_string())
+            .unwrap_or_else(|_| {
+                godot_error!("A vitrail modifier must return a String, got {}", result);
+                source.to_string()
+            })
+    }
+}
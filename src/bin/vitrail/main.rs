@@ -0,0 +1,308 @@
+#[cfg(feature = "tui")]
+mod tui;
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use vitrail::config::GrammarSyntax;
+use vitrail::error::VitrailError;
+use vitrail::grammar::Grammar;
+
+/// Seed used to generate texts, and by the explorer, when none is given
+const DEFAULT_SEED: &str = "vitrail";
+
+/// Number of texts generated when no count is given
+const DEFAULT_COUNT: usize = 15;
+
+/// Generate texts from a vitrail grammar, or inspect it
+#[derive(Debug, Parser)]
+#[command(name = "vitrail", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Print texts generated from the grammar
+    Generate(GenerateArgs),
+    /// List the symbols of the grammar, with their description
+    Symbols(GrammarFile),
+    /// Report the likely mistakes of the grammar, exiting with an error if any
+    Validate(GrammarFile),
+    /// Print the grammar in the plain-text format, e.g. to hand it over to writers
+    Convert(GrammarFile),
+    /// Print the schema of the grammar as JSON, for editor plugins
+    Schema(GrammarFile),
+    /// Explore the grammar in an interactive terminal, with the `tui` feature
+    Tui(TuiArgs),
+}
+
+/// The grammar file a command reads
+#[derive(Debug, Args)]
+struct GrammarFile {
+    /// Grammar file: JSON, plain text if `.txt`, YAML if `.yaml` or `.yml`, TOML if `.toml`
+    grammar: String,
+}
+
+/// Output format of the generated texts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// One text per line
+    Text,
+    /// A JSON array of strings
+    Json,
+}
+
+/// Arguments of `vitrail generate`
+#[derive(Debug, Args)]
+struct GenerateArgs {
+    #[command(flatten)]
+    file: GrammarFile,
+    /// Seed of the generated texts
+    #[arg(short, long, default_value = DEFAULT_SEED)]
+    seed: String,
+    /// Number of texts to generate
+    #[arg(short = 'n', long, default_value_t = DEFAULT_COUNT)]
+    count: usize,
+    /// Symbol to expand, instead of the root of the grammar
+    #[arg(short, long)]
+    root: Option<String>,
+    /// Output format of the texts
+    #[arg(short, long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Print the derivation tree of each text to the error output
+    #[arg(short, long)]
+    trace: bool,
+}
+
+/// Arguments of `vitrail tui`
+#[derive(Debug, Args)]
+struct TuiArgs {
+    #[command(flatten)]
+    file: GrammarFile,
+    /// Seed of the first expansions
+    #[arg(default_value = DEFAULT_SEED)]
+    seed: String,
+}
+
+fn main() {
+    match Cli::parse().command {
+        Command::Generate(args) => generate(&args),
+        Command::Symbols(file) => list_symbols(&file.grammar),
+        Command::Validate(file) => validate(&file.grammar),
+        Command::Convert(file) => convert(&file.grammar),
+        Command::Schema(file) => print_schema(&file.grammar),
+        Command::Tui(args) => run_tui(&args),
+    }
+}
+
+/// `vitrail generate <grammar>`: print texts generated from the grammar
+fn generate(args: &GenerateArgs) {
+    let mut grammar = load_grammar(&args.file.grammar, &args.seed).with_standard_modifiers();
+    warn_deprecations(&grammar);
+    if let Some(root) = &args.root {
+        grammar = grammar.with_root(root);
+    }
+
+    let texts: Vec<String> = if args.trace {
+        (0..args.count)
+            .map(|_| {
+                let (text, tree) = grammar.flatten_traced();
+                eprint!("{}", tree);
+                text
+            })
+            .collect()
+    } else {
+        match (0..args.count).map(|_| grammar.flatten()).collect() {
+            Ok(texts) => texts,
+            Err(error) => exit_with_error(error),
+        }
+    };
+    match args.format {
+        Format::Text => {
+            for text in texts {
+                println!("{}", text);
+            }
+        }
+        Format::Json => match serde_json::to_string_pretty(&texts) {
+            Ok(json) => println!("{}", json),
+            Err(error) => {
+                eprintln!("error: {}", error);
+                std::process::exit(1);
+            }
+        },
+    }
+}
+
+/// Load a grammar file, written in plain text if its extension is `.txt`, in YAML if it is `.yaml` or `.yml`
+/// (with the `yaml` feature), in TOML if it is `.toml` (with the `toml` feature), in JSON otherwise.
+/// Exits with an error if the file cannot be read or is not a valid grammar.
+fn load_grammar(path: &str, seed: &str) -> Grammar<'static> {
+    read_grammar(path, seed).unwrap_or_else(|error| exit_with_error(error))
+}
+
+fn read_grammar(path: &str, seed: &str) -> Result<Grammar<'static>, VitrailError> {
+    #[cfg(feature = "yaml")]
+    if path.ends_with(".yaml") || path.ends_with(".yml") {
+        return Grammar::from_yaml(path, seed, GrammarSyntax::default());
+    }
+    #[cfg(feature = "toml")]
+    if path.ends_with(".toml") {
+        return Grammar::from_toml(path, seed, GrammarSyntax::default());
+    }
+
+    if path.ends_with(".txt") {
+        Grammar::from_plain_text(path, seed, GrammarSyntax::default())
+    } else {
+        Grammar::from_json(path, seed, GrammarSyntax::default())
+    }
+}
+
+/// Load a grammar file, in the format given by its extension as `load_grammar` does, which may not define
+/// a root symbol, e.g. a wordlist.
+/// Exits with an error if the file cannot be read or is not a valid grammar.
+fn load_any_grammar(path: &str) -> Grammar<'static> {
+    Grammar::from_wordlist(path, "", GrammarSyntax::default())
+        .unwrap_or_else(|error| exit_with_error(error))
+}
+
+/// Print the error and exit
+fn exit_with_error(error: VitrailError) -> ! {
+    eprintln!("error: {}", error);
+    std::process::exit(1);
+}
+
+/// Print a warning for each reference to a deprecated symbol
+fn warn_deprecations(grammar: &Grammar) {
+    for reference in grammar.deprecated_references() {
+        eprintln!("warning: {}", reference);
+    }
+}
+
+/// `vitrail symbols <grammar>`: list the symbols of the grammar, with their description
+fn list_symbols(path: &str) {
+    let grammar = load_any_grammar(path);
+
+    let mut symbols: Vec<&String> = grammar
+        .symbols
+        .keys()
+        .chain(grammar.file_symbols.keys())
+        .collect();
+    symbols.sort();
+
+    let width = symbols.iter().map(|symbol| symbol.len()).max().unwrap_or(0);
+    for symbol in symbols {
+        match grammar.description(symbol) {
+            Some(description) => println!("{:width$}  {}", symbol, description, width = width),
+            None => println!("{}", symbol),
+        }
+    }
+}
+
+/// `vitrail validate <grammar>`: report the likely mistakes of the grammar, exiting with an error if any
+fn validate(path: &str) {
+    let grammar = load_any_grammar(path).with_standard_modifiers();
+
+    let report = grammar.validate();
+    for issue in &report.issues {
+        match grammar.issue_source(issue) {
+            Some(source) => eprintln!("warning: {}: {}", source, issue),
+            None => eprintln!("warning: {}", issue),
+        }
+    }
+    if !report.is_empty() {
+        std::process::exit(1);
+    }
+}
+
+/// `vitrail convert <grammar.json>`: print the grammar in the plain-text format, e.g. to hand it over to writers
+fn convert(path: &str) {
+    match load_any_grammar(path).to_plain_text() {
+        Ok(text) => print!("{}", text),
+        Err(message) => {
+            eprintln!("error: {}", message);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `vitrail schema <grammar>`: print the schema of the grammar as JSON, for editor plugins
+fn print_schema(path: &str) {
+    let grammar = load_any_grammar(path).with_standard_modifiers();
+    println!("{}", grammar.export_schema().to_json());
+}
+
+/// `vitrail tui <grammar> [seed]`
+#[cfg(feature = "tui")]
+fn run_tui(args: &TuiArgs) {
+    let grammar = load_grammar(&args.file.grammar, &args.seed).with_standard_modifiers();
+    warn_deprecations(&grammar);
+
+    if let Err(error) = tui::run(grammar, &args.seed) {
+        eprintln!("Terminal error: {}", error);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(not(feature = "tui"))]
+fn run_tui(_args: &TuiArgs) {
+    eprintln!(
+        "vitrail was built without the `tui` feature: cargo install vitrail-rs --features tui"
+    );
+    std::process::exit(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::error::ErrorKind;
+    use clap::CommandFactory;
+
+    #[test]
+    fn command_is_consistent() {
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn grammar_is_required() {
+        let error = Cli::try_parse_from(["vitrail", "generate", "--seed", "a"]).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::MissingRequiredArgument);
+    }
+
+    #[test]
+    fn generate_options() {
+        let cli = Cli::try_parse_from([
+            "vitrail",
+            "generate",
+            "-n",
+            "3",
+            "story.json",
+            "--format",
+            "json",
+            "--trace",
+        ])
+        .unwrap();
+        match cli.command {
+            Command::Generate(args) => {
+                assert_eq!(args.file.grammar, "story.json");
+                assert_eq!(args.seed, DEFAULT_SEED);
+                assert_eq!(args.count, 3);
+                assert_eq!(args.root, None);
+                assert_eq!(args.format, Format::Json);
+                assert!(args.trace);
+            }
+            command => panic!("unexpected command {:?}", command),
+        }
+    }
+
+    #[test]
+    fn help_anywhere() {
+        for args in [
+            &["vitrail", "--help"][..],
+            &["vitrail", "generate", "story.json", "--help"],
+            &["vitrail", "validate", "--help", "story.json"],
+        ] {
+            let error = Cli::try_parse_from(args).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::DisplayHelp);
+        }
+    }
+}
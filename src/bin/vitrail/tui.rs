@@ -0,0 +1,337 @@
+//! Interactive terminal explorer for grammar authors: browse symbols, preview their expansion,
+//! pin captured variables, and re-roll with visible seeds.
+
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+
+use vitrail::grammar::{CaptureValue, Grammar};
+use vitrail::rng::SeededRng;
+
+const HELP: &str = " ↑/↓ select · tab switch list · r re-roll · space pin capture · q quit ";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Focus {
+    Symbols,
+    Captures,
+}
+
+struct App<'a> {
+    grammar: Grammar<'a>,
+    symbols: Vec<String>,
+    symbol_state: ListState,
+    capture_state: ListState,
+    focus: Focus,
+    seed: String,
+    preview: Result<String, String>,
+}
+
+impl<'a> App<'a> {
+    fn new(grammar: Grammar<'a>, seed: &str) -> Self {
+        let mut symbols: Vec<String> = grammar
+            .symbols
+            .keys()
+            .chain(grammar.file_symbols.keys())
+            .chain(grammar.dynamic_symbols.keys())
+            .cloned()
+            .collect();
+        symbols.sort();
+
+        let mut app = Self {
+            grammar,
+            symbols,
+            symbol_state: ListState::default().with_selected(Some(0)),
+            capture_state: ListState::default(),
+            focus: Focus::Symbols,
+            seed: seed.to_string(),
+            preview: Ok(String::new()),
+        };
+        app.refresh();
+        app
+    }
+
+    fn selected_symbol(&self) -> Option<&str> {
+        self.symbol_state
+            .selected()
+            .and_then(|i| self.symbols.get(i))
+            .map(String::as_str)
+    }
+
+    fn captures(&self) -> Vec<(&String, &CaptureValue)> {
+        let mut captures: Vec<(&String, &CaptureValue)> = self.grammar.captures.iter().collect();
+        captures.sort_by_key(|(name, _)| *name);
+        captures
+    }
+
+    /// Expand the selected symbol again with the current seed
+    fn refresh(&mut self) {
+        let symbol = match self.selected_symbol() {
+            Some(symbol) => symbol.to_string(),
+            None => return,
+        };
+
+        self.grammar.reseed(&self.seed);
+        // Expansion errors are returned, but a modifier can still panic on some text
+        let grammar = &mut self.grammar;
+        self.preview =
+            match panic::catch_unwind(AssertUnwindSafe(|| grammar.flatten_from_root(&symbol))) {
+                Ok(result) => result.map_err(|error| error.to_string()),
+                Err(payload) => Err(payload
+                    .downcast_ref::<String>()
+                    .cloned()
+                    .or_else(|| payload.downcast_ref::<&str>().map(|s| s.to_string()))
+                    .unwrap_or_else(|| "Expansion failed".to_string())),
+            };
+    }
+
+    /// Expand the selected symbol with a new seed, derived from the current one
+    fn reroll(&mut self) {
+        self.seed = SeededRng::new(&self.seed).hex(8);
+        self.refresh();
+    }
+
+    fn toggle_pin(&mut self) {
+        let name = match self
+            .capture_state
+            .selected()
+            .and_then(|i| self.captures().get(i).map(|(name, _)| name.to_string()))
+        {
+            Some(name) => name,
+            None => return,
+        };
+
+        if self.grammar.pinned_captures.contains(&name) {
+            self.grammar.unpin_capture(&name);
+        } else {
+            self.grammar.pin_capture(&name);
+        }
+    }
+
+    fn move_selection(&mut self, forward: bool) {
+        let (state, len) = match self.focus {
+            Focus::Symbols => (&mut self.symbol_state, self.symbols.len()),
+            Focus::Captures => (&mut self.capture_state, self.grammar.captures.len()),
+        };
+        if len == 0 {
+            return;
+        }
+
+        let current = state.selected().unwrap_or(0);
+        state.select(Some(if forward {
+            (current + 1) % len
+        } else {
+            (current + len - 1) % len
+        }));
+
+        if self.focus == Focus::Symbols {
+            self.refresh();
+        }
+    }
+
+    /// Handle a key press, and return whether the explorer should keep running
+    fn handle_key(&mut self, key: KeyCode) -> bool {
+        match key {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(false),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(true),
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Symbols => Focus::Captures,
+                    Focus::Captures => Focus::Symbols,
+                };
+                if self.capture_state.selected().is_none() && !self.grammar.captures.is_empty() {
+                    self.capture_state.select(Some(0));
+                }
+            }
+            KeyCode::Char('r') | KeyCode::Enter => self.reroll(),
+            KeyCode::Char(' ') | KeyCode::Char('p') => self.toggle_pin(),
+            _ => (),
+        }
+
+        true
+    }
+
+    /// The rules of the selected symbol, after its description if it has one
+    fn rule_lines(&self) -> Vec<Line<'_>> {
+        let symbol = match self.selected_symbol() {
+            Some(symbol) => symbol,
+            None => return Vec::new(),
+        };
+
+        let mut lines = Vec::new();
+        if let Some(description) = self.grammar.description(symbol) {
+            lines.push(Line::from(description).italic());
+            lines.push(Line::default());
+        }
+        if let Some(rules) = self.grammar.symbols.get(symbol) {
+            lines.extend(rules.iter().map(|rule| Line::from(rule.as_str())));
+        }
+        lines
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(30), Constraint::Percentage(70)])
+                .areas(main);
+        let [preview_area, rules_area, captures_area] = Layout::vertical([
+            Constraint::Percentage(40),
+            Constraint::Percentage(35),
+            Constraint::Percentage(25),
+        ])
+        .areas(right);
+
+        let highlight = Style::new().reversed();
+        let current_focus = self.focus;
+        let focused = |focus: Focus| {
+            if current_focus == focus {
+                Style::new().bold()
+            } else {
+                Style::new()
+            }
+        };
+
+        let symbols = List::new(self.symbols.iter().map(|s| ListItem::new(s.as_str())))
+            .block(
+                Block::bordered()
+                    .title(" Symbols ")
+                    .border_style(focused(Focus::Symbols)),
+            )
+            .highlight_style(highlight);
+        frame.render_stateful_widget(symbols, left, &mut self.symbol_state);
+
+        let preview = match &self.preview {
+            Ok(text) => Paragraph::new(text.as_str()),
+            Err(message) => Paragraph::new(message.as_str()).red(),
+        };
+        frame.render_widget(
+            preview
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title(format!(" Preview · seed {} ", self.seed))),
+            preview_area,
+        );
+
+        let rules = self.rule_lines();
+        frame.render_widget(
+            Paragraph::new(rules)
+                .wrap(Wrap { trim: false })
+                .block(Block::bordered().title(" Rules ")),
+            rules_area,
+        );
+
+        let captures: Vec<ListItem> = self
+            .captures()
+            .into_iter()
+            .map(|(name, value)| {
+                let pin = if self.grammar.pinned_captures.contains(name) {
+                    "[pinned] "
+                } else {
+                    ""
+                };
+                ListItem::new(format!("{}{} = {}", pin, name, value))
+            })
+            .collect();
+        let captures = List::new(captures)
+            .block(
+                Block::bordered()
+                    .title(" Captures ")
+                    .border_style(focused(Focus::Captures)),
+            )
+            .highlight_style(highlight);
+        frame.render_stateful_widget(captures, captures_area, &mut self.capture_state);
+
+        frame.render_widget(Line::from(HELP).reversed(), status);
+    }
+}
+
+/// Run the explorer on the given grammar until the user quits
+pub fn run(grammar: Grammar, seed: &str) -> io::Result<()> {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, App::new(grammar, seed));
+    ratatui::restore();
+
+    panic::set_hook(default_hook);
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, mut app: App) -> io::Result<()> {
+    loop {
+        terminal.draw(|frame| app.draw(frame))?;
+
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press && !app.handle_key(key.code) {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use vitrail::config::GrammarSyntax;
+
+    fn app() -> App<'static> {
+        let grammar =
+            Grammar::from_json("test.json", "testseed", GrammarSyntax::default()).unwrap();
+        App::new(grammar, "testseed")
+    }
+
+    #[test]
+    fn symbols_are_sorted() {
+        let app = app();
+        assert_eq!(app.symbols[0], "character");
+        assert_eq!(app.selected_symbol(), Some("character"));
+    }
+
+    #[test]
+    fn reroll_changes_visible_seed() {
+        let mut app = app();
+        app.reroll();
+        assert_ne!(app.seed, "testseed");
+        assert_eq!(app.seed.len(), 8);
+    }
+
+    #[test]
+    fn pin_selected_capture() {
+        let mut app = app();
+        while app.selected_symbol() != Some("root") {
+            app.handle_key(KeyCode::Down);
+        }
+        let hero = app.grammar.captures["subject"].clone();
+
+        app.handle_key(KeyCode::Tab);
+        app.handle_key(KeyCode::Char(' '));
+        for _ in 0..5 {
+            app.reroll();
+            assert_eq!(app.grammar.captures["subject"], hero);
+        }
+    }
+
+    #[test]
+    fn description_precedes_rules() {
+        let grammar = Grammar::from_json("test.json", "testseed", GrammarSyntax::default())
+            .unwrap()
+            .with_description("character", "The hero of the story");
+        let app = App::new(grammar, "testseed");
+
+        let lines = app.rule_lines();
+        assert_eq!(lines[0].to_string(), "The hero of the story");
+        assert_eq!(lines.len(), app.grammar.symbols["character"].len() + 2);
+    }
+
+    #[test]
+    fn quit() {
+        assert!(!app().handle_key(KeyCode::Char('q')));
+    }
+}
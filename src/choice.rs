@@ -0,0 +1,76 @@
+//! Record of the rules chosen during an expansion, to reproduce an output exactly.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::num::ParseIntError;
+use std::str::FromStr;
+
+/// The sequence of rule indices chosen during a flatten, in the order the symbols were derived.
+///
+/// Its compact text form lists the indices separated by dots, e.g. `2.0.13.1`, so it can be pasted into a bug report.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChoiceScript {
+    /// The index of the rule chosen for each derived symbol
+    pub choices: Vec<usize>,
+}
+
+impl fmt::Display for ChoiceScript {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let choices: Vec<String> = self.choices.iter().map(usize::to_string).collect();
+        write!(f, "{}", choices.join("."))
+    }
+}
+
+impl FromStr for ChoiceScript {
+    type Err = ParseIntError;
+
+    fn from_str(script: &str) -> Result<Self, Self::Err> {
+        let script = script.trim();
+        if script.is_empty() {
+            return Ok(Self::default());
+        }
+
+        let choices = script
+            .split('.')
+            .map(str::parse)
+            .collect::<Result<Vec<usize>, _>>()?;
+
+        Ok(Self { choices })
+    }
+}
+
+/// How a grammar chooses the rule of a derived symbol
+#[derive(Debug, Default)]
+pub(crate) enum ChoiceMode {
+    /// Pick rules randomly
+    #[default]
+    Random,
+    /// Pick rules randomly, and record the chosen indices
+    Recording(Vec<usize>),
+    /// Pick the rules listed by a script, in order
+    Replaying(std::vec::IntoIter<usize>),
+    /// Pick the rule listed for each symbol at the depth it is derived (or the deepest one listed),
+    /// and record the chosen indices
+    Guided {
+        rules: HashMap<String, Vec<usize>>,
+        recorded: Vec<usize>,
+    },
+    /// Pick the given rule for the root symbol, and rules randomly for the symbols it derives
+    RootRule(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compact_text_form() {
+        let script = ChoiceScript {
+            choices: vec![2, 0, 13, 1],
+        };
+        assert_eq!(script.to_string(), "2.0.13.1");
+        assert_eq!("2.0.13.1".parse::<ChoiceScript>(), Ok(script));
+        assert_eq!("".parse::<ChoiceScript>(), Ok(ChoiceScript::default()));
+        assert!("2.x".parse::<ChoiceScript>().is_err());
+    }
+}
@@ -0,0 +1,12 @@
+/// What to do when grammars being merged both define a symbol, used by `Grammar::merge` and `Grammar::from_dir`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ConflictPolicy {
+    /// The merge fails, leaving the grammar unchanged
+    #[default]
+    Error,
+    /// The definition of the grammar merged in replaces the existing one, along with its rule options,
+    /// strategy, description, deprecation and length hint
+    Override,
+    /// The rules of the grammar merged in are added after the existing ones, with their options
+    AppendRules,
+}
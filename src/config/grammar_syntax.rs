@@ -5,7 +5,7 @@
 /// - What determines a symbol to be expanded
 /// - How are modifiers call upon an expansion
 /// - etc.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct GrammarSyntax {
     /// character starting a non-terminal symbol to be expanded
     pub symbol_start: char,
@@ -18,8 +18,17 @@ pub struct GrammarSyntax {
     /// character separating the symbol whose expansion is to be captured,
     /// and the name of the new symbol holding the captured value
     pub capture_operator: char,
+    /// order of the source and the variable on each side of the capture operator
+    pub capture_form: CaptureForm,
     /// character separating the symbol, and the modifier(s) to apply to its expansion
     pub modifier_operator: char,
+    /// character prefixing an arithmetic expression to be evaluated, in place of a symbol name
+    pub expression_prefix: char,
+    /// character prefixing the name of a built-in symbol (e.g. `uuid`), in place of a symbol name
+    pub builtin_prefix: char,
+    /// character making the delimiter following it (or itself) literal text, e.g. `\{` for a literal `{`.
+    /// It is removed from the output, and kept as is before any other character.
+    pub escape: char,
 }
 
 impl Default for GrammarSyntax {
@@ -31,19 +40,95 @@ impl Default for GrammarSyntax {
             capture_start: '[',
             capture_end: ']',
             capture_operator: '>',
+            capture_form: CaptureForm::SymbolFirst,
             modifier_operator: ':',
+            expression_prefix: '#',
+            builtin_prefix: '@',
+            escape: '\\',
         }
     }
 }
 
+/// How the operands of a capture expression are written
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureForm {
+    /// `[symbol>variable]`: the expansion of a symbol is captured
+    SymbolFirst,
+    /// `[variable:rule]`, as Tracery actions: the expansion of a rule, such as `#noun#`, is captured
+    VariableFirst,
+}
+
 impl GrammarSyntax {
+    /// The syntax of Tracery grammars, so they can be loaded unmodified:
+    /// `#symbol.modifier#` expands a symbol, and `[variable:#symbol#]` captures an expansion.
+    ///
+    /// Arithmetic expressions are prefixed with `=` (`#=level*10#`), and built-in symbols with `@`.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::tracery())
+    ///     .with_symbol("root".to_string(), vec!["#[hero:#name#]story#".to_string()])
+    ///     .with_symbol("story".to_string(), vec!["#hero.capitalize# met #animal.a#. #hero# fled.".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["ada".to_string()])
+    ///     .with_symbol("animal".to_string(), vec!["owl".to_string()])
+    ///     .with_standard_modifiers();
+    ///
+    /// assert_eq!(grammar.flatten().unwrap(), "Ada met an owl. ada fled.");
+    /// ```
+    pub fn tracery() -> Self {
+        Self {
+            symbol_start: '#',
+            symbol_end: '#',
+            capture_start: '[',
+            capture_end: ']',
+            capture_operator: ':',
+            capture_form: CaptureForm::VariableFirst,
+            modifier_operator: '.',
+            expression_prefix: '=',
+            builtin_prefix: '@',
+            escape: '\\',
+        }
+    }
+
+    /// Returns whether the given character is made literal by a preceding escape character: a delimiter
+    /// of symbols or captures, or the escape character itself
+    pub fn is_escapable(&self, character: char) -> bool {
+        character == self.symbol_start
+            || character == self.symbol_end
+            || character == self.capture_start
+            || character == self.capture_end
+            || character == self.escape
+    }
+
     /// Returns whether the given symbol has any modifier applied to it, according to the grammar syntax configuration
     pub fn has_modifier(&self, symbol: &str) -> bool {
         symbol.contains(self.modifier_operator)
     }
 
+    /// Returns whether the given symbol contains any capture expression, according to the grammar syntax configuration
+    pub fn has_capture(&self, symbol: &str) -> bool {
+        symbol.contains(self.capture_start) && symbol.contains(self.capture_end)
+    }
+
+    /// Returns whether the given symbol (without its delimiters) is an arithmetic expression, according to the grammar syntax configuration
+    pub fn is_expression(&self, symbol: &str) -> bool {
+        symbol.starts_with(self.expression_prefix)
+    }
+
+    /// Returns whether the given symbol (without its delimiters) is a built-in symbol, according to the grammar syntax configuration
+    pub fn is_builtin(&self, symbol: &str) -> bool {
+        symbol.starts_with(self.builtin_prefix)
+    }
+
     /// Returns whether the given symbol is non-terminal (its value is to be expanded), according to the grammar syntax configuration
     pub fn is_non_terminal(&self, symbol: &str) -> bool {
+        if self.symbol_start == self.symbol_end {
+            return symbol.matches(self.symbol_start).nth(1).is_some();
+        }
         symbol.contains(self.symbol_start) && symbol.contains(self.symbol_end)
     }
 
@@ -69,6 +154,42 @@ mod tests {
         assert!(!syntax.has_modifier("nomodifier"))
     }
 
+    #[test]
+    fn has_capture_when_true() {
+        let syntax = GrammarSyntax::default();
+        assert!(syntax.has_capture("[symbol>variable]text"))
+    }
+
+    #[test]
+    fn has_capture_when_false() {
+        let syntax = GrammarSyntax::default();
+        assert!(!syntax.has_capture("{symbol} text"))
+    }
+
+    #[test]
+    fn is_expression_when_true() {
+        let syntax = GrammarSyntax::default();
+        assert!(syntax.is_expression("#level*10"))
+    }
+
+    #[test]
+    fn is_expression_when_false() {
+        let syntax = GrammarSyntax::default();
+        assert!(!syntax.is_expression("level"))
+    }
+
+    #[test]
+    fn is_builtin_when_true() {
+        let syntax = GrammarSyntax::default();
+        assert!(syntax.is_builtin("@uuid"))
+    }
+
+    #[test]
+    fn is_builtin_when_false() {
+        let syntax = GrammarSyntax::default();
+        assert!(!syntax.is_builtin("uuid"))
+    }
+
     #[test]
     fn is_non_terminal_when_non_terminal() {
         let syntax = GrammarSyntax::default();
@@ -87,6 +208,21 @@ mod tests {
         assert!(!syntax.is_terminal("I {verb} non-terminal"));
     }
 
+    #[test]
+    fn is_non_terminal_with_identical_delimiters() {
+        let syntax = GrammarSyntax::tracery();
+        assert!(syntax.is_non_terminal("I #verb.ed# non-terminal"));
+        assert!(!syntax.is_non_terminal("I am #1"));
+    }
+
+    #[test]
+    fn only_syntax_characters_are_escapable() {
+        let syntax = GrammarSyntax::default();
+        assert!("{}[]\\".chars().all(|c| syntax.is_escapable(c)));
+        assert!(!syntax.is_escapable('n'));
+        assert!(!syntax.is_escapable(':'));
+    }
+
     #[test]
     fn is_terminal_when_terminal() {
         let syntax = GrammarSyntax::default();
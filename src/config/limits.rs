@@ -0,0 +1,44 @@
+/// Limits applied to the expansions of a grammar, so that a malformed grammar, e.g. one whose root derives
+/// itself forever, cannot hang or crash its host. Unlike a sandbox, they do not restrict what the grammar can use.
+///
+/// Reaching a limit is an expansion error, unless a truncation marker is set: the symbols left to derive
+/// are then replaced by the marker.
+///
+/// ```
+/// use vitrail::config::ExpansionLimits;
+///
+/// let limits = ExpansionLimits::default()
+///     .with_max_depth(32)
+///     .with_max_expansions(1000)
+///     .with_truncation_marker("…");
+/// assert_eq!(limits.truncation_marker.as_deref(), Some("…"));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExpansionLimits {
+    /// The maximum nesting depth of an expansion, the rule of the root being the first level
+    pub max_depth: Option<usize>,
+    /// The maximum number of symbols derived by a flatten
+    pub max_expansions: Option<usize>,
+    /// The text replacing each symbol left unexpanded once a limit is reached, instead of an expansion error
+    pub truncation_marker: Option<String>,
+}
+
+impl ExpansionLimits {
+    /// Returns the limits with the given maximum nesting depth
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Returns the limits with the given maximum number of derived symbols per flatten
+    pub fn with_max_expansions(mut self, max_expansions: usize) -> Self {
+        self.max_expansions = Some(max_expansions);
+        self
+    }
+
+    /// Returns the limits truncating the expansion with the given marker when one of them is reached
+    pub fn with_truncation_marker(mut self, marker: &str) -> Self {
+        self.truncation_marker = Some(marker.to_string());
+        self
+    }
+}
@@ -1,3 +1,21 @@
+mod conflict;
 mod grammar_syntax;
+mod limits;
+mod output_profile;
+mod plan;
+mod profile;
+mod quota;
+mod sandbox;
+mod similarity;
+mod variety;
 
-pub use self::grammar_syntax::GrammarSyntax;
+pub use self::conflict::ConflictPolicy;
+pub use self::grammar_syntax::{CaptureForm, GrammarSyntax};
+pub use self::limits::ExpansionLimits;
+pub use self::output_profile::{Decoration, Escaping, OutputProfile, Wrapping};
+pub use self::plan::PlanConstraints;
+pub use self::profile::{Profile, PROFILE_ATTEMPTS};
+pub use self::quota::{Quota, TagQuotas, UnmetQuotas};
+pub use self::sandbox::{PartialOutput, SandboxLimit, SandboxOptions};
+pub use self::similarity::Similarity;
+pub use self::variety::{VarietyBoost, VocabularyDampening};
@@ -0,0 +1,350 @@
+use std::collections::HashMap;
+
+use crate::markup::Markup;
+use crate::ssml;
+use crate::text;
+
+/// How the text coming from the grammar rules is escaped, before being decorated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Escaping {
+    /// Text is output as written
+    #[default]
+    None,
+    /// Characters reserved by XML are escaped, e.g. for SSML or HTML output
+    Xml,
+}
+
+/// Text wrapped around the expansion of a symbol
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoration {
+    /// text inserted before the expansion
+    pub prefix: String,
+    /// text inserted after the expansion
+    pub suffix: String,
+}
+
+impl Decoration {
+    /// Wrap the expansion between the given prefix and suffix
+    pub fn new(prefix: &str, suffix: &str) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            suffix: suffix.to_string(),
+        }
+    }
+
+    /// Wrap the expansion in Markdown bold markers: `**expansion**`
+    pub fn markdown_bold() -> Self {
+        Self::new("**", "**")
+    }
+
+    /// Wrap the expansion in Markdown emphasis markers: `*expansion*`
+    pub fn markdown_italic() -> Self {
+        Self::new("*", "*")
+    }
+
+    /// Wrap the expansion in an HTML element: `<tag>expansion</tag>`
+    pub fn html(tag: &str) -> Self {
+        Self::new(&format!("<{}>", tag), &format!("</{}>", tag))
+    }
+
+    /// Wrap the expansion in an SSML `emphasis` element, with the given level (`strong`, `moderate`, `reduced`)
+    pub fn ssml_emphasis(level: &str) -> Self {
+        let (open, close) = ssml::element("emphasis", &[("level", level)]);
+        Self::new(&open, &close)
+    }
+
+    /// Wrap the expansion in an SSML `prosody` element, with the given rate (e.g. `slow`, `120%`)
+    pub fn ssml_rate(rate: &str) -> Self {
+        let (open, close) = ssml::element("prosody", &[("rate", rate)]);
+        Self::new(&open, &close)
+    }
+
+    /// Follow the expansion with an SSML `break` (pause) of the given duration (e.g. `500ms`)
+    pub fn ssml_break_after(time: &str) -> Self {
+        Self::new("", &format!("<break time=\"{}\"/>", ssml::escape(time)))
+    }
+
+    /// Returns the given expansion wrapped in the decoration
+    pub fn apply(&self, expansion: &str) -> String {
+        format!("{}{}{}", self.prefix, expansion, self.suffix)
+    }
+}
+
+/// How the whole output is wrapped to lines of a given width, e.g. for a terminal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wrapping {
+    /// The maximum length of a line, in grapheme clusters
+    pub width: usize,
+    /// The text starting every line of a paragraph but its first one
+    pub hanging_indent: String,
+}
+
+impl Wrapping {
+    /// Wrap the output to lines of at most `width` grapheme clusters, without indent
+    pub fn new(width: usize) -> Self {
+        Self {
+            width,
+            hanging_indent: String::new(),
+        }
+    }
+
+    /// Returns the wrapping, starting every line of a paragraph but its first one with the given indent
+    pub fn with_hanging_indent(mut self, indent: &str) -> Self {
+        self.hanging_indent = indent.to_string();
+        self
+    }
+}
+
+/// Describes how `Grammar` presents the expansions of specific symbols, so presentation
+/// concerns (Markdown, HTML, etc.) stay out of the grammar rules.
+///
+/// ```
+/// use vitrail::config::{Decoration, OutputProfile};
+///
+/// let profile = OutputProfile::default()
+///     .with_decoration("item", Decoration::markdown_bold())
+///     .with_decoration("place", Decoration::html("em"));
+///
+/// assert_eq!(profile.decorate("item", "sword"), "**sword**");
+/// assert_eq!(profile.decorate("other", "text"), "text");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct OutputProfile {
+    /// The decorations to apply, expressed as a map of symbol => decoration wrapped around each of its expansions
+    pub decorations: HashMap<String, Decoration>,
+    /// How the text coming from the rules is escaped
+    pub escaping: Escaping,
+    /// The decoration wrapped around the whole output, if any
+    pub document: Option<Decoration>,
+    /// Whether the whole output is tidied into sentences (see `text::finish_sentences`), before its document decoration.
+    /// Meant for plain text, as markup is not told apart from text.
+    pub finish_sentences: bool,
+    /// The inline markup whose tags are balanced in the whole output, before its document decoration, if any
+    pub markup: Option<Markup>,
+    /// How the whole output is wrapped to lines, before its document decoration, if it is.
+    /// Meant for plain text, as markup is counted as text.
+    pub wrapping: Option<Wrapping>,
+}
+
+impl OutputProfile {
+    /// Profile producing SSML documents for text-to-speech engines: the rules text is XML-escaped,
+    /// and the whole output is wrapped in a `speak` element.
+    /// SSML elements can then be added around symbols with the `Decoration::ssml_*` decorations.
+    ///
+    /// ```
+    /// use vitrail::config::{Decoration, OutputProfile};
+    ///
+    /// let profile = OutputProfile::ssml()
+    ///     .with_decoration("villain", Decoration::ssml_emphasis("strong"));
+    /// ```
+    pub fn ssml() -> Self {
+        Self {
+            escaping: Escaping::Xml,
+            document: Some(Decoration::new("<speak>", "</speak>")),
+            ..Self::default()
+        }
+    }
+
+    /// Add a decoration applied around every expansion of the given symbol.
+    ///
+    /// The method returns the OutputProfile instance, so you can build upon it.
+    pub fn with_decoration(mut self, symbol: &str, decoration: Decoration) -> Self {
+        self.decorations.insert(symbol.to_string(), decoration);
+        self
+    }
+
+    /// Tidy the whole output into sentences: capitalized sentence starts, terminal punctuation,
+    /// and no space left before punctuation by empty expansions.
+    ///
+    /// The method returns the OutputProfile instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::config::OutputProfile;
+    ///
+    /// let profile = OutputProfile::default().with_sentence_finishing();
+    /// assert_eq!(profile.wrap_document("the end , at last"), "The end, at last.");
+    /// ```
+    pub fn with_sentence_finishing(mut self) -> Self {
+        self.finish_sentences = true;
+        self
+    }
+
+    /// Balance the tags of the given inline markup in the whole output, so rules containing opening or closing
+    /// fragments never produce broken markup.
+    ///
+    /// The method returns the OutputProfile instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::config::OutputProfile;
+    /// use vitrail::markup::Markup;
+    ///
+    /// let profile = OutputProfile::default().with_markup_balancing(Markup::BBCode);
+    /// assert_eq!(profile.wrap_document("[color=red]Danger"), "[color=red]Danger[/color]");
+    /// ```
+    pub fn with_markup_balancing(mut self, markup: Markup) -> Self {
+        self.markup = Some(markup);
+        self
+    }
+
+    /// Wrap the whole output to lines of a given width, with `text::wrap`, e.g. for CLI tools and terminal games
+    /// printing long paragraphs.
+    ///
+    /// The method returns the OutputProfile instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::config::{OutputProfile, Wrapping};
+    ///
+    /// let profile = OutputProfile::default().with_wrapping(Wrapping::new(20).with_hanging_indent("  "));
+    /// assert_eq!(
+    ///     profile.wrap_document("The tavern is loud tonight, and the bard is out of tune."),
+    ///     "The tavern is loud\n  tonight, and the\n  bard is out of\n  tune."
+    /// );
+    /// ```
+    pub fn with_wrapping(mut self, wrapping: Wrapping) -> Self {
+        self.wrapping = Some(wrapping);
+        self
+    }
+
+    /// Returns whether the profile transforms the whole output, beyond the expansions of its symbols
+    pub(crate) fn transforms_document(&self) -> bool {
+        self.document.is_some()
+            || self.finish_sentences
+            || self.markup.is_some()
+            || self.wrapping.is_some()
+    }
+
+    /// Returns the given rule text, escaped as required by the profile
+    pub fn escape(&self, text: &str) -> String {
+        match self.escaping {
+            Escaping::None => text.to_string(),
+            Escaping::Xml => ssml::escape(text),
+        }
+    }
+
+    /// Returns a whole output, tidied into sentences, with its markup balanced and wrapped to lines if enabled,
+    /// and wrapped in the document decoration if there is one
+    pub fn wrap_document(&self, output: &str) -> String {
+        let mut output = if self.finish_sentences {
+            text::finish_sentences(output)
+        } else {
+            output.to_string()
+        };
+        if let Some(markup) = &self.markup {
+            output = markup.balance(&output);
+        }
+        if let Some(wrapping) = &self.wrapping {
+            output = text::wrap(&output, wrapping.width, &wrapping.hanging_indent);
+        }
+
+        match &self.document {
+            Some(decoration) => decoration.apply(&output),
+            None => output,
+        }
+    }
+
+    /// Returns a whole output truncated to at most `max` grapheme clusters, ellipsis included, with `text::truncate`.
+    /// Its content is cut rather than its document decoration, and its markup is balanced again if enabled.
+    pub(crate) fn truncate_document(&self, output: &str, max: usize, ellipsis: &str) -> String {
+        let (prefix, suffix) = match &self.document {
+            Some(decoration)
+                if output.len() >= decoration.prefix.len() + decoration.suffix.len()
+                    && output.starts_with(&decoration.prefix)
+                    && output.ends_with(&decoration.suffix) =>
+            {
+                (decoration.prefix.as_str(), decoration.suffix.as_str())
+            }
+            _ => ("", ""),
+        };
+        let content = &output[prefix.len()..output.len() - suffix.len()];
+
+        // The tags closed by the balancing count too, so the content is cut shorter until they fit
+        let mut room = max.saturating_sub(text::grapheme_len(prefix) + text::grapheme_len(suffix));
+        loop {
+            let mut truncated = text::truncate(content, room, ellipsis);
+            if let Some(markup) = &self.markup {
+                truncated = markup.balance(&truncated);
+            }
+            let truncated = format!("{}{}{}", prefix, truncated, suffix);
+            if room == 0 || text::grapheme_len(&truncated) <= max {
+                return truncated;
+            }
+            room -= 1;
+        }
+    }
+
+    /// Returns the expansion of a symbol, wrapped in its decoration if it has one
+    pub fn decorate(&self, symbol: &str, expansion: &str) -> String {
+        match self.decorations.get(symbol) {
+            Some(decoration) => decoration.apply(expansion),
+            None => expansion.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncated_documents_keep_their_decoration() {
+        let profile = OutputProfile::ssml();
+        let output = profile.wrap_document("The owl hoots at the moon");
+        assert_eq!(
+            profile.truncate_document(&output, 30, "…"),
+            "<speak>The owl hoots…</speak>"
+        );
+
+        let profile = OutputProfile::default().with_markup_balancing(Markup::BBCode);
+        assert_eq!(
+            profile.truncate_document("[b]The owl hoots at the moon[/b]", 20, "…"),
+            "[b]The owl…[/b]"
+        );
+    }
+
+    #[test]
+    fn decorate_markdown() {
+        let profile = OutputProfile::default().with_decoration("item", Decoration::markdown_bold());
+        assert_eq!(profile.decorate("item", "sword"), "**sword**");
+    }
+
+    #[test]
+    fn decorate_html() {
+        let profile = OutputProfile::default().with_decoration("item", Decoration::html("em"));
+        assert_eq!(profile.decorate("item", "sword"), "<em>sword</em>");
+    }
+
+    #[test]
+    fn ssml_profile() {
+        let profile = OutputProfile::ssml();
+        assert_eq!(profile.escape("a & b"), "a &amp; b");
+        assert_eq!(profile.wrap_document("hi"), "<speak>hi</speak>");
+    }
+
+    #[test]
+    fn decorate_ssml_break() {
+        let profile =
+            OutputProfile::default().with_decoration("item", Decoration::ssml_break_after("1s"));
+        assert_eq!(profile.decorate("item", "wait"), "wait<break time=\"1s\"/>");
+    }
+
+    #[test]
+    fn wrapping_before_document_decoration() {
+        let profile = OutputProfile::default()
+            .with_wrapping(Wrapping::new(8))
+            .with_sentence_finishing();
+        let profile = OutputProfile {
+            document: Some(Decoration::new("> ", "")),
+            ..profile
+        };
+        assert_eq!(
+            profile.wrap_document("the night is young"),
+            "> The\nnight is\nyoung."
+        );
+    }
+
+    #[test]
+    fn decorate_undecorated_symbol() {
+        let profile = OutputProfile::default();
+        assert_eq!(profile.decorate("item", "sword"), "sword");
+    }
+}
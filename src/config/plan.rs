@@ -0,0 +1,73 @@
+/// The high-level choices of a two-stage generation, used by `Grammar::flatten_planned`: the rules of the planned
+/// symbols (theme, tone, cast, ...) are chosen first, so that no pair of excluded tags is found among their tags,
+/// then the grammar is flattened with these rules, avoiding the rules whose tags are excluded by them.
+///
+/// ```
+/// use vitrail::config::PlanConstraints;
+///
+/// let constraints = PlanConstraints::new(&["setting", "scene"]).with_exclusion("underwater", "campfire");
+/// assert!(constraints.allows(&["underwater", "night"]));
+/// assert!(!constraints.allows(&["campfire", "underwater"]));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PlanConstraints {
+    /// The symbols whose rules are chosen before the expansion, in order
+    pub symbols: Vec<String>,
+    /// The pairs of tags which the rules of an expansion cannot have together
+    pub exclusions: Vec<(String, String)>,
+}
+
+impl PlanConstraints {
+    pub fn new(symbols: &[&str]) -> Self {
+        Self {
+            symbols: symbols.iter().map(|symbol| symbol.to_string()).collect(),
+            exclusions: Vec::new(),
+        }
+    }
+
+    /// Returns the constraints, forbidding the rules of an expansion to have both tags
+    pub fn with_exclusion(mut self, tag: &str, other: &str) -> Self {
+        self.exclusions.push((tag.to_string(), other.to_string()));
+        self
+    }
+
+    /// Returns whether the given tags, gathered from the rules of an expansion, break none of the exclusions
+    pub fn allows<S: AsRef<str>>(&self, tags: &[S]) -> bool {
+        let has = |tag: &str| tags.iter().any(|t| t.as_ref() == tag);
+        !self
+            .exclusions
+            .iter()
+            .any(|(tag, other)| has(tag) && has(other))
+    }
+
+    /// Returns the tags excluded by any of the given tags
+    pub fn excluded_by<S: AsRef<str>>(&self, tags: &[S]) -> Vec<&str> {
+        let has = |tag: &str| tags.iter().any(|t| t.as_ref() == tag);
+        self.exclusions
+            .iter()
+            .flat_map(|(tag, other)| {
+                let mut excluded = Vec::new();
+                if has(tag) {
+                    excluded.push(other.as_str());
+                }
+                if has(other) {
+                    excluded.push(tag.as_str());
+                }
+                excluded
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exclusions_go_both_ways() {
+        let constraints = PlanConstraints::new(&[]).with_exclusion("sea", "fire");
+        assert_eq!(constraints.excluded_by(&["fire", "day"]), vec!["sea"]);
+        assert_eq!(constraints.excluded_by(&["sea"]), vec!["fire"]);
+        assert!(constraints.excluded_by::<&str>(&[]).is_empty());
+    }
+}
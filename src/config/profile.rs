@@ -0,0 +1,91 @@
+use crate::text;
+
+/// Number of times a grammar is flattened looking for an output fitting a profile,
+/// before falling back to truncation
+pub const PROFILE_ATTEMPTS: usize = 10;
+
+/// Length constraints of a publishing platform, used by `Grammar::flatten_with_profile`.
+///
+/// Lengths are counted in grapheme clusters (user-perceived characters).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Profile {
+    /// Posts of at most 280 characters
+    Twitter,
+    /// Posts of at most 300 characters
+    Bluesky,
+    /// Posts whose maximum length depends on the instance (500 by default)
+    Mastodon { max: usize },
+    /// Single SMS messages of at most 160 characters
+    Sms,
+    /// Any other maximum length, with a custom ellipsis
+    Custom { max: usize, ellipsis: String },
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile::Mastodon { max: 500 }
+    }
+}
+
+impl Profile {
+    /// Returns the maximum length of an output, in grapheme clusters
+    pub fn max_length(&self) -> usize {
+        match self {
+            Profile::Twitter => 280,
+            Profile::Bluesky => 300,
+            Profile::Mastodon { max } => *max,
+            Profile::Sms => 160,
+            Profile::Custom { max, .. } => *max,
+        }
+    }
+
+    /// Returns the text appended to truncated outputs
+    pub fn ellipsis(&self) -> &str {
+        match self {
+            Profile::Custom { ellipsis, .. } => ellipsis,
+            _ => "…",
+        }
+    }
+
+    /// Returns whether the output fits the profile
+    pub fn fits(&self, output: &str) -> bool {
+        text::grapheme_len(output) <= self.max_length()
+    }
+
+    /// Returns the output truncated to fit the profile, ellipsis included
+    ///
+    /// ```
+    /// use vitrail::config::Profile;
+    ///
+    /// let profile = Profile::Custom { max: 10, ellipsis: "...".to_string() };
+    /// assert_eq!(profile.truncate("Let's talk about Eve"), "Let's...");
+    /// ```
+    pub fn truncate(&self, output: &str) -> String {
+        text::truncate(output, self.max_length(), self.ellipsis())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn platform_lengths() {
+        assert_eq!(Profile::Twitter.max_length(), 280);
+        assert_eq!(Profile::default().max_length(), 500);
+        assert_eq!(Profile::Mastodon { max: 1000 }.max_length(), 1000);
+    }
+
+    #[test]
+    fn fits_counts_graphemes() {
+        let profile = Profile::Mastodon { max: 3 };
+        assert!(profile.fits("é👍🏽!"));
+        assert!(!profile.fits("abcd"));
+    }
+
+    #[test]
+    fn truncate_with_default_ellipsis() {
+        let profile = Profile::Mastodon { max: 8 };
+        assert_eq!(profile.truncate("Hello wonderful world"), "Hello…");
+    }
+}
@@ -0,0 +1,154 @@
+use std::error::Error;
+use std::fmt;
+
+/// How many outputs of a batch may have a rule with a given tag
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quota {
+    /// Exactly this many outputs have the tag
+    Exactly(usize),
+    /// At most this many outputs have the tag
+    AtMost(usize),
+}
+
+impl Quota {
+    /// Returns the largest number of outputs which may have the tag
+    fn max(&self) -> usize {
+        match self {
+            Quota::Exactly(count) | Quota::AtMost(count) => *count,
+        }
+    }
+}
+
+/// Quotas of the outputs of a batch having a rule with a given tag, enforced by `Grammar::flatten_batch_with_quotas`,
+/// e.g. for a daily schedule to hold exactly one sponsored post and at most two rare ones.
+///
+/// An output has a tag when any rule selected to expand it has it.
+///
+/// ```
+/// use vitrail::config::TagQuotas;
+///
+/// let quotas = TagQuotas::new().with_exactly("sponsored", 1).with_at_most("rare", 2);
+/// let kept = vec![vec!["rare".to_string()], vec!["rare".to_string()]];
+/// assert!(!quotas.accepts(&["rare"], &kept, 5));
+/// assert!(quotas.accepts(&["sponsored"], &kept, 5));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TagQuotas {
+    /// The quota of each tag, in order of declaration
+    pub quotas: Vec<(String, Quota)>,
+}
+
+impl TagQuotas {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the quotas, requiring exactly `count` outputs of the batch to have the tag
+    pub fn with_exactly(mut self, tag: &str, count: usize) -> Self {
+        self.quotas.push((tag.to_string(), Quota::Exactly(count)));
+        self
+    }
+
+    /// Returns the quotas, allowing at most `count` outputs of the batch to have the tag
+    pub fn with_at_most(mut self, tag: &str, count: usize) -> Self {
+        self.quotas.push((tag.to_string(), Quota::AtMost(count)));
+        self
+    }
+
+    /// Returns whether a candidate output having the given tags can be added to a batch of `count` outputs,
+    /// given the tags of the outputs already kept: it must exceed no quota, and leave enough outputs to fill
+    /// the exact quotas
+    pub fn accepts<S: AsRef<str>>(
+        &self,
+        candidate: &[S],
+        kept: &[Vec<String>],
+        count: usize,
+    ) -> bool {
+        let outputs_after = count.saturating_sub(kept.len() + 1);
+
+        self.quotas.iter().all(|(tag, quota)| {
+            let has_tag = candidate.iter().any(|candidate| candidate.as_ref() == tag);
+            let used = kept.iter().filter(|tags| tags.contains(tag)).count() + usize::from(has_tag);
+
+            match quota {
+                Quota::Exactly(required) => used <= *required && required - used <= outputs_after,
+                Quota::AtMost(max) => used <= *max,
+            }
+        })
+    }
+
+    /// Returns the tags whose exact quota is not met by the outputs kept, with the number of outputs having them
+    pub fn unmet(&self, kept: &[Vec<String>]) -> Vec<(String, usize)> {
+        self.quotas
+            .iter()
+            .filter_map(|(tag, quota)| {
+                let used = kept.iter().filter(|tags| tags.contains(tag)).count();
+                match quota {
+                    Quota::Exactly(required) if used != *required => Some((tag.to_string(), used)),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    /// Returns the tags whose quota is filled by the outputs already kept, which the next outputs cannot have
+    pub fn filled(&self, kept: &[Vec<String>]) -> Vec<&str> {
+        self.quotas
+            .iter()
+            .filter(|(tag, quota)| {
+                kept.iter().filter(|tags| tags.contains(tag)).count() >= quota.max()
+            })
+            .map(|(tag, _)| tag.as_str())
+            .collect()
+    }
+}
+
+/// A batch of outputs which does not meet its exact tag quotas, returned by `Grammar::flatten_batch_with_quotas`
+/// when the grammar cannot fill them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnmetQuotas {
+    /// The outputs kept, which fit the other quotas
+    pub outputs: Vec<String>,
+    /// The tags whose exact quota is not met, with the number of outputs having them
+    pub tags: Vec<(String, usize)>,
+}
+
+impl fmt::Display for UnmetQuotas {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let tags: Vec<String> = self
+            .tags
+            .iter()
+            .map(|(tag, count)| format!("'{}' ({} outputs)", tag, count))
+            .collect();
+        write!(
+            f,
+            "The quotas of {} are not met by the {} outputs generated",
+            tags.join(", "),
+            self.outputs.len()
+        )
+    }
+}
+
+impl Error for UnmetQuotas {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_quotas_keep_room_for_their_outputs() {
+        let quotas = TagQuotas::new().with_exactly("sponsored", 2);
+        let kept = vec![Vec::new()];
+
+        assert!(quotas.accepts::<&str>(&[], &kept, 4));
+        assert!(!quotas.accepts::<&str>(&[], &kept, 3));
+        assert!(quotas.accepts(&["sponsored"], &kept, 3));
+        assert!(quotas.filled(&kept).is_empty());
+
+        let kept = vec![vec!["sponsored".to_string()], vec!["sponsored".to_string()]];
+        assert!(!quotas.accepts(&["sponsored"], &kept, 5));
+        assert_eq!(quotas.filled(&kept), vec!["sponsored"]);
+        assert!(quotas.unmet(&kept).is_empty());
+        assert_eq!(quotas.unmet(&kept[..1]), vec![("sponsored".to_string(), 1)]);
+    }
+}
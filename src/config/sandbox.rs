@@ -0,0 +1,172 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::time::Duration;
+
+/// Nesting depth allowed by the default sandbox
+const DEFAULT_MAX_DEPTH: usize = 64;
+/// Number of symbol derivations allowed per flatten by the default sandbox
+const DEFAULT_MAX_EXPANSIONS: usize = 10_000;
+/// Size of a grammar source, or of a text being expanded, allowed by the default sandbox, in bytes
+const DEFAULT_MAX_BYTES: usize = 1 << 20;
+/// Duration of a flatten allowed by the default sandbox
+const DEFAULT_TIME_LIMIT: Duration = Duration::from_secs(1);
+/// Built-ins banned by the default sandbox, as they expose values of the host
+const DEFAULT_BANNED_BUILTINS: [&str; 1] = ["env"];
+
+/// Limits applied to a grammar coming from an untrusted source, e.g. submitted by the users of a service,
+/// both when it is loaded and when it is flattened.
+///
+/// The default options are meant for such grammars: a bounded depth, number of expansions, size and duration,
+/// no `{@env:...}` built-in and no file-backed symbol.
+///
+/// ```
+/// use std::time::Duration;
+/// use vitrail::config::SandboxOptions;
+///
+/// let sandbox = SandboxOptions::default()
+///     .with_max_depth(16)
+///     .with_time_limit(Duration::from_millis(200))
+///     .with_banned_builtin("uuid");
+/// assert!(sandbox.bans_builtin("env"));
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct SandboxOptions {
+    /// The maximum nesting depth of an expansion, the rule of the root being the first level
+    pub max_depth: usize,
+    /// The maximum number of symbols derived by a flatten
+    pub max_expansions: usize,
+    /// The maximum size of a grammar source, and of any text being expanded, in bytes
+    pub max_bytes: usize,
+    /// The maximum duration of a flatten
+    pub time_limit: Duration,
+    /// The names of the built-ins (and host variable providers) which cannot be used
+    pub banned_builtins: HashSet<String>,
+    /// Whether symbols may read their rules from files
+    pub allow_file_symbols: bool,
+    /// What becomes of the symbols left unexpanded when a limit is reached, instead of an expansion error
+    pub partial_output: Option<PartialOutput>,
+}
+
+impl Default for SandboxOptions {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_expansions: DEFAULT_MAX_EXPANSIONS,
+            max_bytes: DEFAULT_MAX_BYTES,
+            time_limit: DEFAULT_TIME_LIMIT,
+            banned_builtins: DEFAULT_BANNED_BUILTINS
+                .iter()
+                .map(|name| name.to_string())
+                .collect(),
+            allow_file_symbols: false,
+            partial_output: None,
+        }
+    }
+}
+
+impl SandboxOptions {
+    /// Returns the options with the given maximum nesting depth
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Returns the options with the given maximum number of derived symbols per flatten
+    pub fn with_max_expansions(mut self, max_expansions: usize) -> Self {
+        self.max_expansions = max_expansions;
+        self
+    }
+
+    /// Returns the options with the given maximum size, in bytes
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Returns the options with the given maximum duration per flatten
+    pub fn with_time_limit(mut self, time_limit: Duration) -> Self {
+        self.time_limit = time_limit;
+        self
+    }
+
+    /// Returns the options with the given built-in banned
+    pub fn with_banned_builtin(mut self, name: &str) -> Self {
+        self.banned_builtins.insert(name.to_string());
+        self
+    }
+
+    /// Returns the options with file-backed symbols allowed or not
+    pub fn allowing_file_symbols(mut self, allow: bool) -> Self {
+        self.allow_file_symbols = allow;
+        self
+    }
+
+    /// Returns the options producing a partial output when a limit is reached, rather than an expansion error.
+    ///
+    /// Once a limit is reached, the symbols left to derive are not expanded anymore, and are rendered as
+    /// described by `partial_output`. `GenerationResult::partial` tells whether it happened.
+    pub fn with_partial_output(mut self, partial_output: PartialOutput) -> Self {
+        self.partial_output = Some(partial_output);
+        self
+    }
+
+    /// Returns whether the given built-in cannot be used
+    pub fn bans_builtin(&self, name: &str) -> bool {
+        self.banned_builtins.contains(name)
+    }
+}
+
+/// How the symbols left unexpanded by a limit appear in a partial output
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartialOutput {
+    /// The symbols are removed from the output
+    Strip,
+    /// The symbols are left as written in the rules, e.g. `{name}`, without their modifiers
+    Placeholders,
+}
+
+/// A limit of a sandbox, or of the expansion limits of a grammar, reached by an expansion
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SandboxLimit {
+    /// The maximum nesting depth
+    Depth(usize),
+    /// The maximum number of derived symbols
+    Expansions(usize),
+    /// The maximum size of a text being expanded, in bytes
+    Bytes(usize),
+    /// The maximum duration of a flatten
+    Time(Duration),
+}
+
+impl fmt::Display for SandboxLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxLimit::Depth(depth) => write!(f, "depth of {} levels", depth),
+            SandboxLimit::Expansions(expansions) => write!(f, "{} expansions", expansions),
+            SandboxLimit::Bytes(bytes) => write!(f, "size of {} bytes", bytes),
+            SandboxLimit::Time(duration) => write!(f, "duration of {:?}", duration),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_options_are_hardened() {
+        let sandbox = SandboxOptions::default();
+        assert!(sandbox.bans_builtin("env"));
+        assert!(!sandbox.bans_builtin("uuid"));
+        assert!(!sandbox.allow_file_symbols);
+    }
+
+    #[test]
+    fn limit_display() {
+        assert_eq!(SandboxLimit::Depth(3).to_string(), "depth of 3 levels");
+        assert_eq!(
+            SandboxLimit::Time(Duration::from_millis(5)).to_string(),
+            "duration of 5ms"
+        );
+    }
+}
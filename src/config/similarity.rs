@@ -0,0 +1,57 @@
+use crate::text;
+
+/// Criterion deciding whether a generated output is different enough from the outputs
+/// already kept in a batch, used by `Grammar::flatten_batch`
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Similarity {
+    /// Outputs must only be distinct strings
+    #[default]
+    Distinct,
+    /// Outputs must be at least this many character edits away from each other
+    MinEditDistance(usize),
+    /// Outputs must share at most this proportion of their words with each other (from 0.0 to 1.0)
+    MaxTokenOverlap(f64),
+}
+
+impl Similarity {
+    /// Returns whether the candidate is different enough from every output already kept
+    ///
+    /// ```
+    /// use vitrail::config::Similarity;
+    ///
+    /// let kept = vec!["The red door opens".to_string()];
+    /// assert!(!Similarity::MinEditDistance(5).accepts("The red door closes", &kept));
+    /// assert!(Similarity::MinEditDistance(5).accepts("A dragon sleeps", &kept));
+    /// ```
+    pub fn accepts(&self, candidate: &str, kept: &[String]) -> bool {
+        kept.iter().all(|output| match self {
+            Similarity::Distinct => output != candidate,
+            Similarity::MinEditDistance(min) => text::edit_distance(output, candidate) >= *min,
+            Similarity::MaxTokenOverlap(max) => text::token_overlap(output, candidate) <= *max,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn distinct() {
+        let kept = vec!["a".to_string()];
+        assert!(!Similarity::Distinct.accepts("a", &kept));
+        assert!(Similarity::Distinct.accepts("b", &kept));
+    }
+
+    #[test]
+    fn max_token_overlap() {
+        let kept = vec!["the red door".to_string()];
+        assert!(!Similarity::MaxTokenOverlap(0.4).accepts("the blue door", &kept));
+        assert!(Similarity::MaxTokenOverlap(0.5).accepts("the blue door", &kept));
+    }
+
+    #[test]
+    fn accepts_anything_in_empty_batch() {
+        assert!(Similarity::MinEditDistance(100).accepts("a", &[]));
+    }
+}
@@ -0,0 +1,105 @@
+/// Temporary down-weighting of the rules selected recently, so successive flattens of a grammar
+/// vary more, without forbidding repetitions
+///
+/// ```
+/// use vitrail::config::VarietyBoost;
+///
+/// // A selected rule is 4 times less likely to be selected again by the next flatten,
+/// // then recovers half of its remaining penalty after each flatten
+/// let boost = VarietyBoost::new(0.25, 0.5);
+/// assert_eq!(boost.recover(0.25), 0.625);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VarietyBoost {
+    /// The factor applied to the weight of a rule each time it is selected (from 0.0 to 1.0)
+    pub penalty: f64,
+    /// The proportion of its remaining penalty a rule recovers after each flatten (from 0.0 to 1.0)
+    pub recovery: f64,
+}
+
+impl Default for VarietyBoost {
+    fn default() -> Self {
+        Self::new(0.1, 0.25)
+    }
+}
+
+impl VarietyBoost {
+    /// Returns a variety boost with the given penalty and recovery, each clamped between 0.0 and 1.0
+    pub fn new(penalty: f64, recovery: f64) -> Self {
+        Self {
+            penalty: penalty.clamp(0.0, 1.0),
+            recovery: recovery.clamp(0.0, 1.0),
+        }
+    }
+
+    /// Returns the weight factor of a rule after one more flatten, closer to 1.0 (its normal weight)
+    pub fn recover(&self, factor: f64) -> f64 {
+        1.0 - (1.0 - factor) * (1.0 - self.recovery)
+    }
+}
+
+/// Down-weighting of the rules whose words were already used often by the previous flattens, so a long
+/// document generated over many flattens varies its vocabulary as a whole, rather than sentence by sentence
+///
+/// ```
+/// use vitrail::config::VocabularyDampening;
+///
+/// // A rule whose words were used twice on average is half as likely to be selected
+/// let dampening = VocabularyDampening::new(0.5);
+/// assert_eq!(dampening.factor(2.0), 0.5);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VocabularyDampening {
+    /// How much each use of its words reduces the weight of a rule: the weight of a rule whose words were used
+    /// `n` times on average is divided by `1 + strength * n`
+    pub strength: f64,
+    /// The length of the shortest words tracked, in grapheme clusters, so function words ("the", "and") are ignored
+    pub min_word_len: usize,
+}
+
+impl Default for VocabularyDampening {
+    fn default() -> Self {
+        Self::new(0.5)
+    }
+}
+
+impl VocabularyDampening {
+    pub fn new(strength: f64) -> Self {
+        Self {
+            strength: strength.max(0.0),
+            min_word_len: 4,
+        }
+    }
+
+    /// Returns the dampening, tracking the words of at least the given length
+    pub fn with_min_word_len(mut self, min_word_len: usize) -> Self {
+        self.min_word_len = min_word_len;
+        self
+    }
+
+    /// Returns the weight factor of a rule whose words were used the given number of times on average
+    pub fn factor(&self, mean_uses: f64) -> f64 {
+        1.0 / (1.0 + self.strength * mean_uses)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recovery_converges_to_normal_weight() {
+        let boost = VarietyBoost::new(0.1, 0.5);
+        let mut factor = boost.penalty;
+        for _ in 0..20 {
+            factor = boost.recover(factor);
+        }
+        assert!((1.0 - factor).abs() < 1e-5);
+    }
+
+    #[test]
+    fn parameters_are_clamped() {
+        assert_eq!(VarietyBoost::new(2.0, -1.0), VarietyBoost::new(1.0, 0.0));
+        assert_eq!(VocabularyDampening::new(-1.0).factor(3.0), 1.0);
+    }
+}
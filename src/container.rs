@@ -0,0 +1,141 @@
+//! Sealed grammar containers, holding an encrypted or obfuscated JSON grammar, so the grammars shipped
+//! with a game cannot be read directly from its install directory.
+//!
+//! A container is made of the `VTRL` magic bytes, a format version, then the grammar as transformed by
+//! the caller's encryption. `Grammar::from_container` opens it with the matching `Decryptor`.
+
+/// The bytes every container starts with
+const MAGIC: &[u8] = b"VTRL";
+/// The version of the container format, written after the magic bytes
+const VERSION: u8 = 1;
+
+/// Turns the payload of a container back into the JSON grammar it was sealed from.
+///
+/// It is implemented for closures, so any encryption library can be plugged in:
+///
+/// ```
+/// use vitrail::container::Decryptor;
+///
+/// let reverse = |data: &[u8]| -> Result<Vec<u8>, String> { Ok(data.iter().rev().copied().collect()) };
+/// assert_eq!(reverse.decrypt(b"}{").unwrap(), b"{}");
+/// ```
+pub trait Decryptor {
+    /// Returns the decrypted payload, or a message explaining why it cannot be decrypted
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, String>;
+}
+
+impl<F> Decryptor for F
+where
+    F: Fn(&[u8]) -> Result<Vec<u8>, String>,
+{
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        self(data)
+    }
+}
+
+/// Repeating-key XOR obfuscation, which keeps casual players from reading spoilers, without any dependency.
+/// It is not encryption: anyone with the binary of the game can recover the key, so use a real cipher
+/// through `Decryptor` if the grammar must stay secret.
+///
+/// ```
+/// use vitrail::container::{self, Decryptor, XorObfuscation};
+///
+/// let obfuscation = XorObfuscation::new(b"spoilers");
+/// let sealed = container::seal(br#"{"root": "The butler did it"}"#, |data| obfuscation.obfuscate(data));
+/// assert!(!String::from_utf8_lossy(&sealed).contains("butler"));
+/// assert_eq!(container::open(&sealed, &obfuscation).unwrap(), br#"{"root": "The butler did it"}"#);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XorObfuscation {
+    key: Vec<u8>,
+}
+
+impl XorObfuscation {
+    /// Create an obfuscation with the given key, which must not be empty
+    pub fn new(key: &[u8]) -> Self {
+        assert!(!key.is_empty(), "An obfuscation key cannot be empty");
+        Self { key: key.to_vec() }
+    }
+
+    /// Returns the obfuscated data, which the same obfuscation turns back into the original data
+    pub fn obfuscate(&self, data: &[u8]) -> Vec<u8> {
+        data.iter()
+            .zip(self.key.iter().cycle())
+            .map(|(byte, key)| byte ^ key)
+            .collect()
+    }
+}
+
+impl Decryptor for XorObfuscation {
+    fn decrypt(&self, data: &[u8]) -> Result<Vec<u8>, String> {
+        Ok(self.obfuscate(data))
+    }
+}
+
+/// Returns a container holding the given JSON grammar, as transformed by the encryption, e.g. in the build
+/// script of a game
+pub fn seal<E>(grammar: &[u8], encrypt: E) -> Vec<u8>
+where
+    E: FnOnce(&[u8]) -> Vec<u8>,
+{
+    let mut container = MAGIC.to_vec();
+    container.push(VERSION);
+    container.extend(encrypt(grammar));
+    container
+}
+
+/// Returns the JSON grammar held by a container, decrypted with the given decryptor, or a message explaining
+/// why it cannot be opened
+pub fn open(container: &[u8], decryptor: &dyn Decryptor) -> Result<Vec<u8>, String> {
+    let payload = match container.strip_prefix(MAGIC) {
+        Some([VERSION, payload @ ..]) => payload,
+        Some([version, ..]) => {
+            return Err(format!("unsupported container version {}", version));
+        }
+        _ => return Err("not a grammar container".to_string()),
+    };
+
+    decryptor
+        .decrypt(payload)
+        .map_err(|message| format!("cannot decrypt the container: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_grammars_open_with_the_same_key() {
+        let grammar = br#"{"root": "A secret ending"}"#;
+        let sealed = seal(grammar, |data| XorObfuscation::new(b"key").obfuscate(data));
+        assert!(sealed.starts_with(b"VTRL\x01"));
+
+        assert_eq!(
+            open(&sealed, &XorObfuscation::new(b"key")).unwrap(),
+            grammar
+        );
+        assert_ne!(
+            open(&sealed, &XorObfuscation::new(b"other")).unwrap(),
+            grammar
+        );
+    }
+
+    #[test]
+    fn invalid_containers() {
+        let identity = |data: &[u8]| -> Result<Vec<u8>, String> { Ok(data.to_vec()) };
+        assert_eq!(
+            open(br#"{"root": "plain"}"#, &identity).unwrap_err(),
+            "not a grammar container"
+        );
+        assert_eq!(
+            open(b"VTRL\x02{}", &identity).unwrap_err(),
+            "unsupported container version 2"
+        );
+
+        let failing = |_: &[u8]| -> Result<Vec<u8>, String> { Err("bad tag".to_string()) };
+        assert_eq!(
+            open(b"VTRL\x01{}", &failing).unwrap_err(),
+            "cannot decrypt the container: bad tag"
+        );
+    }
+}
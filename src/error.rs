@@ -0,0 +1,275 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::config::SandboxLimit;
+use crate::grammar::{ExpansionContext, Selection};
+
+/// A problem met while expanding a grammar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExpansionError {
+    /// A symbol is referenced, but is neither defined nor captured
+    UnknownSymbol(String),
+    /// A symbol is defined without any rule to select
+    EmptySymbol(String),
+    /// The rules file of a file-backed symbol cannot be read
+    UnloadableSymbol { symbol: String, message: String },
+    /// A built-in symbol does not exist
+    UnknownBuiltin(String),
+    /// A modifier is not registered on the grammar, with the name of the closest registered one, if any
+    UnknownModifier {
+        name: String,
+        suggestion: Option<String>,
+    },
+    /// A modifier is given invalid arguments, or its arguments are malformed
+    InvalidModifierArguments { modifier: String, message: String },
+    /// A built-in symbol is given an invalid argument
+    InvalidBuiltinArgument { builtin: String, argument: String },
+    /// A host variable provider does not have the requested variable
+    UnavailableHostVariable { provider: String, variable: String },
+    /// A switch block on the given variable has no matching `end`
+    UnterminatedSwitch(String),
+    /// A conditional block with the given condition has no matching `end`
+    UnterminatedIf(String),
+    /// An arithmetic expression cannot be evaluated
+    InvalidExpression { expression: String, message: String },
+    /// A capture expression is not of the form `[symbol>variable]`
+    BadCapture(String),
+    /// A replayed choice script has no valid choice left for the given symbol
+    ScriptMismatch(String),
+    /// The expansion reached a limit of the grammar sandbox, or of its expansion limits
+    LimitExceeded(SandboxLimit),
+    /// A built-in is banned by the grammar sandbox
+    ForbiddenBuiltin(String),
+    /// A file-backed symbol is derived, while the grammar sandbox does not allow them
+    ForbiddenFileSymbol(String),
+}
+
+impl fmt::Display for ExpansionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpansionError::UnknownSymbol(symbol) => write!(
+                f,
+                "Unable to expand. Symbol '{}' does not exist in the ruleset.",
+                symbol
+            ),
+            ExpansionError::EmptySymbol(symbol) => {
+                write!(f, "Unable to expand. Symbol '{}' has no rules.", symbol)
+            }
+            ExpansionError::UnloadableSymbol { symbol, message } => {
+                write!(f, "Unable to load symbol '{}': {}", symbol, message)
+            }
+            ExpansionError::UnknownBuiltin(name) => write!(f, "Unknown built-in symbol '{}'", name),
+            ExpansionError::UnknownModifier { name, suggestion } => {
+                write!(f, "Unknown modifier '{}'", name)?;
+                match suggestion {
+                    Some(suggestion) => write!(f, ", did you mean '{}'?", suggestion),
+                    None => Ok(()),
+                }
+            }
+            ExpansionError::InvalidModifierArguments { modifier, message } => write!(
+                f,
+                "Invalid arguments for modifier '{}': {}",
+                modifier, message
+            ),
+            ExpansionError::InvalidBuiltinArgument { builtin, argument } => write!(
+                f,
+                "Invalid length '{}' for built-in symbol '{}'",
+                argument, builtin
+            ),
+            ExpansionError::UnavailableHostVariable { provider, variable } => write!(
+                f,
+                "Host variable '{}' is not available from '{}'",
+                variable, provider
+            ),
+            ExpansionError::UnterminatedSwitch(variable) => {
+                write!(f, "Unterminated switch block on '{}'", variable)
+            }
+            ExpansionError::UnterminatedIf(condition) => {
+                write!(f, "Unterminated if block on '{}'", condition)
+            }
+            ExpansionError::InvalidExpression {
+                expression,
+                message,
+            } => write!(f, "Unable to evaluate '{}': {}", expression, message),
+            ExpansionError::BadCapture(capture) => write!(f, "Bad capture syntax: '{}'", capture),
+            ExpansionError::ScriptMismatch(symbol) => write!(
+                f,
+                "Choice script does not match the rules of symbol '{}'",
+                symbol
+            ),
+            ExpansionError::LimitExceeded(limit) => {
+                write!(f, "Expansion exceeded the limit: {}", limit)
+            }
+            ExpansionError::ForbiddenBuiltin(name) => {
+                write!(
+                    f,
+                    "Built-in symbol '{}' is not allowed by the sandbox",
+                    name
+                )
+            }
+            ExpansionError::ForbiddenFileSymbol(symbol) => write!(
+                f,
+                "File-backed symbol '{}' is not allowed by the sandbox",
+                symbol
+            ),
+        }
+    }
+}
+
+impl Error for ExpansionError {}
+
+/// What the grammar does about an expansion error, as decided by its error handler
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ErrorAction {
+    /// Replace the faulty expression with the given text, which is expanded like a rule
+    Substitute(String),
+    /// Replace the faulty expression with nothing
+    Skip,
+    /// Stop the expansion, panicking with the error. This is what happens without an error handler.
+    Abort,
+}
+
+/// A callback deciding what to do about each expansion error, given the state of the expansion it occurred in
+pub type ErrorHandler<'a> = Box<dyn FnMut(&ExpansionError, &ExpansionContext) -> ErrorAction + 'a>;
+
+/// A failure of the fallible methods of `Grammar`, which return it instead of panicking like their `_unchecked`
+/// counterparts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VitrailError {
+    /// A grammar cannot be read, from the given file if it comes from one
+    Unreadable {
+        path: Option<String>,
+        message: String,
+    },
+    /// A grammar is not valid, in the given file if it comes from one
+    InvalidGrammar {
+        path: Option<String>,
+        message: String,
+    },
+    /// A sealed grammar container, from the given file if it comes from one, cannot be opened: it is not a container,
+    /// or its payload cannot be decrypted
+    InvalidContainer {
+        path: Option<String>,
+        message: String,
+    },
+    /// A symbol of a grammar being merged, from the given file if it comes from one, is already defined
+    SymbolConflict {
+        symbol: String,
+        path: Option<String>,
+    },
+    /// A grammar, from the given file if it comes from one, defines no conventional root symbol (`root` or
+    /// `origin`), and none is set with `Grammar::with_root`
+    MissingRoot { path: Option<String> },
+    /// An expansion met an error which the error handler, if any, did not recover from, along with the rules
+    /// being expanded when it happened, from the root: `root[2] -> creature[0]`
+    Expansion {
+        error: ExpansionError,
+        path: Vec<Selection>,
+    },
+}
+
+impl fmt::Display for VitrailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VitrailError::Unreadable {
+                path: Some(path),
+                message,
+            } => write!(f, "Could not read grammar file at {}: {}", path, message),
+            VitrailError::Unreadable {
+                path: None,
+                message,
+            } => write!(f, "Could not read grammar: {}", message),
+            VitrailError::InvalidGrammar {
+                path: Some(path),
+                message,
+            } => write!(f, "Invalid grammar file at {}: {}", path, message),
+            VitrailError::InvalidGrammar {
+                path: None,
+                message,
+            } => write!(f, "Invalid grammar: {}", message),
+            VitrailError::InvalidContainer {
+                path: Some(path),
+                message,
+            } => write!(f, "Invalid grammar container at {}: {}", path, message),
+            VitrailError::InvalidContainer {
+                path: None,
+                message,
+            } => write!(f, "Invalid grammar container: {}", message),
+            VitrailError::SymbolConflict {
+                symbol,
+                path: Some(path),
+            } => write!(
+                f,
+                "Symbol '{}' of grammar file at {} is already defined",
+                symbol, path
+            ),
+            VitrailError::SymbolConflict { symbol, path: None } => {
+                write!(f, "Symbol '{}' is already defined", symbol)
+            }
+            VitrailError::MissingRoot { path: Some(path) } => write!(
+                f,
+                "Invalid grammar file at {}: no root symbol ('root' or 'origin') and none configured",
+                path
+            ),
+            VitrailError::MissingRoot { path: None } => write!(
+                f,
+                "Invalid grammar: no root symbol ('root' or 'origin') and none configured"
+            ),
+            VitrailError::Expansion { error, path } if path.is_empty() => write!(f, "{}", error),
+            VitrailError::Expansion { error, path } => {
+                let path: Vec<String> = path.iter().map(ToString::to_string).collect();
+                write!(f, "{} (in {})", error, path.join(" -> "))
+            }
+        }
+    }
+}
+
+impl Error for VitrailError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            VitrailError::Expansion { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<ExpansionError> for VitrailError {
+    fn from(error: ExpansionError) -> Self {
+        VitrailError::Expansion {
+            error,
+            path: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_matches_expansion_messages() {
+        assert_eq!(
+            ExpansionError::UnknownSymbol("hero".to_string()).to_string(),
+            "Unable to expand. Symbol 'hero' does not exist in the ruleset."
+        );
+        assert_eq!(
+            ExpansionError::UnavailableHostVariable {
+                provider: "env".to_string(),
+                variable: "USER".to_string(),
+            }
+            .to_string(),
+            "Host variable 'USER' is not available from 'env'"
+        );
+    }
+
+    #[test]
+    fn display_missing_root() {
+        assert_eq!(
+            VitrailError::MissingRoot {
+                path: Some("words.json".to_string())
+            }
+            .to_string(),
+            "Invalid grammar file at words.json: no root symbol ('root' or 'origin') and none configured"
+        );
+    }
+}
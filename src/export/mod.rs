@@ -0,0 +1,4 @@
+//! Exporters turning a grammar into documentation formats, or into descriptions for tooling.
+
+pub mod railroad;
+pub mod schema;
@@ -0,0 +1,279 @@
+//! Railroad diagrams (SVG) of the rules of each symbol: every rule is a track from left to right,
+//! with literal text in rounded boxes, symbols in square boxes, and captures in dashed boxes.
+
+use std::fmt::Write;
+
+use crate::grammar::token::{self, Token};
+use crate::grammar::Grammar;
+
+/// Estimated width of a character of the diagram font
+const CHAR_WIDTH: f64 = 7.5;
+/// Horizontal space between a box border and its text
+const BOX_PADDING: f64 = 10.0;
+const BOX_HEIGHT: f64 = 24.0;
+/// Horizontal space between two boxes of a track
+const ITEM_GAP: f64 = 16.0;
+/// Vertical space between two tracks
+const ROW_GAP: f64 = 10.0;
+const MARGIN: f64 = 10.0;
+/// Length of the connections between the entry/exit points and the tracks
+const RAIL: f64 = 20.0;
+
+const STYLE: &str = "rect{fill:#fff;stroke:#333;stroke-width:1.5}\
+rect.capture{stroke-dasharray:4 2}\
+path,line{fill:none;stroke:#333;stroke-width:1.5}\
+circle{fill:#333}\
+text{font:12px monospace;text-anchor:middle}\
+a text{fill:#1a5fb4}";
+
+enum Item {
+    Terminal(String),
+    NonTerminal {
+        label: String,
+        target: Option<String>,
+    },
+    Capture(String),
+}
+
+impl Item {
+    fn label(&self) -> &str {
+        match self {
+            Item::Terminal(label) | Item::NonTerminal { label, .. } | Item::Capture(label) => label,
+        }
+    }
+
+    fn width(&self) -> f64 {
+        self.label().chars().count() as f64 * CHAR_WIDTH + 2.0 * BOX_PADDING
+    }
+}
+
+/// Returns the railroad diagram of a symbol's rules as a standalone SVG document,
+/// or `None` if the symbol has no rules in the grammar.
+///
+/// Symbols referenced by the rules link to `#<symbol>` anchors, as found in `bundle_html`.
+///
+/// ```
+/// use vitrail::{config::GrammarSyntax, export::railroad, grammar::Grammar};
+///
+/// let grammar = Grammar::from_json("test.json", "anyrandomseed", GrammarSyntax::default()).unwrap();
+/// let svg = railroad::symbol_svg(&grammar, "root").unwrap();
+/// assert!(svg.starts_with("<svg"));
+/// ```
+pub fn symbol_svg(grammar: &Grammar, symbol: &str) -> Option<String> {
+    let rows: Vec<Vec<Item>> = if let Some(rules) = grammar.symbols.get(symbol) {
+        rules.iter().map(|rule| items(grammar, rule)).collect()
+    } else if let Some(path) = grammar.file_symbols.get(symbol) {
+        vec![vec![Item::Terminal(format!(
+            "rules from {}",
+            path.display()
+        ))]]
+    } else {
+        return None;
+    };
+
+    Some(render(&rows))
+}
+
+/// Returns an HTML page holding the railroad diagrams of every symbol of the grammar, sorted by name,
+/// where each symbol box links to the diagram of that symbol
+pub fn bundle_html(grammar: &Grammar) -> String {
+    let mut names: Vec<&String> = grammar
+        .symbols
+        .keys()
+        .chain(grammar.file_symbols.keys())
+        .collect();
+    names.sort();
+
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Grammar</title></head>\n<body>\n",
+    );
+    for name in names {
+        if let Some(svg) = symbol_svg(grammar, name) {
+            let _ = writeln!(html, "<h2 id=\"{0}\">{0}</h2>\n{1}", escape(name), svg);
+        }
+    }
+    html.push_str("</body>\n</html>\n");
+
+    html
+}
+
+fn items(grammar: &Grammar, rule: &str) -> Vec<Item> {
+    token::tokenize(&grammar.syntax, rule)
+        .into_iter()
+        .filter_map(|token| match token {
+            Token::Text(text) if text.trim().is_empty() => None,
+            Token::Text(text) => Some(Item::Terminal(text.trim().to_string())),
+            Token::Symbol(expression) => Some(Item::NonTerminal {
+                label: expression.to_string(),
+                target: token::symbol_name(&grammar.syntax, expression).map(str::to_string),
+            }),
+            Token::Capture(expression) => Some(Item::Capture(expression.to_string())),
+        })
+        .collect()
+}
+
+fn row_width(row: &[Item]) -> f64 {
+    let boxes: f64 = row.iter().map(Item::width).sum();
+    boxes + ITEM_GAP * row.len().saturating_sub(1) as f64
+}
+
+fn render(rows: &[Vec<Item>]) -> String {
+    let content_width = rows.iter().map(|row| row_width(row)).fold(40.0, f64::max);
+    let left_rail = MARGIN + RAIL;
+    let content_start = left_rail + RAIL;
+    let right_rail = content_start + content_width + RAIL;
+    let width = right_rail + RAIL + MARGIN;
+    let row_count = rows.len().max(1) as f64;
+    let height = 2.0 * MARGIN + row_count * BOX_HEIGHT + (row_count - 1.0) * ROW_GAP;
+    let center = |row: usize| MARGIN + row as f64 * (BOX_HEIGHT + ROW_GAP) + BOX_HEIGHT / 2.0;
+    let first = center(0);
+    let last = center(rows.len().saturating_sub(1));
+
+    let mut svg = String::new();
+    let _ = write!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\
+         <style>{style}</style>",
+        w = width,
+        h = height,
+        style = STYLE,
+    );
+
+    // Entry, exit, and the rails joining every track
+    let _ = write!(
+        svg,
+        "<circle cx=\"{x0}\" cy=\"{y0}\" r=\"4\"/><circle cx=\"{x1}\" cy=\"{y0}\" r=\"4\"/>\
+         <line x1=\"{x0}\" y1=\"{y0}\" x2=\"{l}\" y2=\"{y0}\"/><line x1=\"{r}\" y1=\"{y0}\" x2=\"{x1}\" y2=\"{y0}\"/>\
+         <line x1=\"{l}\" y1=\"{y0}\" x2=\"{l}\" y2=\"{y1}\"/><line x1=\"{r}\" y1=\"{y0}\" x2=\"{r}\" y2=\"{y1}\"/>",
+        x0 = MARGIN,
+        x1 = right_rail + RAIL,
+        y0 = first,
+        y1 = last,
+        l = left_rail,
+        r = right_rail,
+    );
+
+    for (i, row) in rows.iter().enumerate() {
+        let y = center(i);
+        let _ = write!(
+            svg,
+            "<line x1=\"{}\" y1=\"{y}\" x2=\"{}\" y2=\"{y}\"/>",
+            left_rail,
+            right_rail,
+            y = y,
+        );
+
+        let mut x = content_start;
+        for item in row {
+            render_item(&mut svg, item, x, y);
+            x += item.width() + ITEM_GAP;
+        }
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+fn render_item(svg: &mut String, item: &Item, x: f64, y: f64) {
+    let (class, radius) = match item {
+        Item::Terminal(_) => ("terminal", 10),
+        Item::NonTerminal { .. } => ("symbol", 0),
+        Item::Capture(_) => ("capture", 0),
+    };
+    let width = item.width();
+    let text = format!(
+        "<text x=\"{}\" y=\"{}\">{}</text>",
+        x + width / 2.0,
+        y + 4.0,
+        escape(item.label()),
+    );
+
+    let _ = write!(
+        svg,
+        "<rect class=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" rx=\"{}\"/>",
+        class,
+        x,
+        y - BOX_HEIGHT / 2.0,
+        width,
+        BOX_HEIGHT,
+        radius,
+    );
+
+    match item {
+        Item::NonTerminal {
+            target: Some(target),
+            ..
+        } => {
+            let _ = write!(svg, "<a href=\"#{}\">{}</a>", escape(target), text);
+        }
+        _ => svg.push_str(&text),
+    }
+}
+
+/// Escape text for the content or an attribute of an SVG or HTML element: the characters reserved by XML are
+/// replaced by entities, and the control characters XML does not allow, which no entity can stand for, are dropped
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            '\t' | '\n' | '\r' => escaped.push(character),
+            _ if character.is_control() => {}
+            _ => escaped.push(character),
+        }
+    }
+
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+
+    fn grammar() -> Grammar<'static> {
+        Grammar::from_json("test.json", "testseed", GrammarSyntax::default()).unwrap()
+    }
+
+    #[test]
+    fn symbol_svg_has_one_track_per_rule() {
+        let svg = symbol_svg(&grammar(), "speak").unwrap();
+        assert_eq!(svg.matches("class=\"terminal\"").count(), 3);
+        assert!(svg.contains(">babble</text>"));
+    }
+
+    #[test]
+    fn symbol_svg_links_symbols() {
+        let svg = symbol_svg(&grammar(), "root").unwrap();
+        assert!(svg.contains("<a href=\"#speak\">"));
+        assert!(svg.contains("<a href=\"#object\"><text"));
+        assert!(svg.contains("class=\"capture\""));
+        assert!(svg.contains("character&gt;subject"));
+    }
+
+    #[test]
+    fn labels_are_escaped_for_svg() {
+        let grammar = Grammar::new("testseed", GrammarSyntax::default()).with_symbol(
+            "sign".to_string(),
+            vec!["Tom's <b>\u{7}bar</b> & \"grill\"".to_string()],
+        );
+        let svg = symbol_svg(&grammar, "sign").unwrap();
+        assert!(svg.contains(">Tom&#39;s &lt;b&gt;bar&lt;/b&gt; &amp; &quot;grill&quot;</text>"));
+    }
+
+    #[test]
+    fn symbol_svg_unknown_symbol() {
+        assert!(symbol_svg(&grammar(), "unknown").is_none());
+    }
+
+    #[test]
+    fn bundle_has_anchors() {
+        let html = bundle_html(&grammar());
+        assert!(html.contains("<h2 id=\"speak\">speak</h2>"));
+        assert_eq!(html.matches("<svg").count(), 5);
+    }
+}
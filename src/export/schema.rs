@@ -0,0 +1,67 @@
+//! Machine-readable description of a grammar, built by `Grammar::export_schema`, for editor plugins to offer
+//! autocompletion and hover documentation while grammar files are edited.
+
+use serde::Serialize;
+
+/// The names a grammar defines and the delimiters of its syntax
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GrammarSchema {
+    /// The symbol expanded by `flatten`
+    pub root: String,
+    pub syntax: SyntaxSchema,
+    /// The symbols of the grammar, sorted by name
+    pub symbols: Vec<SymbolSchema>,
+    /// The variables captured by the rules, sorted by name
+    pub variables: Vec<String>,
+    /// The names of the registered modifiers, for any locale, sorted
+    pub modifiers: Vec<String>,
+    /// The names of the built-ins: the random ones, the host variable providers and the included grammars, sorted
+    pub builtins: Vec<String>,
+    /// The tags of the rules, sorted
+    pub tags: Vec<String>,
+}
+
+/// A symbol of a `GrammarSchema`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SymbolSchema {
+    pub name: String,
+    /// What the symbol is for, as documented in the grammar
+    pub description: Option<String>,
+    /// The number of rules of the symbol, 0 for file-backed symbols not loaded yet and dynamic symbols
+    pub rules: usize,
+    /// The rules file of a file-backed symbol
+    pub file: Option<String>,
+    /// Whether the value of the symbol is computed by the host application
+    pub dynamic: bool,
+    /// Whether the symbol is deprecated
+    pub deprecated: bool,
+    /// The hint about the replacement of a deprecated symbol, if any
+    pub replacement_hint: Option<String>,
+    /// How the rules of the symbol are selected
+    pub strategy: String,
+    /// The tags of the rules of the symbol, sorted
+    pub tags: Vec<String>,
+}
+
+/// The delimiters of a grammar syntax, in a `GrammarSchema`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SyntaxSchema {
+    pub symbol_start: char,
+    pub symbol_end: char,
+    pub capture_start: char,
+    pub capture_end: char,
+    pub capture_operator: char,
+    pub modifier_operator: char,
+    pub expression_prefix: char,
+    pub builtin_prefix: char,
+    pub escape: char,
+    /// The keywords of switch and conditional blocks, written as symbols: `{switch:variable}`, `{end}`
+    pub block_keywords: Vec<String>,
+}
+
+impl GrammarSchema {
+    /// Returns the schema serialized as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("A grammar schema is always serializable")
+    }
+}
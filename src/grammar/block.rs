@@ -0,0 +1,369 @@
+use super::token;
+use crate::config::GrammarSyntax;
+
+/// Keyword opening a switch block: `{switch:variable}`
+const SWITCH_KEYWORD: &str = "switch";
+/// Keyword opening a branch of a switch block: `{case:value}`
+const CASE_KEYWORD: &str = "case";
+/// Keyword opening the fallback branch of a switch block: `{default}`
+const DEFAULT_KEYWORD: &str = "default";
+/// Keyword opening a conditional block: `{if:condition}`
+const IF_KEYWORD: &str = "if";
+/// Keyword opening a further branch of a conditional block: `{elif:condition}`
+const ELSE_IF_KEYWORD: &str = "elif";
+/// Keyword opening the fallback branch of a conditional block: `{else}`
+const ELSE_KEYWORD: &str = "else";
+/// Keyword closing a block: `{end}`
+const END_KEYWORD: &str = "end";
+/// The keywords of switch and conditional blocks
+pub(super) const BLOCK_KEYWORDS: [&str; 7] = [
+    SWITCH_KEYWORD,
+    CASE_KEYWORD,
+    DEFAULT_KEYWORD,
+    IF_KEYWORD,
+    ELSE_IF_KEYWORD,
+    ELSE_KEYWORD,
+    END_KEYWORD,
+];
+/// Comparison operators of numeric cases (`{case:>=10}`), longest first so `<=` is not read as `<`
+const COMPARISON_OPERATORS: [&str; 6] = ["<=", ">=", "!=", "<", ">", "="];
+
+/// A switch or conditional block split into its branches, borrowing from the rule it was parsed from
+#[derive(Debug, PartialEq)]
+pub(crate) struct SwitchBlock<'a> {
+    /// The `(value, body)` pairs of each `case` branch, or the `(condition, body)` pairs of each `if` and `elif`
+    /// branch, in declaration order
+    pub cases: Vec<(&'a str, &'a str)>,
+    /// The body of the `default` or `else` branch, if any
+    pub default: Option<&'a str>,
+    /// The text following the closing `end` tag
+    pub rest: &'a str,
+}
+
+impl<'a> SwitchBlock<'a> {
+    /// Returns the body of the first branch matching the given value, falling back to the default branch
+    pub fn select(&self, value: &str) -> &'a str {
+        self.cases
+            .iter()
+            .find(|(case, _)| case_matches(case, value))
+            .map(|(_, body)| *body)
+            .or(self.default)
+            .unwrap_or("")
+    }
+}
+
+/// Returns whether a switch value matches a case: a numeric value matches a comparison case (`<10`, `>=3`, `!=0`)
+/// it satisfies, or a case of the same number (`3` for `3.0`), and any value matches a case of the same text
+fn case_matches(case: &str, value: &str) -> bool {
+    if case == value {
+        return true;
+    }
+
+    let value = match value.parse::<f64>() {
+        Ok(value) => value,
+        Err(_) => return false,
+    };
+    let (operator, operand) = COMPARISON_OPERATORS
+        .iter()
+        .find_map(|operator| {
+            case.strip_prefix(operator)
+                .map(|operand| (*operator, operand))
+        })
+        .unwrap_or(("=", case));
+
+    match operand.trim().parse::<f64>() {
+        Ok(operand) => match operator {
+            "<=" => value <= operand,
+            ">=" => value >= operand,
+            "!=" => value != operand,
+            "<" => value < operand,
+            ">" => value > operand,
+            _ => value == operand,
+        },
+        Err(_) => false,
+    }
+}
+
+/// A condition of a conditional block, comparing the expanded value of a variable: `gender=female`, `hp<5`,
+/// or a lone variable, which holds when its value is not empty
+#[derive(Debug, PartialEq)]
+pub(crate) struct Condition<'a> {
+    pub variable: &'a str,
+    /// The comparison operator and its operand, if any
+    pub comparison: Option<(&'a str, &'a str)>,
+}
+
+impl<'a> Condition<'a> {
+    /// Parse a condition, splitting it around its first comparison operator
+    pub fn parse(condition: &'a str) -> Self {
+        let comparison = condition.char_indices().find_map(|(i, _)| {
+            COMPARISON_OPERATORS
+                .iter()
+                .find(|operator| condition[i..].starts_with(**operator))
+                .map(|operator| (i, *operator))
+        });
+
+        match comparison {
+            Some((i, operator)) => Condition {
+                variable: condition[..i].trim(),
+                comparison: Some((operator, condition[i + operator.len()..].trim())),
+            },
+            None => Condition {
+                variable: condition.trim(),
+                comparison: None,
+            },
+        }
+    }
+
+    /// Returns whether the condition holds for the given value of its variable: `=` and `!=` compare text,
+    /// or numbers, and the other operators compare numbers
+    pub fn holds(&self, value: &str) -> bool {
+        match self.comparison {
+            None => !value.is_empty(),
+            Some(("=", operand)) => case_matches(operand, value),
+            Some(("!=", operand)) => !case_matches(operand, value),
+            Some((operator, operand)) => case_matches(&format!("{}{}", operator, operand), value),
+        }
+    }
+}
+
+/// Returns the name of the variable switched upon, if the given tag content opens a switch block
+pub(crate) fn switch_variable<'a>(syntax: &GrammarSyntax, tag: &'a str) -> Option<&'a str> {
+    keyword_argument(syntax, tag, SWITCH_KEYWORD)
+}
+
+/// Returns the condition of the first branch, if the given tag content opens a conditional block
+pub(crate) fn if_condition<'a>(syntax: &GrammarSyntax, tag: &'a str) -> Option<&'a str> {
+    keyword_argument(syntax, tag, IF_KEYWORD)
+}
+
+/// Returns the variable tested by the condition, if the given tag content opens a conditional block
+/// or one of its further branches
+pub(crate) fn condition_variable<'a>(syntax: &GrammarSyntax, tag: &'a str) -> Option<&'a str> {
+    if_condition(syntax, tag)
+        .or_else(|| keyword_argument(syntax, tag, ELSE_IF_KEYWORD))
+        .map(|condition| Condition::parse(condition).variable)
+}
+
+/// Returns the argument of a `keyword<operator>argument` tag, if the tag uses the given keyword
+fn keyword_argument<'a>(syntax: &GrammarSyntax, tag: &'a str, keyword: &str) -> Option<&'a str> {
+    tag.strip_prefix(keyword)?
+        .strip_prefix(syntax.modifier_operator)
+        .map(str::trim)
+}
+
+/// Returns whether the given tag content is a block keyword tag, rather than a symbol
+pub(crate) fn is_keyword(syntax: &GrammarSyntax, tag: &str) -> bool {
+    tag == END_KEYWORD
+        || tag == DEFAULT_KEYWORD
+        || tag == ELSE_KEYWORD
+        || keyword_argument(syntax, tag, CASE_KEYWORD).is_some()
+        || keyword_argument(syntax, tag, ELSE_IF_KEYWORD).is_some()
+        || is_block_opening(syntax, tag)
+}
+
+/// Returns whether the given tag content opens a nested block
+fn is_block_opening(syntax: &GrammarSyntax, tag: &str) -> bool {
+    switch_variable(syntax, tag).is_some() || if_condition(syntax, tag).is_some()
+}
+
+/// Split the body of a switch block (the text right after its opening tag) into its branches.
+///
+/// Nested blocks are kept intact inside the branch they belong to.
+/// Returns `None` if the block is never closed.
+pub(crate) fn parse_switch<'a>(syntax: &GrammarSyntax, body: &'a str) -> Option<SwitchBlock<'a>> {
+    parse_block(syntax, body, None, CASE_KEYWORD, DEFAULT_KEYWORD)
+}
+
+/// Split the body of a conditional block (the text right after its opening tag, whose condition is given)
+/// into its branches.
+///
+/// Nested blocks are kept intact inside the branch they belong to.
+/// Returns `None` if the block is never closed.
+pub(crate) fn parse_if<'a>(
+    syntax: &GrammarSyntax,
+    condition: &'a str,
+    body: &'a str,
+) -> Option<SwitchBlock<'a>> {
+    parse_block(syntax, body, Some(condition), ELSE_IF_KEYWORD, ELSE_KEYWORD)
+}
+
+/// Split the body of a block into its branches, opened by `branch_keyword` tags with an argument, or by a
+/// `fallback_keyword` tag. The argument of a branch opened by the opening tag of the block is given, if any.
+fn parse_block<'a>(
+    syntax: &GrammarSyntax,
+    body: &'a str,
+    first_argument: Option<&'a str>,
+    branch_keyword: &str,
+    fallback_keyword: &str,
+) -> Option<SwitchBlock<'a>> {
+    let mut cases = Vec::new();
+    let mut default = None;
+    let mut depth = 0;
+    // The argument (or `None` for the fallback branch) and body start of the branch being read
+    let mut branch: Option<(Option<&'a str>, usize)> =
+        first_argument.map(|argument| (Some(argument), 0));
+    let mut cursor = 0;
+
+    while let Some(start) = token::syntax_chars(syntax, &body[cursor..])
+        .find(|(_, character)| *character == syntax.symbol_start)
+        .map(|(i, _)| cursor + i)
+    {
+        let tag_start = start + syntax.symbol_start.len_utf8();
+        let end = tag_start + body[tag_start..].find(syntax.symbol_end)?;
+        let tag = &body[tag_start..end];
+        cursor = end + syntax.symbol_end.len_utf8();
+
+        let is_branch_boundary = depth == 0
+            && (tag == END_KEYWORD
+                || tag == fallback_keyword
+                || keyword_argument(syntax, tag, branch_keyword).is_some());
+
+        if is_branch_boundary {
+            match branch.take() {
+                Some((Some(value), body_start)) => cases.push((value, &body[body_start..start])),
+                Some((None, body_start)) => default = Some(&body[body_start..start]),
+                None => (),
+            }
+
+            if tag == END_KEYWORD {
+                return Some(SwitchBlock {
+                    cases,
+                    default,
+                    rest: &body[cursor..],
+                });
+            }
+
+            branch = Some((keyword_argument(syntax, tag, branch_keyword), cursor));
+        } else if is_block_opening(syntax, tag) {
+            depth += 1;
+        } else if tag == END_KEYWORD {
+            depth -= 1;
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numeric_cases() {
+        assert!(case_matches("3", "3.0"));
+        assert!(case_matches("<10", "4"));
+        assert!(case_matches(">= 10", "10"));
+        assert!(case_matches("!=0", "-1"));
+        assert!(!case_matches(">10", "10"));
+        assert!(!case_matches("<10", "ten"));
+        assert!(case_matches("<10", "<10"));
+    }
+
+    #[test]
+    fn switch_variable_when_switch() {
+        let syntax = GrammarSyntax::default();
+        assert_eq!(switch_variable(&syntax, "switch:weather"), Some("weather"));
+    }
+
+    #[test]
+    fn switch_variable_when_symbol() {
+        let syntax = GrammarSyntax::default();
+        assert_eq!(switch_variable(&syntax, "switches:s"), None);
+    }
+
+    #[test]
+    fn parse_switch_branches() {
+        let syntax = GrammarSyntax::default();
+        let block =
+            parse_switch(&syntax, "{case:rain}wet{case:snow}cold{default}fine{end}!").unwrap();
+
+        assert_eq!(block.cases, vec![("rain", "wet"), ("snow", "cold")]);
+        assert_eq!(block.default, Some("fine"));
+        assert_eq!(block.rest, "!");
+    }
+
+    #[test]
+    fn parse_switch_nested() {
+        let syntax = GrammarSyntax::default();
+        let block = parse_switch(
+            &syntax,
+            "{case:rain}{switch:mood}{case:sad}gloomy{end}{case:snow}cold{end}",
+        )
+        .unwrap();
+
+        assert_eq!(
+            block.cases,
+            vec![
+                ("rain", "{switch:mood}{case:sad}gloomy{end}"),
+                ("snow", "cold"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_switch_unterminated() {
+        let syntax = GrammarSyntax::default();
+        assert_eq!(parse_switch(&syntax, "{case:rain}wet"), None);
+    }
+
+    #[test]
+    fn select_falls_back_to_default() {
+        let syntax = GrammarSyntax::default();
+        let block = parse_switch(&syntax, "{case:rain}wet{default}fine{end}").unwrap();
+
+        assert_eq!(block.select("rain"), "wet");
+        assert_eq!(block.select("sun"), "fine");
+    }
+
+    #[test]
+    fn parse_if_branches() {
+        let syntax = GrammarSyntax::default();
+        let block = parse_if(
+            &syntax,
+            "gender=female",
+            "she{elif:gender=male}he{else}{if:plural}they{end}{end}!",
+        )
+        .unwrap();
+
+        assert_eq!(
+            block.cases,
+            vec![("gender=female", "she"), ("gender=male", "he")]
+        );
+        assert_eq!(block.default, Some("{if:plural}they{end}"));
+        assert_eq!(block.rest, "!");
+    }
+
+    #[test]
+    fn conditions() {
+        assert!(Condition::parse("gender = female").holds("female"));
+        assert!(Condition::parse("gender!=female").holds("male"));
+        assert!(Condition::parse("hp<5").holds("3"));
+        assert!(!Condition::parse("hp>=5").holds("3"));
+        assert!(Condition::parse("title").holds("Dr"));
+        assert!(!Condition::parse("title").holds(""));
+        assert_eq!(
+            Condition::parse("hp<=5"),
+            Condition {
+                variable: "hp",
+                comparison: Some(("<=", "5")),
+            }
+        );
+    }
+
+    #[test]
+    fn condition_variables() {
+        let syntax = GrammarSyntax::default();
+        assert_eq!(condition_variable(&syntax, "if:hp<5"), Some("hp"));
+        assert_eq!(condition_variable(&syntax, "elif:title"), Some("title"));
+        assert_eq!(condition_variable(&syntax, "switch:hp"), None);
+    }
+
+    #[test]
+    fn select_without_default() {
+        let syntax = GrammarSyntax::default();
+        let block = parse_switch(&syntax, "{case:rain}wet{end}").unwrap();
+
+        assert_eq!(block.select("sun"), "");
+    }
+}
@@ -0,0 +1,122 @@
+use std::mem;
+
+use super::Grammar;
+use crate::error::{ExpansionError, VitrailError};
+use crate::rng::SeededRng;
+
+/// Length of the generated value when `hex` or `base32` are used without argument
+const DEFAULT_IDENTIFIER_LENGTH: usize = 8;
+/// Built-in generating a random version 4 UUID
+const UUID_BUILTIN: &str = "uuid";
+/// Built-in generating a random lowercase hexadecimal string
+const HEX_BUILTIN: &str = "hex";
+/// Built-in generating a random base32 code
+const BASE32_BUILTIN: &str = "base32";
+/// Names of the built-ins generating random values, available to every grammar
+pub(super) const RANDOM_BUILTINS: [&str; 3] = [UUID_BUILTIN, HEX_BUILTIN, BASE32_BUILTIN];
+
+impl Grammar<'_> {
+    /// Expand a built-in symbol (without its prefix), whose arguments are separated by the modifier operator.
+    ///
+    /// Available built-ins:
+    /// - `uuid`: a random version 4 UUID
+    /// - `hex:<length>`: a random lowercase hexadecimal string
+    /// - `base32:<length>`: a random base32 code
+    /// - `<provider>:<variable>`: a variable from a registered host variable provider
+    /// - `<grammar>:<symbol>`: a symbol of an included grammar, its root symbol without argument
+    ///
+    /// Panics if the built-in does not exist, or its arguments are invalid, unless the error handler decides otherwise.
+    pub(super) fn expand_builtin(&mut self, symbol: &str) -> String {
+        let mut parts = symbol.split(self.syntax.modifier_operator);
+        let name = parts.next().unwrap_or_default();
+        let args: Vec<&str> = parts.collect();
+        if let Err(error) = self.check_builtin_allowed(name) {
+            return self.handle_error(error);
+        }
+
+        let value = match name {
+            UUID_BUILTIN => Ok(self.rng.uuid()),
+            HEX_BUILTIN => self
+                .length_argument(name, &args)
+                .map(|len| self.rng.hex(len)),
+            BASE32_BUILTIN => self
+                .length_argument(name, &args)
+                .map(|len| self.rng.base32(len)),
+            _ => match self.host_variables.get(name) {
+                Some(provider) => {
+                    let variable = args.join(&self.syntax.modifier_operator.to_string());
+                    match provider.get_in_context(&variable, &self.context()) {
+                        Some(value) => Ok(self.output_profile.escape(&value)),
+                        None => Err(ExpansionError::UnavailableHostVariable {
+                            provider: name.to_string(),
+                            variable,
+                        }),
+                    }
+                }
+                None if self.included_grammars.contains_key(name) => {
+                    let symbol = args.join(&self.syntax.modifier_operator.to_string());
+                    Ok(self.expand_included(name, &symbol))
+                }
+                None => Err(ExpansionError::UnknownBuiltin(name.to_string())),
+            },
+        };
+
+        value.unwrap_or_else(|error| self.handle_error(error))
+    }
+
+    /// Expand a symbol of an included grammar, or its root symbol if none is given, with a seed derived
+    /// from the random number generator of this grammar.
+    ///
+    /// The included grammar escapes the text of its rules as this one does, and its expansion is inserted as is,
+    /// without its own document decoration. An error it does not recover from goes to the error handler of this
+    /// grammar.
+    fn expand_included(&mut self, name: &str, symbol: &str) -> String {
+        let seed = format!("{}/{}#{}", self.seed, name, self.rng.hex(16));
+        let escaping = self.output_profile.escaping;
+        let grammar = match self.included_grammars.get_mut(name) {
+            Some(grammar) => grammar,
+            None => return String::new(),
+        };
+
+        let root = match symbol {
+            "" => grammar.root().to_string(),
+            symbol => symbol.to_string(),
+        };
+        let own_escaping = mem::replace(&mut grammar.output_profile.escaping, escaping);
+        let own_seed = mem::replace(&mut grammar.seed, seed.clone());
+        let own_rng = mem::replace(&mut grammar.rng, SeededRng::new(&seed));
+        let expansion = grammar.catch_errors(|grammar| {
+            grammar.start_expansion();
+            grammar.expand_symbol(&root)
+        });
+        grammar.output_profile.escaping = own_escaping;
+        grammar.seed = own_seed;
+        grammar.rng = own_rng;
+
+        match expansion {
+            Ok(expansion) => expansion,
+            Err(VitrailError::Expansion { error, .. }) => self.handle_error(error),
+            Err(error) => {
+                unreachable!("An expansion only fails with an expansion error: {}", error)
+            }
+        }
+    }
+
+    /// Read the length of a generated identifier, which must fit in the size limit of the sandbox, if any
+    fn length_argument(&self, name: &str, args: &[&str]) -> Result<usize, ExpansionError> {
+        let length = match args.first() {
+            None => DEFAULT_IDENTIFIER_LENGTH,
+            Some(arg) => {
+                arg.trim()
+                    .parse()
+                    .map_err(|_| ExpansionError::InvalidBuiltinArgument {
+                        builtin: name.to_string(),
+                        argument: arg.to_string(),
+                    })?
+            }
+        };
+
+        self.check_size_limit(length)?;
+        Ok(length)
+    }
+}
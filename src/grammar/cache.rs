@@ -0,0 +1,163 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::Grammar;
+
+/// The outputs of the latest expansions, by seed and root symbol, evicting the least recently used one when full
+#[derive(Debug, Clone)]
+pub(crate) struct ExpansionCache {
+    capacity: usize,
+    /// The outputs, with the stamp of their last use
+    outputs: HashMap<(String, String), (String, u64)>,
+    /// The keys of the outputs by the stamp of their last use, least recently used first
+    recency: BTreeMap<u64, (String, String)>,
+    /// The stamp of the next use
+    next_stamp: u64,
+}
+
+impl ExpansionCache {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            outputs: HashMap::new(),
+            recency: BTreeMap::new(),
+            next_stamp: 0,
+        }
+    }
+
+    fn get(&mut self, key: &(String, String)) -> Option<&String> {
+        let stamp = self.next_stamp;
+        let (output, last_use) = self.outputs.get_mut(key)?;
+        let key = self.recency.remove(last_use)?;
+        *last_use = stamp;
+        self.recency.insert(stamp, key);
+        self.next_stamp += 1;
+
+        Some(output)
+    }
+
+    fn insert(&mut self, key: (String, String), output: String) {
+        if self.capacity == 0 {
+            return;
+        }
+        if let Some((_, last_use)) = self.outputs.remove(&key) {
+            self.recency.remove(&last_use);
+        }
+        if self.outputs.len() >= self.capacity {
+            if let Some((_, oldest)) = self.recency.pop_first() {
+                self.outputs.remove(&oldest);
+            }
+        }
+        self.recency.insert(self.next_stamp, key.clone());
+        self.outputs.insert(key, (output, self.next_stamp));
+        self.next_stamp += 1;
+    }
+
+    fn clear(&mut self) {
+        self.outputs.clear();
+        self.recency.clear();
+    }
+}
+
+impl Grammar<'_> {
+    /// Keep the outputs of the latest `capacity` expansions made by `flatten_cached`, so requesting the same seed
+    /// and root again, e.g. to render the same entity of a web service, skips the expansion entirely.
+    ///
+    /// Cached outputs are not updated when the grammar changes: call `invalidate_cache` after changing its rules,
+    /// or any state its expansions depend on, such as its captured variables.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json("test.json", "anyrandomseed", GrammarSyntax::default()).unwrap()
+    ///     .with_cache(100);
+    ///
+    /// let first = grammar.flatten_cached("entity 42", "root");
+    /// assert_eq!(grammar.flatten_cached("entity 42", "root"), first);
+    ///
+    /// grammar.symbols.insert("verb".to_string(), vec!["painted".to_string()]);
+    /// grammar.invalidate_cache();
+    /// assert!(grammar.flatten_cached("entity 42", "root").contains("painted"));
+    /// ```
+    pub fn with_cache(mut self, capacity: usize) -> Self {
+        self.cache = Some(ExpansionCache::new(capacity));
+        self
+    }
+
+    /// Expand the whole grammar from a given root symbol, with a random number generator created from the given
+    /// seed, as `flatten_with` does, returning the cached output of the same request if there is one
+    pub fn flatten_cached(&mut self, seed: &str, root: &str) -> String {
+        let key = (seed.to_string(), root.to_string());
+        if let Some(output) = self.cache.as_mut().and_then(|cache| cache.get(&key)) {
+            return output.to_string();
+        }
+
+        let output = self.flatten_with(Some(seed), root);
+        if let Some(cache) = self.cache.as_mut() {
+            cache.insert(key, output.to_string());
+        }
+        output
+    }
+
+    /// Forget every output kept by the cache, so the following requests are expanded again
+    pub fn invalidate_cache(&mut self) {
+        if let Some(cache) = self.cache.as_mut() {
+            cache.clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(seed: &str) -> (String, String) {
+        (seed.to_string(), "root".to_string())
+    }
+
+    #[test]
+    fn least_recently_used_output_is_evicted() {
+        let mut cache = ExpansionCache::new(2);
+        cache.insert(key("a"), "A".to_string());
+        cache.insert(key("b"), "B".to_string());
+        assert_eq!(cache.get(&key("a")), Some(&"A".to_string()));
+
+        cache.insert(key("c"), "C".to_string());
+        assert_eq!(cache.get(&key("b")), None);
+        assert_eq!(cache.get(&key("a")), Some(&"A".to_string()));
+        assert_eq!(cache.get(&key("c")), Some(&"C".to_string()));
+    }
+
+    #[test]
+    fn reinserted_outputs_are_replaced() {
+        let mut cache = ExpansionCache::new(2);
+        cache.insert(key("a"), "A".to_string());
+        cache.insert(key("b"), "B".to_string());
+        cache.insert(key("a"), "A2".to_string());
+
+        cache.insert(key("c"), "C".to_string());
+        assert_eq!(cache.get(&key("b")), None);
+        assert_eq!(cache.get(&key("a")), Some(&"A2".to_string()));
+        assert_eq!(cache.recency.len(), cache.outputs.len());
+    }
+
+    #[test]
+    fn cached_outputs_skip_expansion() {
+        use crate::config::GrammarSyntax;
+
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["{n}".to_string()])
+            .with_dynamic_symbol("n".to_string(), || "1".to_string())
+            .with_cache(1);
+        assert_eq!(grammar.flatten_cached("seed", "root"), "1");
+
+        grammar = grammar.with_dynamic_symbol("n".to_string(), || "2".to_string());
+        assert_eq!(grammar.flatten_cached("seed", "root"), "1");
+        grammar.invalidate_cache();
+        assert_eq!(grammar.flatten_cached("seed", "root"), "2");
+    }
+}
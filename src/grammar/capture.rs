@@ -0,0 +1,384 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{expression, Grammar};
+use crate::config::{CaptureForm, GrammarSyntax};
+
+/// The captures made in a branch of the expansion, with the value and rule tags each variable had before,
+/// to restore on leaving it
+pub(crate) type CaptureScope = Vec<(String, Option<CaptureValue>, Option<Vec<String>>)>;
+
+/// Split a capture expression into its source, the name of its variable, and whether the capture is promoted
+/// out of the branch it is made in, with a doubled operator (`[hero>>protagonist]`, `[protagonist::#hero#]`)
+pub(crate) fn parse_capture<'c>(
+    syntax: &GrammarSyntax,
+    capture: &'c str,
+) -> Option<(&'c str, &'c str, bool)> {
+    if syntax.capture_form == CaptureForm::VariableFirst {
+        // The rule captured may hold the operator itself, but not the variable
+        let (variable, source) = capture.split_once(syntax.capture_operator)?;
+        return match source.strip_prefix(syntax.capture_operator) {
+            Some(source) => Some((source, variable, true)),
+            None => Some((source, variable, false)),
+        };
+    }
+
+    let (source, variable) = capture.split_once(syntax.capture_operator)?;
+    let (variable, promoted) = match variable.strip_prefix(syntax.capture_operator) {
+        Some(variable) => (variable, true),
+        None => (variable, false),
+    };
+
+    if variable.contains(syntax.capture_operator) {
+        return None;
+    }
+    Some((source, variable, promoted))
+}
+
+/// The value of a captured variable, typed from the text it was captured from, so numbers can be used
+/// in arithmetic and compared in switch blocks. It renders as text when interpolated.
+///
+/// ```
+/// use vitrail::grammar::CaptureValue;
+///
+/// assert_eq!(CaptureValue::from_text("12"), CaptureValue::Number(12.0));
+/// assert_eq!(CaptureValue::from_text("true"), CaptureValue::Boolean(true));
+/// assert_eq!(CaptureValue::from_text("012"), CaptureValue::Text("012".to_string()));
+/// assert_eq!(CaptureValue::Number(2.5).to_string(), "2.5");
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CaptureValue {
+    Boolean(bool),
+    Number(f64),
+    Text(String),
+}
+
+impl CaptureValue {
+    /// Type a captured text: `true` and `false` are booleans, and finite numbers rendering back to the same text
+    /// are numbers, so interpolating the value never changes the text
+    pub fn from_text(text: &str) -> Self {
+        match text {
+            "true" => return CaptureValue::Boolean(true),
+            "false" => return CaptureValue::Boolean(false),
+            _ => (),
+        }
+
+        match text.parse::<f64>() {
+            Ok(number) if number.is_finite() && expression::format_number(number) == text => {
+                CaptureValue::Number(number)
+            }
+            _ => CaptureValue::Text(text.to_string()),
+        }
+    }
+
+    /// Returns the value as a number, if it is one
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            CaptureValue::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    /// Returns the value as a boolean, if it is one
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            CaptureValue::Boolean(boolean) => Some(*boolean),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CaptureValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureValue::Boolean(boolean) => write!(f, "{}", boolean),
+            CaptureValue::Number(number) => write!(f, "{}", expression::format_number(*number)),
+            CaptureValue::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl From<&str> for CaptureValue {
+    fn from(text: &str) -> Self {
+        CaptureValue::Text(text.to_string())
+    }
+}
+
+impl From<String> for CaptureValue {
+    fn from(text: String) -> Self {
+        CaptureValue::Text(text)
+    }
+}
+
+impl From<f64> for CaptureValue {
+    fn from(number: f64) -> Self {
+        CaptureValue::Number(number)
+    }
+}
+
+impl From<bool> for CaptureValue {
+    fn from(boolean: bool) -> Self {
+        CaptureValue::Boolean(boolean)
+    }
+}
+
+impl PartialEq<str> for CaptureValue {
+    /// A value equals the text it renders as
+    fn eq(&self, other: &str) -> bool {
+        match self {
+            CaptureValue::Boolean(boolean) => other.parse() == Ok(*boolean),
+            CaptureValue::Number(number) => expression::format_number(*number) == other,
+            CaptureValue::Text(text) => text == other,
+        }
+    }
+}
+
+impl PartialEq<&str> for CaptureValue {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl Grammar<'_> {
+    /// Scope the captures to the branch of the expansion they are made in: a capture made while expanding a symbol
+    /// is only visible to the rest of its rule and the symbols it references, and the variable gets its previous
+    /// value back once the symbol is expanded. Captures made by the root rule are kept after the expansion.
+    ///
+    /// Doubling the capture operator (`[hero>>protagonist]`) promotes a capture, which is kept in every case.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["[name>hero]{tale} and {hero}".to_string()])
+    ///     .with_symbol("tale".to_string(), vec!["[other>hero][place>>home]{hero}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["Alice".to_string()])
+    ///     .with_symbol("other".to_string(), vec!["Bob".to_string()])
+    ///     .with_symbol("place".to_string(), vec!["Paris".to_string()])
+    ///     .with_scoped_captures();
+    ///
+    /// assert_eq!(grammar.flatten().unwrap(), "Bob and Alice");
+    /// assert_eq!(grammar.captures["home"], "Paris");
+    /// ```
+    pub fn with_scoped_captures(mut self) -> Self {
+        self.capture_scopes = Some(Vec::new());
+        self
+    }
+
+    /// Open the capture scope of a branch of the expansion, if captures are scoped
+    pub(super) fn enter_capture_scope(&mut self) {
+        if let Some(scopes) = self.capture_scopes.as_mut() {
+            scopes.push(CaptureScope::new());
+        }
+    }
+
+    /// Close the capture scope of a branch of the expansion, giving its variables their previous value
+    /// and rule tags back
+    pub(super) fn leave_capture_scope(&mut self) {
+        let scope = match self.capture_scopes.as_mut().and_then(Vec::pop) {
+            Some(scope) => scope,
+            None => return,
+        };
+
+        for (variable, previous, tags) in scope.into_iter().rev() {
+            self.restore_capture(variable, previous, tags);
+        }
+    }
+
+    /// Give a variable back a previous value and the tags of the rule it was captured from,
+    /// removing it if it had no value
+    pub(super) fn restore_capture(
+        &mut self,
+        variable: String,
+        value: Option<CaptureValue>,
+        tags: Option<Vec<String>>,
+    ) {
+        match tags {
+            Some(tags) => self.capture_tags.insert(variable.to_string(), tags),
+            None => self.capture_tags.remove(&variable),
+        };
+        match value {
+            Some(value) => self.captures.insert(variable, value),
+            None => self.captures.remove(&variable),
+        };
+    }
+
+    /// Store a captured value along with the tags of the rule it was captured from, remembering the previous
+    /// value and tags of the variable in the current scope, unless the capture is promoted out of every scope
+    pub(super) fn store_capture(
+        &mut self,
+        variable: String,
+        value: CaptureValue,
+        tags: Vec<String>,
+        promoted: bool,
+    ) {
+        if let Some(scopes) = self.capture_scopes.as_mut() {
+            if promoted {
+                for scope in scopes.iter_mut() {
+                    scope.retain(|(name, _, _)| *name != variable);
+                }
+            } else if let Some(scope) = scopes.last_mut() {
+                if !scope.iter().any(|(name, _, _)| *name == variable) {
+                    scope.push((
+                        variable.to_string(),
+                        self.captures.get(&variable).cloned(),
+                        self.capture_tags.get(&variable).cloned(),
+                    ));
+                }
+            }
+        }
+
+        if tags.is_empty() {
+            self.capture_tags.remove(&variable);
+        } else {
+            self.capture_tags.insert(variable.to_string(), tags);
+        }
+        self.expansion_captures.insert(variable.to_string());
+        self.captures.insert(variable, value);
+    }
+
+    /// Returns the text of a captured variable, e.g. to reuse a name generated inside a text,
+    /// or `None` if it has not been captured
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["[name>hero_name]Meet {hero_name}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["Alice".to_string()]);
+    ///
+    /// assert_eq!(grammar.captured("hero_name"), None);
+    /// grammar.flatten().unwrap();
+    /// assert_eq!(grammar.captured("hero_name").as_deref(), Some("Alice"));
+    /// ```
+    pub fn captured(&self, variable: &str) -> Option<String> {
+        self.captures.get(variable).map(ToString::to_string)
+    }
+
+    /// Expand the whole grammar from the default root symbol, and return the expanded string along with
+    /// the text of the variables it captured and kept, by name.
+    ///
+    /// Variables captured by previous expansions, or imported, are left out unless this one captures them again,
+    /// and so are the captures scoped to a branch of the expansion, which are gone once it ends.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["[name>hero_name][#3*4>age]{hero_name}, {age}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["Alice".to_string()]);
+    ///
+    /// let (output, captures) = grammar.flatten_with_context();
+    /// assert_eq!(output, "Alice, 12");
+    /// assert_eq!(captures["hero_name"], "Alice");
+    /// assert_eq!(captures["age"], "12");
+    /// ```
+    pub fn flatten_with_context(&mut self) -> (String, HashMap<String, String>) {
+        let output = self.flatten_unchecked();
+        let captures = self
+            .expansion_captures
+            .iter()
+            .filter_map(|variable| {
+                self.captured(variable)
+                    .map(|value| (variable.to_string(), value))
+            })
+            .collect();
+
+        (output, captures)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_holds_the_captures_of_the_last_flatten() {
+        use crate::config::GrammarSyntax;
+
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["[name>hero]{tale}".to_string()])
+            .with_symbol(
+                "tale".to_string(),
+                vec!["[name>sidekick][name>>rival]{hero}".to_string()],
+            )
+            .with_symbol("name".to_string(), vec!["Ada".to_string()])
+            .with_scoped_captures();
+        grammar.import_captures(&HashMap::from([("world".to_string(), "Earth".into())]));
+
+        let (output, captures) = grammar.flatten_with_context();
+        assert_eq!(output, "Ada");
+
+        let mut names: Vec<&str> = captures.keys().map(String::as_str).collect();
+        names.sort();
+        assert_eq!(names, vec!["hero", "rival"]);
+        assert_eq!(grammar.captured("world").as_deref(), Some("Earth"));
+        assert_eq!(grammar.captured("sidekick"), None);
+    }
+
+    #[test]
+    fn inferred_types() {
+        assert_eq!(CaptureValue::from_text("-3.5"), CaptureValue::Number(-3.5));
+        assert_eq!(CaptureValue::from_text("False"), "False");
+        assert_eq!(
+            CaptureValue::from_text("inf"),
+            CaptureValue::Text("inf".to_string())
+        );
+        assert_eq!(
+            CaptureValue::from_text("1e3"),
+            CaptureValue::Text("1e3".to_string())
+        );
+    }
+
+    #[test]
+    fn renders_as_text() {
+        assert_eq!(CaptureValue::Number(3.0), "3");
+        assert_eq!(CaptureValue::Boolean(false), "false");
+        assert_eq!(CaptureValue::from("dragon"), "dragon");
+    }
+
+    #[test]
+    fn serialized_untagged() {
+        let values = vec![
+            CaptureValue::Number(3.0),
+            CaptureValue::Boolean(true),
+            CaptureValue::from("dragon"),
+        ];
+        let json = serde_json::to_string(&values).unwrap();
+        assert_eq!(json, r#"[3.0,true,"dragon"]"#);
+        assert_eq!(
+            serde_json::from_str::<Vec<CaptureValue>>(&json).unwrap(),
+            values
+        );
+    }
+
+    #[test]
+    fn capture_expressions() {
+        let syntax = GrammarSyntax::default();
+        assert_eq!(parse_capture(&syntax, "a>b"), Some(("a", "b", false)));
+        assert_eq!(parse_capture(&syntax, "a>>b"), Some(("a", "b", true)));
+        assert_eq!(parse_capture(&syntax, "#x>1>b"), None);
+        assert_eq!(parse_capture(&syntax, "a"), None);
+
+        let syntax = GrammarSyntax::tracery();
+        assert_eq!(parse_capture(&syntax, "b:#a#"), Some(("#a#", "b", false)));
+        assert_eq!(parse_capture(&syntax, "b::a"), Some(("a", "b", true)));
+        assert_eq!(
+            parse_capture(&syntax, "time:12:30"),
+            Some(("12:30", "time", false))
+        );
+    }
+}
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use super::{CaptureValue, Grammar};
+
+/// The selection of a rule during an expansion
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    symbol: String,
+    rule: usize,
+}
+
+impl Selection {
+    pub(crate) fn new(symbol: &str, rule: usize) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            rule,
+        }
+    }
+
+    /// Returns the symbol whose rule was selected
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// Returns the index of the selected rule, among the rules of the symbol
+    pub fn rule(&self) -> usize {
+        self.rule
+    }
+}
+
+impl fmt::Display for Selection {
+    /// The symbol followed by the index of the rule: `creature[0]`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}[{}]", self.symbol, self.rule)
+    }
+}
+
+/// The state of the expansion in progress, given to context-aware modifiers (`Modifier::apply_in_context`),
+/// host variable providers (`HostVariables::get_in_context`), dynamic symbols (`Grammar::with_contextual_symbol`)
+/// and error handlers (`Grammar::on_error_in_context`).
+///
+/// It is only read through its accessors, so new information can be added without breaking them.
+pub struct ExpansionContext<'g> {
+    seed: &'g str,
+    depth: usize,
+    captures: &'g HashMap<String, CaptureValue>,
+    selections: &'g [Selection],
+    tags: &'g [String],
+}
+
+impl<'g> ExpansionContext<'g> {
+    /// Returns the seed of the grammar random number generator
+    pub fn seed(&self) -> &'g str {
+        self.seed
+    }
+
+    /// Returns the nesting depth of the rule being expanded, the rule of the root being at depth 1
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Returns the value of a captured variable, if it was captured
+    pub fn capture(&self, variable: &str) -> Option<&'g CaptureValue> {
+        self.captures.get(variable)
+    }
+
+    /// Returns every captured variable, with its value
+    pub fn captures(&self) -> &'g HashMap<String, CaptureValue> {
+        self.captures
+    }
+
+    /// Returns the rules selected so far by the expansion, in order
+    pub fn selections(&self) -> &'g [Selection] {
+        self.selections
+    }
+
+    /// Returns the tags of the rule selected for the symbol whose expansion a modifier is applied to,
+    /// or of the rule its captured value comes from, e.g. to agree with a mass noun
+    pub fn tags(&self) -> &'g [String] {
+        self.tags
+    }
+}
+
+impl Grammar<'_> {
+    /// Returns the rules being expanded, from the root to the innermost one
+    pub(crate) fn derivation_path(&self) -> Vec<Selection> {
+        self.derivation_path
+            .iter()
+            .take(self.depth)
+            .flatten()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the context of the expansion in progress
+    pub(crate) fn context(&self) -> ExpansionContext<'_> {
+        ExpansionContext {
+            seed: &self.seed,
+            depth: self.depth,
+            captures: &self.captures,
+            selections: &self.selections,
+            tags: &self.modified_tags,
+        }
+    }
+}
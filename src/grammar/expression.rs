@@ -0,0 +1,165 @@
+/// Evaluates simple arithmetic expressions (`hero_level*10+5`), as found in expression tags.
+///
+/// Supports `+`, `-`, `*`, `/`, `%`, parentheses, unary minus, numeric literals, and variables,
+/// whose values are resolved through the given lookup function.
+pub(crate) fn evaluate(
+    expression: &str,
+    lookup: &mut dyn FnMut(&str) -> Result<f64, String>,
+) -> Result<f64, String> {
+    let mut parser = Parser {
+        chars: expression.chars().filter(|c| !c.is_whitespace()).collect(),
+        position: 0,
+        lookup,
+    };
+
+    let value = parser.sum()?;
+    match parser.peek() {
+        None => Ok(value),
+        Some(c) => Err(format!(
+            "Unexpected character '{}' in expression '{}'",
+            c, expression
+        )),
+    }
+}
+
+/// Renders a computed value, dropping the decimal part of whole numbers
+pub(crate) fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        format!("{}", value)
+    }
+}
+
+struct Parser<'l> {
+    chars: Vec<char>,
+    position: usize,
+    lookup: &'l mut dyn FnMut(&str) -> Result<f64, String>,
+}
+
+impl<'l> Parser<'l> {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn sum(&mut self) -> Result<f64, String> {
+        let mut value = self.product()?;
+        while let Some(operator @ ('+' | '-')) = self.peek() {
+            self.position += 1;
+            let rhs = self.product()?;
+            value = if operator == '+' {
+                value + rhs
+            } else {
+                value - rhs
+            };
+        }
+
+        Ok(value)
+    }
+
+    fn product(&mut self) -> Result<f64, String> {
+        let mut value = self.factor()?;
+        while let Some(operator @ ('*' | '/' | '%')) = self.peek() {
+            self.position += 1;
+            let rhs = self.factor()?;
+            if operator != '*' && rhs == 0.0 {
+                return Err("Division by zero in expression".to_string());
+            }
+
+            value = match operator {
+                '*' => value * rhs,
+                '/' => value / rhs,
+                _ => value % rhs,
+            };
+        }
+
+        Ok(value)
+    }
+
+    fn factor(&mut self) -> Result<f64, String> {
+        match self.peek() {
+            Some('-') => {
+                self.position += 1;
+                Ok(-self.factor()?)
+            }
+            Some('(') => {
+                self.position += 1;
+                let value = self.sum()?;
+                if self.peek() != Some(')') {
+                    return Err("Missing closing parenthesis in expression".to_string());
+                }
+                self.position += 1;
+                Ok(value)
+            }
+            Some(c) if c.is_ascii_digit() || c == '.' => {
+                let literal = self.take_while(|c| c.is_ascii_digit() || c == '.');
+                literal
+                    .parse()
+                    .map_err(|_| format!("Invalid number '{}' in expression", literal))
+            }
+            Some(c) if c.is_alphanumeric() || c == '_' => {
+                let name = self.take_while(|c| c.is_alphanumeric() || c == '_');
+                (self.lookup)(&name)
+            }
+            Some(c) => Err(format!("Unexpected character '{}' in expression", c)),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+
+    fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+        let start = self.position;
+        while self.peek().is_some_and(&predicate) {
+            self.position += 1;
+        }
+
+        self.chars[start..self.position].iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(expression: &str) -> Result<f64, String> {
+        evaluate(expression, &mut |name| match name {
+            "level" => Ok(3.0),
+            _ => Err(format!("unknown {}", name)),
+        })
+    }
+
+    #[test]
+    fn evaluate_precedence() {
+        assert_eq!(eval("2+3*4"), Ok(14.0));
+    }
+
+    #[test]
+    fn evaluate_parentheses_and_unary_minus() {
+        assert_eq!(eval("-(2+3) * 2"), Ok(-10.0));
+    }
+
+    #[test]
+    fn evaluate_variables() {
+        assert_eq!(eval("level*10+5"), Ok(35.0));
+    }
+
+    #[test]
+    fn evaluate_unknown_variable() {
+        assert_eq!(eval("other+1"), Err("unknown other".to_string()));
+    }
+
+    #[test]
+    fn evaluate_division_by_zero() {
+        assert!(eval("1/0").is_err());
+    }
+
+    #[test]
+    fn evaluate_trailing_garbage() {
+        assert!(eval("1+2)").is_err());
+    }
+
+    #[test]
+    fn format_whole_and_decimal_numbers() {
+        assert_eq!(format_number(35.0), "35");
+        assert_eq!(format_number(2.5), "2.5");
+    }
+}
@@ -0,0 +1,333 @@
+use std::collections::{HashMap, HashSet};
+
+use super::capture;
+use super::token::{self, Token};
+use super::Grammar;
+use crate::choice::{ChoiceMode, ChoiceScript};
+use crate::text;
+
+/// The parts of a rule contributing to the length of its expansions
+pub(super) struct RuleShape {
+    /// The length of the literal text of the rule, in graphemes
+    literal_len: usize,
+    /// The symbols derived by the rule, with the number of times their expansion is output.
+    /// A captured symbol is derived once, and output as many times as its variable is referenced.
+    derived: Vec<(String, usize)>,
+}
+
+impl Grammar<'_> {
+    /// Returns the shortest output the grammar can produce from the given root symbol, along with
+    /// the script of its derivation, or `None` if the root has no finite expansion.
+    ///
+    /// Lengths are computed over the rule graph from the literal text of the rules, before modifiers,
+    /// so the result is the shortest one up to what modifiers add.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol(
+    ///         "root".to_string(),
+    ///         vec!["{name} waves".to_string(), "{name} and {root}".to_string()],
+    ///     )
+    ///     .with_symbol("name".to_string(), vec!["Bob".to_string(), "Alice".to_string()]);
+    ///
+    /// let (output, _) = grammar.shortest_expansion("root").unwrap();
+    /// assert_eq!(output, "Bob waves");
+    /// ```
+    pub fn shortest_expansion(&mut self, root: &str) -> Option<(String, ChoiceScript)> {
+        let shapes = self.rule_shapes();
+        let shortest = Self::shortest_choices(&shapes);
+        shortest.get(root)?;
+
+        let rules = shortest
+            .into_iter()
+            .map(|(symbol, (index, _))| (symbol, vec![index]))
+            .collect();
+
+        Some(self.guided_expansion(root, rules))
+    }
+
+    /// Returns the longest output the grammar can produce from the given root symbol, without exceeding
+    /// `depth_cap` levels of nested expansions (the rule of the root being the first one), along with the script
+    /// of its derivation, or `None` if the root cannot be expanded within the cap.
+    ///
+    /// Lengths are computed over the rule graph from the literal text of the rules, before modifiers.
+    pub fn longest_expansion(
+        &mut self,
+        root: &str,
+        depth_cap: usize,
+    ) -> Option<(String, ChoiceScript)> {
+        let shapes = self.rule_shapes();
+        let shortest = Self::shortest_choices(&shapes);
+
+        // longest[k][symbol]: the longest expansion of the symbol within k levels, and the rule to choose
+        let mut longest: Vec<HashMap<&str, (usize, usize)>> = vec![HashMap::new()];
+        for k in 1..=depth_cap {
+            let previous = &longest[k - 1];
+            let level = shapes
+                .iter()
+                .filter_map(|(symbol, rules)| {
+                    rules
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(index, shape)| {
+                            Self::shape_len(shape, |name| previous.get(name).map(|(_, len)| *len))
+                                .map(|len| (index, len))
+                        })
+                        .max_by_key(|(index, len)| (*len, std::cmp::Reverse(*index)))
+                        .map(|choice| (symbol.as_str(), choice))
+                })
+                .collect();
+            longest.push(level);
+        }
+        longest[depth_cap].get(root)?;
+
+        // A symbol derived at depth d has depth_cap - d levels left, and falls back to its shortest
+        // expansion if it has no expansion within them
+        let rules = shapes
+            .keys()
+            .map(|symbol| {
+                let fallback = shortest.get(symbol).map_or(0, |(index, _)| *index);
+                let mut indices: Vec<usize> = (0..depth_cap)
+                    .map(|depth| {
+                        longest[depth_cap - depth]
+                            .get(symbol.as_str())
+                            .map_or(fallback, |(index, _)| *index)
+                    })
+                    .collect();
+                indices.push(fallback);
+                (symbol.to_string(), indices)
+            })
+            .collect();
+
+        Some(self.guided_expansion(root, rules))
+    }
+
+    /// Flatten the grammar from the root, choosing the rules listed for each symbol at the depth
+    /// of its derivation, and record the choices
+    pub(super) fn guided_expansion(
+        &mut self,
+        root: &str,
+        rules: HashMap<String, Vec<usize>>,
+    ) -> (String, ChoiceScript) {
+        self.choice_mode = ChoiceMode::Guided {
+            rules,
+            recorded: Vec::new(),
+        };
+        let output = self.flatten_from_root_unchecked(root);
+
+        match std::mem::take(&mut self.choice_mode) {
+            ChoiceMode::Guided { recorded, .. } => (output, ChoiceScript { choices: recorded }),
+            _ => (output, ChoiceScript::default()),
+        }
+    }
+
+    /// Returns the longest expansion length of each symbol having a finite expansion, over the rules loaded
+    /// in the symbol table, or `None` if its expansions can grow without bound through recursion.
+    ///
+    /// Lengths are computed over the rule graph from the literal text of the rules, before modifiers.
+    pub(super) fn longest_lengths(&self) -> HashMap<String, Option<usize>> {
+        let shapes = self.loaded_rule_shapes();
+        let mut longest: HashMap<String, usize> = Self::shortest_choices(&shapes)
+            .into_iter()
+            .map(|(symbol, (_, len))| (symbol, len))
+            .collect();
+
+        // Without recursion, the lengths are stable after one relaxation per symbol. Otherwise, the symbols
+        // still growing over as many more relaxations reach a recursion making their expansions longer each time.
+        let mut unbounded: HashSet<String> = HashSet::new();
+        for round in 0..shapes.len() * 2 + 1 {
+            let mut changed = false;
+            for (symbol, rules) in &shapes {
+                let len = rules
+                    .iter()
+                    .filter_map(|shape| Self::shape_len(shape, |name| longest.get(name).copied()))
+                    .max();
+                if let Some(len) = len {
+                    if longest.get(symbol).is_some_and(|best| len > *best) {
+                        longest.insert(symbol.to_string(), len);
+                        changed = true;
+                        if round > shapes.len() {
+                            unbounded.insert(symbol.to_string());
+                        }
+                    }
+                }
+            }
+
+            if !changed {
+                break;
+            }
+        }
+
+        longest
+            .into_iter()
+            .map(|(symbol, len)| {
+                let bounded = len < usize::MAX && !unbounded.contains(&symbol);
+                (symbol, bounded.then_some(len))
+            })
+            .collect()
+    }
+
+    /// Returns the shortest expansion length of each symbol having a finite one, with the rule to choose,
+    /// computed by relaxing the rule lengths until they are stable
+    pub(super) fn shortest_choices(
+        shapes: &HashMap<String, Vec<RuleShape>>,
+    ) -> HashMap<String, (usize, usize)> {
+        let mut shortest: HashMap<String, (usize, usize)> = HashMap::new();
+        loop {
+            let mut changed = false;
+            for (symbol, rules) in shapes {
+                for (index, shape) in rules.iter().enumerate() {
+                    let len =
+                        Self::shape_len(shape, |name| shortest.get(name).map(|(_, len)| *len));
+                    if let Some(len) = len {
+                        if shortest.get(symbol).is_none_or(|(_, best)| len < *best) {
+                            shortest.insert(symbol.to_string(), (index, len));
+                            changed = true;
+                        }
+                    }
+                }
+            }
+
+            if !changed {
+                return shortest;
+            }
+        }
+    }
+
+    /// Returns the expansion length of a rule, given the expansion length of the symbols it derives,
+    /// or `None` if one of them has none. Lengths too large to be represented are `usize::MAX`.
+    pub(super) fn shape_len<F>(shape: &RuleShape, symbol_len: F) -> Option<usize>
+    where
+        F: Fn(&str) -> Option<usize>,
+    {
+        shape
+            .derived
+            .iter()
+            .try_fold(shape.literal_len, |total, (name, times)| {
+                symbol_len(name).map(|len| total.saturating_add(len.saturating_mul(*times)))
+            })
+    }
+
+    /// Returns the shape of every rule of the grammar, loading the rules of file-backed symbols
+    fn rule_shapes(&mut self) -> HashMap<String, Vec<RuleShape>> {
+        self.load_file_symbols();
+        self.loaded_rule_shapes()
+    }
+
+    /// Returns the shape of every rule loaded in the symbol table
+    pub(super) fn loaded_rule_shapes(&self) -> HashMap<String, Vec<RuleShape>> {
+        self.symbols
+            .iter()
+            .map(|(symbol, rules)| {
+                let shapes = rules.iter().map(|rule| self.rule_shape(rule)).collect();
+                (symbol.to_string(), shapes)
+            })
+            .collect()
+    }
+
+    fn rule_shape(&self, rule: &str) -> RuleShape {
+        let mut shape = RuleShape {
+            literal_len: 0,
+            derived: Vec::new(),
+        };
+        let mut variables: HashMap<&str, usize> = HashMap::new();
+
+        for token in token::tokenize(&self.syntax, rule) {
+            match token {
+                Token::Text(text) => shape.literal_len += text::grapheme_len(&text),
+                Token::Symbol(expression) => {
+                    let name = match token::symbol_name(&self.syntax, expression) {
+                        Some(name) => name,
+                        None => continue,
+                    };
+
+                    if let Some(position) = variables.get(name) {
+                        shape.derived[*position].1 += 1;
+                    } else if self.symbols.contains_key(name) {
+                        shape.derived.push((name.to_string(), 1));
+                    }
+                }
+                Token::Capture(capture) => {
+                    if let Some((source, variable, _)) =
+                        capture::parse_capture(&self.syntax, capture)
+                    {
+                        if self.symbols.contains_key(source) {
+                            variables.insert(variable, shape.derived.len());
+                            shape.derived.push((source.to_string(), 0));
+                        }
+                    }
+                }
+            }
+        }
+
+        shape
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+
+    fn grammar() -> Grammar<'static> {
+        Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol(
+                "root".to_string(),
+                vec!["{phrase}".to_string(), "{phrase}, then {root}".to_string()],
+            )
+            .with_symbol(
+                "phrase".to_string(),
+                vec!["[name>n]{n} met {n}".to_string(), "{name} left".to_string()],
+            )
+            .with_symbol(
+                "name".to_string(),
+                vec!["Al".to_string(), "Beatrice".to_string()],
+            )
+    }
+
+    #[test]
+    fn shortest_expansion() {
+        let mut grammar = grammar();
+        let (output, script) = grammar.shortest_expansion("root").unwrap();
+        assert_eq!(output, "Al left");
+
+        grammar.reseed("another seed");
+        assert_eq!(grammar.flatten_with_script(&script), output);
+    }
+
+    #[test]
+    fn longest_expansion() {
+        let mut grammar = grammar();
+        let (output, _) = grammar.longest_expansion("root", 3).unwrap();
+        assert_eq!(output, "Beatrice met Beatrice");
+
+        let (output, script) = grammar.longest_expansion("root", 4).unwrap();
+        assert_eq!(output, "Beatrice met Beatrice, then Beatrice met Beatrice");
+        assert_eq!(grammar.flatten_with_script(&script), output);
+
+        assert_eq!(grammar.longest_expansion("root", 2), None);
+    }
+
+    #[test]
+    fn longest_lengths() {
+        let grammar = grammar().with_symbol("loop".to_string(), vec!["again {loop}".to_string()]);
+        let longest = grammar.longest_lengths();
+        assert_eq!(longest["name"], Some(8));
+        assert_eq!(longest["phrase"], Some(21));
+        assert_eq!(longest["root"], None);
+        assert!(!longest.contains_key("loop"));
+    }
+
+    #[test]
+    fn no_finite_expansion() {
+        let mut grammar =
+            grammar().with_symbol("loop".to_string(), vec!["again {loop}".to_string()]);
+        assert_eq!(grammar.shortest_expansion("loop"), None);
+        assert_eq!(grammar.shortest_expansion("missing"), None);
+    }
+}
@@ -0,0 +1,310 @@
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::{loader, Grammar};
+
+/// Offset basis of the 64-bit FNV-1a hash used for grammar fingerprints
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+/// Prime of the 64-bit FNV-1a hash used for grammar fingerprints
+const FNV_PRIME: u64 = 0x0000_0100_0000_01b3;
+
+/// A generation performed by a grammar, as written to its generation log, one JSON object per line
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GenerationLogEntry {
+    /// When the generation finished, in milliseconds since the Unix epoch
+    pub timestamp: u64,
+    /// The seed of the grammar when generating
+    pub seed: String,
+    /// The symbol the generation started from
+    pub root: String,
+    /// The fingerprint of the rules of the grammar, as returned by `Grammar::fingerprint`
+    pub fingerprint: String,
+    /// The generated text
+    pub output: String,
+}
+
+/// The sink a grammar appends its generation log to
+pub(super) struct GenerationLog<'a> {
+    sink: Box<dyn Write + 'a>,
+    /// The first error met while writing to the sink
+    error: Option<io::Error>,
+    /// The fingerprint of the rules of the grammar, computed for the first entry and kept until they change
+    fingerprint: Option<String>,
+}
+
+impl<'a> Grammar<'a> {
+    /// Returns a fingerprint of the rules of the grammar: 16 hexadecimal digits, which are the same for grammars
+    /// having the same rules according to `Grammar::same_rules_as`, and almost certainly different otherwise.
+    ///
+    /// The rules of a file-backed symbol are read from its file, so the fingerprint is the same whether the symbol
+    /// is loaded yet or not. A file which cannot be read is fingerprinted by its path.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let original = Grammar::from_json("test.json", "seed", GrammarSyntax::default()).unwrap();
+    /// let reloaded = Grammar::from_json("test.json", "other seed", GrammarSyntax::default()).unwrap();
+    /// assert_eq!(original.fingerprint(), reloaded.fingerprint());
+    /// assert_eq!(original.fingerprint().len(), 16);
+    /// ```
+    pub fn fingerprint(&self) -> String {
+        let mut hash = FNV_OFFSET_BASIS;
+        let mut write = |bytes: &[u8]| {
+            for byte in bytes.iter().chain(&[0xff]) {
+                hash ^= u64::from(*byte);
+                hash = hash.wrapping_mul(FNV_PRIME);
+            }
+        };
+
+        let mut symbols: Vec<&String> = self
+            .symbols
+            .keys()
+            .chain(self.file_symbols.keys())
+            .collect();
+        symbols.sort();
+        for symbol in symbols {
+            let rules = match self.file_symbols.get(symbol) {
+                None => Cow::Borrowed(&self.symbols[symbol]),
+                Some(path) => match loader::load_rules_file(path) {
+                    Ok((rules, _)) => Cow::Owned(rules),
+                    Err(_) => {
+                        write(b"file");
+                        write(symbol.as_bytes());
+                        write(path.to_string_lossy().as_bytes());
+                        continue;
+                    }
+                },
+            };
+
+            write(b"symbol");
+            write(symbol.as_bytes());
+            for rule in rules.iter() {
+                write(rule.as_bytes());
+            }
+        }
+
+        let mut optioned_symbols: Vec<&String> = self.rule_options.keys().collect();
+        optioned_symbols.sort();
+        for symbol in optioned_symbols {
+            write(b"options");
+            write(symbol.as_bytes());
+            for options in &self.rule_options[symbol] {
+                write(format!("{:?}", options).as_bytes());
+            }
+        }
+
+        format!("{:016x}", hash)
+    }
+
+    /// Append an entry to the given sink for every generation of the grammar (timestamp, seed, root, fingerprint
+    /// and output), one JSON object per line, e.g. for a bot to keep an audit trail of what it published.
+    ///
+    /// Each entry is flushed once written. A failure to write stops neither the generation nor the following entries,
+    /// and the first one is returned by `Grammar::generation_log_error`.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::{Grammar, GenerationLogEntry},
+    /// };
+    ///
+    /// let mut log = Vec::new();
+    /// let output = {
+    ///     let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///         .with_symbol("root".to_string(), vec!["Hello {name}".to_string()])
+    ///         .with_symbol("name".to_string(), vec!["world".to_string()])
+    ///         .with_generation_log(&mut log);
+    ///     grammar.flatten().unwrap()
+    /// };
+    ///
+    /// let log = String::from_utf8(log).unwrap();
+    /// let entry: GenerationLogEntry = serde_json::from_str(log.lines().next().unwrap()).unwrap();
+    /// assert_eq!(entry.seed, "anyrandomseed");
+    /// assert_eq!(entry.root, "root");
+    /// assert_eq!(entry.output, output);
+    /// ```
+    pub fn with_generation_log<W>(mut self, sink: W) -> Self
+    where
+        W: Write + 'a,
+    {
+        self.generation_log = Some(GenerationLog {
+            sink: Box::new(sink),
+            error: None,
+            fingerprint: None,
+        });
+        self
+    }
+
+    /// Returns the first error met while writing the generation log, if any
+    pub fn generation_log_error(&self) -> Option<&io::Error> {
+        self.generation_log
+            .as_ref()
+            .and_then(|log| log.error.as_ref())
+    }
+
+    /// Forget the fingerprint kept by the generation log, once the rules of the grammar change
+    pub(super) fn invalidate_fingerprint(&mut self) {
+        if let Some(log) = self.generation_log.as_mut() {
+            log.fingerprint = None;
+        }
+    }
+
+    /// Append a generation to the generation log, if the grammar has one
+    pub(super) fn log_generation(&mut self, root: &str, output: &str) {
+        let fingerprint = match self.generation_log.as_ref() {
+            None => return,
+            Some(log) => log.fingerprint.clone(),
+        };
+        let fingerprint = fingerprint.unwrap_or_else(|| self.fingerprint());
+
+        let entry = GenerationLogEntry {
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|elapsed| elapsed.as_millis() as u64)
+                .unwrap_or_default(),
+            seed: self.seed.clone(),
+            root: root.to_string(),
+            fingerprint: fingerprint.clone(),
+            output: output.to_string(),
+        };
+        let mut line =
+            serde_json::to_string(&entry).expect("A generation log entry is always serializable");
+        line.push('\n');
+
+        if let Some(log) = self.generation_log.as_mut() {
+            log.fingerprint = Some(fingerprint);
+            let written = log
+                .sink
+                .write_all(line.as_bytes())
+                .and_then(|_| log.sink.flush());
+            if let Err(error) = written {
+                log.error.get_or_insert(error);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+    use crate::grammar::RuleOptions;
+
+    fn grammar() -> Grammar<'static> {
+        Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["a {pet}".to_string()])
+            .with_symbol("other".to_string(), vec!["the {pet}".to_string()])
+            .with_symbol(
+                "pet".to_string(),
+                vec!["cat".to_string(), "dog".to_string()],
+            )
+    }
+
+    /// A sink refusing every write
+    struct BrokenSink;
+
+    impl Write for BrokenSink {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("disk full"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn every_generation_is_logged() {
+        let mut log = Vec::new();
+        let (fingerprint, outputs) = {
+            let mut grammar = grammar().with_generation_log(&mut log);
+            let outputs = vec![
+                grammar.flatten().unwrap(),
+                grammar.flatten_from_root("other").unwrap(),
+                grammar.flatten_with(Some("otherseed"), "root"),
+            ];
+            assert!(grammar.generation_log_error().is_none());
+            (grammar.fingerprint(), outputs)
+        };
+
+        let entries: Vec<GenerationLogEntry> = String::from_utf8(log)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let logged: Vec<(&str, &str, &str)> = entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.seed.as_str(),
+                    entry.root.as_str(),
+                    entry.output.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            logged,
+            vec![
+                ("testseed", "root", outputs[0].as_str()),
+                ("testseed", "other", outputs[1].as_str()),
+                ("otherseed", "root", outputs[2].as_str()),
+            ]
+        );
+        assert!(entries
+            .iter()
+            .all(|entry| entry.fingerprint == fingerprint && entry.timestamp > 0));
+    }
+
+    #[test]
+    fn fingerprints_follow_the_rules() {
+        let fingerprint = grammar().fingerprint();
+        assert_eq!(fingerprint, grammar().fingerprint());
+        assert_ne!(
+            fingerprint,
+            grammar()
+                .with_rule_options("pet", 0, RuleOptions::default().with_weight(2.0))
+                .fingerprint()
+        );
+        assert_ne!(
+            fingerprint,
+            grammar()
+                .with_symbol("pet".to_string(), vec!["catdog".to_string()])
+                .fingerprint()
+        );
+    }
+
+    #[test]
+    fn loading_file_symbols_keeps_the_fingerprint() {
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["{name}".to_string()])
+            .with_file_symbol("name".to_string(), "tests/data/names.txt");
+        let fingerprint = grammar.fingerprint();
+
+        grammar.flatten().unwrap();
+        assert!(grammar.file_symbols.is_empty());
+        assert_eq!(grammar.fingerprint(), fingerprint);
+        assert_ne!(
+            grammar
+                .with_symbol("name".to_string(), vec!["Ada".to_string()])
+                .fingerprint(),
+            fingerprint
+        );
+    }
+
+    #[test]
+    fn write_failures_do_not_stop_generation() {
+        let mut grammar = grammar().with_generation_log(BrokenSink);
+        assert!(grammar.flatten().unwrap().starts_with("a "));
+        assert_eq!(
+            grammar.generation_log_error().unwrap().to_string(),
+            "disk full"
+        );
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::{HashMap, HashSet};
+
+use super::validation::LengthHintOverrun;
+use super::Grammar;
+use crate::text;
+
+impl Grammar<'_> {
+    /// Declare the maximum length expected of the expansions of a symbol, in graphemes, e.g. for a button label
+    /// or an item name shown in a slot of fixed size, after the Grammar construction.
+    ///
+    /// Hints are checked by `validate`, and only enforced at expansion time with `with_enforced_length_hints`.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("label".to_string(), vec!["Buy {item}".to_string()])
+    ///     .with_symbol("item".to_string(), vec!["a sword".to_string(), "an enchanted shield".to_string()])
+    ///     .with_length_hint("label", 12);
+    ///
+    /// assert_eq!(
+    ///     grammar.length_hint_overruns()[0].to_string(),
+    ///     "Symbol 'label' can expand to 23 graphemes, over its hint of 12"
+    /// );
+    /// ```
+    pub fn with_length_hint(mut self, symbol: &str, max_len: usize) -> Self {
+        self.length_hints.insert(symbol.to_string(), max_len);
+        self
+    }
+
+    /// Returns the maximum expansion length declared for a symbol, if it has a length hint
+    pub fn length_hint(&self, symbol: &str) -> Option<usize> {
+        self.length_hints.get(symbol).copied()
+    }
+
+    /// Enforce the length hints at expansion time. The rules whose shortest expansion exceeds the hint of their
+    /// symbol are never selected at random by weight, unless all of them do, and a symbol expanding past its hint
+    /// is expanded again, up to `retries` times, keeping its shortest expansion if none fits.
+    ///
+    /// Lengths are counted after modifiers, before the output decoration. The captures made by a discarded
+    /// expansion are kept.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["({label})".to_string()])
+    ///     .with_symbol("label".to_string(), vec!["Buy {item}".to_string()])
+    ///     .with_symbol("item".to_string(), vec!["a sword".to_string(), "an enchanted shield".to_string()])
+    ///     .with_length_hint("label", 12)
+    ///     .with_enforced_length_hints(10);
+    ///
+    /// assert_eq!(grammar.flatten().unwrap(), "(Buy a sword)");
+    /// ```
+    pub fn with_enforced_length_hints(mut self, retries: usize) -> Self {
+        self.length_hint_attempts = Some(retries + 1);
+        self
+    }
+
+    /// Returns the symbols whose longest expansion exceeds their length hint, sorted by symbol.
+    ///
+    /// Lengths are computed over the rule graph from the literal text of the rules, before modifiers,
+    /// and the rules of file-backed symbols are only checked once they are loaded.
+    pub fn length_hint_overruns(&self) -> Vec<LengthHintOverrun> {
+        let longest = self.longest_lengths();
+        let mut symbols: Vec<&String> = self.length_hints.keys().collect();
+        symbols.sort();
+
+        symbols
+            .into_iter()
+            .filter_map(|symbol| {
+                let max_len = self.length_hints[symbol];
+                let longest = *longest.get(symbol)?;
+                if longest.is_some_and(|len| len <= max_len) {
+                    return None;
+                }
+
+                Some(LengthHintOverrun {
+                    symbol: symbol.to_string(),
+                    max_len,
+                    longest,
+                    description: self.descriptions.get(symbol).cloned(),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns how many times a symbol can be expanded to fit its length hint
+    pub(super) fn length_hint_attempts(&self, symbol: &str) -> usize {
+        match self.length_hint_attempts {
+            Some(attempts)
+                if self.length_hints.contains_key(symbol)
+                    && !self.captures.contains_key(symbol) =>
+            {
+                attempts
+            }
+            _ => 1,
+        }
+    }
+
+    /// Returns whether an expansion of a symbol fits its length hint, if it has one
+    pub(super) fn fits_length_hint(&self, symbol: &str, expansion: &str) -> bool {
+        self.length_hint(symbol)
+            .is_none_or(|max_len| text::grapheme_len(expansion) <= max_len)
+    }
+
+    /// Returns whether a rule is never selected, its shortest expansion exceeding the hint of its symbol
+    pub(super) fn is_oversized_rule(&self, symbol: &str, index: usize) -> bool {
+        self.oversized_rules
+            .get(symbol)
+            .is_some_and(|rules| rules.contains(&index))
+    }
+
+    /// Find the rules whose shortest expansion exceeds the length hint of their symbol, before an expansion
+    /// enforcing the hints
+    pub(super) fn prune_oversized_rules(&mut self) {
+        self.oversized_rules.clear();
+        if self.length_hint_attempts.is_none() || self.length_hints.is_empty() {
+            return;
+        }
+
+        let shapes = self.loaded_rule_shapes();
+        let shortest: HashMap<String, usize> = Self::shortest_choices(&shapes)
+            .into_iter()
+            .map(|(symbol, (_, len))| (symbol, len))
+            .collect();
+
+        for (symbol, max_len) in &self.length_hints {
+            let rules = match shapes.get(symbol) {
+                Some(rules) => rules,
+                None => continue,
+            };
+            let oversized: HashSet<usize> = rules
+                .iter()
+                .enumerate()
+                .filter(|(_, shape)| {
+                    Self::shape_len(shape, |name| shortest.get(name).copied())
+                        .is_none_or(|len| len > *max_len)
+                })
+                .map(|(index, _)| index)
+                .collect();
+
+            if !oversized.is_empty() {
+                self.oversized_rules.insert(symbol.to_string(), oversized);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+
+    fn grammar() -> Grammar<'static> {
+        Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["{label}".to_string()])
+            .with_symbol(
+                "label".to_string(),
+                vec!["{adjective} {item}".to_string(), "{item}".to_string()],
+            )
+            .with_symbol(
+                "adjective".to_string(),
+                vec!["old".to_string(), "shiny".to_string()],
+            )
+            .with_symbol(
+                "item".to_string(),
+                vec!["axe".to_string(), "lantern".to_string()],
+            )
+    }
+
+    #[test]
+    fn overruns_of_bounded_and_recursive_symbols() {
+        let grammar = grammar()
+            .with_length_hint("label", 12)
+            .with_length_hint("item", 7)
+            .with_length_hint("unknown", 1);
+        let overruns = grammar.length_hint_overruns();
+        assert_eq!(overruns.len(), 1);
+        assert_eq!(overruns[0].symbol, "label");
+        assert_eq!(overruns[0].longest, Some(13));
+
+        let grammar = grammar
+            .with_symbol(
+                "item".to_string(),
+                vec!["axe".to_string(), "{item} and axe".to_string()],
+            )
+            .with_length_hint("label", 100);
+        let overruns = grammar.length_hint_overruns();
+        assert_eq!(overruns.len(), 2);
+        assert_eq!(overruns[0].longest, None);
+        assert_eq!(
+            overruns[1].to_string(),
+            "Symbol 'label' can expand without bound, over its hint of 100 graphemes"
+        );
+    }
+
+    #[test]
+    fn enforced_hints_prune_and_retry() {
+        let mut grammar = grammar()
+            .with_length_hint("label", 7)
+            .with_enforced_length_hints(20);
+        for _ in 0..20 {
+            assert!(grammar.flatten().unwrap().len() <= 7);
+        }
+
+        // No rule is pruned when all are oversized, and the shortest of the attempts is kept
+        let mut grammar = grammar.with_length_hint("label", 2);
+        for _ in 0..20 {
+            assert_eq!(grammar.flatten().unwrap(), "axe");
+        }
+
+        let mut grammar = grammar
+            .with_length_hint("label", 3)
+            .with_enforced_length_hints(0);
+        for _ in 0..20 {
+            let output = grammar.flatten().unwrap();
+            assert!(output == "axe" || output == "lantern", "{}", output);
+        }
+    }
+}
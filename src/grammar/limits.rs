@@ -0,0 +1,89 @@
+use super::Grammar;
+use crate::config::ExpansionLimits;
+
+impl Grammar<'_> {
+    /// Limit the depth and number of derivations of the following expansions, so a malformed grammar cannot
+    /// recurse until the stack overflows. Reaching a limit is an expansion error, which panics without an error
+    /// handler, unless the limits set a truncation marker. A sandbox applies the stricter of both limits.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{ExpansionLimits, GrammarSyntax},
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["{root} again".to_string()])
+    ///     .with_limits(ExpansionLimits::default().with_max_depth(3).with_truncation_marker("…"));
+    ///
+    /// let result = grammar.generate().unwrap();
+    /// assert_eq!(result.output, "… again again again");
+    /// assert!(result.partial);
+    /// ```
+    pub fn with_limits(mut self, limits: ExpansionLimits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Returns the limits applied to the expansions, if any
+    pub fn limits(&self) -> Option<&ExpansionLimits> {
+        self.limits.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{GrammarSyntax, PartialOutput, SandboxOptions};
+    use crate::error::{ErrorAction, ExpansionError};
+
+    fn recursive() -> Grammar<'static> {
+        Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["very {root}".to_string()])
+    }
+
+    #[test]
+    #[should_panic(expected = "Expansion exceeded the limit: depth of 4 levels")]
+    fn depth_limit_is_an_error() {
+        recursive()
+            .with_limits(ExpansionLimits::default().with_max_depth(4))
+            .flatten_unchecked();
+    }
+
+    #[test]
+    fn expansion_limit_truncates_with_marker() {
+        let mut grammar = recursive().with_limits(
+            ExpansionLimits::default()
+                .with_max_expansions(3)
+                .with_truncation_marker("[...]"),
+        );
+        assert_eq!(grammar.flatten().unwrap(), "very very very [...]");
+
+        let mut grammar = grammar.on_error(|error| match error {
+            ExpansionError::LimitExceeded(_) => ErrorAction::Skip,
+            _ => ErrorAction::Abort,
+        });
+        assert_eq!(grammar.flatten().unwrap(), "very very very [...]");
+    }
+
+    #[test]
+    fn stricter_limit_applies_with_sandbox() {
+        let mut grammar = recursive()
+            .with_sandbox(
+                SandboxOptions::default().with_partial_output(PartialOutput::Placeholders),
+            )
+            .with_limits(ExpansionLimits::default().with_max_depth(2));
+        assert_eq!(grammar.flatten().unwrap(), "very very {root}");
+
+        let mut grammar = grammar
+            .with_sandbox(
+                SandboxOptions::default()
+                    .with_max_depth(1)
+                    .with_partial_output(PartialOutput::Strip),
+            )
+            .with_limits(ExpansionLimits::default().with_truncation_marker("…"));
+        assert_eq!(grammar.flatten().unwrap(), "very ");
+    }
+}
@@ -0,0 +1,676 @@
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{RuleOptions, Strategy};
+
+/// Key of a symbol definition object whose rules are read from an external file
+pub(super) const FILE_KEY: &str = "file";
+/// Key of a symbol definition object making the symbol an alias of another one
+const ALIAS_KEY: &str = "alias";
+/// Key of the rules of a symbol definition object, when it declares metadata along with inline rules
+pub(super) const RULES_KEY: &str = "rules";
+/// Key of a symbol definition object marking the symbol as deprecated, with `true` or a replacement hint
+const DEPRECATED_KEY: &str = "deprecated";
+/// Key of a symbol definition object documenting what the symbol is for
+const DESCRIPTION_KEY: &str = "description";
+/// Key of a symbol definition object giving the name of the strategy selecting its rules
+const STRATEGY_KEY: &str = "strategy";
+/// Key of a symbol definition object giving the maximum length expected of its expansions, in graphemes
+const MAX_LENGTH_KEY: &str = "maxLength";
+/// Key of the text of a rule written as an object with options
+pub(super) const RULE_KEY: &str = "rule";
+
+/// Names of the root symbol by convention, in order of precedence: `origin` is the root of Tracery grammars
+pub(crate) const ROOT_CONVENTIONS: [&str; 2] = ["root", "origin"];
+
+/// Symbol definitions read from a grammar source, before being added to a `Grammar`
+#[derive(Debug, Default)]
+pub(crate) struct Definitions {
+    /// Symbols whose rules are written inline
+    pub symbols: HashMap<String, Vec<String>>,
+    /// Symbols whose rules are read from an external file, on first use
+    pub file_symbols: HashMap<String, PathBuf>,
+    /// Options of the rules of the symbols having rules with options, by rule index
+    pub rule_options: HashMap<String, Vec<RuleOptions>>,
+    /// Deprecated symbols, with their replacement hint, if any
+    pub deprecations: HashMap<String, Option<String>>,
+    /// Selection strategies of the symbols declaring one
+    pub strategies: HashMap<String, Strategy>,
+    /// Descriptions of the symbols documenting what they are for
+    pub descriptions: HashMap<String, String>,
+    /// Maximum expansion lengths of the symbols declaring one
+    pub length_hints: HashMap<String, usize>,
+}
+
+/// The symbols of a grammar document, deserialized only to check that none is defined twice
+struct UniqueSymbols;
+
+impl<'de> Deserialize<'de> for UniqueSymbols {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_map(UniqueSymbolsVisitor)
+    }
+}
+
+struct UniqueSymbolsVisitor;
+
+impl<'de> Visitor<'de> for UniqueSymbolsVisitor {
+    type Value = UniqueSymbols;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("an object mapping symbols to rules")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<UniqueSymbols, A::Error> {
+        let mut symbols = HashSet::new();
+        while let Some(symbol) = map.next_key::<String>()? {
+            if !symbols.insert(symbol.clone()) {
+                return Err(de::Error::custom(format!(
+                    "symbol '{}' is defined twice",
+                    symbol
+                )));
+            }
+            map.next_value::<UniqueKeys>()?;
+        }
+
+        Ok(UniqueSymbols)
+    }
+}
+
+/// A value of a grammar document, deserialized only to check that none of its objects has a key given twice,
+/// e.g. a rule object with two `rule` entries
+struct UniqueKeys;
+
+impl<'de> Deserialize<'de> for UniqueKeys {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(UniqueKeysVisitor)
+    }
+}
+
+struct UniqueKeysVisitor;
+
+impl<'de> Visitor<'de> for UniqueKeysVisitor {
+    type Value = UniqueKeys;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("a definition of rules")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<UniqueKeys, A::Error> {
+        let mut keys = HashSet::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if !keys.insert(key.clone()) {
+                return Err(de::Error::custom(format!("key '{}' is given twice", key)));
+            }
+            map.next_value::<UniqueKeys>()?;
+        }
+
+        Ok(UniqueKeys)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<UniqueKeys, A::Error> {
+        while seq.next_element::<UniqueKeys>()?.is_some() {}
+        Ok(UniqueKeys)
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+
+    fn visit_unit<E>(self) -> Result<UniqueKeys, E> {
+        Ok(UniqueKeys)
+    }
+}
+
+/// Read a JSON grammar document, rejecting the symbols defined twice and the keys given twice in a definition,
+/// which reading it into a `Value` would silently collapse into their last definition
+pub(crate) fn parse_json(content: &[u8]) -> Result<Value, serde_json::Error> {
+    let document: Value = serde_json::from_slice(content)?;
+    if document.is_object() {
+        serde_json::from_slice::<UniqueSymbols>(content)?;
+    }
+
+    Ok(document)
+}
+
+/// Read a YAML grammar document, rejecting the symbols defined twice and the keys given twice in a definition
+#[cfg(feature = "yaml")]
+pub(crate) fn parse_yaml(content: &str) -> Result<Value, serde_yaml::Error> {
+    let document: Value = serde_yaml::from_str(content)?;
+    if document.is_object() {
+        serde_yaml::from_str::<UniqueSymbols>(content)?;
+    }
+
+    Ok(document)
+}
+
+/// Read the symbol definitions of a grammar document.
+///
+/// The document must be an object mapping each symbol to either:
+/// - a list of rules, each one being a string or an object `{"rule": "...", <options>}`
+/// - a single rule
+/// - an object `{"file": "path"}`, referencing a rules file, relative to `base_dir`
+/// - an object `{"alias": "symbol"}`, giving the symbol the same definition as another one
+/// - an object `{"rules": ...}`, holding a list of rules or a single rule
+///
+/// A definition object can also mark its symbol as deprecated: `{"alias": "color", "deprecated": "use 'color'"}`,
+/// name the strategy selecting its rules: `{"rules": [...], "strategy": "shuffleBag"}`, and describe what the symbol
+/// is for: `{"rules": [...], "description": "..."}`, and give the maximum length expected of its expansions:
+/// `{"rules": [...], "maxLength": 20}`. An alias without a strategy uses the strategy of the symbol it refers to.
+pub(crate) fn parse_definitions(document: &Value, base_dir: &Path) -> Result<Definitions, String> {
+    let entries = document
+        .as_object()
+        .ok_or_else(|| "the grammar must be an object mapping symbols to rules".to_string())?;
+
+    let mut definitions = Definitions::default();
+    let mut aliases: HashMap<&str, &str> = HashMap::new();
+    for (symbol, definition) in entries {
+        let fields = match definition {
+            Value::Object(fields) => fields,
+            _ => {
+                parse_inline_rules(&mut definitions, symbol, definition)?;
+                continue;
+            }
+        };
+
+        if let Some(path) = fields.get(FILE_KEY).and_then(Value::as_str) {
+            definitions
+                .file_symbols
+                .insert(symbol.to_string(), base_dir.join(path));
+        } else if let Some(target) = fields.get(ALIAS_KEY).and_then(Value::as_str) {
+            aliases.insert(symbol, target);
+        } else if let Some(rules) = fields.get(RULES_KEY) {
+            parse_inline_rules(&mut definitions, symbol, rules)?;
+        } else {
+            return Err(format!(
+                "symbol '{}' is an object without a '{}', '{}' or '{}' entry",
+                symbol, FILE_KEY, ALIAS_KEY, RULES_KEY
+            ));
+        }
+
+        match fields.get(DESCRIPTION_KEY) {
+            None => {}
+            Some(Value::String(description)) => {
+                definitions
+                    .descriptions
+                    .insert(symbol.to_string(), description.to_string());
+            }
+            Some(_) => {
+                return Err(format!(
+                    "the description of symbol '{}' must be a string",
+                    symbol
+                ))
+            }
+        }
+
+        match fields.get(STRATEGY_KEY) {
+            None => {}
+            Some(Value::String(name)) => {
+                let strategy = name
+                    .parse()
+                    .map_err(|message| format!("symbol '{}' has an {}", symbol, message))?;
+                definitions.strategies.insert(symbol.to_string(), strategy);
+            }
+            Some(_) => {
+                return Err(format!(
+                    "the strategy of symbol '{}' must be a name",
+                    symbol
+                ))
+            }
+        }
+
+        match fields.get(MAX_LENGTH_KEY) {
+            None => {}
+            Some(max_len) => {
+                let max_len = max_len
+                    .as_u64()
+                    .and_then(|max_len| usize::try_from(max_len).ok())
+                    .ok_or_else(|| {
+                        format!(
+                            "the maximum length of symbol '{}' must be a number of graphemes",
+                            symbol
+                        )
+                    })?;
+                definitions.length_hints.insert(symbol.to_string(), max_len);
+            }
+        }
+
+        match fields.get(DEPRECATED_KEY) {
+            None | Some(Value::Bool(false)) => {}
+            Some(Value::Bool(true)) => {
+                definitions.deprecations.insert(symbol.to_string(), None);
+            }
+            Some(Value::String(hint)) => {
+                definitions
+                    .deprecations
+                    .insert(symbol.to_string(), Some(hint.to_string()));
+            }
+            Some(_) => {
+                return Err(format!(
+                    "symbol '{}' must be deprecated with true or a replacement hint",
+                    symbol
+                ))
+            }
+        }
+    }
+
+    resolve_aliases(&mut definitions, &aliases)?;
+    Ok(definitions)
+}
+
+/// Give each alias the definition of the symbol it refers to, following chains of aliases
+fn resolve_aliases(
+    definitions: &mut Definitions,
+    aliases: &HashMap<&str, &str>,
+) -> Result<(), String> {
+    let mut names: Vec<&&str> = aliases.keys().collect();
+    names.sort();
+
+    for alias in names {
+        let mut target = aliases[*alias];
+        let mut steps = 0;
+        while let Some(next) = aliases.get(target) {
+            steps += 1;
+            if steps > aliases.len() {
+                return Err(format!("alias '{}' is part of a cycle", alias));
+            }
+            target = next;
+        }
+
+        if let Some(strategy) = definitions.strategies.get(target) {
+            let strategy = *strategy;
+            definitions
+                .strategies
+                .entry(alias.to_string())
+                .or_insert(strategy);
+        }
+
+        if let Some(rules) = definitions.symbols.get(target) {
+            let rules = rules.clone();
+            definitions.symbols.insert(alias.to_string(), rules);
+            if let Some(options) = definitions.rule_options.get(target) {
+                let options = options.clone();
+                definitions.rule_options.insert(alias.to_string(), options);
+            }
+        } else if let Some(path) = definitions.file_symbols.get(target) {
+            let path = path.clone();
+            definitions.file_symbols.insert(alias.to_string(), path);
+        } else {
+            return Err(format!(
+                "alias '{}' refers to undefined symbol '{}'",
+                alias, target
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Read the rules of a symbol written inline, as a list of rules or a single rule
+fn parse_inline_rules(
+    definitions: &mut Definitions,
+    symbol: &str,
+    definition: &Value,
+) -> Result<(), String> {
+    match definition {
+        Value::String(rule) => {
+            definitions
+                .symbols
+                .insert(symbol.to_string(), vec![rule.to_string()]);
+        }
+        Value::Array(rules) => {
+            let (rules, options) = parse_rules(symbol, rules)?;
+            if options
+                .iter()
+                .any(|options| *options != RuleOptions::default())
+            {
+                definitions.rule_options.insert(symbol.to_string(), options);
+            }
+            definitions.symbols.insert(symbol.to_string(), rules);
+        }
+        _ => return Err(format!("symbol '{}' has an invalid definition", symbol)),
+    }
+
+    Ok(())
+}
+
+fn parse_rules(symbol: &str, rules: &[Value]) -> Result<(Vec<String>, Vec<RuleOptions>), String> {
+    let rules = rules
+        .iter()
+        .map(|rule| parse_rule(symbol, rule))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(rules.into_iter().unzip())
+}
+
+/// Read a rule written as a string, or as an object with its text under `rule` and its options
+fn parse_rule(symbol: &str, rule: &Value) -> Result<(String, RuleOptions), String> {
+    match rule {
+        Value::String(text) => Ok((text.to_string(), RuleOptions::default())),
+        Value::Object(fields) => {
+            let mut options = fields.clone();
+            let text = options
+                .remove(RULE_KEY)
+                .and_then(|text| text.as_str().map(str::to_string))
+                .ok_or_else(|| {
+                    format!(
+                        "a rule of symbol '{}' is an object without a '{}' entry",
+                        symbol, RULE_KEY
+                    )
+                })?;
+            let options: RuleOptions =
+                serde_json::from_value(Value::Object(options)).map_err(|error| {
+                    format!(
+                        "a rule of symbol '{}' has invalid options: {}",
+                        symbol, error
+                    )
+                })?;
+            let is_share = |share: Option<f64>| share.is_none_or(|s| (0.0..=1.0).contains(&s));
+            if !is_share(options.floor) || !is_share(options.ceiling) {
+                return Err(format!(
+                    "a rule of symbol '{}' has a floor or ceiling out of the 0 to 1 range",
+                    symbol
+                ));
+            }
+
+            Ok((text, options))
+        }
+        _ => Err(format!(
+            "the rules of symbol '{}' must be strings or objects",
+            symbol
+        )),
+    }
+}
+
+/// Read the rules listed in an external file: a JSON list of strings for `.json` files,
+/// or one rule per non-empty line otherwise, along with the content of the file
+pub(crate) fn load_rules_file(path: &Path) -> Result<(Vec<String>, String), String> {
+    let content = fs::read_to_string(path)
+        .map_err(|_| format!("could not read rules file at {}", path.display()))?;
+
+    let rules = if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        serde_json::from_str(&content)
+            .map_err(|_| format!("rules file {} must be a list of strings", path.display()))?
+    } else {
+        content
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+
+    Ok((rules, content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parse_rule_lists_and_single_rules() {
+        let definitions = parse_definitions(
+            &json!({"root": "{animal}", "animal": ["cat", "dog"]}),
+            Path::new(""),
+        )
+        .unwrap();
+
+        assert_eq!(definitions.symbols["root"], vec!["{animal}"]);
+        assert_eq!(definitions.symbols["animal"], vec!["cat", "dog"]);
+    }
+
+    #[test]
+    fn symbols_defined_twice_are_rejected() {
+        let content =
+            "{\n  \"root\": \"{animal}\",\n  \"animal\": [\"cat\"],\n  \"animal\": [\"dog\"]\n}";
+        assert_eq!(
+            parse_json(content.as_bytes()).unwrap_err().to_string(),
+            "symbol 'animal' is defined twice at line 4 column 10"
+        );
+
+        let content = r#"{"root": ["x", {"rule": "a", "rule": "b"}], "other": ["a", "a"]}"#;
+        assert_eq!(
+            parse_json(content.as_bytes()).unwrap_err().to_string(),
+            "key 'rule' is given twice at line 1 column 35"
+        );
+        let content = r#"{"root": {"rules": ["a"], "rules": "b"}, "other": ["a", "a"]}"#;
+        assert!(parse_json(content.as_bytes()).is_err());
+        let content = r#"{"root": {"rules": ["a", null, 1.5]}, "other": ["a", "a"]}"#;
+        assert!(parse_json(content.as_bytes()).is_ok());
+        assert!(parse_json(b"[\"root\", \"root\"]").unwrap().is_array());
+
+        #[cfg(feature = "yaml")]
+        assert!(parse_yaml("root: a\nother: b\nroot: c\n")
+            .unwrap_err()
+            .to_string()
+            .starts_with("symbol 'root' is defined twice"));
+    }
+
+    #[test]
+    fn parse_rules_with_options() {
+        let definitions = parse_definitions(
+            &json!({
+                "joke": ["plain", {"rule": "cooling", "cooldown": 7, "weight": 2, "tags": ["pun"], "ceiling": 0.5}],
+                "other": ["a"]
+            }),
+            Path::new(""),
+        )
+        .unwrap();
+
+        assert_eq!(definitions.symbols["joke"], vec!["plain", "cooling"]);
+        assert_eq!(
+            definitions.rule_options["joke"],
+            vec![
+                RuleOptions::default(),
+                RuleOptions::default()
+                    .with_cooldown(7)
+                    .with_weight(2.0)
+                    .with_tag("pun")
+                    .with_ceiling(0.5)
+            ]
+        );
+        assert!(!definitions.rule_options.contains_key("other"));
+        assert!(parse_definitions(
+            &json!({"joke": [{"rule": "typo", "cooldwn": 7}]}),
+            Path::new("")
+        )
+        .is_err());
+        assert!(parse_definitions(
+            &json!({"ad": [{"rule": "sponsor", "ceiling": 5}]}),
+            Path::new("")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_file_symbols_relative_to_base_dir() {
+        let definitions = parse_definitions(
+            &json!({"name": {"file": "names.txt"}}),
+            Path::new("grammars"),
+        )
+        .unwrap();
+
+        assert_eq!(
+            definitions.file_symbols["name"],
+            Path::new("grammars").join("names.txt")
+        );
+    }
+
+    #[test]
+    fn parse_aliases() {
+        let definitions = parse_definitions(
+            &json!({
+                "color": ["red", {"rule": "blue", "weight": 3}],
+                "colour": {"alias": "color"},
+                "hue": {"alias": "colour"},
+                "name": {"file": "names.txt"},
+                "nom": {"alias": "name"}
+            }),
+            Path::new(""),
+        )
+        .unwrap();
+
+        assert_eq!(definitions.symbols["colour"], vec!["red", "blue"]);
+        assert_eq!(definitions.symbols["hue"], definitions.symbols["color"]);
+        assert_eq!(
+            definitions.rule_options["hue"],
+            definitions.rule_options["color"]
+        );
+        assert_eq!(definitions.file_symbols["nom"], Path::new("names.txt"));
+    }
+
+    #[test]
+    fn parse_deprecations() {
+        let definitions = parse_definitions(
+            &json!({
+                "color": ["red"],
+                "colour": {"alias": "color", "deprecated": "use 'color'"},
+                "hue": {"rules": "{color}", "deprecated": true},
+                "tint": {"rules": ["pale"], "deprecated": false}
+            }),
+            Path::new(""),
+        )
+        .unwrap();
+
+        assert_eq!(
+            definitions.deprecations["colour"],
+            Some("use 'color'".to_string())
+        );
+        assert_eq!(definitions.deprecations["hue"], None);
+        assert_eq!(definitions.symbols["hue"], vec!["{color}"]);
+        assert!(!definitions.deprecations.contains_key("tint"));
+        assert!(parse_definitions(
+            &json!({"hue": {"rules": "x", "deprecated": 3}}),
+            Path::new("")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_length_hints() {
+        let definitions = parse_definitions(
+            &json!({
+                "label": {"rules": ["Buy", "Sell"], "maxLength": 12},
+                "name": ["Ada"]
+            }),
+            Path::new(""),
+        )
+        .unwrap();
+
+        assert_eq!(definitions.length_hints["label"], 12);
+        assert_eq!(definitions.length_hints.len(), 1);
+        for max_len in [json!(-1), json!(2.5), json!("12")] {
+            assert!(parse_definitions(
+                &json!({"label": {"rules": "x", "maxLength": max_len}}),
+                Path::new("")
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn parse_descriptions() {
+        let definitions = parse_definitions(
+            &json!({
+                "greeting": {"rules": ["Hi", "Hello"], "description": "Opens a dialogue line"},
+                "salute": {"alias": "greeting"},
+                "name": ["Ada"]
+            }),
+            Path::new(""),
+        )
+        .unwrap();
+
+        assert_eq!(
+            definitions.descriptions["greeting"],
+            "Opens a dialogue line"
+        );
+        assert_eq!(definitions.descriptions.len(), 1);
+        assert!(parse_definitions(
+            &json!({"hue": {"rules": "x", "description": ["red"]}}),
+            Path::new("")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_strategies() {
+        let definitions = parse_definitions(
+            &json!({
+                "weather": {"rules": ["rain", "sun"], "strategy": "shuffleBag"},
+                "climate": {"alias": "weather"},
+                "mood": {"rules": ["calm", "angry", "sad"], "strategy": "noRepeat:2"},
+                "feeling": {"alias": "mood", "strategy": "sequential"},
+                "place": ["here"]
+            }),
+            Path::new(""),
+        )
+        .unwrap();
+
+        assert_eq!(definitions.strategies["weather"], Strategy::ShuffleBag);
+        assert_eq!(definitions.strategies["climate"], Strategy::ShuffleBag);
+        assert_eq!(definitions.strategies["mood"], Strategy::NoRepeat(2));
+        assert_eq!(definitions.strategies["feeling"], Strategy::Sequential);
+        assert!(!definitions.strategies.contains_key("place"));
+
+        for strategy in [json!("random"), json!(2)] {
+            assert!(parse_definitions(
+                &json!({"hue": {"rules": "x", "strategy": strategy}}),
+                Path::new("")
+            )
+            .is_err());
+        }
+    }
+
+    #[test]
+    fn parse_invalid_aliases() {
+        assert!(parse_definitions(&json!({"a": {"alias": "missing"}}), Path::new("")).is_err());
+        assert!(parse_definitions(
+            &json!({"a": {"alias": "b"}, "b": {"alias": "a"}}),
+            Path::new("")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn parse_invalid_definition() {
+        assert!(parse_definitions(&json!({"root": 3}), Path::new("")).is_err());
+        assert!(parse_definitions(&json!({"root": [3]}), Path::new("")).is_err());
+        assert!(parse_definitions(&json!(["root"]), Path::new("")).is_err());
+    }
+
+    #[test]
+    fn load_text_rules_file() {
+        let (rules, _) = load_rules_file(Path::new("tests/data/names.txt")).unwrap();
+        assert_eq!(rules, vec!["Alice", "Bob", "Eve"]);
+    }
+
+    #[test]
+    fn load_json_rules_file() {
+        let (rules, _) = load_rules_file(Path::new("tests/data/colours.json")).unwrap();
+        assert_eq!(rules, vec!["red", "blue", "yellow"]);
+    }
+}
@@ -0,0 +1,110 @@
+use super::Grammar;
+use crate::modifier::Modifier;
+
+impl<'a> Grammar<'a> {
+    /// Dynamically add a modifier to the Grammar for a locale, after its construction, taking ownership of it.
+    /// While the locale is active, it replaces the modifier registered under the same name with `with_modifier`
+    /// or for no locale, so the same rules can be flattened in several languages.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    ///     modifier::FrenchPluralizeModifier,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["{animal:s}".to_string()])
+    ///     .with_symbol("animal".to_string(), vec!["cheval".to_string()])
+    ///     .with_standard_modifiers()
+    ///     .with_locale_modifier("fr", "s".to_string(), FrenchPluralizeModifier {});
+    ///
+    /// assert_eq!(grammar.flatten().unwrap(), "chevals");
+    /// grammar.set_locale(Some("fr"));
+    /// assert_eq!(grammar.flatten().unwrap(), "chevaux");
+    /// ```
+    pub fn with_locale_modifier<M>(mut self, locale: &str, name: String, modifier: M) -> Self
+    where
+        M: Modifier + 'a,
+    {
+        self.locale_modifiers
+            .entry(locale.to_string())
+            .or_default()
+            .insert(name, Box::new(modifier));
+        self
+    }
+
+    /// Set the locale whose modifiers are applied, or `None` to apply the modifiers registered for no locale.
+    /// A `Session` flattening the grammar with a locale of its own applies the modifiers of that locale instead.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    pub fn with_locale(mut self, locale: &str) -> Self {
+        self.set_locale(Some(locale));
+        self
+    }
+
+    /// Set the locale whose modifiers are applied, or `None` to apply the modifiers registered for no locale
+    pub fn set_locale(&mut self, locale: Option<&str>) {
+        self.locale = locale.map(str::to_string);
+    }
+
+    /// Returns the locale whose modifiers are applied, outside of sessions
+    pub fn locale(&self) -> Option<&str> {
+        self.locale.as_deref()
+    }
+
+    /// Returns the locale of the session of the flatten in progress, if it sets one, or else of the grammar
+    fn active_locale(&self) -> Option<&str> {
+        self.session
+            .as_ref()
+            .and_then(|session| session.locale())
+            .or(self.locale())
+    }
+
+    /// Returns the modifier applied under the given name: the one registered for the active locale, if any,
+    /// or else the one registered for no locale
+    pub(super) fn modifier(&self, name: &str) -> Option<&(dyn Modifier + 'a)> {
+        self.active_locale()
+            .and_then(|locale| self.locale_modifiers.get(locale))
+            .and_then(|modifiers| modifiers.get(name))
+            .or_else(|| self.modifiers.get(name))
+            .map(|modifier| modifier.as_ref())
+    }
+
+    /// Returns whether a modifier is registered under the given name, for any locale or for none
+    pub(super) fn is_modifier_registered(&self, name: &str) -> bool {
+        self.modifiers.contains_key(name)
+            || self
+                .locale_modifiers
+                .values()
+                .any(|modifiers| modifiers.contains_key(name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::GrammarSyntax;
+    use crate::grammar::{Grammar, Session};
+    use crate::modifier::FrenchPluralizeModifier;
+
+    #[test]
+    fn session_locale_selects_modifiers() {
+        let mut grammar = Grammar::new("seed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["{noun:s}".to_string()])
+            .with_symbol("noun".to_string(), vec!["bateau".to_string()])
+            .with_standard_modifiers()
+            .with_locale_modifier("fr", "s".to_string(), FrenchPluralizeModifier {})
+            .with_locale("en");
+
+        let mut english = Session::new();
+        let mut french = Session::new();
+        french.set_locale(Some("fr"));
+
+        assert_eq!(english.flatten(&mut grammar), "bateaus");
+        assert_eq!(french.flatten(&mut grammar), "bateaux");
+        assert_eq!(grammar.flatten().unwrap(), "bateaus");
+        assert_eq!(grammar.locale(), Some("en"));
+    }
+}
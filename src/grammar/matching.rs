@@ -0,0 +1,194 @@
+use regex::Regex;
+use regex_automata::dfa::{dense, Automaton};
+use regex_automata::Input;
+
+use super::token::{self, Token};
+use super::Grammar;
+use crate::choice::ChoiceMode;
+
+/// Maximum size of the automaton built to prune the rules of the root symbol, in bytes.
+/// Patterns needing a larger one are only matched against complete outputs.
+const PRUNING_DFA_SIZE_LIMIT: usize = 1 << 20;
+
+impl Grammar<'_> {
+    /// Expand the grammar from the given root symbol up to `max_attempts` times, and return the first output
+    /// matching the pattern, if any.
+    ///
+    /// When the pattern allows it, the rules of the root symbol whose leading literal text cannot start
+    /// a match (e.g. `"The {noun}"` for `^B`) are never chosen, so fewer attempts are wasted.
+    /// Flags set on the `Regex` through `RegexBuilder` rather than in the pattern itself disable this pruning,
+    /// as do output profiles transforming the whole output.
+    ///
+    /// ```
+    /// use regex::Regex;
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol(
+    ///         "name".to_string(),
+    ///         vec!["Alice".to_string(), "Bob".to_string(), "Bertha".to_string()],
+    ///     );
+    ///
+    /// let name = grammar.flatten_matching("name", &Regex::new("^B").unwrap(), 10);
+    /// assert!(name.unwrap().starts_with('B'));
+    /// ```
+    pub fn flatten_matching(
+        &mut self,
+        root: &str,
+        pattern: &Regex,
+        max_attempts: usize,
+    ) -> Option<String> {
+        let candidates = self.matching_root_rules(root, pattern);
+        if candidates.as_ref().is_some_and(Vec::is_empty) {
+            return None;
+        }
+
+        for _ in 0..max_attempts {
+            let output = match &candidates {
+                Some(indices) => {
+                    let index = indices[self.rng.index(indices.len())];
+                    self.choosing(ChoiceMode::RootRule(index), None)
+                        .flatten_from_root_unchecked(root)
+                }
+                None => self.flatten_from_root_unchecked(root),
+            };
+
+            if pattern.is_match(&output) {
+                return Some(output);
+            }
+        }
+
+        None
+    }
+
+    /// Returns the indices of the rules of the root symbol whose leading literal text can start a match,
+    /// or `None` if the rules cannot be pruned for this root or pattern
+    fn matching_root_rules(&mut self, root: &str, pattern: &Regex) -> Option<Vec<usize>> {
+        if self.captures.contains_key(root) || self.output_profile.transforms_document() {
+            return None;
+        }
+        self.load_file_symbol(root).ok()?;
+
+        let dfa = dense::Builder::new()
+            .configure(dense::Config::new().dfa_size_limit(Some(PRUNING_DFA_SIZE_LIMIT)))
+            .build(pattern.as_str())
+            .ok()?;
+        let start = dfa.start_state_forward(&Input::new("")).ok()?;
+
+        let rules = self.symbols.get(root)?;
+        let indices = rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| {
+                let prefix = self.literal_prefix(&self.escape_rule(rule));
+                let mut state = start;
+                for byte in prefix.bytes() {
+                    state = dfa.next_state(state, byte);
+                    if dfa.is_match_state(state) {
+                        return true;
+                    }
+                    if dfa.is_dead_state(state) {
+                        return false;
+                    }
+                }
+                true
+            })
+            .map(|(index, _)| index)
+            .collect();
+
+        Some(indices)
+    }
+
+    /// Returns the literal text at the start of a rule, up to its first symbol expression.
+    /// Capture expressions are skipped, since they do not produce any text.
+    fn literal_prefix(&self, rule: &str) -> String {
+        token::tokenize(&self.syntax, rule)
+            .into_iter()
+            .take_while(|token| !matches!(token, Token::Symbol(_)))
+            .filter_map(|token| match token {
+                Token::Text(text) => Some(text),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+
+    fn grammar() -> Grammar<'static> {
+        Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol(
+                "root".to_string(),
+                vec![
+                    "The {noun}".to_string(),
+                    "[noun>n]A {n}".to_string(),
+                    "{noun}".to_string(),
+                ],
+            )
+            .with_symbol(
+                "noun".to_string(),
+                vec!["bear".to_string(), "owl".to_string()],
+            )
+    }
+
+    #[test]
+    fn prunes_root_rules_by_literal_prefix() {
+        let mut grammar = grammar();
+        assert_eq!(
+            grammar.matching_root_rules("root", &Regex::new("^A ").unwrap()),
+            Some(vec![1, 2])
+        );
+        assert_eq!(
+            grammar.matching_root_rules("root", &Regex::new("owl$").unwrap()),
+            Some(vec![0, 1, 2])
+        );
+        assert_eq!(
+            grammar.matching_root_rules("root", &Regex::new("^Th").unwrap()),
+            Some(vec![0, 2])
+        );
+        assert_eq!(
+            grammar.matching_root_rules("root", &Regex::new(r"^\w+$").unwrap()),
+            Some(vec![2])
+        );
+    }
+
+    #[test]
+    fn finds_matching_output() {
+        let mut grammar = grammar();
+        let pattern = Regex::new("^A owl$").unwrap();
+        assert_eq!(
+            grammar.flatten_matching("root", &pattern, 50),
+            Some("A owl".to_string())
+        );
+    }
+
+    #[test]
+    fn matching_outputs_record_their_root_rule() {
+        let mut grammar = grammar();
+        let output = grammar.flatten_matching("root", &Regex::new("^A ").unwrap(), 50);
+        assert!(output.unwrap().starts_with("A "));
+
+        let root = &grammar.selections[0];
+        assert_eq!((root.symbol(), root.rule()), ("root", 1));
+        assert!(matches!(grammar.choice_mode, ChoiceMode::Random));
+    }
+
+    #[test]
+    fn gives_up_without_match() {
+        let mut grammar = grammar();
+        assert_eq!(
+            grammar.flatten_matching("root", &Regex::new("^Z").unwrap(), 5),
+            None
+        );
+        assert_eq!(
+            grammar.flatten_matching("root", &Regex::new("cat").unwrap(), 5),
+            None
+        );
+    }
+}
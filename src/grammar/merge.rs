@@ -0,0 +1,382 @@
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::loader::{self, Definitions};
+use super::plain_text;
+use super::Grammar;
+use crate::config::{ConflictPolicy, GrammarSyntax};
+use crate::error::VitrailError;
+
+impl Grammar<'_> {
+    /// Add the symbols of another grammar to this one, e.g. to reuse wordlists kept in their own grammar files
+    /// across projects. The symbols defined by both grammars are resolved by the conflict policy.
+    ///
+    /// Only the symbol definitions are taken from the other grammar: their rules, rule options, strategies,
+    /// descriptions, deprecations and length hints. The root symbol, seed, modifiers and every other setting
+    /// of this grammar are kept.
+    ///
+    /// Returns an error if both grammars define a symbol and the policy is `ConflictPolicy::Error`, in which case
+    /// the grammar is unchanged, or if the rules file of a symbol whose rules are appended cannot be read.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{ConflictPolicy, GrammarSyntax},
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["A {colour} door".to_string()])
+    ///     .with_symbol("colour".to_string(), vec!["red".to_string()]);
+    /// let colours = || {
+    ///     Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///         .with_symbol("colour".to_string(), vec!["blue".to_string(), "green".to_string()])
+    /// };
+    ///
+    /// assert!(grammar.merge(colours(), ConflictPolicy::Error).is_err());
+    /// grammar.merge(colours(), ConflictPolicy::AppendRules).unwrap();
+    /// assert_eq!(grammar.symbols["colour"], vec!["red", "blue", "green"]);
+    /// ```
+    pub fn merge(
+        &mut self,
+        other: Grammar<'_>,
+        policy: ConflictPolicy,
+    ) -> Result<(), VitrailError> {
+        self.merge_from(other, policy, None)
+    }
+
+    /// Same as `from_dir`, panicking instead of returning an error if a grammar file cannot be read, is not a valid
+    /// grammar, or conflicts with another one.
+    pub fn from_dir_unchecked(
+        dir_path: &str,
+        seed: &str,
+        syntax: GrammarSyntax,
+        policy: ConflictPolicy,
+    ) -> Self {
+        Self::from_dir(dir_path, seed, syntax, policy).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Create a Grammar instance from every grammar file of a directory, merged in the order of their names with
+    /// the given conflict policy, e.g. a story grammar along with wordlists of colours and animals:
+    /// `.json` files, `.txt` files in the plain-text format, and `.yaml`, `.yml` and `.toml` files with the
+    /// matching features. Other files and sub-directories are ignored, so rules files can be kept in a
+    /// sub-directory. No file has to define a root symbol, so wordlists can be merged as `from_wordlist` reads them.
+    ///
+    /// Returns an error if a grammar file cannot be read, is not a valid grammar, or conflicts with another one.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{ConflictPolicy, GrammarSyntax},
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::from_dir(
+    ///     "tests/data/wordlists",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    ///     ConflictPolicy::AppendRules,
+    /// ).unwrap();
+    /// assert_eq!(grammar.symbols["colour"], vec!["blue", "green", "red"]);
+    /// ```
+    pub fn from_dir(
+        dir_path: &str,
+        seed: &str,
+        syntax: GrammarSyntax,
+        policy: ConflictPolicy,
+    ) -> Result<Self, VitrailError> {
+        let unreadable = |error: io::Error| VitrailError::Unreadable {
+            path: Some(dir_path.to_string()),
+            message: error.to_string(),
+        };
+        let mut paths = fs::read_dir(dir_path)
+            .map_err(unreadable)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<PathBuf>, io::Error>>()
+            .map_err(unreadable)?;
+        paths.retain(|path| path.is_file() && is_grammar_file(path));
+        paths.sort();
+
+        let mut grammar = Self::new(seed, syntax);
+        for path in paths {
+            let part = Self::read_part(&path, seed, syntax)?;
+            grammar.merge_from(part, policy, Some(&path.to_string_lossy()))?;
+        }
+
+        Ok(grammar)
+    }
+
+    /// Create a Grammar instance from a wordlist: a grammar file which does not define a root symbol, e.g. a list
+    /// of colours, meant to be merged into another grammar or expanded from a given symbol. The file is read
+    /// in the format given by its extension, as `from_dir` does.
+    ///
+    /// Returns an error if the file cannot be read or is not a valid grammar.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{ConflictPolicy, GrammarSyntax},
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let colours = Grammar::from_wordlist(
+    ///     "tests/data/wordlists/colours.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap();
+    /// assert!(!colours.has_root());
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["A {colour} door".to_string()]);
+    /// grammar.merge(colours, ConflictPolicy::Error).unwrap();
+    /// ```
+    pub fn from_wordlist(
+        file_path: &str,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Result<Self, VitrailError> {
+        Self::read_part(Path::new(file_path), seed, syntax)
+    }
+
+    /// Read a grammar file in the format given by its extension, without requiring it to define a root symbol
+    fn read_part(path: &Path, seed: &str, syntax: GrammarSyntax) -> Result<Self, VitrailError> {
+        let file_path = path.to_string_lossy().to_string();
+        let content = fs::read_to_string(path).map_err(|error| VitrailError::Unreadable {
+            path: Some(file_path.clone()),
+            message: error.to_string(),
+        })?;
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let definitions = read_definitions(path, &content, base_dir).map_err(|message| {
+            VitrailError::InvalidGrammar {
+                path: Some(file_path),
+                message,
+            }
+        })?;
+
+        let mut part = Self::from_definitions(definitions, seed, syntax);
+        part.locate_symbols(&content, path);
+        Ok(part)
+    }
+
+    /// Add the symbols of another grammar, read from the given file if it comes from one
+    fn merge_from(
+        &mut self,
+        mut other: Grammar<'_>,
+        policy: ConflictPolicy,
+        file_path: Option<&str>,
+    ) -> Result<(), VitrailError> {
+        self.invalidate_fingerprint();
+
+        let mut symbols: Vec<String> = other
+            .symbols
+            .keys()
+            .chain(other.file_symbols.keys())
+            .cloned()
+            .collect();
+        symbols.sort();
+        let conflicts: Vec<&String> = symbols
+            .iter()
+            .filter(|symbol| {
+                self.symbols.contains_key(*symbol) || self.file_symbols.contains_key(*symbol)
+            })
+            .collect();
+
+        match policy {
+            ConflictPolicy::Error => {
+                if let Some(symbol) = conflicts.first() {
+                    return Err(VitrailError::SymbolConflict {
+                        symbol: symbol.to_string(),
+                        path: file_path.map(str::to_string),
+                    });
+                }
+            }
+            // Rules read from files are loaded to be appended to
+            ConflictPolicy::AppendRules => {
+                for symbol in conflicts {
+                    self.load_file_symbol(symbol)?;
+                    other.load_file_symbol(symbol)?;
+                }
+            }
+            ConflictPolicy::Override => {}
+        }
+
+        for symbol in symbols {
+            self.dynamic_symbols.remove(&symbol);
+            self.strategy_states.remove(&symbol);
+
+            let rules = other.symbols.remove(&symbol);
+            let options = other.rule_options.remove(&symbol);
+            let source = other.sources.remove(&symbol);
+
+            match (self.symbols.get_mut(&symbol), rules) {
+                (Some(own_rules), Some(rules)) if policy == ConflictPolicy::AppendRules => {
+                    if let Some(options) = options {
+                        let own_options = self.rule_options.entry(symbol.clone()).or_default();
+                        own_options.resize(own_rules.len(), Default::default());
+                        own_options.extend(options);
+                    }
+                    own_rules.extend(rules);
+
+                    if let (Some(own_source), Some(source)) = (self.sources.remove(&symbol), source)
+                    {
+                        self.sources
+                            .insert(symbol.clone(), own_source.append(source));
+                    }
+                }
+                (_, rules) => {
+                    self.symbols.remove(&symbol);
+                    self.file_symbols.remove(&symbol);
+                    self.rule_options.remove(&symbol);
+                    self.sources.remove(&symbol);
+                    self.strategies.remove(&symbol);
+                    self.descriptions.remove(&symbol);
+                    self.deprecations.remove(&symbol);
+                    self.length_hints.remove(&symbol);
+
+                    match rules {
+                        Some(rules) => {
+                            self.symbols.insert(symbol.clone(), rules);
+                        }
+                        None => {
+                            if let Some(path) = other.file_symbols.remove(&symbol) {
+                                self.file_symbols.insert(symbol.clone(), path);
+                            }
+                        }
+                    }
+                    if let Some(options) = options {
+                        self.rule_options.insert(symbol.clone(), options);
+                    }
+                    if let Some(source) = source {
+                        self.sources.insert(symbol.clone(), source);
+                    }
+                }
+            }
+
+            // The settings of a symbol whose rules are appended to are kept, filled in by the other definition
+            take_setting(&mut self.strategies, &mut other.strategies, &symbol);
+            take_setting(&mut self.descriptions, &mut other.descriptions, &symbol);
+            take_setting(&mut self.deprecations, &mut other.deprecations, &symbol);
+            take_setting(&mut self.length_hints, &mut other.length_hints, &symbol);
+        }
+
+        Ok(())
+    }
+}
+
+/// Move the setting of a symbol from another grammar, if it has one and this grammar does not
+fn take_setting<V>(own: &mut HashMap<String, V>, other: &mut HashMap<String, V>, symbol: &str) {
+    if let Some(value) = other.remove(symbol) {
+        own.entry(symbol.to_string()).or_insert(value);
+    }
+}
+
+/// Returns whether a file holds a grammar in a format which can be read, according to its extension
+fn is_grammar_file(path: &Path) -> bool {
+    match path.extension().and_then(OsStr::to_str) {
+        Some("json") | Some("txt") => true,
+        #[cfg(feature = "yaml")]
+        Some("yaml") | Some("yml") => true,
+        #[cfg(feature = "toml")]
+        Some("toml") => true,
+        _ => false,
+    }
+}
+
+/// Read the symbol definitions of a grammar file, in the format given by its extension, whose rules files are
+/// relative to `base_dir`
+fn read_definitions(path: &Path, content: &str, base_dir: &Path) -> Result<Definitions, String> {
+    let document: serde_json::Value = match path.extension().and_then(OsStr::to_str) {
+        Some("txt") => return plain_text::parse_plain_text(content),
+        #[cfg(feature = "yaml")]
+        Some("yaml") | Some("yml") => {
+            loader::parse_yaml(content).map_err(|error| error.to_string())?
+        }
+        #[cfg(feature = "toml")]
+        Some("toml") => toml::from_str(content).map_err(|error| error.to_string())?,
+        _ => loader::parse_json(content.as_bytes()).map_err(|error| error.to_string())?,
+    };
+
+    loader::parse_definitions(&document, base_dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::grammar::RuleOptions;
+
+    fn grammar(symbol: &str, rules: &[&str]) -> Grammar<'static> {
+        Grammar::new("testseed", GrammarSyntax::default()).with_symbol(
+            symbol.to_string(),
+            rules.iter().map(|rule| rule.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn conflicts_follow_the_policy() {
+        let mut merged = grammar("colour", &["red"])
+            .with_rule_options("colour", 0, RuleOptions::default().with_tag("warm"))
+            .with_description("colour", "A colour");
+        let other = grammar("colour", &["blue", "green"])
+            .with_rule_options("colour", 1, RuleOptions::default().with_tag("cold"))
+            .with_description("colour", "Another colour");
+
+        assert_eq!(
+            merged.merge(other, ConflictPolicy::Error).unwrap_err(),
+            VitrailError::SymbolConflict {
+                symbol: "colour".to_string(),
+                path: None,
+            }
+        );
+        assert_eq!(merged.symbols["colour"], vec!["red"]);
+
+        let other = grammar("colour", &["blue", "green"])
+            .with_rule_options("colour", 1, RuleOptions::default().with_tag("cold"))
+            .with_description("colour", "Another colour");
+        merged.merge(other, ConflictPolicy::AppendRules).unwrap();
+        assert_eq!(merged.symbols["colour"], vec!["red", "blue", "green"]);
+        assert_eq!(merged.rule_tags("colour", 0), ["warm".to_string()]);
+        assert!(merged.rule_tags("colour", 1).is_empty());
+        assert_eq!(merged.rule_tags("colour", 2), ["cold".to_string()]);
+        assert_eq!(merged.description("colour"), Some("A colour"));
+
+        let other = grammar("colour", &["black"]).with_description("colour", "Dark");
+        merged.merge(other, ConflictPolicy::Override).unwrap();
+        assert_eq!(merged.symbols["colour"], vec!["black"]);
+        assert!(merged.rule_tags("colour", 0).is_empty());
+        assert_eq!(merged.description("colour"), Some("Dark"));
+    }
+
+    #[test]
+    fn directories_are_merged_in_order() {
+        let mut grammar = Grammar::from_dir(
+            "tests/data/wordlists",
+            "testseed",
+            GrammarSyntax::default(),
+            ConflictPolicy::Override,
+        )
+        .unwrap();
+        assert_eq!(grammar.root(), "root");
+        assert_eq!(grammar.symbols["colour"], vec!["red"]);
+        assert_eq!(
+            grammar.rule_source("animal", 1).unwrap().to_string(),
+            Path::new("tests/data/wordlists")
+                .join("animals.txt:4")
+                .display()
+                .to_string()
+        );
+        assert!(grammar.flatten().unwrap().starts_with("A red "));
+
+        let error = Grammar::from_dir(
+            "tests/data/wordlists",
+            "testseed",
+            GrammarSyntax::default(),
+            ConflictPolicy::Error,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            error,
+            VitrailError::SymbolConflict { ref symbol, path: Some(ref path) }
+                if symbol == "colour" && path.ends_with("story.json")
+        ));
+    }
+}
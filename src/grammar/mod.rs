@@ -0,0 +1,3888 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+use crate::choice::{ChoiceMode, ChoiceScript};
+use crate::config::{
+    CaptureForm, Escaping, ExpansionLimits, GrammarSyntax, OutputProfile, Profile, SandboxLimit,
+    SandboxOptions, Similarity, TagQuotas, UnmetQuotas, VarietyBoost, VocabularyDampening,
+    PROFILE_ATTEMPTS,
+};
+use crate::container::{self, Decryptor};
+use crate::error::{ErrorAction, ErrorHandler, ExpansionError, VitrailError};
+use crate::host::HostVariables;
+use crate::modifier::{self, call, Modifier};
+use crate::report::{ExpansionMetrics, GenerationResult, SelectionCounters};
+use crate::rng::SeededRng;
+use crate::text;
+
+mod block;
+mod builtin;
+mod cache;
+mod capture;
+mod context;
+mod expression;
+mod extremes;
+mod generation_log;
+mod length_hint;
+mod limits;
+mod loader;
+mod locale;
+mod matching;
+mod merge;
+mod outputs;
+mod pin;
+mod plain_text;
+mod planner;
+mod probability;
+mod recovery;
+mod rule;
+mod sampling;
+mod sandbox;
+mod schema;
+mod session;
+mod size;
+mod source;
+mod strategy;
+pub(crate) mod token;
+mod trace;
+mod validation;
+mod variety;
+
+pub use self::capture::CaptureValue;
+pub use self::context::{ExpansionContext, Selection};
+pub use self::generation_log::GenerationLogEntry;
+pub use self::outputs::Outputs;
+pub use self::pin::Pin;
+pub use self::rule::RuleOptions;
+pub use self::session::{Session, WeightBias};
+pub use self::source::SourceLocation;
+pub use self::strategy::Strategy;
+pub use self::trace::{DerivationNode, DerivationTree};
+pub use self::validation::{
+    DeprecatedReference, DuplicateRule, EmptySymbol, InvalidWeight, LengthHintOverrun,
+    MalformedCapture, MissingRoot, UndefinedSymbol, UnknownModifier, UnreachableSymbol,
+    ValidationIssue, ValidationReport,
+};
+
+/// By default, the grammar will be expanded starting from a symbol named `root`, or from the first conventional
+/// root it defines
+const DEFAULT_ROOT_KEY: &str = "root";
+/// Seed of the random number generator of a default grammar
+const DEFAULT_SEED: &str = "vitrail";
+/// Number of flattens attempted per requested output, when generating a batch
+const BATCH_ATTEMPTS_PER_OUTPUT: usize = 10;
+
+/// A callback computing the value of a dynamic symbol, each time it is derived
+pub type DynamicSymbol<'a> = Box<dyn Fn(&ExpansionContext) -> String + 'a>;
+
+/// Context-free grammar definition, which can be randomly expanded until all symbols are terminal,
+/// based on a given syntax configuration
+pub struct Grammar<'a> {
+    /// The context-free rules, expressed as a map of symbol => list of possible expansions
+    pub symbols: HashMap<String, Vec<String>>,
+    /// The symbols whose rules are stored in an external file, expressed as a map of symbol => path of the file.
+    /// A file is only read when its symbol is first derived, after which its rules are cached into `symbols`.
+    pub file_symbols: HashMap<String, PathBuf>,
+    /// The options of the rules, expressed as a map of symbol => options of each rule, by rule index.
+    /// Rules without an entry use the default options.
+    pub rule_options: HashMap<String, Vec<RuleOptions>>,
+    /// The deprecated symbols, expressed as a map of symbol => hint about its replacement, if any.
+    /// References to them are reported by `deprecated_references`.
+    pub deprecations: HashMap<String, Option<String>>,
+    /// The descriptions of the symbols, documenting what they are for, expressed as a map of symbol => description
+    pub descriptions: HashMap<String, String>,
+    /// The maximum lengths expected of the expansions of the symbols, in graphemes,
+    /// expressed as a map of symbol => length
+    pub length_hints: HashMap<String, usize>,
+    /// The symbols whose value is computed by the host application at expansion time, expressed as a map of symbol => callback
+    pub dynamic_symbols: HashMap<String, DynamicSymbol<'a>>,
+    /// The values captured during expansion, expressed as a map of variable name => captured value.
+    /// Captured variables take precedence over symbols of the same name.
+    pub captures: HashMap<String, CaptureValue>,
+    /// The captured variables which keep their current value, instead of being captured again
+    pub pinned_captures: HashSet<String>,
+    /// The symbols pinned to a rule or a value, for the following expansions
+    pins: HashMap<String, Pin>,
+    /// The syntax to be used to interpret the grammar rules
+    pub syntax: GrammarSyntax,
+    /// The presentation applied to the expansions of specific symbols
+    pub output_profile: OutputProfile,
+    /// The symbol expanded by `flatten`, when one is set with `with_root`. Otherwise, the first conventional root
+    /// defined (`root`, then `origin`) is expanded, resolved when expanding so symbols can be added or merged first.
+    root: Option<String>,
+    /// The seed the random number generator was created from
+    pub seed: String,
+    /// A seeded random number generator instance, to generate reproducible results
+    pub rng: SeededRng,
+    /// The modifiers featured for the grammar, expressed as a map of modifier name (used as function name in the rules) => the corresponding modifier implementation
+    pub modifiers: HashMap<String, Box<dyn Modifier + 'a>>,
+    /// The modifiers featured for specific locales, replacing the modifiers of the same name while their locale
+    /// is active, expressed as a map of locale => modifier name => the corresponding modifier implementation
+    pub locale_modifiers: HashMap<String, HashMap<String, Box<dyn Modifier + 'a>>>,
+    /// The locale whose modifiers are applied, unless the session of the flatten sets another one
+    locale: Option<String>,
+    /// The host variable providers available to the grammar, expressed as a map of built-in name (e.g. `env` for `{@env:USER}`) => the corresponding provider
+    pub host_variables: HashMap<String, &'a dyn HostVariables>,
+    /// The grammars whose symbols can be expanded by the grammar, expressed as a map of built-in name
+    /// (e.g. `names` for `{@names:root}`) => the corresponding grammar
+    pub included_grammars: HashMap<String, Grammar<'a>>,
+    /// The callback deciding what to do about expansion errors. Without one, the expansion panics.
+    pub error_handler: Option<ErrorHandler<'a>>,
+    /// Whether referencing a modifier which is not registered is an expansion error, rather than being ignored
+    strict_modifiers: bool,
+    /// Whether rules are chosen randomly, recorded or replayed
+    choice_mode: ChoiceMode,
+    /// The weight factors of the rules selected recently, when variety boosting is enabled
+    recent_selections: Option<variety::RecentSelections>,
+    /// The number of uses of the words output so far, when vocabulary dampening is enabled
+    word_frequencies: Option<variety::WordFrequencies>,
+    /// The session the flatten in progress is part of, if any
+    session: Option<Session>,
+    /// The number of selections of each rule, when enabled
+    selection_counters: Option<SelectionCounters>,
+    /// The selection strategy of the symbols not using the default one
+    strategies: HashMap<String, Strategy>,
+    /// The selections tracked by the strategies, by symbol
+    strategy_states: HashMap<String, strategy::StrategyState>,
+    /// The captures made by each branch of the expansion in progress, innermost last, when captures are scoped
+    capture_scopes: Option<Vec<capture::CaptureScope>>,
+    /// The variables captured by the expansion in progress, or by the last one
+    expansion_captures: HashSet<String>,
+    /// The tags of the rule each captured variable was selected from, when it has any
+    capture_tags: HashMap<String, Vec<String>>,
+    /// The tags of the rule selected for the symbol whose expansion is being modified
+    modified_tags: Vec<String>,
+    /// The nesting depth of the expansion in progress
+    depth: usize,
+    /// The rules selected by the expansion in progress, in order
+    selections: Vec<Selection>,
+    /// The rule selected last at each depth of the expansion in progress, whose first entries up to the current
+    /// depth are the rules being expanded
+    derivation_path: Vec<Option<Selection>>,
+    /// The derivation tree of the flatten in progress, when it is traced
+    trace: Option<trace::TraceBuilder>,
+    /// The metrics of the expansion in progress
+    metrics: ExpansionMetrics,
+    /// The limits applied to the expansions, for grammars from untrusted sources
+    sandbox: Option<SandboxOptions>,
+    /// The limits of the expansions, applied whether the grammar is sandboxed or not
+    limits: Option<ExpansionLimits>,
+    /// The time by which the expansion in progress must end, when the grammar is sandboxed
+    deadline: Option<Instant>,
+    /// The limit reached by the expansion in progress, when it produces a partial output
+    limit_reached: Option<SandboxLimit>,
+    /// The symbols left unexpanded by the expansion in progress, once it reached a limit
+    placeholders: sandbox::Placeholders,
+    /// The outputs of the latest expansions by seed and root symbol, when caching is enabled
+    cache: Option<cache::ExpansionCache>,
+    /// Whether expansion errors aborting the expansion are caught, rather than panicking, for the methods returning a `Result`
+    catching_errors: bool,
+    /// The first expansion error caught, which ends the expansion in progress
+    caught_error: Option<VitrailError>,
+    /// The tags excluded by the plan of the expansion in progress, whose rules are not selected
+    excluded_tags: HashSet<String>,
+    /// How many times a symbol having a length hint can be expanded to fit it, if the hints are enforced
+    length_hint_attempts: Option<usize>,
+    /// The rules whose shortest expansion exceeds the length hint of their symbol, by symbol
+    oversized_rules: HashMap<String, HashSet<usize>>,
+    /// The sink every generation is logged to, if any
+    generation_log: Option<generation_log::GenerationLog<'a>>,
+    /// Where the symbols read from files and their rules are written, by symbol
+    sources: HashMap<String, source::SymbolSource>,
+}
+
+impl Default for Grammar<'_> {
+    /// An empty grammar, with the default seed and syntax
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED, GrammarSyntax::default())
+    }
+}
+
+impl PartialEq for Grammar<'_> {
+    /// Two grammars are equal when they have the same rules, root, syntax and output profile.
+    ///
+    /// Their random number generator state, captured variables, modifiers, dynamic symbols and host
+    /// variable providers are ignored.
+    fn eq(&self, other: &Self) -> bool {
+        self.same_rules_as(other)
+            && self.root() == other.root()
+            && self.syntax == other.syntax
+            && self.output_profile == other.output_profile
+    }
+}
+
+impl fmt::Debug for Grammar<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut modifiers: Vec<&String> = self.modifiers.keys().collect();
+        modifiers.sort();
+
+        f.debug_struct("Grammar")
+            .field("seed", &self.seed)
+            .field("symbols", &self.symbols.len())
+            .field("rules", &self.rule_count())
+            .field("file_symbols", &self.file_symbols.len())
+            .field("dynamic_symbols", &self.dynamic_symbols.len())
+            .field("captures", &self.captures)
+            .field("modifiers", &modifiers)
+            .field("locale", &self.locale)
+            .field("included_grammars", &self.included_grammars.len())
+            .field("root", &self.root())
+            .field("has_root", &self.has_symbol(self.root()))
+            .field("syntax", &self.syntax)
+            .field("error_handler", &self.error_handler.is_some())
+            .field("generation_log", &self.generation_log.is_some())
+            .finish()
+    }
+}
+
+impl fmt::Display for Grammar<'_> {
+    /// Summarizes the grammar on a single line.
+    /// The alternate form (`{:#}`) lists every symbol and its rules, sorted by symbol name.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Grammar (seed \"{}\"): {} symbols, {} rules, {} modifiers, {}",
+            self.seed,
+            self.symbols.len() + self.file_symbols.len() + self.dynamic_symbols.len(),
+            self.rule_count(),
+            self.modifiers.len(),
+            if self.has_symbol(self.root()) {
+                "with root"
+            } else {
+                "without root"
+            },
+        )?;
+
+        if f.alternate() {
+            let mut names: Vec<&String> = self
+                .symbols
+                .keys()
+                .chain(self.file_symbols.keys())
+                .chain(self.dynamic_symbols.keys())
+                .collect();
+            names.sort();
+
+            for name in names {
+                write!(f, "\n{}", name)?;
+                if let Some(rules) = self.symbols.get(name) {
+                    for rule in rules {
+                        write!(f, "\n  - {}", rule)?;
+                    }
+                } else if let Some(path) = self.file_symbols.get(name) {
+                    write!(f, "\n  (rules from {})", path.display())?;
+                } else {
+                    write!(f, "\n  (dynamic)")?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Grammar<'a> {
+    /// Create an empty Grammar instance, without any symbol, to be populated with `with_symbol` and the other builders
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["Hello {name}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["world".to_string()]);
+    ///
+    /// assert_eq!(grammar.flatten().unwrap(), "Hello world");
+    /// ```
+    pub fn new(seed: &str, syntax: GrammarSyntax) -> Self {
+        Self {
+            symbols: HashMap::new(),
+            file_symbols: HashMap::new(),
+            rule_options: HashMap::new(),
+            deprecations: HashMap::new(),
+            descriptions: HashMap::new(),
+            length_hints: HashMap::new(),
+            dynamic_symbols: HashMap::new(),
+            captures: HashMap::new(),
+            pinned_captures: HashSet::new(),
+            pins: HashMap::new(),
+            syntax,
+            output_profile: OutputProfile::default(),
+            root: None,
+            seed: seed.to_string(),
+            rng: SeededRng::new(seed),
+            modifiers: HashMap::new(),
+            locale_modifiers: HashMap::new(),
+            locale: None,
+            host_variables: HashMap::new(),
+            included_grammars: HashMap::new(),
+            error_handler: None,
+            strict_modifiers: false,
+            choice_mode: ChoiceMode::Random,
+            recent_selections: None,
+            word_frequencies: None,
+            session: None,
+            selection_counters: None,
+            strategies: HashMap::new(),
+            strategy_states: HashMap::new(),
+            capture_scopes: None,
+            expansion_captures: HashSet::new(),
+            capture_tags: HashMap::new(),
+            modified_tags: Vec::new(),
+            depth: 0,
+            selections: Vec::new(),
+            derivation_path: Vec::new(),
+            trace: None,
+            metrics: ExpansionMetrics::default(),
+            sandbox: None,
+            limits: None,
+            deadline: None,
+            limit_reached: None,
+            placeholders: sandbox::Placeholders::default(),
+            cache: None,
+            catching_errors: false,
+            caught_error: None,
+            excluded_tags: HashSet::new(),
+            length_hint_attempts: None,
+            oversized_rules: HashMap::new(),
+            generation_log: None,
+            sources: HashMap::new(),
+        }
+    }
+
+    /// Same as `from_json`, panicking instead of returning an error if the file cannot be read or is not a valid
+    /// grammar.
+    pub fn from_json_unchecked(file_path: &str, seed: &str, syntax: GrammarSyntax) -> Self {
+        Self::from_json(file_path, seed, syntax).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Create a Grammar instance from a grammar described in a JSON file.
+    ///
+    /// Each symbol maps to a list of rules, a single rule, or `{"file": "path"}` to read its rules from
+    /// an external file (relative to the grammar file), either a JSON list or a text file with one rule per line.
+    /// A symbol can also be an alias of another one, `{"alias": "symbol"}`, resolved when the grammar is loaded.
+    ///
+    /// Returns an error if the file cannot be read or is not a valid grammar.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     error::VitrailError,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::from_json(
+    ///     "test.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap();
+    ///
+    /// let result = Grammar::from_json("missing.json", "anyrandomseed", GrammarSyntax::default());
+    /// assert!(matches!(result, Err(VitrailError::Unreadable { .. })));
+    /// ```
+    pub fn from_json(
+        file_path: &str,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Result<Self, VitrailError> {
+        let content = fs::read_to_string(file_path).map_err(|error| VitrailError::Unreadable {
+            path: Some(file_path.to_string()),
+            message: error.to_string(),
+        })?;
+        let document = loader::parse_json(content.as_bytes()).map_err(|error| {
+            VitrailError::InvalidGrammar {
+                path: Some(file_path.to_string()),
+                message: error.to_string(),
+            }
+        })?;
+
+        let mut grammar = Self::from_document(&document, Some(file_path), seed, syntax)?;
+        grammar.locate_symbols(&content, Path::new(file_path));
+        Ok(grammar)
+    }
+
+    /// Same as `from_json_str`, panicking instead of returning an error if the string is not a valid grammar.
+    pub fn from_json_str_unchecked(content: &str, seed: &str, syntax: GrammarSyntax) -> Self {
+        Self::from_json_str(content, seed, syntax).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Create a Grammar instance from a grammar described in a JSON string, e.g. embedded with `include_str!`
+    /// or received over the network. Having no directory to resolve them against, it cannot reference rules files.
+    ///
+    /// Returns an error if the string is not a valid grammar.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json_str(
+    ///     r#"{"root": "Hello {name}", "name": ["world"]}"#,
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap();
+    /// assert_eq!(grammar.flatten().unwrap(), "Hello world");
+    /// ```
+    pub fn from_json_str(
+        content: &str,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Result<Self, VitrailError> {
+        let document = loader::parse_json(content.as_bytes()).map_err(|error| {
+            VitrailError::InvalidGrammar {
+                path: None,
+                message: error.to_string(),
+            }
+        })?;
+
+        Self::from_document(&document, None, seed, syntax)
+    }
+
+    /// Same as `from_reader`, panicking instead of returning an error if the reader fails or the grammar is not valid.
+    pub fn from_reader_unchecked<R: Read>(reader: R, seed: &str, syntax: GrammarSyntax) -> Self {
+        Self::from_reader(reader, seed, syntax).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Create a Grammar instance from a grammar described in JSON, read until the end of the given reader,
+    /// e.g. a network stream. Having no directory to resolve them against, it cannot reference rules files.
+    ///
+    /// Returns an error if the reader fails or the grammar is not valid.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let request_body = br#"{"root": "Hello"}"#;
+    /// let mut grammar = Grammar::from_reader(&request_body[..], "anyrandomseed", GrammarSyntax::default()).unwrap();
+    /// assert_eq!(grammar.flatten().unwrap(), "Hello");
+    /// ```
+    pub fn from_reader<R: Read>(
+        mut reader: R,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Result<Self, VitrailError> {
+        let mut content = String::new();
+        reader
+            .read_to_string(&mut content)
+            .map_err(|error| VitrailError::Unreadable {
+                path: None,
+                message: error.to_string(),
+            })?;
+
+        Self::from_json_str(&content, seed, syntax)
+    }
+
+    /// Same as `from_container`, panicking instead of returning an error if the file cannot be read or decrypted, or is
+    /// not a valid grammar.
+    pub fn from_container_unchecked(
+        file_path: &str,
+        decryptor: &dyn Decryptor,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Self {
+        Self::from_container(file_path, decryptor, seed, syntax)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Create a Grammar instance from a JSON grammar sealed in a container file by `container::seal`, e.g. to ship
+    /// a spoiler-heavy grammar with a game. Its rules files, if any, are relative to the container file.
+    ///
+    /// Returns an error if the file cannot be read or decrypted, or is not a valid grammar.
+    ///
+    /// ```no_run
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     container::XorObfuscation,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::from_container(
+    ///     "assets/endings.vtrl",
+    ///     &XorObfuscation::new(b"build key"),
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap();
+    /// ```
+    pub fn from_container(
+        file_path: &str,
+        decryptor: &dyn Decryptor,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Result<Self, VitrailError> {
+        let content = fs::read(file_path).map_err(|error| VitrailError::Unreadable {
+            path: Some(file_path.to_string()),
+            message: error.to_string(),
+        })?;
+
+        Self::from_sealed(&content, decryptor, Some(file_path), seed, syntax)
+    }
+
+    /// Same as `from_container_bytes`, panicking instead of returning an error if the container cannot be decrypted or
+    /// the grammar is not valid.
+    pub fn from_container_bytes_unchecked(
+        content: &[u8],
+        decryptor: &dyn Decryptor,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Self {
+        Self::from_container_bytes(content, decryptor, seed, syntax)
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Create a Grammar instance from a JSON grammar sealed in a container, e.g. embedded with `include_bytes!`.
+    /// Having no directory to resolve them against, it cannot reference rules files.
+    ///
+    /// Returns an error if the container cannot be decrypted or the grammar is not valid.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     container::{self, XorObfuscation},
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let obfuscation = XorObfuscation::new(b"build key");
+    /// let sealed = container::seal(br#"{"root": "The butler did it"}"#, |data| obfuscation.obfuscate(data));
+    ///
+    /// let mut grammar =
+    ///     Grammar::from_container_bytes(&sealed, &obfuscation, "anyrandomseed", GrammarSyntax::default()).unwrap();
+    /// assert_eq!(grammar.flatten().unwrap(), "The butler did it");
+    /// ```
+    pub fn from_container_bytes(
+        content: &[u8],
+        decryptor: &dyn Decryptor,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Result<Self, VitrailError> {
+        Self::from_sealed(content, decryptor, None, seed, syntax)
+    }
+
+    /// Create a Grammar instance from a sealed JSON grammar, coming from the given container file, if any
+    fn from_sealed(
+        content: &[u8],
+        decryptor: &dyn Decryptor,
+        file_path: Option<&str>,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Result<Self, VitrailError> {
+        let path = file_path.map(str::to_string);
+        let grammar = container::open(content, decryptor).map_err(|message| {
+            VitrailError::InvalidContainer {
+                path: path.clone(),
+                message,
+            }
+        })?;
+        // A wrong key gives garbage rather than an error with most ciphers, hence the hint
+        let document =
+            loader::parse_json(&grammar).map_err(|error| VitrailError::InvalidGrammar {
+                path: path.clone(),
+                message: format!("{} (is the key right?)", error),
+            })?;
+
+        // Rules files are not sealed, so reading them would expose the rules the container hides
+        let file_symbol = document.as_object().and_then(|entries| {
+            entries
+                .iter()
+                .filter(|(_, definition)| definition.get(loader::FILE_KEY).is_some())
+                .map(|(symbol, _)| symbol)
+                .min()
+        });
+        if let Some(symbol) = file_symbol {
+            return Err(VitrailError::InvalidGrammar {
+                path,
+                message: format!(
+                    "symbol '{}' reads its rules from a file, which a sealed grammar cannot do",
+                    symbol
+                ),
+            });
+        }
+
+        Self::from_document(&document, file_path, seed, syntax)
+    }
+
+    /// Same as `from_yaml`, panicking instead of returning an error if the file cannot be read or is not a valid
+    /// grammar.
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml_unchecked(file_path: &str, seed: &str, syntax: GrammarSyntax) -> Self {
+        Self::from_yaml(file_path, seed, syntax).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Create a Grammar instance from a grammar described in a YAML file, with the same structure as a JSON one:
+    ///
+    /// ```yaml
+    /// # Comments are allowed
+    /// root: "Hello {name}"
+    /// name:
+    ///   - world
+    ///   - rule: you
+    ///     weight: 2
+    /// ```
+    ///
+    /// Returns an error if the file cannot be read or is not a valid grammar.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::from_yaml("test.yaml", "anyrandomseed", GrammarSyntax::default()).unwrap();
+    /// let same = Grammar::from_json("test.json", "anyrandomseed", GrammarSyntax::default()).unwrap();
+    /// assert!(grammar.same_rules_as(&same));
+    /// ```
+    #[cfg(feature = "yaml")]
+    pub fn from_yaml(
+        file_path: &str,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Result<Self, VitrailError> {
+        let content = fs::read_to_string(file_path).map_err(|error| VitrailError::Unreadable {
+            path: Some(file_path.to_string()),
+            message: error.to_string(),
+        })?;
+        let document =
+            loader::parse_yaml(&content).map_err(|error| VitrailError::InvalidGrammar {
+                path: Some(file_path.to_string()),
+                message: error.to_string(),
+            })?;
+
+        let mut grammar = Self::from_document(&document, Some(file_path), seed, syntax)?;
+        grammar.locate_symbols(&content, Path::new(file_path));
+        Ok(grammar)
+    }
+
+    /// Same as `from_toml`, panicking instead of returning an error if the file cannot be read or is not a valid
+    /// grammar.
+    #[cfg(feature = "toml")]
+    pub fn from_toml_unchecked(file_path: &str, seed: &str, syntax: GrammarSyntax) -> Self {
+        Self::from_toml(file_path, seed, syntax).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Create a Grammar instance from a grammar described in a TOML file, with the same structure as a JSON one:
+    /// its top-level table maps each symbol to its rules.
+    ///
+    /// ```toml
+    /// root = "Hello {name}"
+    /// name = ["world", { rule = "you", weight = 2 }]
+    /// ```
+    ///
+    /// Returns an error if the file cannot be read or is not a valid grammar.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::from_toml("test.toml", "anyrandomseed", GrammarSyntax::default()).unwrap();
+    /// let same = Grammar::from_json("test.json", "anyrandomseed", GrammarSyntax::default()).unwrap();
+    /// assert!(grammar.same_rules_as(&same));
+    /// ```
+    #[cfg(feature = "toml")]
+    pub fn from_toml(
+        file_path: &str,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Result<Self, VitrailError> {
+        let content = fs::read_to_string(file_path).map_err(|error| VitrailError::Unreadable {
+            path: Some(file_path.to_string()),
+            message: error.to_string(),
+        })?;
+        let document: serde_json::Value =
+            toml::from_str(&content).map_err(|error| VitrailError::InvalidGrammar {
+                path: Some(file_path.to_string()),
+                message: error.to_string(),
+            })?;
+
+        let mut grammar = Self::from_document(&document, Some(file_path), seed, syntax)?;
+        grammar.locate_symbols(&content, Path::new(file_path));
+        Ok(grammar)
+    }
+
+    /// Create a Grammar instance from the document of a grammar, whose rules files are relative to the grammar file
+    /// it comes from. A document which does not come from a file cannot reference rules files, as they would be read
+    /// from wherever the process runs, e.g. `/etc/passwd` for a grammar received over the network.
+    fn from_document(
+        document: &serde_json::Value,
+        file_path: Option<&str>,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Result<Self, VitrailError> {
+        let base_dir = file_path
+            .and_then(|path| Path::new(path).parent())
+            .unwrap_or_else(|| Path::new(""));
+        let definitions = loader::parse_definitions(document, base_dir).map_err(|message| {
+            VitrailError::InvalidGrammar {
+                path: file_path.map(str::to_string),
+                message,
+            }
+        })?;
+        if file_path.is_none() {
+            if let Some(symbol) = definitions.file_symbols.keys().min() {
+                return Err(VitrailError::InvalidGrammar {
+                    path: None,
+                    message: format!(
+                        "symbol '{}' reads its rules from a file, which a grammar not loaded from a file cannot do",
+                        symbol
+                    ),
+                });
+            }
+        }
+
+        let grammar = Self::from_definitions(definitions, seed, syntax);
+        grammar.check_root(file_path)?;
+        Ok(grammar)
+    }
+
+    /// Create a Grammar instance holding the given symbol definitions
+    fn from_definitions(
+        definitions: loader::Definitions,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Self {
+        Self {
+            symbols: definitions.symbols,
+            file_symbols: definitions.file_symbols,
+            rule_options: definitions.rule_options,
+            deprecations: definitions.deprecations,
+            strategies: definitions.strategies,
+            descriptions: definitions.descriptions,
+            length_hints: definitions.length_hints,
+            ..Self::new(seed, syntax)
+        }
+    }
+
+    /// Returns the selection counters, if they were enabled with `with_counters`
+    pub fn counters(&self) -> Option<&SelectionCounters> {
+        self.selection_counters.as_ref()
+    }
+
+    /// Set the selection counters back to zero, if they are enabled
+    pub fn reset_counters(&mut self) {
+        if let Some(counters) = self.selection_counters.as_mut() {
+            counters.reset();
+        }
+    }
+
+    /// Returns the total number of rules of the symbols loaded in the grammar
+    pub fn rule_count(&self) -> usize {
+        self.symbols.values().map(Vec::len).sum()
+    }
+
+    /// Replace the random number generator with a new one, created from the given seed
+    pub fn reseed(&mut self, seed: &str) {
+        self.seed = seed.to_string();
+        self.rng = SeededRng::new(seed);
+    }
+
+    /// Returns whether both grammars define the same symbols, with the same rules in the same order
+    /// and the same rule options, or the same rules files, regardless of their syntax or any other setting
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let original = Grammar::from_json("test.json", "seed", GrammarSyntax::default()).unwrap();
+    /// let reloaded = Grammar::from_json("test.json", "other seed", GrammarSyntax::default()).unwrap();
+    /// assert!(original.same_rules_as(&reloaded));
+    /// ```
+    pub fn same_rules_as(&self, other: &Grammar) -> bool {
+        self.symbols == other.symbols
+            && self.file_symbols == other.file_symbols
+            && self.rule_options == other.rule_options
+    }
+
+    /// Returns the sorted names of the symbols whose rules differ between both grammars,
+    /// including the symbols defined by only one of them
+    pub fn differing_symbols(&self, other: &Grammar) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .symbols
+            .keys()
+            .chain(other.symbols.keys())
+            .chain(self.file_symbols.keys())
+            .chain(other.file_symbols.keys())
+            .chain(self.rule_options.keys())
+            .chain(other.rule_options.keys())
+            .filter(|name| {
+                self.symbols.get(*name) != other.symbols.get(*name)
+                    || self.file_symbols.get(*name) != other.file_symbols.get(*name)
+                    || self.rule_options.get(*name) != other.rule_options.get(*name)
+            })
+            .cloned()
+            .collect();
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Dynamically add a modifier to the Grammar, after its construction.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    ///     modifier::CapitalizeModifier,
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json(
+    ///     "test.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap()
+    ///     .with_modifier("capitalize".to_string(), &CapitalizeModifier{});
+    /// ```
+    pub fn with_modifier(mut self, name: String, modifier: &'a dyn Modifier) -> Self {
+        self.modifiers.insert(name, Box::new(modifier));
+        self
+    }
+
+    /// Dynamically add a modifier to the Grammar, after its construction, taking ownership of it.
+    /// Unlike `with_modifier`, the Grammar does not borrow the modifier, so a Grammar built from owned modifiers
+    /// only is `Grammar<'static>`, and can be stored in a struct or returned from a function.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    ///     modifier::CapitalizeModifier,
+    /// };
+    ///
+    /// fn load() -> Grammar<'static> {
+    ///     Grammar::from_json("test.json", "anyrandomseed", GrammarSyntax::default()).unwrap()
+    ///         .with_owned_modifier("capitalize".to_string(), CapitalizeModifier {})
+    /// }
+    ///
+    /// let mut grammar = load();
+    /// grammar.flatten().unwrap();
+    /// ```
+    pub fn with_owned_modifier<M>(mut self, name: String, modifier: M) -> Self
+    where
+        M: Modifier + 'a,
+    {
+        self.modifiers.insert(name, Box::new(modifier));
+        self
+    }
+
+    /// Dynamically add a modifier to the Grammar, after its construction, as a closure transforming the expansion
+    /// of the symbol it is applied to.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["{name:shout}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["bob".to_string()])
+    ///     .with_modifier_fn("shout".to_string(), |text| format!("{}!", text.to_uppercase()));
+    ///
+    /// assert_eq!(grammar.flatten().unwrap(), "BOB!");
+    /// ```
+    pub fn with_modifier_fn<F>(self, name: String, modifier: F) -> Self
+    where
+        F: Fn(&str) -> String + 'a,
+    {
+        self.with_owned_modifier(name, modifier::FnModifier(modifier))
+    }
+
+    /// Dynamically add several modifiers to the Grammar at once, after its construction,
+    /// e.g. a standard set and a user set.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    ///     modifier::{CapitalizeModifier, Modifier, PluralizeModifier},
+    /// };
+    ///
+    /// let modifiers: Vec<(String, Box<dyn Modifier>)> = vec![
+    ///     ("capitalize".to_string(), Box::new(CapitalizeModifier {})),
+    ///     ("s".to_string(), Box::new(PluralizeModifier {})),
+    /// ];
+    ///
+    /// let mut grammar = Grammar::from_json(
+    ///     "test.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap()
+    ///     .with_modifiers(modifiers);
+    /// ```
+    pub fn with_modifiers<I>(mut self, modifiers: I) -> Self
+    where
+        I: IntoIterator<Item = (String, Box<dyn Modifier + 'a>)>,
+    {
+        self.modifiers.extend(modifiers);
+        self
+    }
+
+    /// Dynamically add the standard modifiers to the Grammar, after its construction,
+    /// under the names documented in `modifier::standard_modifiers` (`capitalize`, `s`, `a`, etc.).
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json(
+    ///     "test.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap()
+    ///     .with_standard_modifiers();
+    /// ```
+    pub fn with_standard_modifiers(self) -> Self {
+        self.with_modifiers(modifier::standard_modifiers())
+    }
+
+    /// Dynamically add a map of modifiers to the Grammar at once, after its construction.
+    /// Modifiers already registered under the same names are replaced.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    pub fn with_modifier_map(self, modifiers: HashMap<String, Box<dyn Modifier + 'a>>) -> Self {
+        self.with_modifiers(modifiers)
+    }
+
+    /// Dynamically add a host variable provider to the Grammar, after its construction.
+    /// Its variables are then available as `{@name:VARIABLE}` built-in symbols.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    ///     host::EnvironmentVariables,
+    /// };
+    ///
+    /// let env = EnvironmentVariables::allowing(&["USER"]);
+    /// let mut grammar = Grammar::from_json(
+    ///     "test.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap()
+    ///     .with_host_variables("env".to_string(), &env);
+    /// ```
+    pub fn with_host_variables(mut self, name: String, provider: &'a dyn HostVariables) -> Self {
+        self.host_variables.insert(name, provider);
+        self
+    }
+
+    /// Include another grammar, whose symbols are then available as `{@name:symbol}` built-in symbols (or
+    /// `{@name}` for its root symbol), without merging it into this one and risking name collisions.
+    ///
+    /// Each expansion of an included symbol flattens the other grammar with a seed derived from the random number
+    /// generator of this one, so the outputs of both are reproduced from the seed of this grammar alone.
+    /// The text of its rules is escaped for the output profile of this grammar, and the errors it does not recover
+    /// from are handled by this grammar.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let names = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["{first} {last}".to_string()])
+    ///     .with_symbol("first".to_string(), vec!["Ada".to_string()])
+    ///     .with_symbol("last".to_string(), vec!["Lovelace".to_string()]);
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["{first}, or {@names} for short".to_string()])
+    ///     .with_symbol("first".to_string(), vec!["{@names:first}".to_string()])
+    ///     .with_included_grammar("names".to_string(), names);
+    ///
+    /// assert_eq!(grammar.flatten().unwrap(), "Ada, or Ada Lovelace for short");
+    /// ```
+    pub fn with_included_grammar(mut self, name: String, grammar: Grammar<'a>) -> Self {
+        self.included_grammars.insert(name, grammar);
+        self
+    }
+
+    /// Install an error handler on the Grammar, deciding whether a problem met during expansion
+    /// (unknown symbol, invalid built-in, etc.) is replaced with a placeholder, skipped, or aborts the expansion.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     error::{ErrorAction, ExpansionError},
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["Hello {name}".to_string()])
+    ///     .on_error(|error| match error {
+    ///         ExpansionError::UnknownSymbol(_) => ErrorAction::Substitute("stranger".to_string()),
+    ///         _ => ErrorAction::Abort,
+    ///     });
+    ///
+    /// assert_eq!(grammar.flatten().unwrap(), "Hello stranger");
+    /// ```
+    pub fn on_error<F>(self, mut handler: F) -> Self
+    where
+        F: FnMut(&ExpansionError) -> ErrorAction + 'a,
+    {
+        self.on_error_in_context(move |error, _| handler(error))
+    }
+
+    /// Install an error handler on the Grammar, as `on_error` does, which is also given the state of the expansion
+    /// the error occurred in, e.g. to fill a placeholder with a captured variable.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     error::ErrorAction,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["[first>name]Hello {name} {last}".to_string()])
+    ///     .with_symbol("first".to_string(), vec!["Ada".to_string()])
+    ///     .on_error_in_context(|_, context| match context.capture("name") {
+    ///         Some(name) => ErrorAction::Substitute(format!("{}son", name)),
+    ///         None => ErrorAction::Skip,
+    ///     });
+    ///
+    /// assert_eq!(grammar.flatten().unwrap(), "Hello Ada Adason");
+    /// ```
+    pub fn on_error_in_context<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(&ExpansionError, &ExpansionContext) -> ErrorAction + 'a,
+    {
+        self.error_handler = Some(Box::new(handler));
+        self
+    }
+
+    /// Enable variety boosting: the rules selected by a flatten get temporarily down-weighted in the following ones,
+    /// decaying back to their normal weight, so the same rule is less likely to be repeated several times in a row.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{GrammarSyntax, VarietyBoost},
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json(
+    ///     "test.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap()
+    ///     .with_variety_boost(VarietyBoost::default());
+    /// ```
+    pub fn with_variety_boost(mut self, boost: VarietyBoost) -> Self {
+        self.recent_selections = Some(variety::RecentSelections::new(boost));
+        self
+    }
+
+    /// Enable vocabulary dampening: the words output by the flattens are counted, and the rules whose literal
+    /// words were used often get down-weighted in the following ones, so a long document generated over many
+    /// flattens varies its vocabulary as a whole.
+    ///
+    /// Only the rules selected at random by weight are dampened. Call `forget_vocabulary` before starting
+    /// a new document.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{GrammarSyntax, VocabularyDampening},
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol(
+    ///         "root".to_string(),
+    ///         vec!["The wind howls.".to_string(), "The rain falls.".to_string()],
+    ///     )
+    ///     .with_vocabulary_dampening(VocabularyDampening::new(100.0));
+    ///
+    /// let first = grammar.flatten().unwrap();
+    /// assert_ne!(grammar.flatten().unwrap(), first);
+    /// ```
+    pub fn with_vocabulary_dampening(mut self, dampening: VocabularyDampening) -> Self {
+        self.word_frequencies = Some(variety::WordFrequencies::new(dampening));
+        self
+    }
+
+    /// Forget the words counted by vocabulary dampening, e.g. before generating a new document
+    pub fn forget_vocabulary(&mut self) {
+        if let Some(frequencies) = self.word_frequencies.as_mut() {
+            frequencies.clear();
+        }
+    }
+
+    /// Enable the selection counters of the Grammar, counting how many times each rule is selected
+    /// by the following expansions, to report which content is actually served.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["heads".to_string(), "tails".to_string()])
+    ///     .with_counters();
+    ///
+    /// for _ in 0..10 {
+    ///     grammar.flatten().unwrap();
+    /// }
+    ///
+    /// let counters = grammar.counters().unwrap();
+    /// assert_eq!(counters.count("root", 0) + counters.count("root", 1), 10);
+    /// ```
+    pub fn with_counters(mut self) -> Self {
+        self.selection_counters = Some(SelectionCounters::default());
+        self
+    }
+
+    /// Set the output profile of the Grammar, deciding how the expansions of specific symbols are presented.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{Decoration, GrammarSyntax, OutputProfile},
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json(
+    ///     "test.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap()
+    ///     .with_output_profile(
+    ///         OutputProfile::default().with_decoration("object", Decoration::markdown_bold()),
+    ///     );
+    /// ```
+    pub fn with_output_profile(mut self, output_profile: OutputProfile) -> Self {
+        self.output_profile = output_profile;
+        self
+    }
+
+    /// Replace the random number generator of the Grammar, e.g. with a `ScriptedRng` to script its choices in tests.
+    /// Reseeding the Grammar, or flattening it with another seed, uses a generator created from the seed instead.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    pub fn with_rng<R: Into<SeededRng>>(mut self, rng: R) -> Self {
+        self.rng = rng.into();
+        self
+    }
+
+    /// Set the symbol expanded by `flatten` and the other methods expanding the grammar from its root,
+    /// instead of the first conventional root it defines (`root`, then `origin`).
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("greeting".to_string(), vec!["Hello".to_string()])
+    ///     .with_root("greeting");
+    ///
+    /// assert_eq!(grammar.root(), "greeting");
+    /// assert_eq!(grammar.flatten().unwrap(), "Hello");
+    /// ```
+    pub fn with_root(mut self, root: &str) -> Self {
+        self.root = Some(root.to_string());
+        self
+    }
+
+    /// Returns the symbol expanded by `flatten`: the one set with `with_root`, or else the first conventional root
+    /// the grammar defines (`root`, then `origin`), or `root` if it defines none
+    pub fn root(&self) -> &str {
+        match &self.root {
+            Some(root) => root,
+            None => self.conventional_root().unwrap_or(DEFAULT_ROOT_KEY),
+        }
+    }
+
+    /// Returns whether the grammar has a root symbol to expand: one set with `with_root`, or a conventional root
+    /// it defines (`root` or `origin`). A wordlist, meant to be merged into another grammar, has none.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let colours = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("colour".to_string(), vec!["red".to_string(), "blue".to_string()]);
+    /// assert!(!colours.has_root());
+    /// assert!(colours.with_root("colour").has_root());
+    /// ```
+    pub fn has_root(&self) -> bool {
+        self.root.is_some() || self.conventional_root().is_some()
+    }
+
+    /// Returns the first conventional root the grammar defines (`root`, then `origin`), if any
+    fn conventional_root(&self) -> Option<&'static str> {
+        loader::ROOT_CONVENTIONS
+            .iter()
+            .find(|name| self.has_symbol(name))
+            .copied()
+    }
+
+    /// Returns an error if the grammar has no root symbol to expand, mentioning the file it comes from, if any
+    pub(super) fn check_root(&self, file_path: Option<&str>) -> Result<(), VitrailError> {
+        if self.has_root() {
+            Ok(())
+        } else {
+            Err(VitrailError::MissingRoot {
+                path: file_path.map(str::to_string),
+            })
+        }
+    }
+
+    /// Dynamically add a symbol to the Grammar, after its construction.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json(
+    ///     "test.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap()
+    ///     .with_symbol(
+    ///         "colour".to_string(),
+    ///         vec!["red".to_string(), "blue".to_string(), "yellow".to_string()],
+    ///     );
+    /// ```
+    pub fn with_symbol(mut self, key: String, rules: Vec<String>) -> Self {
+        self.set_symbol(key, rules);
+        self
+    }
+
+    /// Set the rules of a symbol, as `with_symbol` does, on a grammar which is borrowed, e.g. by a host
+    /// application binding. The rules replace any previous definition of the symbol, file-backed or dynamic.
+    pub fn set_symbol(&mut self, key: String, rules: Vec<String>) {
+        self.sources.remove(&key);
+        self.file_symbols.remove(&key);
+        self.rule_options.remove(&key);
+        self.dynamic_symbols.remove(&key);
+        self.symbols.insert(key, rules);
+        self.invalidate_fingerprint();
+    }
+
+    /// Set the options of the rule at `index` of a symbol, e.g. its cooldown, after the Grammar construction.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    pub fn with_rule_options(mut self, symbol: &str, index: usize, options: RuleOptions) -> Self {
+        let rule_options = self.rule_options.entry(symbol.to_string()).or_default();
+        if rule_options.len() <= index {
+            rule_options.resize(index + 1, RuleOptions::default());
+        }
+        rule_options[index] = options;
+        self.invalidate_fingerprint();
+        self
+    }
+
+    /// Mark a symbol as deprecated, with a hint about its replacement, after the Grammar construction.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    pub fn with_deprecation(mut self, symbol: &str, hint: Option<&str>) -> Self {
+        self.deprecations
+            .insert(symbol.to_string(), hint.map(str::to_string));
+        self
+    }
+
+    /// Describe what a symbol is for, after the Grammar construction.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    pub fn with_description(mut self, symbol: &str, description: &str) -> Self {
+        self.descriptions
+            .insert(symbol.to_string(), description.to_string());
+        self
+    }
+
+    /// Returns the description of a symbol, if it has one
+    pub fn description(&self, symbol: &str) -> Option<&str> {
+        self.descriptions.get(symbol).map(String::as_str)
+    }
+
+    /// Dynamically add a symbol whose rules are read from an external file, after the Grammar construction.
+    /// The file is only read when the symbol is first derived.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json(
+    ///     "test.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap()
+    ///     .with_file_symbol("first_name".to_string(), "tests/data/names.txt");
+    /// ```
+    pub fn with_file_symbol<P: Into<PathBuf>>(mut self, key: String, path: P) -> Self {
+        self.sources.remove(&key);
+        self.symbols.remove(&key);
+        self.rule_options.remove(&key);
+        self.dynamic_symbols.remove(&key);
+        self.file_symbols.insert(key, path.into());
+        self.invalidate_fingerprint();
+        self
+    }
+
+    /// Dynamically add a symbol whose value is computed by a callback each time it is derived,
+    /// after the Grammar construction. The returned value is expanded like any other rule.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let hp = 42;
+    /// let mut grammar = Grammar::from_json(
+    ///     "test.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap()
+    ///     .with_dynamic_symbol("current_hp".to_string(), move || hp.to_string());
+    /// ```
+    pub fn with_dynamic_symbol<F>(self, key: String, callback: F) -> Self
+    where
+        F: Fn() -> String + 'a,
+    {
+        self.with_contextual_symbol(key, move |_| callback())
+    }
+
+    /// Dynamically add a symbol whose value is computed by a callback from the state of the expansion
+    /// (captured variables, depth, etc.) each time it is derived, after the Grammar construction.
+    /// The returned value is expanded like any other rule.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["[hero>name]{title}".to_string()])
+    ///     .with_symbol("hero".to_string(), vec!["Ada".to_string()])
+    ///     .with_contextual_symbol("title".to_string(), |context| match context.capture("name") {
+    ///         Some(name) => format!("Sir {}", name),
+    ///         None => "Nobody".to_string(),
+    ///     });
+    ///
+    /// assert_eq!(grammar.flatten().unwrap(), "Sir Ada");
+    /// ```
+    pub fn with_contextual_symbol<F>(mut self, key: String, callback: F) -> Self
+    where
+        F: Fn(&ExpansionContext) -> String + 'a,
+    {
+        self.sources.remove(&key);
+        self.symbols.remove(&key);
+        self.rule_options.remove(&key);
+        self.file_symbols.remove(&key);
+        self.dynamic_symbols.insert(key, Box::new(callback));
+        self.invalidate_fingerprint();
+        self
+    }
+
+    /// Same as `flatten`, panicking on the first expansion error the error handler does not recover from
+    pub fn flatten_unchecked(&mut self) -> String {
+        let root = self.root().to_string();
+        self.flatten_from_root_unchecked(&root)
+    }
+
+    /// Same as `flatten_from_root`, panicking on the first expansion error the error handler does not recover from
+    pub fn flatten_from_root_unchecked(&mut self, root: &str) -> String {
+        self.generate_from_root_unchecked(root).output
+    }
+
+    /// Expand the whole grammar from a given root symbol, optionally with a random number generator created
+    /// from another seed, e.g. to preview the output of a seed in an editor.
+    ///
+    /// The grammar's own random number generator is left untouched, so the following expansions draw the same
+    /// random numbers as if this one never happened. The rest of the state updated by expansions is updated as
+    /// usual though: the selections recorded by a session or a variety boost, the word frequencies and the
+    /// generation log all count this expansion, and may change the rules selected afterwards.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json("test.json", "anyrandomseed", GrammarSyntax::default()).unwrap();
+    /// let mut same = Grammar::from_json("test.json", "anyrandomseed", GrammarSyntax::default()).unwrap();
+    /// let mut other = Grammar::from_json("test.json", "other seed", GrammarSyntax::default()).unwrap();
+    ///
+    /// assert_eq!(grammar.flatten_with(Some("other seed"), "root"), other.flatten().unwrap());
+    /// assert_eq!(grammar.flatten().unwrap(), same.flatten().unwrap());
+    /// ```
+    pub fn flatten_with(&mut self, seed_override: Option<&str>, root: &str) -> String {
+        let seed = match seed_override {
+            Some(seed) => seed,
+            None => return self.flatten_from_root_unchecked(root),
+        };
+
+        let main_seed = std::mem::replace(&mut self.seed, seed.to_string());
+        let main_rng = std::mem::replace(&mut self.rng, SeededRng::new(seed));
+        let output = self.flatten_from_root_unchecked(root);
+        self.seed = main_seed;
+        self.rng = main_rng;
+
+        output
+    }
+
+    /// Same as `generate`, panicking on the first expansion error the error handler does not recover from
+    pub fn generate_unchecked(&mut self) -> GenerationResult {
+        let root = self.root().to_string();
+        self.generate_from_root_unchecked(&root)
+    }
+
+    /// Same as `generate_from_root`, panicking on the first expansion error the error handler does not recover from
+    pub fn generate_from_root_unchecked(&mut self, root: &str) -> GenerationResult {
+        self.start_expansion();
+
+        let expansion = self.expand_symbol(root);
+        let expansion = self.finish_partial_output(&expansion);
+        let output = self.output_profile.wrap_document(&expansion);
+
+        if let Some(recent) = self.recent_selections.as_mut() {
+            recent.recover();
+        }
+        if let Some(frequencies) = self.word_frequencies.as_mut() {
+            frequencies.record(&expansion);
+        }
+
+        self.log_generation(root, &output);
+
+        let mut metrics = self.metrics;
+        metrics.output_length = text::grapheme_len(&output);
+
+        GenerationResult {
+            output,
+            metrics,
+            partial: self.limit_reached.is_some(),
+        }
+    }
+
+    /// Expand the whole grammar from the default root symbol, and return the expanded string along with
+    /// the script of the rules chosen, which `flatten_with_script` replays to reproduce the same output.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json("test.json", "anyrandomseed", GrammarSyntax::default()).unwrap();
+    /// let (output, script) = grammar.flatten_recording();
+    ///
+    /// // e.g. attached to a bug report, then replayed with any seed
+    /// let mut reloaded = Grammar::from_json("test.json", "other seed", GrammarSyntax::default()).unwrap();
+    /// assert_eq!(reloaded.flatten_with_script(&script.to_string().parse().unwrap()), output);
+    /// ```
+    pub fn flatten_recording(&mut self) -> (String, ChoiceScript) {
+        self.choice_mode = ChoiceMode::Recording(Vec::new());
+        let output = self.flatten_unchecked();
+
+        match std::mem::take(&mut self.choice_mode) {
+            ChoiceMode::Recording(choices) => (output, ChoiceScript { choices }),
+            _ => (output, ChoiceScript::default()),
+        }
+    }
+
+    /// Expand the whole grammar from the default root symbol, choosing the rules listed by the script
+    /// instead of random ones.
+    ///
+    /// The values of dynamic symbols and of random built-ins (`uuid`, `hex`, `base32`) are not part of the script.
+    /// Panics if the script does not match the rules of the grammar, unless the error handler decides otherwise.
+    pub fn flatten_with_script(&mut self, script: &ChoiceScript) -> String {
+        self.choosing(
+            ChoiceMode::Replaying(script.choices.clone().into_iter()),
+            None,
+        )
+        .flatten_unchecked()
+    }
+
+    /// Expand the whole grammar from the default root symbol, looking for an output fitting the length
+    /// constraints of a platform profile.
+    ///
+    /// The grammar is flattened up to `PROFILE_ATTEMPTS` times, and the shortest output is truncated
+    /// with an ellipsis if none of them fits. The document decoration of the output profile is kept whole,
+    /// its content being truncated instead.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{GrammarSyntax, Profile},
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json(
+    ///     "test.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap();
+    ///
+    /// let post = grammar.flatten_with_profile(&Profile::Mastodon { max: 40 });
+    /// assert!(Profile::Mastodon { max: 40 }.fits(&post));
+    /// ```
+    pub fn flatten_with_profile(&mut self, profile: &Profile) -> String {
+        let mut shortest: Option<String> = None;
+        for _ in 0..PROFILE_ATTEMPTS {
+            let output = self.flatten_unchecked();
+            if profile.fits(&output) {
+                return output;
+            }
+
+            if shortest
+                .as_ref()
+                .is_none_or(|s| text::grapheme_len(&output) < text::grapheme_len(s))
+            {
+                shortest = Some(output);
+            }
+        }
+
+        self.output_profile.truncate_document(
+            &shortest.unwrap_or_default(),
+            profile.max_length(),
+            profile.ellipsis(),
+        )
+    }
+
+    /// Expand the whole grammar from the default root symbol several times, and return up to `count` outputs
+    /// which are all different from each other according to the given similarity criterion.
+    ///
+    /// Fewer outputs are returned if the grammar cannot produce enough different ones
+    /// within `count * BATCH_ATTEMPTS_PER_OUTPUT` flattens.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{GrammarSyntax, Similarity},
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json(
+    ///     "test.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap();
+    ///
+    /// let quests = grammar.flatten_batch(5, Similarity::MaxTokenOverlap(0.8));
+    /// assert!(quests.len() <= 5);
+    /// ```
+    pub fn flatten_batch(&mut self, count: usize, similarity: Similarity) -> Vec<String> {
+        let mut batch: Vec<String> = Vec::with_capacity(count);
+        for _ in 0..count * BATCH_ATTEMPTS_PER_OUTPUT {
+            if batch.len() == count {
+                break;
+            }
+
+            let output = self.flatten_unchecked();
+            if similarity.accepts(&output, &batch) {
+                batch.push(output);
+            }
+        }
+
+        batch
+    }
+
+    /// Expand the whole grammar from the default root symbol several times, and return up to `count` outputs
+    /// which are all different from each other according to the given similarity criterion, as `flatten_batch`
+    /// does, and whose tags fit the quotas: an output has the tags of the rules selected to expand it.
+    ///
+    /// Once the quota of a tag is filled, its rules are not selected again, for the symbols using a weighted
+    /// strategy, and outputs having it are discarded. The outputs are drawn in order from the random number
+    /// generator of the grammar, so the same seed gives the same batch. Fewer outputs are returned if the grammar
+    /// cannot generate enough of them within `count * BATCH_ATTEMPTS_PER_OUTPUT` flattens, and an error holding
+    /// the outputs kept if their exact quotas are not met.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{GrammarSyntax, Similarity, TagQuotas},
+    ///     grammar::{Grammar, RuleOptions},
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["Today: {post}".to_string()])
+    ///     .with_symbol(
+    ///         "post".to_string(),
+    ///         vec!["a poem".to_string(), "a joke".to_string(), "an ad".to_string()],
+    ///     )
+    ///     .with_rule_options("post", 2, RuleOptions::default().with_tag("sponsored"));
+    ///
+    /// let quotas = TagQuotas::new().with_exactly("sponsored", 1);
+    /// let schedule = grammar.flatten_batch_with_quotas(3, Similarity::Distinct, &quotas).unwrap();
+    /// assert_eq!(schedule.len(), 3);
+    /// assert!(schedule.contains(&"Today: an ad".to_string()));
+    ///
+    /// let quotas = TagQuotas::new().with_exactly("sponsored", 2);
+    /// let unmet = grammar.flatten_batch_with_quotas(3, Similarity::Distinct, &quotas).unwrap_err();
+    /// assert_eq!(unmet.tags, vec![("sponsored".to_string(), 1)]);
+    /// ```
+    pub fn flatten_batch_with_quotas(
+        &mut self,
+        count: usize,
+        similarity: Similarity,
+        quotas: &TagQuotas,
+    ) -> Result<Vec<String>, UnmetQuotas> {
+        let mut batch: Vec<String> = Vec::with_capacity(count);
+        let mut batch_tags: Vec<Vec<String>> = Vec::with_capacity(count);
+        for _ in 0..count * BATCH_ATTEMPTS_PER_OUTPUT {
+            if batch.len() == count {
+                break;
+            }
+
+            self.excluded_tags = quotas
+                .filled(&batch_tags)
+                .into_iter()
+                .map(str::to_string)
+                .collect();
+            let output = self.flatten_unchecked();
+            self.excluded_tags.clear();
+
+            let mut tags: Vec<String> = self
+                .selections
+                .iter()
+                .flat_map(|selection| self.rule_tags(selection.symbol(), selection.rule()))
+                .cloned()
+                .collect();
+            tags.sort();
+            tags.dedup();
+
+            if similarity.accepts(&output, &batch) && quotas.accepts(&tags, &batch_tags, count) {
+                batch.push(output);
+                batch_tags.push(tags);
+            }
+        }
+
+        let unmet = quotas.unmet(&batch_tags);
+        if unmet.is_empty() {
+            Ok(batch)
+        } else {
+            Err(UnmetQuotas {
+                outputs: batch,
+                tags: unmet,
+            })
+        }
+    }
+
+    /// Returns a copy of the variables captured so far, to be imported into another grammar
+    pub fn export_captures(&self) -> HashMap<String, CaptureValue> {
+        self.captures.clone()
+    }
+
+    /// Import captured variables, e.g. exported from another grammar. They replace any variable of the same name,
+    /// and can be captured again by the following expansions.
+    pub fn import_captures(&mut self, captures: &HashMap<String, CaptureValue>) {
+        for (name, value) in captures {
+            self.capture_tags.remove(name);
+            self.captures.insert(name.to_string(), value.clone());
+        }
+    }
+
+    /// Expand the whole grammar from the default root symbol, using the given captured variables,
+    /// typically exported from another grammar.
+    ///
+    /// The given variables keep their value during this expansion, even if the grammar captures them again,
+    /// so several grammars can stay consistent about the same named entities.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut world = Grammar::from_json("test.json", "world", GrammarSyntax::default()).unwrap();
+    /// world.flatten().unwrap();
+    ///
+    /// let mut event = Grammar::from_json("test.json", "event", GrammarSyntax::default()).unwrap();
+    /// let story = event.flatten_with_captures(&world.export_captures());
+    /// assert!(story.contains(&world.captures["subject"].to_string()));
+    /// ```
+    pub fn flatten_with_captures(&mut self, captures: &HashMap<String, CaptureValue>) -> String {
+        let newly_pinned: Vec<String> = captures
+            .keys()
+            .filter(|name| !self.pinned_captures.contains(*name))
+            .cloned()
+            .collect();
+
+        self.import_captures(captures);
+        self.pinned_captures.extend(newly_pinned.iter().cloned());
+
+        let output = self.flatten_unchecked();
+
+        for name in newly_pinned {
+            self.pinned_captures.remove(&name);
+        }
+
+        output
+    }
+
+    /// Expand the whole grammar from the default root symbol, with the given variables set from runtime data,
+    /// e.g. the name of the player. They are typed as captured text is, so numbers can be used in arithmetic.
+    ///
+    /// The variables keep their value during this expansion, as with `flatten_with_captures`, and are then
+    /// restored to what they were before, so they do not outlive the expansion.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["{player} finds {#gold*2} gold".to_string()]);
+    ///
+    /// let output = grammar.flatten_with_vars(&[("player", "Ada"), ("gold", "21")]);
+    /// assert_eq!(output, "Ada finds 42 gold");
+    /// assert_eq!(grammar.captured("player"), None);
+    /// ```
+    pub fn flatten_with_vars(&mut self, vars: &[(&str, &str)]) -> String {
+        let previous: Vec<(String, Option<CaptureValue>, Option<Vec<String>>)> = vars
+            .iter()
+            .map(|(name, _)| {
+                (
+                    name.to_string(),
+                    self.captures.get(*name).cloned(),
+                    self.capture_tags.get(*name).cloned(),
+                )
+            })
+            .collect();
+        let captures: HashMap<String, CaptureValue> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), CaptureValue::from_text(value)))
+            .collect();
+
+        let output = self.flatten_with_captures(&captures);
+
+        for (name, value, tags) in previous {
+            self.restore_capture(name, value, tags);
+        }
+
+        output
+    }
+
+    /// Pin a captured variable, so it keeps its current value in the following expansions,
+    /// instead of being captured again. Returns whether the variable has a value to keep: an unset variable
+    /// is not pinned.
+    pub fn pin_capture(&mut self, variable: &str) -> bool {
+        if !self.captures.contains_key(variable) {
+            return false;
+        }
+
+        self.pinned_captures.insert(variable.to_string());
+        true
+    }
+
+    /// Unpin a captured variable, so it is captured again by the following expansions
+    pub fn unpin_capture(&mut self, variable: &str) {
+        self.pinned_captures.remove(variable);
+    }
+
+    /// Same as `derive_symbol`, panicking if the symbol cannot be derived, unless the error handler decides otherwise
+    pub fn derive_symbol_unchecked(&mut self, symbol: &str) -> String {
+        // After a caught error, the expansion only winds down
+        if self.caught_error.is_some() {
+            return String::new();
+        }
+        if let Err(error) = self.check_derivation_limits() {
+            return if self.reach_limit(&error) {
+                self.placeholder(symbol)
+            } else {
+                self.handle_error(error)
+            };
+        }
+        self.metrics.expansions += 1;
+
+        if let Some(value) = self.pinned_value(symbol) {
+            return self.output_profile.escape(value);
+        }
+        if let Some(value) = self.captures.get(symbol) {
+            return value.to_string();
+        }
+
+        let derivation = self.derive_rule(symbol);
+        self.escape_rule(&derivation)
+    }
+
+    /// Escape the literal text of a rule according to the output profile, leaving its symbols
+    /// and capture expressions untouched
+    fn escape_rule(&self, rule: &str) -> String {
+        if self.output_profile.escaping == Escaping::None {
+            return rule.to_string();
+        }
+
+        let mut escaped = String::with_capacity(rule.len());
+        let mut literal_start = 0;
+        // The opening delimiters of the expressions the character is in, innermost last
+        let mut open: Vec<char> = Vec::new();
+
+        for (i, character) in token::syntax_chars(&self.syntax, rule) {
+            let closes = match open.last() {
+                None => false,
+                // Identical symbol delimiters only close the innermost expression if it is a symbol
+                Some(&innermost) if self.syntax.symbol_start == self.syntax.symbol_end => {
+                    if character == self.syntax.symbol_end {
+                        innermost == character
+                    } else {
+                        character == self.syntax.capture_end
+                    }
+                }
+                Some(_) => {
+                    character == self.syntax.symbol_end || character == self.syntax.capture_end
+                }
+            };
+
+            if closes {
+                open.pop();
+                if open.is_empty() {
+                    let end = i + character.len_utf8();
+                    escaped.push_str(&rule[literal_start..end]);
+                    literal_start = end;
+                }
+            } else if character == self.syntax.symbol_start
+                || character == self.syntax.capture_start
+            {
+                if open.is_empty() {
+                    escaped.push_str(&self.output_profile.escape(&rule[literal_start..i]));
+                    literal_start = i;
+                }
+                open.push(character);
+            }
+        }
+
+        if open.is_empty() {
+            escaped.push_str(&self.output_profile.escape(&rule[literal_start..]));
+        } else {
+            escaped.push_str(&rule[literal_start..]);
+        }
+
+        escaped
+    }
+
+    /// Returns whether the given name is a captured variable, or a symbol of any kind
+    fn has_symbol(&self, symbol: &str) -> bool {
+        self.captures.contains_key(symbol)
+            || self.symbols.contains_key(symbol)
+            || self.file_symbols.contains_key(symbol)
+            || self.dynamic_symbols.contains_key(symbol)
+    }
+
+    fn derive_rule(&mut self, symbol: &str) -> String {
+        if let Err(error) = self.load_file_symbol(symbol) {
+            return self.handle_error(error);
+        }
+
+        let derivation = match self.symbols.get(symbol).map(Vec::len) {
+            Some(0) => return self.handle_error(ExpansionError::EmptySymbol(symbol.to_string())),
+            Some(rule_count) => {
+                let depth = self.depth;
+                let index = match &mut self.choice_mode {
+                    ChoiceMode::Guided { rules, .. } => rules
+                        .get(symbol)
+                        .map(|indices| indices[depth.min(indices.len() - 1)]),
+                    ChoiceMode::Replaying(choices) => match choices.next() {
+                        Some(index) if index < rule_count => Some(index),
+                        _ => {
+                            return self
+                                .handle_error(ExpansionError::ScriptMismatch(symbol.to_string()))
+                        }
+                    },
+                    // The root symbol is the only one derived before the expansion of a rule
+                    ChoiceMode::RootRule(index) if depth == 0 => Some(*index),
+                    ChoiceMode::Random | ChoiceMode::Recording(_) | ChoiceMode::RootRule(_) => None,
+                };
+                let index = index
+                    .or_else(|| self.pinned_rule(symbol, rule_count))
+                    .unwrap_or_else(|| self.select_rule(symbol, rule_count));
+                self.record_selection(symbol, index);
+                self.symbols
+                    .get(symbol)
+                    .and_then(|rules| rules.get(index))
+                    .cloned()
+            }
+            None => self
+                .dynamic_symbols
+                .get(symbol)
+                .map(|callback| callback(&self.context())),
+        };
+
+        derivation
+            .unwrap_or_else(|| self.handle_error(ExpansionError::UnknownSymbol(symbol.to_string())))
+    }
+
+    /// Draw the index of a rule of the symbol, proportionally to its weight, as biased by the current session
+    /// and reduced if it was selected recently with variety boosting enabled. A rule cooling down in the current
+    /// session is not selected, unless every rule is.
+    fn random_rule(&mut self, symbol: &str, rule_count: usize) -> usize {
+        let weights = self.rule_weights(symbol, rule_count);
+        if weights.iter().all(|weight| *weight == 1.0) {
+            self.rng.index(rule_count)
+        } else {
+            self.rng.weighted_index(&weights)
+        }
+    }
+
+    /// Returns the weight of each rule of the symbol, as biased by the current session, vocabulary dampening
+    /// and variety boosting,
+    /// and constrained by the floors and ceilings of the rules over the session, or 0 if the plan of the
+    /// expansion excludes one of its tags, or if it is too long for the enforced length hint of its symbol
+    fn rule_weights(&self, symbol: &str, rule_count: usize) -> Vec<f64> {
+        let default_options = RuleOptions::default();
+        let options: Vec<&RuleOptions> = (0..rule_count)
+            .map(|index| {
+                self.rule_options
+                    .get(symbol)
+                    .and_then(|options| options.get(index))
+                    .unwrap_or(&default_options)
+            })
+            .collect();
+
+        let mut weights: Vec<f64> = options
+            .iter()
+            .enumerate()
+            .map(|(index, options)| {
+                if options
+                    .tags
+                    .iter()
+                    .any(|tag| self.excluded_tags.contains(tag))
+                    || self.is_oversized_rule(symbol, index)
+                {
+                    return 0.0;
+                }
+                if !validation::is_valid_weight(options.weight) {
+                    return 0.0;
+                }
+                let weight = match &self.session {
+                    Some(session) => session.rule_weight(symbol, index, options),
+                    None => options.weight,
+                };
+
+                let weight = weight * self.vocabulary_factor(symbol, index);
+                match &self.recent_selections {
+                    Some(recent) => weight * recent.factor(symbol, index),
+                    None => weight,
+                }
+            })
+            .collect();
+
+        if let Some(session) = &self.session {
+            session.constrain_weights(symbol, &mut weights, &options);
+        }
+        weights
+    }
+
+    /// Update the choice records, counters, variety factors and session after the selection of a rule
+    fn record_selection(&mut self, symbol: &str, index: usize) {
+        self.selections.push(Selection::new(symbol, index));
+        self.derivation_path.resize(self.depth, None);
+        self.derivation_path
+            .push(Some(Selection::new(symbol, index)));
+        match &mut self.choice_mode {
+            ChoiceMode::Recording(choices) => choices.push(index),
+            ChoiceMode::Guided { recorded, .. } => recorded.push(index),
+            ChoiceMode::Random | ChoiceMode::Replaying(_) | ChoiceMode::RootRule(_) => {}
+        }
+        if let Some(counters) = self.selection_counters.as_mut() {
+            counters.record(symbol, index);
+        }
+        if let Some(recent) = self.recent_selections.as_mut() {
+            recent.penalize(symbol, index);
+        }
+        if let Some(session) = self.session.as_mut() {
+            session.record(symbol, index);
+        }
+    }
+
+    /// Read the rules of every file-backed symbol, for the analyses working on the whole grammar.
+    /// An unreadable file is treated as an unknown symbol, until it is derived.
+    fn load_file_symbols(&mut self) {
+        let file_symbols: Vec<String> = self.file_symbols.keys().cloned().collect();
+        for symbol in file_symbols {
+            let _ = self.load_file_symbol(&symbol);
+        }
+    }
+
+    /// Read the rules of a file-backed symbol into the symbol table, if it has not been loaded yet.
+    /// A file which cannot be read is attempted again on the next derivation.
+    fn load_file_symbol(&mut self, symbol: &str) -> Result<(), ExpansionError> {
+        if let Some(path) = self.file_symbols.get(symbol) {
+            self.check_file_symbol_allowed(symbol)?;
+            let (rules, content) = loader::load_rules_file(path).map_err(|message| {
+                ExpansionError::UnloadableSymbol {
+                    symbol: symbol.to_string(),
+                    message,
+                }
+            })?;
+            let path = path.clone();
+            self.file_symbols.remove(symbol);
+            self.symbols.insert(symbol.to_string(), rules);
+            self.locate_rules_file(symbol, &content, &path);
+        }
+
+        Ok(())
+    }
+
+    /// Reset the depth, capture scopes, captured variables, selections, metrics, limits and pruned rules before
+    /// expanding from a root symbol
+    fn start_expansion(&mut self) {
+        self.depth = 0;
+        if let Some(scopes) = self.capture_scopes.as_mut() {
+            scopes.clear();
+        }
+        self.expansion_captures.clear();
+        self.selections.clear();
+        self.derivation_path.clear();
+        self.metrics = ExpansionMetrics::default();
+        self.limit_reached = None;
+        self.placeholders.reset();
+        self.prune_oversized_rules();
+        self.start_deadline();
+    }
+
+    /// Let the error handler decide what to do about an expansion error, and return the text
+    /// replacing the faulty expression. Panics with the error when there is no handler, or it aborts,
+    /// unless the error is caught for a method returning a `Result`, rather than its `_unchecked` counterpart.
+    pub(crate) fn handle_error(&mut self, error: ExpansionError) -> String {
+        if self.caught_error.is_some() {
+            return String::new();
+        }
+
+        // The handler is set aside while it reads the context, which borrows the grammar
+        let mut handler = self.error_handler.take();
+        let action = match handler.as_mut() {
+            Some(handler) => handler(&error, &self.context()),
+            None => ErrorAction::Abort,
+        };
+        self.error_handler = handler;
+
+        match action {
+            ErrorAction::Substitute(text) => text,
+            ErrorAction::Skip => String::new(),
+            ErrorAction::Abort => {
+                let error = VitrailError::Expansion {
+                    error,
+                    path: self.derivation_path(),
+                };
+                if !self.catching_errors {
+                    panic!("{}", error);
+                }
+                self.caught_error = Some(error);
+                String::new()
+            }
+        }
+    }
+
+    /// Expand a rule into terminal text, walking it once from left to right.
+    ///
+    /// Symbols and capture expressions are handled in the order they appear in the rule, so captures are
+    /// available to every expression on their right. Nested expressions (`{{gender}_pronoun}`) are handled first,
+    /// and their expansion becomes part of the enclosing expression. The expansions themselves are not read again,
+    /// so text they produce is never mistaken for an expression. An unterminated expression is kept as literal text.
+    fn expand(&mut self, rule: &str) -> String {
+        self.depth += 1;
+        self.metrics.max_depth = self.metrics.max_depth.max(self.depth);
+        // The captures of the root rule outlive the expansion
+        let is_branch = self.depth > 1;
+        if is_branch {
+            self.enter_capture_scope();
+        }
+
+        let expansion = self.walk_rule(rule);
+
+        if is_branch {
+            self.leave_capture_scope();
+        }
+        self.depth -= 1;
+        expansion
+    }
+
+    fn walk_rule(&mut self, rule: &str) -> String {
+        // The text left to walk, replaced by the selected branch and the rest of the rule after a switch block
+        let mut source = rule.to_string();
+        let mut cursor = 0;
+        let mut expansion = String::with_capacity(rule.len());
+        // The expressions opened and not closed yet, innermost last, with their content expanded so far
+        let mut open: Vec<(char, String)> = Vec::new();
+
+        while let Some(character) = source[cursor..].chars().next() {
+            cursor += character.len_utf8();
+
+            // An escaped delimiter is literal text, without its escape character
+            let escaped = source[cursor..]
+                .chars()
+                .next()
+                .filter(|next| character == self.syntax.escape && self.syntax.is_escapable(*next));
+            if let Some(escaped) = escaped {
+                cursor += escaped.len_utf8();
+                match open.last_mut() {
+                    Some((_, content)) => content.push(escaped),
+                    None => expansion.push(escaped),
+                }
+                continue;
+            }
+
+            let innermost = open.last().map(|(opening, _)| *opening);
+            let closes_symbol =
+                character == self.syntax.symbol_end && innermost == Some(self.syntax.symbol_start);
+            let closes_capture = character == self.syntax.capture_end
+                && innermost == Some(self.syntax.capture_start);
+
+            let replacement = if closes_symbol || closes_capture {
+                let (_, content) = open.pop().expect("the closed expression is open");
+                if closes_capture {
+                    self.capture_symbol(&content)
+                } else if let Some(variable) = block::switch_variable(&self.syntax, &content) {
+                    source = self.expand_switch(variable, &source[cursor..]);
+                    cursor = 0;
+                    String::new()
+                } else if let Some(condition) = block::if_condition(&self.syntax, &content) {
+                    source = self.expand_if(condition, &source[cursor..]);
+                    cursor = 0;
+                    String::new()
+                } else if open.is_empty()
+                    && !self.syntax.is_expression(&content)
+                    && !self.syntax.is_builtin(&content)
+                {
+                    // The output of the symbol is part of the expansion of the rule as is
+                    let completed_before = self.traced_children();
+                    let expansion_of_symbol = self.expand_expression(&content);
+                    self.trace_span(completed_before, expansion.len());
+                    expansion_of_symbol
+                } else {
+                    self.expand_expression(&content)
+                }
+            } else if character == self.syntax.symbol_start
+                || character == self.syntax.capture_start
+            {
+                open.push((character, String::new()));
+                continue;
+            } else {
+                match open.last_mut() {
+                    Some((_, content)) => content.push(character),
+                    None => expansion.push(character),
+                }
+                continue;
+            };
+
+            match open.last_mut() {
+                Some((_, content)) => content.push_str(&replacement),
+                None => expansion.push_str(&replacement),
+            }
+
+            // With partial output, the following derivations are left as placeholders until the walk ends
+            let size = expansion.len()
+                + open
+                    .iter()
+                    .map(|(_, content)| content.len() + 1)
+                    .sum::<usize>()
+                + source.len()
+                - cursor;
+            if let Err(error) = self.check_size_limit(size) {
+                if !self.reach_limit(&error) {
+                    return self.handle_error(error);
+                }
+            }
+        }
+
+        // Unterminated expressions are literal text, including the expansion of the expressions they contain
+        while let Some((opening, content)) = open.pop() {
+            let parent = match open.last_mut() {
+                Some((_, parent)) => parent,
+                None => &mut expansion,
+            };
+            parent.push(opening);
+            parent.push_str(&content);
+        }
+
+        expansion
+    }
+
+    /// Expand the content of a symbol expression, between its delimiters: an arithmetic expression,
+    /// a built-in symbol, or a symbol with its modifiers
+    fn expand_expression(&mut self, key: &str) -> String {
+        if self.syntax.is_expression(key) {
+            self.evaluate_expression(&key[self.syntax.expression_prefix.len_utf8()..])
+        } else if self.syntax.is_builtin(key) {
+            self.expand_builtin(&key[self.syntax.builtin_prefix.len_utf8()..])
+        } else {
+            self.expand_non_terminal(key)
+        }
+    }
+
+    /// Evaluate a switch block against the expanded value of a variable, and return the selected
+    /// branch followed by the rest of the rule.
+    ///
+    /// An unknown variable is treated as an empty value, so it falls back to the `default` branch.
+    fn expand_switch(&mut self, variable: &str, body: &str) -> String {
+        let block = match block::parse_switch(&self.syntax, body) {
+            Some(block) => block,
+            None => {
+                return self.handle_error(ExpansionError::UnterminatedSwitch(variable.to_string()))
+            }
+        };
+
+        let value = self.block_value(variable);
+        format!("{}{}", block.select(value.trim()), block.rest)
+    }
+
+    /// Evaluate the conditions of a conditional block in order, and return the branch of the first one holding
+    /// (or the `else` branch, if none does) followed by the rest of the rule.
+    ///
+    /// Each variable is expanded once for the whole block, so the conditions on a symbol which is not captured
+    /// test the same value. An unknown variable is treated as an empty value, as in switch blocks.
+    fn expand_if(&mut self, condition: &str, body: &str) -> String {
+        let block = match block::parse_if(&self.syntax, condition, body) {
+            Some(block) => block,
+            None => {
+                return self.handle_error(ExpansionError::UnterminatedIf(condition.to_string()))
+            }
+        };
+
+        let mut values: HashMap<&str, String> = HashMap::new();
+        let branch = block
+            .cases
+            .iter()
+            .find(|(condition, _)| {
+                let condition = block::Condition::parse(condition);
+                let value = values
+                    .entry(condition.variable)
+                    .or_insert_with(|| self.block_value(condition.variable));
+                condition.holds(value.trim())
+            })
+            .map(|(_, body)| *body)
+            .or(block.default)
+            .unwrap_or("");
+
+        format!("{}{}", branch, block.rest)
+    }
+
+    /// Returns the expanded value of the variable of a block, or an empty value if it is unknown
+    fn block_value(&mut self, variable: &str) -> String {
+        if self.has_symbol(variable) {
+            self.expand_symbol(variable)
+        } else {
+            String::new()
+        }
+    }
+
+    /// Evaluate an arithmetic expression, whose variables are symbols expanding to numbers
+    /// (typically captures), and return its formatted result
+    fn evaluate_expression(&mut self, expression: &str) -> String {
+        match self.evaluate_number(expression) {
+            Ok(value) => expression::format_number(value),
+            Err(error) => self.handle_error(error),
+        }
+    }
+
+    /// Evaluate an arithmetic expression, whose variables are numeric captures, or symbols expanding to numbers
+    fn evaluate_number(&mut self, expression: &str) -> Result<f64, ExpansionError> {
+        expression::evaluate(expression, &mut |name| {
+            if let Some(number) = self.captures.get(name).and_then(CaptureValue::as_number) {
+                return Ok(number);
+            }
+
+            let value = self.expand_symbol(name);
+            value.trim().parse::<f64>().map_err(|_| {
+                format!(
+                    "Symbol '{}' expanded to non-numeric value '{}' in an expression",
+                    name, value,
+                )
+            })
+        })
+        .map_err(|message| ExpansionError::InvalidExpression {
+            expression: expression.to_string(),
+            message,
+        })
+    }
+
+    /// Fully expand a symbol, then apply its modifiers and its output decoration to the expansion
+    fn expand_non_terminal(&mut self, symbol: &str) -> String {
+        let operator_idx = symbol.find(self.syntax.modifier_operator);
+        let key = match operator_idx {
+            Some(idx) => &symbol[0..idx],
+            None => symbol,
+        };
+
+        let modifier_calls: Vec<&str> = match operator_idx {
+            Some(idx) => call::split_calls(
+                &symbol[idx + self.syntax.modifier_operator.len_utf8()..],
+                self.syntax.modifier_operator,
+            ),
+            None => Vec::new(),
+        };
+        let modifier_names: Vec<&str> = modifier_calls.iter().map(|c| call::call_name(c)).collect();
+        if let Err(error) = self.check_modifiers(&modifier_names) {
+            return self.handle_error(error);
+        }
+
+        // A symbol expanding past its enforced length hint is expanded again, keeping its shortest expansion
+        let mut shortest: Option<String> = None;
+        let first_node = self.traced_children();
+        let mut kept = 0;
+        for attempt in 0..self.length_hint_attempts(key) {
+            let derivation = self.expand_derivation(key, &modifier_calls);
+            if self.fits_length_hint(key, &derivation)
+                || self.caught_error.is_some()
+                || self.limit_reached.is_some()
+            {
+                shortest = Some(derivation);
+                kept = attempt;
+                break;
+            }
+            if shortest
+                .as_ref()
+                .is_none_or(|s| text::grapheme_len(&derivation) < text::grapheme_len(s))
+            {
+                shortest = Some(derivation);
+                kept = attempt;
+            }
+        }
+        self.keep_traced_attempt(first_node, kept);
+
+        self.output_profile
+            .decorate(key, &shortest.unwrap_or_default())
+    }
+
+    /// Derive a symbol and expand its derivation, applying the given modifier calls to it
+    fn expand_derivation(&mut self, key: &str, modifier_calls: &[&str]) -> String {
+        let first_selection = self.selections.len();
+        let mut derivation = self.expand_symbol(key);
+        if !modifier_calls.is_empty() {
+            self.modified_tags = self.selected_tags(key, first_selection);
+            let modified = self.apply_modifier(&derivation, modifier_calls);
+            self.modified_tags.clear();
+            derivation = modified.unwrap_or_else(|error| self.handle_error(error));
+            self.trace_modifiers(modifier_calls, &derivation);
+        }
+
+        derivation
+    }
+
+    /// Returns the tags of the rule selected for a symbol derived from the given selection on,
+    /// or of the rule its value was captured from. A value pinned or set from outside the grammar has none.
+    fn selected_tags(&self, symbol: &str, first_selection: usize) -> Vec<String> {
+        if self.pinned_value(symbol).is_some() {
+            return Vec::new();
+        }
+
+        match self.selections.get(first_selection) {
+            Some(selection) if selection.symbol() == symbol => self
+                .rule_options
+                .get(symbol)
+                .and_then(|options| options.get(selection.rule()))
+                .map(|options| options.tags.clone())
+                .unwrap_or_default(),
+            _ => self.capture_tags.get(symbol).cloned().unwrap_or_default(),
+        }
+    }
+
+    /// Execute a capture expression, storing the fully expanded value of the source symbol
+    /// (or of the source rule, with `CaptureForm::VariableFirst`) as a captured variable,
+    /// so every later reference expands to the same text.
+    /// The source can also be an arithmetic expression (`[#hp-1>hp]`), whose result is captured as a number.
+    ///
+    /// Returns the text replacing the capture expression, which is empty unless the expression is invalid.
+    fn capture_symbol(&mut self, symbol: &str) -> String {
+        let (extrapolation_key, new_symbol, promoted) =
+            match capture::parse_capture(&self.syntax, symbol) {
+                Some(capture) => capture,
+                None => return self.handle_error(ExpansionError::BadCapture(symbol.to_string())),
+            };
+
+        if self.pinned_captures.contains(new_symbol) && self.captures.contains_key(new_symbol) {
+            return String::new();
+        }
+
+        let (value, tags) = if self.syntax.is_expression(extrapolation_key) {
+            let expression = &extrapolation_key[self.syntax.expression_prefix.len_utf8()..];
+            match self.evaluate_number(expression) {
+                Ok(number) => (CaptureValue::Number(number), Vec::new()),
+                Err(error) => return self.handle_error(error),
+            }
+        } else if self.syntax.capture_form == CaptureForm::VariableFirst {
+            // The symbols of the rule were expanded before the capture, as innermost expressions
+            let value = CaptureValue::from_text(&self.expand(extrapolation_key));
+            (value, Vec::new())
+        } else {
+            let first_selection = self.selections.len();
+            let value = CaptureValue::from_text(&self.expand_symbol(extrapolation_key));
+            (
+                value,
+                self.selected_tags(extrapolation_key, first_selection),
+            )
+        };
+
+        self.store_capture(new_symbol.to_string(), value, tags, promoted);
+        String::new()
+    }
+
+    /// Apply modifier calls in order to an expansion, skipping the modifiers which are not registered
+    fn apply_modifier(
+        &self,
+        symbol: &str,
+        modifier_calls: &[&str],
+    ) -> Result<String, ExpansionError> {
+        let mut modified = Cow::Borrowed(symbol);
+        for text in modifier_calls {
+            let invalid = |message| ExpansionError::InvalidModifierArguments {
+                modifier: call::call_name(text).to_string(),
+                message,
+            };
+            let call = call::parse_call(text).map_err(invalid)?;
+            if let Some(modifier) = self.modifier(call.name) {
+                modified = modifier
+                    .apply_with_arguments(modified, &call.arguments, &self.context())
+                    .map_err(invalid)?;
+            }
+        }
+
+        Ok(modified.into_owned())
+    }
+
+    /// Switch to another choice mode, and optionally another random generator, until the returned guard is
+    /// dropped, even by an expansion panicking
+    fn choosing(&mut self, choice_mode: ChoiceMode, rng: Option<SeededRng>) -> ChoiceGuard<'_, 'a> {
+        let choice_mode = std::mem::replace(&mut self.choice_mode, choice_mode);
+        let rng = rng.map(|rng| std::mem::replace(&mut self.rng, rng));
+
+        ChoiceGuard {
+            grammar: self,
+            choice_mode,
+            rng,
+        }
+    }
+}
+
+/// A grammar expanding with another choice mode, restoring its previous one (and random generator) when dropped
+struct ChoiceGuard<'g, 'a> {
+    grammar: &'g mut Grammar<'a>,
+    /// The choice mode to restore
+    choice_mode: ChoiceMode,
+    /// The random generator to restore, if it was replaced
+    rng: Option<SeededRng>,
+}
+
+impl<'a> std::ops::Deref for ChoiceGuard<'_, 'a> {
+    type Target = Grammar<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        self.grammar
+    }
+}
+
+impl std::ops::DerefMut for ChoiceGuard<'_, '_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.grammar
+    }
+}
+
+impl Drop for ChoiceGuard<'_, '_> {
+    fn drop(&mut self) {
+        self.grammar.choice_mode = std::mem::take(&mut self.choice_mode);
+        if let Some(rng) = self.rng.take() {
+            self.grammar.rng = rng;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grammar() -> Grammar<'static> {
+        Grammar::new("testseed", GrammarSyntax::default())
+    }
+
+    #[test]
+    fn closure_modifiers_borrow_their_environment() {
+        let titles: HashMap<&str, &str> = vec![("bob", "Sir")].into_iter().collect();
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["{name:title}".to_string()])
+            .with_symbol("name".to_string(), vec!["bob".to_string()])
+            .with_modifier_fn("title".to_string(), |name| match titles.get(name) {
+                Some(title) => format!("{} {}", title, name),
+                None => name.to_string(),
+            });
+
+        assert_eq!(grammar.flatten().unwrap(), "Sir bob");
+    }
+
+    #[test]
+    fn rule_is_walked_once() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["{gender:code}[word>w]{w} {{gender}_title} a} {w".to_string()],
+            )
+            .with_modifier_fn("code".to_string(), |text| format!("[{}>y]", text))
+            .with_symbol("word".to_string(), vec!["{literal".to_string()])
+            .with_symbol("gender".to_string(), vec!["f".to_string()])
+            .with_symbol("f_title".to_string(), vec!["Madam".to_string()]);
+
+        assert_eq!(grammar.flatten().unwrap(), "[f>y]{literal Madam a} {w");
+        assert!(grammar.captures.contains_key("w"));
+    }
+
+    #[test]
+    fn multiple_captures_in_one_rule() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[first>a][second>b]{a} {b}".to_string()],
+            )
+            .with_symbol("first".to_string(), vec!["one".to_string()])
+            .with_symbol("second".to_string(), vec!["two".to_string()]);
+
+        assert_eq!(grammar.flatten().unwrap(), "one two");
+    }
+
+    #[test]
+    fn runtime_vars_do_not_outlive_the_expansion() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[name>player]{player} meets {rival}".to_string()],
+            )
+            .with_symbol("name".to_string(), vec!["Bo".to_string()]);
+        grammar.import_captures(&HashMap::from([("rival".to_string(), "Cy".into())]));
+
+        assert_eq!(
+            grammar.flatten_with_vars(&[("player", "Ada"), ("rival", "Dee")]),
+            "Ada meets Dee"
+        );
+        assert!(!grammar.captures.contains_key("player"));
+        assert_eq!(grammar.captures["rival"], "Cy");
+        assert_eq!(grammar.flatten().unwrap(), "Bo meets Cy");
+    }
+
+    #[test]
+    fn scoped_captures_stay_in_their_branch() {
+        let rules = |grammar: Grammar<'static>| {
+            grammar
+                .with_symbol(
+                    "root".to_string(),
+                    vec!["[first>hero]{inner}{inner}{hero}".to_string()],
+                )
+                .with_symbol("inner".to_string(), vec!["[second>hero]{hero}".to_string()])
+                .with_symbol("first".to_string(), vec!["A".to_string()])
+                .with_symbol("second".to_string(), vec!["B".to_string()])
+        };
+
+        let mut flat = rules(Grammar::new("testseed", GrammarSyntax::default()));
+        assert_eq!(flat.flatten().unwrap(), "BBB");
+
+        let mut scoped = rules(Grammar::new("testseed", GrammarSyntax::default()))
+            .with_scoped_captures()
+            .with_symbol(
+                "inner".to_string(),
+                vec!["[second>hero]{hero}[second>>kept]".to_string()],
+            );
+        assert_eq!(scoped.flatten().unwrap(), "BBA");
+        assert_eq!(scoped.captures["hero"], "A");
+        assert_eq!(scoped.captures["kept"], "B");
+    }
+
+    #[test]
+    fn grammar_from_string() {
+        use crate::error::VitrailError;
+
+        let mut grammar = Grammar::from_json_str(
+            r#"{"root": "{a}", "a": "b"}"#,
+            "testseed",
+            GrammarSyntax::default(),
+        )
+        .unwrap();
+        assert_eq!(grammar.flatten().unwrap(), "b");
+
+        let error =
+            Grammar::from_json_str(r#"["a"]"#, "testseed", GrammarSyntax::default()).unwrap_err();
+        assert!(matches!(
+            error,
+            VitrailError::InvalidGrammar { path: None, .. }
+        ));
+        assert!(error.to_string().starts_with("Invalid grammar: "));
+
+        let error = Grammar::from_json_str(
+            r#"{"root": {"file": "/etc/passwd"}}"#,
+            "testseed",
+            GrammarSyntax::default(),
+        )
+        .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("symbol 'root' reads its rules from a file"));
+    }
+
+    #[test]
+    fn captures_without_symbols_are_processed() {
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["[first>a]done".to_string()])
+            .with_symbol("first".to_string(), vec!["one".to_string()]);
+
+        assert_eq!(grammar.flatten().unwrap(), "done");
+        assert_eq!(grammar.captured("a"), Some("one".to_string()));
+    }
+
+    #[test]
+    fn captures_are_kept_apart_from_symbols() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[first>a]{a}, [first>name]{name}".to_string()],
+            )
+            .with_symbol("first".to_string(), vec!["one".to_string()])
+            .with_symbol("name".to_string(), vec!["Ada".to_string()]);
+
+        // Capturing adds no symbol, and a captured variable takes precedence over the symbol of the same name
+        assert_eq!(grammar.flatten().unwrap(), "one, one");
+        assert!(!grammar.symbols.contains_key("a"));
+        assert_eq!(grammar.symbols["name"], vec!["Ada".to_string()]);
+        assert_eq!(grammar.captured("name"), Some("one".to_string()));
+    }
+
+    #[test]
+    fn identical_captures_are_each_executed() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[count>n]{n}[count>n]{n}".to_string()],
+            )
+            .with_symbol("count".to_string(), vec!["{digit}".to_string()])
+            .with_symbol("digit".to_string(), vec!["1".to_string(), "2".to_string()]);
+
+        let output = grammar.flatten().unwrap();
+        assert_eq!(output.len(), 2);
+        assert!(output.chars().all(|c| c == '1' || c == '2'));
+    }
+
+    #[test]
+    fn capture_after_symbol_is_ordered() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["{greeting} [first>a]{a}".to_string()],
+            )
+            .with_symbol("greeting".to_string(), vec!["hello".to_string()])
+            .with_symbol("first".to_string(), vec!["one".to_string()]);
+
+        assert_eq!(grammar.flatten().unwrap(), "hello one");
+    }
+
+    #[test]
+    fn switch_on_captured_variable() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[weather>w]{switch:w}{case:rain}wet{case:snow}cold{end} day".to_string()],
+            )
+            .with_symbol("weather".to_string(), vec!["snow".to_string()]);
+
+        assert_eq!(grammar.flatten().unwrap(), "cold day");
+    }
+
+    #[test]
+    fn switch_branch_is_expanded() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[weather>w]{switch:w}{case:rain}{wet}{default}fine{end}".to_string()],
+            )
+            .with_symbol("weather".to_string(), vec!["rain".to_string()])
+            .with_symbol("wet".to_string(), vec!["soaked".to_string()]);
+
+        assert_eq!(grammar.flatten().unwrap(), "soaked");
+    }
+
+    #[test]
+    fn switch_on_unknown_variable_uses_default() {
+        let mut grammar = grammar().with_symbol(
+            "root".to_string(),
+            vec!["{switch:missing}{case:rain}wet{default}fine{end}".to_string()],
+        );
+
+        assert_eq!(grammar.flatten().unwrap(), "fine");
+    }
+
+    #[test]
+    fn if_on_captured_variables() {
+        let mut grammar = Grammar::new("seed", GrammarSyntax::default())
+            .with_symbol(
+                "root".to_string(),
+                vec!["[gender>g]{if:g=female}she{elif:g=male}he{else}they{end} {if:g!=female}left{end}"
+                    .to_string()],
+            )
+            .with_symbol(
+                "gender".to_string(),
+                vec!["female".to_string(), "male".to_string(), "other".to_string()],
+            );
+
+        let mut outputs: Vec<String> = (0..30).map(|_| grammar.flatten().unwrap()).collect();
+        outputs.sort();
+        outputs.dedup();
+        assert_eq!(outputs, vec!["he left", "she ", "they left"]);
+    }
+
+    #[test]
+    fn if_expands_uncaptured_symbol_once() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["{if:gender=female}F{elif:gender=male}M{else}O{end}".to_string()],
+            )
+            .with_symbol(
+                "gender".to_string(),
+                vec!["female".to_string(), "male".to_string()],
+            );
+
+        let outputs: HashSet<String> = (0..200).map(|_| grammar.flatten().unwrap()).collect();
+        assert_eq!(outputs, HashSet::from(["F".to_string(), "M".to_string()]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Unterminated if block on 'g=female'")]
+    fn if_unterminated() {
+        let mut grammar = Grammar::new("seed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["{if:g=female}she".to_string()]);
+        grammar.flatten_unchecked();
+    }
+
+    #[test]
+    #[should_panic(expected = "Unterminated switch block")]
+    fn switch_unterminated() {
+        let mut grammar = grammar().with_symbol(
+            "root".to_string(),
+            vec!["{switch:w}{case:rain}wet".to_string()],
+        );
+
+        grammar.flatten_unchecked();
+    }
+
+    #[test]
+    fn escaped_delimiters_next_to_symbols() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec![r"\{{name}\} \[{name}\]\[{name}] \\{name} C:\dir {switch:name}{case:Ada}\{case\}{end}"
+                    .to_string()],
+            )
+            .with_symbol("name".to_string(), vec!["Ada".to_string()]);
+
+        assert_eq!(
+            grammar.flatten().unwrap(),
+            r"{Ada} [Ada][Ada] \Ada C:\dir {case}"
+        );
+        assert!(grammar.validate().is_empty());
+
+        let mut tracery = Grammar::new("testseed", GrammarSyntax::tracery())
+            .with_symbol("root".to_string(), vec![r"\##name#\#".to_string()])
+            .with_symbol("name".to_string(), vec!["Ada".to_string()]);
+        assert_eq!(tracery.flatten().unwrap(), "#Ada#");
+    }
+
+    #[test]
+    fn expression_over_captured_number() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[level>hero_level]{#hero_level*10+5} gold".to_string()],
+            )
+            .with_symbol("level".to_string(), vec!["3".to_string()]);
+
+        assert_eq!(grammar.flatten().unwrap(), "35 gold");
+    }
+
+    #[test]
+    #[should_panic(expected = "non-numeric value")]
+    fn expression_over_non_numeric_symbol() {
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["{#name+1}".to_string()])
+            .with_symbol("name".to_string(), vec!["Alice".to_string()]);
+
+        grammar.flatten().unwrap();
+    }
+
+    #[test]
+    fn builtin_identifiers() {
+        let mut grammar = grammar().with_symbol(
+            "root".to_string(),
+            vec!["{@uuid} {@hex:4} {@base32:6} {@hex}".to_string()],
+        );
+
+        let output = grammar.flatten().unwrap();
+        let parts: Vec<usize> = output.split(' ').map(str::len).collect();
+        assert_eq!(parts, vec![36, 4, 6, 8]);
+    }
+
+    #[test]
+    fn builtin_identifiers_are_seeded() {
+        let rules = vec!["{@uuid}".to_string()];
+        let mut first = grammar().with_symbol("root".to_string(), rules.clone());
+        let mut second = grammar().with_symbol("root".to_string(), rules);
+
+        assert_eq!(first.flatten().unwrap(), second.flatten().unwrap());
+    }
+
+    #[test]
+    #[should_panic(expected = "Unknown built-in symbol 'nope'")]
+    fn unknown_builtin() {
+        let mut grammar = grammar().with_symbol("root".to_string(), vec!["{@nope}".to_string()]);
+        grammar.flatten_unchecked();
+    }
+
+    #[test]
+    fn host_variables() {
+        let mut variables = HashMap::new();
+        variables.insert("player".to_string(), "Ada".to_string());
+
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["Hi {@host:player}".to_string()])
+            .with_host_variables("host".to_string(), &variables);
+
+        assert_eq!(grammar.flatten().unwrap(), "Hi Ada");
+    }
+
+    #[test]
+    fn included_grammars_use_derived_seeds() {
+        let included = || {
+            Grammar::new("includedseed", GrammarSyntax::default())
+                .with_symbol("root".to_string(), vec!["{pet}".to_string()])
+                .with_symbol(
+                    "pet".to_string(),
+                    vec!["cat".to_string(), "dog".to_string(), "owl".to_string()],
+                )
+        };
+        let including = |seed: &str| {
+            Grammar::new(seed, GrammarSyntax::default())
+                .with_symbol(
+                    "root".to_string(),
+                    vec!["{@pets}/{@pets:pet}/{@pets:root}/{@pets:pet}".to_string()],
+                )
+                .with_included_grammar("pets".to_string(), included())
+        };
+
+        let mut grammar = including("testseed");
+        let outputs: Vec<String> = grammar.iter().take(10).collect();
+        assert!(outputs.iter().any(|output| {
+            let pets: HashSet<&str> = output.split('/').collect();
+            pets.len() > 1
+        }));
+        assert_eq!(
+            including("testseed")
+                .iter()
+                .take(10)
+                .collect::<Vec<String>>(),
+            outputs
+        );
+        assert_ne!(
+            including("otherseed")
+                .iter()
+                .take(10)
+                .collect::<Vec<String>>(),
+            outputs
+        );
+
+        let before = included().flatten().unwrap();
+        assert_eq!(
+            grammar
+                .included_grammars
+                .get_mut("pets")
+                .unwrap()
+                .flatten()
+                .unwrap(),
+            before
+        );
+    }
+
+    #[test]
+    fn included_grammars_are_escaped_once_and_report_errors() {
+        let included = Grammar::new("includedseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["Tom & {friend}".to_string()])
+            .with_symbol("friend".to_string(), vec!["Jerry".to_string()])
+            .with_symbol("broken".to_string(), vec!["{missing}".to_string()])
+            .with_output_profile(OutputProfile::ssml());
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["{@cast} <{@cast:broken}>".to_string()],
+            )
+            .with_included_grammar("cast".to_string(), included)
+            .with_output_profile(OutputProfile::ssml())
+            .on_error(|error| ErrorAction::Substitute(format!("({})", error)));
+
+        assert_eq!(
+            grammar.flatten().unwrap(),
+            "<speak>Tom &amp; Jerry &lt;(Unable to expand. Symbol 'missing' does not exist in the ruleset.)&gt;</speak>"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Host variable 'USER' is not available from 'env'")]
+    fn environment_variable_not_allowed() {
+        let env = crate::host::EnvironmentVariables::allowing(&[]);
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["{@env:USER}".to_string()])
+            .with_host_variables("env".to_string(), &env);
+
+        grammar.flatten_unchecked();
+    }
+
+    #[test]
+    fn containers_need_the_right_key() {
+        use crate::container::{self, XorObfuscation};
+
+        let sealed = container::seal(br#"{"root": "Hello"}"#, |data| {
+            XorObfuscation::new(b"right").obfuscate(data)
+        });
+        let mut grammar = Grammar::from_container_bytes(
+            &sealed,
+            &XorObfuscation::new(b"right"),
+            "testseed",
+            GrammarSyntax::default(),
+        )
+        .unwrap();
+        assert_eq!(grammar.flatten().unwrap(), "Hello");
+
+        let error = Grammar::from_container_bytes(
+            &sealed,
+            &XorObfuscation::new(b"wrong"),
+            "testseed",
+            GrammarSyntax::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, VitrailError::InvalidGrammar { .. }));
+        assert!(error.to_string().ends_with("(is the key right?)"));
+
+        let error = Grammar::from_container(
+            "test.json",
+            &XorObfuscation::new(b"right"),
+            "testseed",
+            GrammarSyntax::default(),
+        )
+        .unwrap_err();
+        assert!(matches!(error, VitrailError::InvalidContainer { .. }));
+        assert_eq!(
+            error.to_string(),
+            "Invalid grammar container at test.json: not a grammar container"
+        );
+
+        let sealed = container::seal(br#"{"root": {"file": "test.txt"}}"#, |data| {
+            XorObfuscation::new(b"right").obfuscate(data)
+        });
+        let error = Grammar::from_container_bytes(
+            &sealed,
+            &XorObfuscation::new(b"right"),
+            "testseed",
+            GrammarSyntax::default(),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid grammar: symbol 'root' reads its rules from a file, which a sealed grammar cannot do"
+        );
+    }
+
+    #[test]
+    fn file_symbols_are_loaded_lazily() {
+        let mut grammar = Grammar::from_json(
+            "tests/data/files.json",
+            "testseed",
+            GrammarSyntax::default(),
+        )
+        .unwrap();
+        assert!(grammar.file_symbols.contains_key("name"));
+
+        let output = grammar.flatten().unwrap();
+        assert!(output.contains(" likes "));
+        assert!(!grammar.file_symbols.contains_key("name"));
+        assert_eq!(grammar.symbols["name"], vec!["Alice", "Bob", "Eve"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Unable to load symbol 'name'")]
+    fn missing_file_symbol() {
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["{name}".to_string()])
+            .with_file_symbol("name".to_string(), "tests/data/missing.txt");
+
+        grammar.flatten_unchecked();
+    }
+
+    #[test]
+    fn dynamic_symbols_are_resolved_at_flatten_time() {
+        use std::cell::Cell;
+
+        let hp = Cell::new(10);
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["HP: {current_hp}".to_string()])
+            .with_dynamic_symbol("current_hp".to_string(), || hp.get().to_string());
+
+        assert_eq!(grammar.flatten().unwrap(), "HP: 10");
+        hp.set(3);
+        assert_eq!(grammar.flatten().unwrap(), "HP: 3");
+    }
+
+    #[test]
+    fn dynamic_symbol_value_is_expanded() {
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["{status}".to_string()])
+            .with_symbol("word".to_string(), vec!["fine".to_string()])
+            .with_dynamic_symbol("status".to_string(), || "all {word}".to_string());
+
+        assert_eq!(grammar.flatten().unwrap(), "all fine");
+    }
+
+    #[test]
+    fn flatten_with_seed_override_keeps_main_stream() {
+        let rules: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let build = |seed: &str| {
+            Grammar::new(seed, GrammarSyntax::default())
+                .with_symbol("root".to_string(), vec!["{number}".to_string()])
+                .with_symbol("number".to_string(), rules.clone())
+        };
+        let mut grammar = build("main");
+        let mut reference = build("main");
+
+        let preview = grammar.flatten_with(Some("preview"), "number");
+        assert_eq!(
+            preview,
+            build("preview").flatten_from_root("number").unwrap()
+        );
+        assert_eq!(grammar.flatten_with(Some("preview"), "number"), preview);
+        assert_eq!(grammar.seed, "main");
+        assert_eq!(grammar.flatten().unwrap(), reference.flatten().unwrap());
+        assert_eq!(
+            grammar.flatten_with(None, "number"),
+            reference.flatten_from_root("number").unwrap()
+        );
+    }
+
+    #[test]
+    fn contextual_symbol_reads_expansion_state() {
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["{title}".to_string()])
+            .with_symbol("title".to_string(), vec!["{where}".to_string()])
+            .with_contextual_symbol("where".to_string(), |context| {
+                let path: Vec<&str> = context
+                    .selections()
+                    .iter()
+                    .map(|selection| selection.symbol())
+                    .collect();
+                format!("{} at depth {}", path.join(">"), context.depth())
+            });
+
+        assert_eq!(grammar.flatten().unwrap(), "root>title at depth 2");
+        assert_eq!(grammar.flatten().unwrap(), "root>title at depth 2");
+    }
+
+    #[test]
+    fn context_aware_modifier_reads_captures() {
+        use crate::modifier::Modifier;
+        use std::borrow::Cow;
+
+        struct PronounModifier {}
+        impl Modifier for PronounModifier {
+            fn apply(&self, source: &str) -> String {
+                source.to_string()
+            }
+
+            fn apply_in_context<'s>(
+                &self,
+                source: Cow<'s, str>,
+                context: &ExpansionContext,
+            ) -> Cow<'s, str> {
+                match context
+                    .capture("gender")
+                    .map(|value| value.to_string())
+                    .as_deref()
+                {
+                    Some("f") => Cow::Borrowed("she"),
+                    Some("m") => Cow::Borrowed("he"),
+                    _ => source,
+                }
+            }
+        }
+
+        let agree = PronounModifier {};
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[f>gender]{pronoun:agree} sings".to_string()],
+            )
+            .with_symbol("f".to_string(), vec!["f".to_string()])
+            .with_symbol("pronoun".to_string(), vec!["they".to_string()])
+            .with_modifier("agree".to_string(), &agree);
+
+        assert_eq!(grammar.flatten().unwrap(), "she sings");
+    }
+
+    #[test]
+    fn decorations_wrap_full_expansion() {
+        use crate::config::Decoration;
+
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["A {item}.".to_string()])
+            .with_symbol("item".to_string(), vec!["{adjective} sword".to_string()])
+            .with_symbol("adjective".to_string(), vec!["sharp".to_string()])
+            .with_output_profile(
+                OutputProfile::default().with_decoration("item", Decoration::html("em")),
+            );
+
+        assert_eq!(grammar.flatten().unwrap(), "A <em>sharp sword</em>.");
+    }
+
+    #[test]
+    fn sentence_finishing_cleans_empty_expansions() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["the {adjective} door opens {manner} . a cat enters".to_string()],
+            )
+            .with_symbol("adjective".to_string(), vec!["".to_string()])
+            .with_symbol("manner".to_string(), vec!["".to_string()])
+            .with_output_profile(OutputProfile::default().with_sentence_finishing());
+
+        assert_eq!(grammar.flatten().unwrap(), "The door opens. A cat enters.");
+    }
+
+    #[test]
+    fn modifiers_apply_to_full_expansion() {
+        use crate::modifier::CapitalizeModifier;
+
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["{item:capitalize}".to_string()])
+            .with_symbol("item".to_string(), vec!["{adjective} sword".to_string()])
+            .with_symbol("adjective".to_string(), vec!["sharp".to_string()])
+            .with_modifier("capitalize".to_string(), &CapitalizeModifier {});
+
+        assert_eq!(grammar.flatten().unwrap(), "Sharp sword");
+    }
+
+    #[test]
+    fn modifiers_never_see_the_symbols_of_their_rule() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["{item:upper}, {item:s}".to_string()],
+            )
+            .with_symbol("item".to_string(), vec!["{adjective} {noun}".to_string()])
+            .with_symbol("adjective".to_string(), vec!["sharp".to_string()])
+            .with_symbol("noun".to_string(), vec!["sword".to_string()])
+            .with_standard_modifiers();
+
+        // Applied to the rule, the modifiers would rename its symbols ({ADJECTIVE}, {noun}s)
+        assert_eq!(grammar.flatten().unwrap(), "SHARP SWORD, sharp swords");
+    }
+
+    #[test]
+    fn ssml_output() {
+        use crate::config::Decoration;
+
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["[hero>h]{h} & {h}".to_string()])
+            .with_symbol("hero".to_string(), vec!["Tom & <Jerry>".to_string()])
+            .with_output_profile(
+                OutputProfile::ssml().with_decoration("h", Decoration::ssml_emphasis("strong")),
+            );
+
+        let output = grammar.flatten().unwrap();
+        assert_eq!(
+            output,
+            "<speak><emphasis level=\"strong\">Tom &amp; &lt;Jerry&gt;</emphasis> &amp; \
+             <emphasis level=\"strong\">Tom &amp; &lt;Jerry&gt;</emphasis></speak>"
+        );
+        assert!(crate::ssml::validate(&output).is_ok());
+    }
+
+    #[test]
+    fn conventional_root_resolved_when_expanding() {
+        use crate::config::ConflictPolicy;
+
+        let mut grammar = Grammar::from_json_str(
+            r##"{"origin": "#[a:#b#]c#", "b": "x", "c": "#a##a#"}"##,
+            "testseed",
+            GrammarSyntax::tracery(),
+        )
+        .unwrap();
+        assert_eq!(grammar.root(), "origin");
+        assert_eq!(grammar.flatten().unwrap(), "xx");
+
+        assert_eq!(
+            Grammar::from_json_str(r#"{"colour": "red"}"#, "testseed", GrammarSyntax::default())
+                .unwrap_err(),
+            VitrailError::MissingRoot { path: None }
+        );
+
+        let mut words = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("colour".to_string(), vec!["red".to_string()]);
+        assert_eq!(words.root(), "root");
+        assert_eq!(
+            words.flatten(),
+            Err(VitrailError::MissingRoot { path: None })
+        );
+        assert_eq!(words.flatten_from_root("colour").unwrap(), "red");
+
+        let mut story = Grammar::from_json_str(
+            r#"{"root": "A {colour} door", "origin": "unused"}"#,
+            "testseed",
+            GrammarSyntax::default(),
+        )
+        .unwrap();
+        story.merge(words, ConflictPolicy::Error).unwrap();
+        assert_eq!(story.flatten().unwrap(), "A red door");
+    }
+
+    #[test]
+    fn tracery_syntax_with_escaping() {
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::tracery())
+            .with_symbol(
+                "root".to_string(),
+                vec!["#[pet:#animal.s#]story# & #pet#".to_string()],
+            )
+            .with_symbol("story".to_string(), vec!["<#pet#>".to_string()])
+            .with_symbol("animal".to_string(), vec!["cat".to_string()])
+            .with_standard_modifiers()
+            .with_output_profile(OutputProfile::ssml());
+
+        assert_eq!(
+            grammar.flatten().unwrap(),
+            "<speak>&lt;cats&gt; &amp; cats</speak>"
+        );
+    }
+
+    #[test]
+    fn flatten_with_profile_retries_before_truncating() {
+        let mut grammar = grammar().with_symbol(
+            "root".to_string(),
+            vec![
+                "a very long output that cannot fit".to_string(),
+                "short".to_string(),
+            ],
+        );
+
+        assert_eq!(
+            grammar.flatten_with_profile(&Profile::Mastodon { max: 10 }),
+            "short"
+        );
+    }
+
+    #[test]
+    fn flatten_with_profile_truncates() {
+        let mut grammar = grammar().with_symbol(
+            "root".to_string(),
+            vec!["a very long output that cannot fit".to_string()],
+        );
+
+        assert_eq!(
+            grammar.flatten_with_profile(&Profile::Mastodon { max: 10 }),
+            "a very…"
+        );
+
+        let mut grammar = grammar.with_output_profile(OutputProfile::ssml());
+        assert_eq!(
+            grammar.flatten_with_profile(&Profile::Mastodon { max: 25 }),
+            "<speak>a very…</speak>"
+        );
+    }
+
+    #[test]
+    fn flatten_batch_filters_similar_outputs() {
+        let mut grammar = grammar().with_symbol(
+            "root".to_string(),
+            vec![
+                "the red door".to_string(),
+                "the red doors".to_string(),
+                "a sleeping dragon".to_string(),
+            ],
+        );
+
+        let batch = grammar.flatten_batch(3, Similarity::MinEditDistance(3));
+        assert_eq!(batch.len(), 2);
+        assert!(batch.contains(&"a sleeping dragon".to_string()));
+    }
+
+    #[test]
+    fn flatten_batch_with_quotas_fills_them() {
+        use crate::config::TagQuotas;
+
+        let grammar = || {
+            Grammar::new("testseed", GrammarSyntax::default())
+                .with_symbol("root".to_string(), vec!["{post} #{@hex:4}".to_string()])
+                .with_symbol(
+                    "post".to_string(),
+                    vec!["poem".to_string(), "ad".to_string(), "gem".to_string()],
+                )
+                .with_rule_options("post", 0, RuleOptions::default().with_weight(20.0))
+                .with_rule_options("post", 1, RuleOptions::default().with_tag("sponsored"))
+                .with_rule_options("post", 2, RuleOptions::default().with_tag("rare"))
+        };
+        let quotas = TagQuotas::new()
+            .with_exactly("sponsored", 3)
+            .with_at_most("rare", 1);
+
+        let batch = grammar()
+            .flatten_batch_with_quotas(10, Similarity::Distinct, &quotas)
+            .unwrap();
+        assert_eq!(batch.len(), 10);
+        let count = |post: &str| {
+            batch
+                .iter()
+                .filter(|output| output.starts_with(post))
+                .count()
+        };
+        assert_eq!(count("ad "), 3);
+        assert!(count("gem ") <= 1);
+
+        assert_eq!(
+            grammar().flatten_batch_with_quotas(10, Similarity::Distinct, &quotas),
+            Ok(batch)
+        );
+
+        let quotas = TagQuotas::new().with_exactly("sponsored", 1);
+        let unmet = grammar()
+            .with_rule_options("post", 1, RuleOptions::default())
+            .flatten_batch_with_quotas(3, Similarity::Distinct, &quotas)
+            .unwrap_err();
+        assert_eq!(unmet.tags, vec![("sponsored".to_string(), 0)]);
+        assert!(unmet.outputs.len() <= 2);
+    }
+
+    #[test]
+    fn flatten_batch_distinct() {
+        let mut grammar = grammar().with_symbol(
+            "root".to_string(),
+            vec!["a".to_string(), "b".to_string(), "c".to_string()],
+        );
+
+        let mut batch = grammar.flatten_batch(3, Similarity::Distinct);
+        batch.sort();
+        assert_eq!(batch, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn pinned_capture_keeps_its_value() {
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["[name>hero]{hero}".to_string()])
+            .with_symbol("name".to_string(), vec!["Alice".to_string()]);
+
+        assert_eq!(grammar.flatten().unwrap(), "Alice");
+        assert!(!grammar.pin_capture("villain"));
+        assert!(!grammar.pinned_captures.contains("villain"));
+        assert!(grammar.pin_capture("hero"));
+
+        grammar = grammar.with_symbol("name".to_string(), vec!["Bob".to_string()]);
+        assert_eq!(grammar.flatten().unwrap(), "Alice");
+
+        grammar.unpin_capture("hero");
+        assert_eq!(grammar.flatten().unwrap(), "Bob");
+    }
+
+    #[test]
+    fn captures_are_shared_between_grammars() {
+        let mut world = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[name>hero]{hero} rules".to_string()],
+            )
+            .with_symbol("name".to_string(), vec!["Morgra".to_string()]);
+        world.flatten().unwrap();
+
+        let mut event = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[name>hero]{hero} wakes up".to_string()],
+            )
+            .with_symbol("name".to_string(), vec!["Alice".to_string()]);
+
+        assert_eq!(
+            event.flatten_with_captures(&world.export_captures()),
+            "Morgra wakes up"
+        );
+        assert!(event.pinned_captures.is_empty());
+        assert_eq!(event.flatten().unwrap(), "Alice wakes up");
+    }
+
+    #[test]
+    fn bulk_modifier_registration() {
+        use crate::modifier::{CapitalizeModifier, PluralizeModifier};
+
+        let mut map: HashMap<String, Box<dyn Modifier>> = HashMap::new();
+        map.insert("s".to_string(), Box::new(PluralizeModifier {}));
+
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["{animal:s:capitalize}".to_string()],
+            )
+            .with_symbol("animal".to_string(), vec!["fox".to_string()])
+            .with_modifiers(vec![(
+                "capitalize".to_string(),
+                Box::new(CapitalizeModifier {}) as Box<dyn Modifier>,
+            )])
+            .with_modifier_map(map);
+
+        assert_eq!(grammar.flatten().unwrap(), "Foxes");
+    }
+
+    #[test]
+    fn standard_modifiers() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["{animal:a:capitalize}, {animal:s}".to_string()],
+            )
+            .with_symbol("animal".to_string(), vec!["owl".to_string()])
+            .with_standard_modifiers();
+
+        assert_eq!(grammar.flatten().unwrap(), "An owl, owls");
+    }
+
+    #[test]
+    fn articles_agree_with_rule_tags() {
+        use crate::modifier::{MASS_NOUN_TAG, PLURAL_NOUN_TAG};
+
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[food>meal]{food:a}|{meal:a}|{other}".to_string()],
+            )
+            .with_symbol("food".to_string(), vec!["rice".to_string()])
+            .with_symbol("other".to_string(), vec!["{food:a}".to_string()])
+            .with_rule_options("food", 0, RuleOptions::default().with_tag(MASS_NOUN_TAG))
+            .with_standard_modifiers();
+        assert_eq!(grammar.flatten().unwrap(), "rice|rice|rice");
+
+        grammar =
+            grammar.with_rule_options("food", 0, RuleOptions::default().with_tag(PLURAL_NOUN_TAG));
+        assert_eq!(grammar.flatten().unwrap(), "some rice|some rice|some rice");
+    }
+
+    #[test]
+    fn capture_tags_do_not_outlive_their_value() {
+        use crate::modifier::MASS_NOUN_TAG;
+
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[food>meal]{meal:a}{dish}|{meal:a}".to_string()],
+            )
+            .with_symbol("dish".to_string(), vec!["[fruit>meal]".to_string()])
+            .with_symbol("food".to_string(), vec!["rice".to_string()])
+            .with_symbol("fruit".to_string(), vec!["apple".to_string()])
+            .with_rule_options("food", 0, RuleOptions::default().with_tag(MASS_NOUN_TAG))
+            .with_standard_modifiers()
+            .with_scoped_captures();
+        assert_eq!(grammar.flatten().unwrap(), "rice|rice");
+
+        assert_eq!(
+            grammar.flatten_with_vars(&[("meal", "apple")]),
+            "an apple|an apple"
+        );
+        assert_eq!(grammar.flatten().unwrap(), "rice|rice");
+
+        let mut imported = HashMap::new();
+        imported.insert("meal".to_string(), CaptureValue::from("egg"));
+        assert_eq!(grammar.flatten_with_captures(&imported), "an egg|an egg");
+
+        grammar.pin("meal", "orange");
+        assert_eq!(grammar.flatten().unwrap(), "an orange|an orange");
+    }
+
+    #[test]
+    fn modifiers_with_arguments() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec![
+                    "|{name:pad(6,'.')}|{name:pad(6, ':', right):upper}| {title:truncate(9)}"
+                        .to_string(),
+                ],
+            )
+            .with_symbol("name".to_string(), vec!["Ada".to_string()])
+            .with_symbol("title".to_string(), vec!["The long way home".to_string()])
+            .with_standard_modifiers();
+
+        assert_eq!(grammar.flatten().unwrap(), "|Ada...|:::ADA| The long…");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid arguments for modifier 'pad': invalid width 'wide'")]
+    fn modifiers_with_invalid_arguments() {
+        grammar()
+            .with_symbol("root".to_string(), vec!["{name:pad(wide)}".to_string()])
+            .with_symbol("name".to_string(), vec!["Ada".to_string()])
+            .with_standard_modifiers()
+            .flatten_unchecked();
+    }
+
+    #[test]
+    fn acronym_of_captured_name() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[org>name]The {name} ({name:acronym})".to_string()],
+            )
+            .with_symbol(
+                "org".to_string(),
+                vec!["Guild of {trade} Makers".to_string()],
+            )
+            .with_symbol("trade".to_string(), vec!["Clock".to_string()])
+            .with_standard_modifiers();
+
+        assert_eq!(
+            grammar.flatten().unwrap(),
+            "The Guild of Clock Makers (GCM)"
+        );
+    }
+
+    #[test]
+    fn set_symbol_replaces_file_and_dynamic_symbols() {
+        let mut grammar = grammar()
+            .with_file_symbol("name".to_string(), "missing.txt")
+            .with_dynamic_symbol("time".to_string(), || "noon".to_string())
+            .with_symbol("root".to_string(), vec!["{name} at {time}".to_string()]);
+        grammar.set_symbol("name".to_string(), vec!["Ada".to_string()]);
+        grammar.set_symbol("time".to_string(), vec!["dawn".to_string()]);
+
+        assert_eq!(grammar.flatten().unwrap(), "Ada at dawn");
+    }
+
+    #[test]
+    fn new_grammar_is_empty() {
+        let grammar = Grammar::default();
+        assert!(grammar.symbols.is_empty());
+        assert!(grammar.file_symbols.is_empty());
+        assert!(grammar.modifiers.is_empty());
+    }
+
+    #[test]
+    fn equality_ignores_rng_and_runtime_state() {
+        let mut first = Grammar::from_json("test.json", "seed", GrammarSyntax::default()).unwrap();
+        let second = Grammar::from_json("test.json", "other", GrammarSyntax::default())
+            .unwrap()
+            .with_standard_modifiers();
+        first.flatten().unwrap();
+
+        assert!(first == second);
+    }
+
+    #[test]
+    fn equality_compares_rules_and_syntax() {
+        let base = grammar().with_symbol("root".to_string(), vec!["a".to_string()]);
+        let reordered =
+            grammar().with_symbol("root".to_string(), vec!["a".to_string(), "b".to_string()]);
+        let other_syntax = Grammar::new(
+            "testseed",
+            GrammarSyntax {
+                symbol_start: '<',
+                ..GrammarSyntax::default()
+            },
+        )
+        .with_symbol("root".to_string(), vec!["a".to_string()]);
+
+        assert!(base != reordered);
+        assert!(base != other_syntax);
+        assert!(base.same_rules_as(&other_syntax));
+        assert_eq!(base.differing_symbols(&reordered), vec!["root"]);
+    }
+
+    #[test]
+    fn display_summary() {
+        let grammar = Grammar::from_json("test.json", "testseed", GrammarSyntax::default())
+            .unwrap()
+            .with_standard_modifiers();
+
+        assert_eq!(
+            grammar.to_string(),
+            "Grammar (seed \"testseed\"): 5 symbols, 17 rules, 13 modifiers, with root"
+        );
+    }
+
+    #[test]
+    fn display_alternate_lists_rules() {
+        let grammar = grammar()
+            .with_symbol("b".to_string(), vec!["x".to_string(), "y".to_string()])
+            .with_symbol("a".to_string(), vec!["{b}".to_string()]);
+
+        assert_eq!(
+            format!("{:#}", grammar),
+            "Grammar (seed \"testseed\"): 2 symbols, 3 rules, 0 modifiers, without root\n\
+             a\n  - {b}\nb\n  - x\n  - y"
+        );
+    }
+
+    #[test]
+    fn debug_summary() {
+        let grammar = grammar().with_symbol("root".to_string(), vec!["x".to_string()]);
+        let debug = format!("{:?}", grammar);
+
+        assert!(debug.starts_with("Grammar { seed: \"testseed\", symbols: 1, rules: 1"));
+        assert!(debug.contains("has_root: true"));
+    }
+
+    #[test]
+    fn error_handler_substitutes_placeholder() {
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["Hi {name}, {@nope}".to_string()])
+            .on_error(|_| ErrorAction::Substitute("???".to_string()));
+
+        assert_eq!(grammar.flatten().unwrap(), "Hi ???, ???");
+    }
+
+    #[test]
+    fn error_handler_sees_the_expansion_context() {
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["{missing} {line}".to_string()])
+            .with_symbol("line".to_string(), vec!["{missing}".to_string()])
+            .on_error_in_context(|_, context| {
+                let symbols: Vec<&str> = context
+                    .selections()
+                    .iter()
+                    .map(|selection| selection.symbol())
+                    .collect();
+                ErrorAction::Substitute(format!("{}:{}", context.depth(), symbols.join(">")))
+            });
+
+        assert_eq!(grammar.flatten().unwrap(), "1:root 2:root>line");
+        assert!(grammar.error_handler.is_some());
+    }
+
+    #[test]
+    fn error_handler_skips_and_sees_every_error() {
+        let mut errors = Vec::new();
+        {
+            let mut grammar = grammar()
+                .with_symbol("root".to_string(), vec!["a{name}b[oops]c{#1/}".to_string()])
+                .on_error(|error| {
+                    errors.push(error.clone());
+                    ErrorAction::Skip
+                });
+
+            assert_eq!(grammar.flatten().unwrap(), "abc");
+        }
+
+        assert_eq!(errors.len(), 3);
+        assert_eq!(errors[0], ExpansionError::UnknownSymbol("name".to_string()));
+        assert_eq!(errors[1], ExpansionError::BadCapture("oops".to_string()));
+    }
+
+    #[test]
+    fn error_handler_handles_empty_symbols() {
+        let mut errors = Vec::new();
+        {
+            let mut grammar = grammar()
+                .with_symbol("root".to_string(), vec!["a{empty}b".to_string()])
+                .with_symbol("empty".to_string(), Vec::new())
+                .on_error(|error| {
+                    errors.push(error.clone());
+                    ErrorAction::Substitute("-".to_string())
+                });
+
+            assert_eq!(grammar.flatten().unwrap(), "a-b");
+            grammar.set_strategy("empty", Strategy::Sequential);
+            assert_eq!(grammar.flatten().unwrap(), "a-b");
+        }
+
+        assert_eq!(
+            errors,
+            vec![ExpansionError::EmptySymbol("empty".to_string()); 2]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Symbol 'name' does not exist")]
+    fn error_handler_aborts() {
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["Hi {name}".to_string()])
+            .on_error(|_| ErrorAction::Abort);
+        grammar.flatten_unchecked();
+    }
+
+    #[test]
+    fn generation_metrics() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[name>hero]{hero} meets {friend}".to_string()],
+            )
+            .with_symbol("friend".to_string(), vec!["{name}".to_string()])
+            .with_symbol("name".to_string(), vec!["Ada".to_string()]);
+
+        let result = grammar.generate().unwrap();
+        assert_eq!(result.output, "Ada meets Ada");
+        assert_eq!(result.metrics.max_depth, 3);
+        assert_eq!(result.metrics.expansions, 5);
+        assert_eq!(result.metrics.output_length, 13);
+
+        assert_eq!(grammar.generate().unwrap().metrics, result.metrics);
+    }
+
+    #[test]
+    fn selection_counters() {
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["{colour} {colour}".to_string()])
+            .with_symbol("colour".to_string(), vec!["red".to_string()]);
+        grammar.flatten().unwrap();
+        assert!(grammar.counters().is_none());
+
+        grammar = grammar.with_counters();
+        grammar.flatten().unwrap();
+        grammar.flatten().unwrap();
+        assert_eq!(grammar.counters().unwrap().count("root", 0), 2);
+        assert_eq!(grammar.counters().unwrap().count("colour", 0), 4);
+
+        grammar.reset_counters();
+        assert_eq!(grammar.counters().unwrap().total("colour"), 0);
+    }
+
+    #[test]
+    fn replay_choice_script() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["{a} {b}".to_string(), "{b} {a}".to_string()],
+            )
+            .with_symbol("a".to_string(), vec!["1".to_string(), "2".to_string()])
+            .with_symbol("b".to_string(), vec!["3".to_string(), "4".to_string()]);
+
+        let script = ChoiceScript {
+            choices: vec![1, 0, 1],
+        };
+        assert_eq!(grammar.flatten_with_script(&script), "3 2");
+
+        let (output, recorded) = grammar.flatten_recording();
+        assert_eq!(recorded.choices.len(), 3);
+        grammar.reseed("another seed");
+        assert_eq!(grammar.flatten_with_script(&recorded), output);
+    }
+
+    #[test]
+    #[should_panic(expected = "Choice script does not match the rules of symbol 'a'")]
+    fn replay_mismatching_script() {
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["{a}".to_string()])
+            .with_symbol("a".to_string(), vec!["1".to_string()]);
+
+        grammar.flatten_with_script(&ChoiceScript {
+            choices: vec![0, 1],
+        });
+    }
+
+    #[test]
+    fn mismatching_scripts_restore_the_choice_mode() {
+        let mut grammar = grammar()
+            .with_symbol("root".to_string(), vec!["{a}".to_string()])
+            .with_symbol("a".to_string(), vec!["1".to_string()]);
+
+        let script = ChoiceScript {
+            choices: vec![0, 1, 0],
+        };
+        let replayed = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            grammar.flatten_with_script(&script)
+        }));
+        assert!(replayed.is_err());
+        assert!(matches!(grammar.choice_mode, ChoiceMode::Random));
+        assert_eq!(grammar.flatten().unwrap(), "1");
+    }
+
+    #[test]
+    fn variety_boost_reduces_repetitions() {
+        let rules: Vec<String> = ["ancient", "old", "weathered", "crumbling"]
+            .iter()
+            .map(|rule| rule.to_string())
+            .collect();
+        let repetitions = |grammar: &mut Grammar| {
+            let outputs: Vec<String> = (0..200).map(|_| grammar.flatten().unwrap()).collect();
+            outputs.windows(2).filter(|pair| pair[0] == pair[1]).count()
+        };
+
+        let mut plain = grammar().with_symbol("root".to_string(), rules.clone());
+        let mut boosted = grammar()
+            .with_symbol("root".to_string(), rules)
+            .with_variety_boost(VarietyBoost::default());
+
+        assert!(repetitions(&mut boosted) * 3 < repetitions(&mut plain) * 2);
+    }
+
+    #[test]
+    fn typed_captures() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec![
+                    "[hp>hp][#hp - 3>hp][alive>alive]{hp} {#hp * 2} {switch:hp}{case:<5}low{default}high{end}"
+                        .to_string(),
+                ],
+            )
+            .with_symbol("hp".to_string(), vec!["7".to_string()])
+            .with_symbol("alive".to_string(), vec!["true".to_string()]);
+
+        assert_eq!(grammar.flatten().unwrap(), "4 8 low");
+        assert_eq!(grammar.captures["hp"], CaptureValue::Number(4.0));
+        assert_eq!(grammar.captures["alive"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn captured_value_is_stable() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["[name>hero]{hero} {hero} {hero} {hero}".to_string()],
+            )
+            .with_symbol("name".to_string(), vec!["{letter}{letter}".to_string()])
+            .with_symbol(
+                "letter".to_string(),
+                vec!["a".to_string(), "b".to_string(), "c".to_string()],
+            );
+
+        let output = grammar.flatten().unwrap();
+        let parts: Vec<&str> = output.split(' ').collect();
+        assert!(parts.iter().all(|part| *part == parts[0]));
+    }
+}
@@ -0,0 +1,84 @@
+use super::Grammar;
+
+/// Endless iterator of the texts generated by a grammar from a root symbol, created by `Grammar::iter`
+/// and `Grammar::iter_from_root`, each item being the output of one more flatten
+#[derive(Debug)]
+pub struct Outputs<'g, 'a> {
+    grammar: &'g mut Grammar<'a>,
+    root: String,
+}
+
+impl Iterator for Outputs<'_, '_> {
+    type Item = String;
+
+    fn next(&mut self) -> Option<String> {
+        Some(self.grammar.flatten_from_root_unchecked(&self.root))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (usize::MAX, None)
+    }
+}
+
+impl<'a> Grammar<'a> {
+    /// Returns an endless iterator of texts flattened from the default root symbol, to be bounded with `take`,
+    /// `take_while` or `find`. It borrows the grammar, whose state advances with each item as with `flatten`.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["{n}{n}".to_string()])
+    ///     .with_symbol("n".to_string(), vec!["1".to_string(), "2".to_string()]);
+    ///
+    /// let outputs: Vec<String> = grammar.iter().take(5).collect();
+    /// assert_eq!(outputs.len(), 5);
+    ///
+    /// let double = grammar.iter().find(|output| output == "22");
+    /// assert_eq!(double.as_deref(), Some("22"));
+    /// ```
+    pub fn iter(&mut self) -> Outputs<'_, 'a> {
+        let root = self.root().to_string();
+        self.iter_from_root(&root)
+    }
+
+    /// Returns an endless iterator of texts flattened from the given root symbol
+    pub fn iter_from_root(&mut self, root: &str) -> Outputs<'_, 'a> {
+        Outputs {
+            grammar: self,
+            root: root.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+
+    fn grammar(seed: &str) -> Grammar<'static> {
+        Grammar::new(seed, GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["a {pet}".to_string()])
+            .with_symbol("other".to_string(), vec!["the {pet}".to_string()])
+            .with_symbol(
+                "pet".to_string(),
+                vec!["cat".to_string(), "dog".to_string(), "owl".to_string()],
+            )
+    }
+
+    #[test]
+    fn items_are_successive_flattens() {
+        let mut looped = grammar("testseed");
+        let expected: Vec<String> = (0..10)
+            .map(|_| looped.flatten_from_root("other").unwrap())
+            .collect();
+
+        let mut iterated = grammar("testseed");
+        let outputs: Vec<String> = iterated.iter_from_root("other").take(10).collect();
+        assert_eq!(outputs, expected);
+        assert_eq!(iterated.flatten().unwrap(), looped.flatten().unwrap());
+    }
+}
@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use super::Grammar;
+
+/// What a pinned symbol expands to, in every following expansion until it is unpinned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Pin {
+    /// The rule at the given index is always selected, its own symbols being expanded as usual
+    Rule(usize),
+    /// The symbol always expands to the given text, as a captured variable does
+    Value(String),
+}
+
+impl Grammar<'_> {
+    /// Pin a symbol to a value, so the following expansions use it instead of deriving the symbol,
+    /// e.g. for an editor to lock a name while re-rolling the rest of a generated text.
+    /// A pinned value takes precedence over the rules of the symbol, and over a captured variable of the same name.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::{Grammar, Pin},
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["{villain_name} the {title}".to_string()])
+    ///     .with_symbol("villain_name".to_string(), vec!["Grimm".to_string(), "Vex".to_string()])
+    ///     .with_symbol("title".to_string(), vec!["Cruel".to_string(), "Unseen".to_string()]);
+    ///
+    /// grammar.pin("villain_name", "Morgra");
+    /// assert!(grammar.flatten().unwrap().starts_with("Morgra the "));
+    /// assert_eq!(grammar.pins()["villain_name"], Pin::Value("Morgra".to_string()));
+    ///
+    /// grammar.clear_pins();
+    /// assert!(!grammar.flatten().unwrap().starts_with("Morgra"));
+    /// ```
+    pub fn pin(&mut self, symbol: &str, value: &str) {
+        self.pins
+            .insert(symbol.to_string(), Pin::Value(value.to_string()));
+    }
+
+    /// Pin a symbol to one of its rules, so the following expansions always select it.
+    /// The pin is ignored while the symbol has no rule at this index.
+    pub fn pin_rule(&mut self, symbol: &str, index: usize) {
+        self.pins.insert(symbol.to_string(), Pin::Rule(index));
+    }
+
+    /// Pin a symbol to the rule selected for it by the last expansion, the first one if it was derived
+    /// several times. Returns whether the last expansion derived the symbol.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["{villain_name} the {title}".to_string()])
+    ///     .with_symbol("villain_name".to_string(), vec!["Grimm".to_string(), "Vex".to_string()])
+    ///     .with_symbol("title".to_string(), vec!["Cruel".to_string(), "Unseen".to_string()]);
+    ///
+    /// let first = grammar.flatten().unwrap();
+    /// assert!(grammar.pin_last_selection("villain_name"));
+    /// let name = first.split(' ').next().unwrap();
+    /// assert!(grammar.flatten().unwrap().starts_with(name));
+    /// ```
+    pub fn pin_last_selection(&mut self, symbol: &str) -> bool {
+        let index = self
+            .selections
+            .iter()
+            .find(|selection| selection.symbol() == symbol)
+            .map(|selection| selection.rule());
+
+        match index {
+            Some(index) => {
+                self.pin_rule(symbol, index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Unpin a symbol, so the following expansions derive it as usual
+    pub fn unpin(&mut self, symbol: &str) {
+        self.pins.remove(symbol);
+    }
+
+    /// Unpin every symbol
+    pub fn clear_pins(&mut self) {
+        self.pins.clear();
+    }
+
+    /// Returns the pinned symbols, with what they are pinned to
+    pub fn pins(&self) -> &HashMap<String, Pin> {
+        &self.pins
+    }
+
+    /// Returns the index of the rule a symbol having the given number of rules is pinned to, if any
+    pub(super) fn pinned_rule(&self, symbol: &str, rule_count: usize) -> Option<usize> {
+        match self.pins.get(symbol) {
+            Some(Pin::Rule(index)) if *index < rule_count => Some(*index),
+            _ => None,
+        }
+    }
+
+    /// Returns the value a symbol is pinned to, if any
+    pub(super) fn pinned_value(&self, symbol: &str) -> Option<&str> {
+        match self.pins.get(symbol) {
+            Some(Pin::Value(value)) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+
+    fn grammar() -> Grammar<'static> {
+        Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol(
+                "root".to_string(),
+                vec!["[hero>name]{name} and {sidekick}".to_string()],
+            )
+            .with_symbol(
+                "hero".to_string(),
+                vec!["Ada".to_string(), "Bo".to_string(), "Cy".to_string()],
+            )
+            .with_symbol(
+                "sidekick".to_string(),
+                vec!["a {pet}".to_string(), "nobody".to_string()],
+            )
+            .with_symbol(
+                "pet".to_string(),
+                vec!["cat".to_string(), "dog".to_string()],
+            )
+    }
+
+    #[test]
+    fn pinned_rules_expand_their_symbols() {
+        let mut grammar = grammar();
+        grammar.pin_rule("sidekick", 0);
+        grammar.pin_rule("hero", 5);
+        for _ in 0..10 {
+            let output = grammar.flatten().unwrap();
+            assert!(output.ends_with(" and a cat") || output.ends_with(" and a dog"));
+        }
+
+        grammar.unpin("sidekick");
+        assert_eq!(grammar.pins().len(), 1);
+    }
+
+    #[test]
+    fn pinned_values_override_captures() {
+        let mut grammar = grammar();
+        grammar.pin("hero", "Morgra");
+        grammar.pin("pet", "owl");
+        grammar.pin_rule("sidekick", 0);
+        assert_eq!(grammar.flatten().unwrap(), "Morgra and a owl");
+
+        grammar.pin("name", "Zed");
+        assert_eq!(grammar.flatten().unwrap(), "Zed and a owl");
+    }
+
+    #[test]
+    fn pinned_values_are_escaped() {
+        use crate::config::OutputProfile;
+
+        let mut grammar = grammar().with_output_profile(OutputProfile::ssml());
+        grammar.pin("hero", "Tom & Jerry");
+        grammar.pin_rule("sidekick", 1);
+        assert_eq!(
+            grammar.flatten().unwrap(),
+            "<speak>Tom &amp; Jerry and nobody</speak>"
+        );
+    }
+
+    #[test]
+    fn pin_last_selection_keeps_a_part() {
+        let mut grammar = grammar();
+        let first = grammar.flatten().unwrap();
+        assert!(grammar.pin_last_selection("hero"));
+        assert!(!grammar.pin_last_selection("unknown"));
+
+        let hero = first.split(' ').next().unwrap();
+        for _ in 0..10 {
+            assert!(grammar.flatten().unwrap().starts_with(hero));
+        }
+    }
+}
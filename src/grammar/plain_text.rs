@@ -0,0 +1,387 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use super::loader::Definitions;
+use super::{Grammar, RuleOptions, Strategy};
+use crate::config::GrammarSyntax;
+use crate::error::VitrailError;
+
+/// Character starting a comment line, or a line of the description of the symbol following it
+const COMMENT_START: char = '#';
+/// Character starting an escape sequence in a rule
+const ESCAPE: char = '\\';
+/// Indentation of the rules written by `to_plain_text`
+const INDENT: &str = "    ";
+
+impl Grammar<'_> {
+    /// Same as `from_plain_text`, panicking instead of returning an error if the file cannot be read or is not a valid
+    /// grammar.
+    pub fn from_plain_text_unchecked(file_path: &str, seed: &str, syntax: GrammarSyntax) -> Self {
+        Self::from_plain_text(file_path, seed, syntax).unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Create a Grammar instance from a grammar described in a plain-text file, meant to be written by hand
+    /// without any quoting:
+    ///
+    /// ```text
+    /// # Comment lines right above a symbol describe it
+    /// root
+    ///     The {animal} is {mood}.
+    /// animal (shuffleBag)
+    ///     cat
+    ///     dog
+    /// ```
+    ///
+    /// Each symbol is named on its own line, optionally followed by the name of its strategy in parentheses,
+    /// and its rules are indented beneath it. Comment and blank lines are ignored.
+    ///
+    /// Rules are read as written, trailing whitespace excepted. A backslash keeps the whitespace following it
+    /// (`\  indented`), `\n` is a line break, `\\` is a backslash, and a backslash ending a line ends the rule,
+    /// keeping the whitespace before it: a line holding only a backslash is an empty rule. Before any other
+    /// character, the backslash is part of the rule, so `\{` is an escaped delimiter, as in the other formats.
+    ///
+    /// Returns an error if the file cannot be read or is not a valid grammar.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::from_plain_text(
+    ///     "test.txt",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap();
+    /// let same = Grammar::from_json("test.json", "anyrandomseed", GrammarSyntax::default()).unwrap();
+    /// assert!(grammar.same_rules_as(&same));
+    /// ```
+    pub fn from_plain_text(
+        file_path: &str,
+        seed: &str,
+        syntax: GrammarSyntax,
+    ) -> Result<Self, VitrailError> {
+        let content = fs::read_to_string(file_path).map_err(|error| VitrailError::Unreadable {
+            path: Some(file_path.to_string()),
+            message: error.to_string(),
+        })?;
+        let definitions =
+            parse_plain_text(&content).map_err(|message| VitrailError::InvalidGrammar {
+                path: Some(file_path.to_string()),
+                message,
+            })?;
+
+        let mut grammar = Self {
+            symbols: definitions.symbols,
+            strategies: definitions.strategies,
+            descriptions: definitions.descriptions,
+            ..Self::new(seed, syntax)
+        };
+        grammar.check_root(Some(file_path))?;
+        grammar.locate_symbols(&content, Path::new(file_path));
+        Ok(grammar)
+    }
+
+    /// Returns the symbols of the grammar in the plain-text format read by `from_plain_text`, sorted by name,
+    /// along with their strategy and description.
+    ///
+    /// Returns an error if the grammar holds what the format cannot: rule options, deprecations, length hints,
+    /// symbols whose rules are read from a file, or symbols which cannot be written on a line of their own.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["Hello, {name}!".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["world".to_string(), " padded ".to_string()])
+    ///     .with_description("name", "Who is greeted");
+    ///
+    /// assert_eq!(
+    ///     grammar.to_plain_text().unwrap(),
+    ///     "# Who is greeted\nname\n    world\n    \\ padded \\\n\nroot\n    Hello, {name}!\n",
+    /// );
+    /// ```
+    pub fn to_plain_text(&self) -> Result<String, String> {
+        if let Some(symbol) = self.file_symbols.keys().min() {
+            return Err(format!(
+                "symbol '{}' reads its rules from a file, which plain text cannot reference",
+                symbol
+            ));
+        }
+        if let Some(symbol) = self.deprecations.keys().min() {
+            return Err(format!(
+                "symbol '{}' is deprecated, which plain text cannot express",
+                symbol
+            ));
+        }
+        if let Some(symbol) = self.length_hints.keys().min() {
+            return Err(format!(
+                "symbol '{}' has a length hint, which plain text cannot express",
+                symbol
+            ));
+        }
+        let mut with_options: Vec<&String> = self
+            .rule_options
+            .iter()
+            .filter(|(_, options)| {
+                options
+                    .iter()
+                    .any(|options| *options != RuleOptions::default())
+            })
+            .map(|(symbol, _)| symbol)
+            .collect();
+        with_options.sort();
+        if let Some(symbol) = with_options.first() {
+            return Err(format!(
+                "symbol '{}' has rule options, which plain text cannot express",
+                symbol
+            ));
+        }
+
+        let mut symbols: Vec<&String> = self.symbols.keys().collect();
+        symbols.sort();
+
+        let mut text = String::new();
+        for (i, symbol) in symbols.into_iter().enumerate() {
+            let is_writable = !symbol.is_empty()
+                && symbol.trim() == symbol
+                && !symbol.starts_with(COMMENT_START)
+                && !symbol.ends_with(')')
+                && !symbol.contains('\n');
+            if !is_writable {
+                return Err(format!(
+                    "symbol '{}' cannot be named on a line of its own",
+                    symbol
+                ));
+            }
+
+            if i > 0 {
+                text.push('\n');
+            }
+            if let Some(description) = self.description(symbol) {
+                for line in description.lines() {
+                    let _ = writeln!(text, "{} {}", COMMENT_START, line);
+                }
+            }
+            match self.strategies.get(symbol.as_str()) {
+                Some(strategy) => {
+                    let _ = writeln!(text, "{} ({})", symbol, strategy);
+                }
+                None => {
+                    let _ = writeln!(text, "{}", symbol);
+                }
+            }
+            for rule in &self.symbols[symbol] {
+                let _ = writeln!(text, "{}{}", INDENT, escape_rule(rule));
+            }
+        }
+
+        Ok(text)
+    }
+}
+
+/// Read the symbol definitions of a grammar written in plain text
+pub(crate) fn parse_plain_text(content: &str) -> Result<Definitions, String> {
+    let mut definitions = Definitions::default();
+    let mut description: Vec<&str> = Vec::new();
+    let mut symbol: Option<String> = None;
+
+    for (number, line) in content.lines().enumerate() {
+        let number = number + 1;
+        if line.trim().is_empty() {
+            description.clear();
+            continue;
+        }
+
+        if line.starts_with(char::is_whitespace) {
+            let symbol = symbol
+                .as_ref()
+                .ok_or_else(|| format!("line {}: a rule must follow a symbol name", number))?;
+            let rule = unescape_rule(line.trim_start());
+            definitions
+                .symbols
+                .get_mut(symbol)
+                .expect("the symbol is defined by its name line")
+                .push(rule);
+        } else if let Some(comment) = line.strip_prefix(COMMENT_START) {
+            description.push(comment.strip_prefix(' ').unwrap_or(comment).trim_end());
+        } else {
+            let (name, strategy) = parse_symbol_line(line.trim_end())
+                .map_err(|message| format!("line {}: {}", number, message))?;
+            if definitions.symbols.contains_key(name) {
+                return Err(format!(
+                    "line {}: symbol '{}' is defined twice",
+                    number, name
+                ));
+            }
+
+            definitions.symbols.insert(name.to_string(), Vec::new());
+            if let Some(strategy) = strategy {
+                definitions.strategies.insert(name.to_string(), strategy);
+            }
+            if !description.is_empty() {
+                definitions
+                    .descriptions
+                    .insert(name.to_string(), description.join("\n"));
+                description.clear();
+            }
+            symbol = Some(name.to_string());
+        }
+    }
+
+    Ok(definitions)
+}
+
+/// Read the name of a symbol, and the strategy in parentheses following it, if any
+fn parse_symbol_line(line: &str) -> Result<(&str, Option<Strategy>), String> {
+    let (name, strategy) = match line
+        .strip_suffix(')')
+        .and_then(|line| line.rsplit_once('('))
+    {
+        Some((name, strategy)) => (name.trim_end(), Some(strategy.trim().parse()?)),
+        None => (line, None),
+    };
+
+    if name.is_empty() {
+        return Err("a symbol must have a name".to_string());
+    }
+    Ok((name, strategy))
+}
+
+/// Read the text of a rule, whose indentation was removed.
+///
+/// Only whitespace, line breaks and backslashes are escaped in plain text: before any other character,
+/// the backslash is kept for the rule itself, where it escapes the syntax delimiters.
+fn unescape_rule(line: &str) -> String {
+    let mut rule = String::with_capacity(line.len());
+    let mut chars = line.trim_end().chars();
+    while let Some(c) = chars.next() {
+        if c != ESCAPE {
+            rule.push(c);
+            continue;
+        }
+
+        // A backslash ending the line only keeps the whitespace before it
+        match chars.next() {
+            Some('n') => rule.push('\n'),
+            Some(escaped) if escaped == ESCAPE || escaped.is_whitespace() => rule.push(escaped),
+            Some(other) => {
+                rule.push(ESCAPE);
+                rule.push(other);
+            }
+            None => {}
+        }
+    }
+
+    rule
+}
+
+/// Write the text of a rule so that reading it back gives the same rule
+fn escape_rule(rule: &str) -> String {
+    let mut line = String::with_capacity(rule.len() + 2);
+    if rule.starts_with(char::is_whitespace) {
+        line.push(ESCAPE);
+    }
+    for c in rule.chars() {
+        match c {
+            '\n' => {
+                line.push(ESCAPE);
+                line.push('n');
+            }
+            ESCAPE => {
+                line.push(ESCAPE);
+                line.push(ESCAPE);
+            }
+            _ => line.push(c),
+        }
+    }
+    if rule.is_empty() || rule.ends_with(char::is_whitespace) {
+        line.push(ESCAPE);
+    }
+
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_symbols_rules_and_metadata() {
+        let definitions = parse_plain_text(
+            "# A grammar\n\n# What is said\nroot\n    Hello, {name}!\n\tBye\nname (shuffleBag)\n    world  \n",
+        )
+        .unwrap();
+
+        assert_eq!(definitions.symbols["root"], vec!["Hello, {name}!", "Bye"]);
+        assert_eq!(definitions.symbols["name"], vec!["world"]);
+        assert_eq!(definitions.strategies["name"], Strategy::ShuffleBag);
+        assert_eq!(definitions.descriptions["root"], "What is said");
+        assert_eq!(definitions.descriptions.len(), 1);
+    }
+
+    #[test]
+    fn escapes_round_trip() {
+        for rule in [
+            "",
+            " leading",
+            "trailing\t",
+            "two\nlines",
+            r"back\slash",
+            r"\{literal\}",
+            "\\",
+        ] {
+            assert_eq!(unescape_rule(&escape_rule(rule)), rule);
+        }
+        assert_eq!(unescape_rule(r"kept\ space"), "kept space");
+        assert_eq!(unescape_rule("ended \\  "), "ended ");
+        assert_eq!(unescape_rule(r"\{literal\} \\{x}"), r"\{literal\} \{x}");
+
+        let definitions = parse_plain_text("root\n    \\{{name}\\}\nname\n    Ada").unwrap();
+        let mut grammar = Grammar {
+            symbols: definitions.symbols,
+            ..Grammar::new("testseed", GrammarSyntax::default())
+        };
+        assert_eq!(grammar.flatten().unwrap(), "{Ada}");
+    }
+
+    #[test]
+    fn invalid_grammars() {
+        for content in [
+            "    rule before any symbol",
+            "root\n    a\nroot\n    b",
+            "root (random)\n    a",
+            "(uniform)\n    a",
+        ] {
+            assert!(parse_plain_text(content).is_err(), "{}", content);
+        }
+    }
+
+    #[test]
+    fn export_round_trips() {
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol(
+                "root".to_string(),
+                vec![
+                    "{a}".to_string(),
+                    String::new(),
+                    "# not a comment".to_string(),
+                ],
+            )
+            .with_symbol("a".to_string(), vec![" x ".to_string(), "y\nz".to_string()])
+            .with_description("a", "first line\nsecond line");
+        grammar.set_strategy("a", Strategy::NoRepeat(1));
+
+        let definitions = parse_plain_text(&grammar.to_plain_text().unwrap()).unwrap();
+        assert_eq!(definitions.symbols, grammar.symbols);
+        assert_eq!(definitions.strategies, grammar.strategies);
+        assert_eq!(definitions.descriptions, grammar.descriptions);
+
+        let grammar = grammar.with_deprecation("a", None);
+        assert!(grammar.to_plain_text().is_err());
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use super::Grammar;
+use crate::config::PlanConstraints;
+
+impl Grammar<'_> {
+    /// Choose a rule for each planned symbol, at random according to their weights, so that the tags of the chosen
+    /// rules break none of the exclusions of the constraints. Returns the index of the rule chosen for each symbol,
+    /// or `None` if no consistent set of rules exists, or a planned symbol has no rules.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{GrammarSyntax, PlanConstraints},
+    ///     grammar::{Grammar, RuleOptions},
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("setting".to_string(), vec!["a reef".to_string()])
+    ///     .with_symbol("scene".to_string(), vec!["a campfire".to_string(), "a shipwreck".to_string()])
+    ///     .with_rule_options("setting", 0, RuleOptions::default().with_tag("underwater"))
+    ///     .with_rule_options("scene", 0, RuleOptions::default().with_tag("campfire"));
+    ///
+    /// let constraints = PlanConstraints::new(&["setting", "scene"]).with_exclusion("underwater", "campfire");
+    /// let plan = grammar.plan(&constraints).unwrap();
+    /// assert_eq!(plan["scene"], 1);
+    /// ```
+    pub fn plan(&mut self, constraints: &PlanConstraints) -> Option<HashMap<String, usize>> {
+        let mut chosen = Vec::new();
+        if !self.search_plan(constraints, &mut chosen) {
+            return None;
+        }
+
+        Some(constraints.symbols.iter().cloned().zip(chosen).collect())
+    }
+
+    /// Expand the whole grammar from its root symbol in two stages: choose the rules of the planned symbols
+    /// as `plan` does, then flatten the grammar with these rules, never selecting a rule having a tag excluded by
+    /// the tags of the planned rules. Returns `None` if no consistent plan exists.
+    ///
+    /// Only the rules of the planned symbols are checked against each other: the rules of the other symbols are
+    /// only checked against the planned ones, and only when their symbol uses a weighted strategy.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{GrammarSyntax, PlanConstraints},
+    ///     grammar::{Grammar, RuleOptions},
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["Under {setting}, we {activity}.".to_string()])
+    ///     .with_symbol("setting".to_string(), vec!["the sea".to_string(), "the stars".to_string()])
+    ///     .with_symbol("activity".to_string(), vec!["light a campfire".to_string(), "sing".to_string()])
+    ///     .with_rule_options("setting", 0, RuleOptions::default().with_tag("underwater"))
+    ///     .with_rule_options("activity", 0, RuleOptions::default().with_tag("campfire"));
+    ///
+    /// let constraints = PlanConstraints::new(&["setting"]).with_exclusion("underwater", "campfire");
+    /// for _ in 0..10 {
+    ///     let story = grammar.flatten_planned(&constraints).unwrap();
+    ///     assert_ne!(story, "Under the sea, we light a campfire.");
+    /// }
+    /// ```
+    pub fn flatten_planned(&mut self, constraints: &PlanConstraints) -> Option<String> {
+        let plan = self.plan(constraints)?;
+
+        let tags: Vec<&String> = plan
+            .iter()
+            .flat_map(|(symbol, index)| self.rule_tags(symbol, *index))
+            .collect();
+        self.excluded_tags = constraints
+            .excluded_by(&tags)
+            .into_iter()
+            .map(str::to_string)
+            .collect();
+
+        let rules = plan
+            .into_iter()
+            .map(|(symbol, index)| (symbol, vec![index]))
+            .collect();
+        let root = self.root().to_string();
+        let (output, _) = self.guided_expansion(&root, rules);
+        self.excluded_tags.clear();
+
+        Some(output)
+    }
+
+    /// Choose a rule for the next planned symbol, after the ones already chosen, backtracking when none of its rules
+    /// is compatible with them. Returns whether every planned symbol has a rule.
+    fn search_plan(&mut self, constraints: &PlanConstraints, chosen: &mut Vec<usize>) -> bool {
+        let symbol = match constraints.symbols.get(chosen.len()) {
+            Some(symbol) => symbol.to_string(),
+            None => return true,
+        };
+        if self.load_file_symbol(&symbol).is_err() {
+            return false;
+        }
+        let rule_count = self.symbols.get(&symbol).map_or(0, Vec::len);
+
+        // The rules are tried in a random order drawn from their weights, leaving out the ones never selected
+        let mut weights = self.rule_weights(&symbol, rule_count);
+        while weights.iter().any(|weight| *weight > 0.0) {
+            let index = self.rng.weighted_index(&weights);
+            weights[index] = 0.0;
+
+            chosen.push(index);
+            let tags: Vec<&String> = constraints
+                .symbols
+                .iter()
+                .zip(chosen.iter())
+                .flat_map(|(symbol, index)| self.rule_tags(symbol, *index))
+                .collect();
+            if constraints.allows(&tags) && self.search_plan(constraints, chosen) {
+                return true;
+            }
+            chosen.pop();
+        }
+
+        false
+    }
+
+    /// Returns the tags of a rule of a symbol
+    pub(super) fn rule_tags(&self, symbol: &str, index: usize) -> &[String] {
+        self.rule_options
+            .get(symbol)
+            .and_then(|options| options.get(index))
+            .map_or(&[], |options| options.tags.as_slice())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+    use crate::grammar::RuleOptions;
+
+    #[test]
+    fn plan_backtracks_to_a_consistent_choice() {
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol(
+                "theme".to_string(),
+                vec!["sea".to_string(), "forest".to_string()],
+            )
+            .with_symbol("tone".to_string(), vec!["cozy".to_string()])
+            .with_rule_options(
+                "theme",
+                0,
+                RuleOptions::default().with_tag("wet").with_weight(100.0),
+            )
+            .with_rule_options("tone", 0, RuleOptions::default().with_tag("dry"));
+
+        let constraints = PlanConstraints::new(&["theme", "tone"]).with_exclusion("wet", "dry");
+        for _ in 0..10 {
+            let plan = grammar.plan(&constraints).unwrap();
+            assert_eq!(plan["theme"], 1);
+            assert_eq!(plan["tone"], 0);
+        }
+
+        let impossible = constraints.with_exclusion("dry", "dry");
+        assert_eq!(grammar.plan(&impossible), None);
+        assert_eq!(grammar.plan(&PlanConstraints::new(&["unknown"])), None);
+    }
+}
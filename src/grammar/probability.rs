@@ -0,0 +1,312 @@
+use std::collections::HashMap;
+
+use super::token::{self, Token};
+use super::{Grammar, Strategy};
+
+/// A symbol and the byte range of the text it is parsed against
+type Span = (String, usize, usize);
+
+/// A rule, the number of its last tokens, and the byte range of the text they are parsed against
+type Suffix<'p> = (&'p str, usize, usize, usize);
+
+/// Parse of a text against the rules of a grammar, summing the probabilities of its derivations
+struct Parse<'p, 'a> {
+    grammar: &'p Grammar<'a>,
+    text: &'p str,
+    /// The probability of each span, or `None` if it cannot be computed
+    probabilities: HashMap<Span, Option<f64>>,
+    /// The probability of the last tokens of a rule producing a range of the text, or `None` if it cannot be computed
+    suffixes: HashMap<Suffix<'p>, Option<f64>>,
+    /// The spans being parsed, with their depth, to cut derivations going round in circles
+    in_progress: HashMap<Span, usize>,
+    /// The lowest depth of the spans whose derivations were cut since the parse of the current span started.
+    /// Probabilities computed while a derivation of an outer span was cut lack its share, and are not memoized.
+    cut_depth: usize,
+}
+
+impl Grammar<'_> {
+    /// Returns the probability of the grammar producing the given text from the root symbol, under the current
+    /// weights of the rules, e.g. to tune the rarity of a "golden ticket" output.
+    ///
+    /// The text is parsed against the literal text of the rules, before escaping and decorations.
+    /// Returns `None` if a possible derivation of the text goes through an expression whose output cannot be
+    /// predicted: a modifier, a capture, a built-in, an arithmetic expression, a switch or if block, or a dynamic symbol.
+    /// Symbols using a stateful strategy are counted with their long-run frequencies: uniform for shuffle bags
+    /// and sequences, weighted for the no-repeat strategy.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::{Grammar, RuleOptions},
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["You win {prize}".to_string()])
+    ///     .with_symbol(
+    ///         "prize".to_string(),
+    ///         vec!["a cookie".to_string(), "the golden ticket".to_string()],
+    ///     )
+    ///     .with_rule_options("prize", 0, RuleOptions::default().with_weight(99.0));
+    ///
+    /// assert_eq!(grammar.probability_of("root", "You win the golden ticket"), Some(0.01));
+    /// assert_eq!(grammar.probability_of("root", "You win a car"), Some(0.0));
+    /// ```
+    pub fn probability_of(&mut self, root: &str, text: &str) -> Option<f64> {
+        self.load_file_symbols();
+
+        Parse {
+            grammar: self,
+            text,
+            probabilities: HashMap::new(),
+            suffixes: HashMap::new(),
+            in_progress: HashMap::new(),
+            cut_depth: usize::MAX,
+        }
+        .symbol(root, 0, text.len())
+    }
+
+    /// Returns the probability of selecting each rule of a symbol, in the long run, under the current weights
+    /// of its rules: the weights normalized to sum to 1, e.g. to spot a mistyped weight skewing a symbol.
+    /// Rules whose weight is negative or not finite are never selected, as reported by `Grammar::invalid_weights`.
+    /// Returns `None` if the grammar does not define the symbol.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::{Grammar, RuleOptions},
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol(
+    ///         "weather".to_string(),
+    ///         vec!["sunny".to_string(), "rainy".to_string(), "stormy".to_string()],
+    ///     )
+    ///     .with_rule_options("weather", 0, RuleOptions::default().with_weight(2.0))
+    ///     .with_rule_options("weather", 2, RuleOptions::default().with_weight(-1.0));
+    ///
+    /// assert_eq!(grammar.rule_probabilities("weather"), Some(vec![2.0 / 3.0, 1.0 / 3.0, 0.0]));
+    /// assert_eq!(grammar.rule_probabilities("climate"), None);
+    /// ```
+    pub fn rule_probabilities(&mut self, symbol: &str) -> Option<Vec<f64>> {
+        self.load_file_symbols();
+
+        let rule_count = self.symbols.get(symbol)?.len();
+        Some(self.long_run_probabilities(symbol, rule_count))
+    }
+
+    /// Returns the probability of selecting each rule of the symbol, in the long run
+    fn long_run_probabilities(&self, symbol: &str, rule_count: usize) -> Vec<f64> {
+        let weights = match self.strategy(symbol) {
+            Strategy::Weighted | Strategy::NoRepeat(_) => self.rule_weights(symbol, rule_count),
+            Strategy::Uniform | Strategy::ShuffleBag | Strategy::Sequential => {
+                vec![1.0; rule_count]
+            }
+        };
+
+        // As when drawing, rules without a positive weight are never selected, unless no rule has one
+        let total: f64 = weights.iter().filter(|weight| **weight > 0.0).sum();
+        if total <= 0.0 {
+            return vec![1.0 / rule_count as f64; rule_count];
+        }
+        weights
+            .iter()
+            .map(|weight| weight.max(0.0) / total)
+            .collect()
+    }
+}
+
+impl<'p> Parse<'p, '_> {
+    /// Returns the probability of the symbol producing the text between `start` and `end`
+    fn symbol(&mut self, symbol: &str, start: usize, end: usize) -> Option<f64> {
+        let span = (symbol.to_string(), start, end);
+        if let Some(probability) = self.probabilities.get(&span) {
+            return *probability;
+        }
+        // A derivation producing the same span again adds nothing the outer one does not cover
+        if let Some(depth) = self.in_progress.get(&span) {
+            self.cut_depth = self.cut_depth.min(*depth);
+            return Some(0.0);
+        }
+
+        let depth = self.in_progress.len();
+        self.in_progress.insert(span.clone(), depth);
+        let (probability, complete) =
+            self.memoizable(depth, |parse| parse.derive(symbol, start, end));
+        self.in_progress.remove(&span);
+        if complete {
+            self.probabilities.insert(span, probability);
+        }
+
+        probability
+    }
+
+    /// Run a parse, returning its result along with whether it can be memoized: whether none of the derivations
+    /// cut while it ran belong to spans of a depth lower than the given one
+    fn memoizable(
+        &mut self,
+        depth: usize,
+        parse: impl FnOnce(&mut Self) -> Option<f64>,
+    ) -> (Option<f64>, bool) {
+        let outer_cut_depth = std::mem::replace(&mut self.cut_depth, usize::MAX);
+        let probability = parse(self);
+        let cut_depth = self.cut_depth;
+        self.cut_depth = outer_cut_depth.min(cut_depth);
+
+        (probability, cut_depth >= depth)
+    }
+
+    fn derive(&mut self, symbol: &str, start: usize, end: usize) -> Option<f64> {
+        let grammar = self.grammar;
+        if grammar.captures.contains_key(symbol) || grammar.dynamic_symbols.contains_key(symbol) {
+            return None;
+        }
+
+        let rules = grammar.symbols.get(symbol)?;
+        let probabilities = grammar.long_run_probabilities(symbol, rules.len());
+
+        let mut total = 0.0;
+        for (rule, probability) in rules.iter().zip(probabilities) {
+            if probability > 0.0 {
+                let tokens = token::tokenize(&grammar.syntax, rule);
+                total += probability * self.tokens(rule, &tokens, start, end)?;
+            }
+        }
+
+        Some(total)
+    }
+
+    /// Returns the probability of the last tokens of a rule producing the text between `start` and `end`
+    fn tokens(&mut self, rule: &'p str, tokens: &[Token], start: usize, end: usize) -> Option<f64> {
+        let suffix = (rule, tokens.len(), start, end);
+        if let Some(probability) = self.suffixes.get(&suffix) {
+            return *probability;
+        }
+
+        let depth = self.in_progress.len();
+        let (probability, complete) =
+            self.memoizable(depth, |parse| parse.parse_tokens(rule, tokens, start, end));
+        if complete {
+            self.suffixes.insert(suffix, probability);
+        }
+
+        probability
+    }
+
+    fn parse_tokens(
+        &mut self,
+        rule: &'p str,
+        tokens: &[Token],
+        start: usize,
+        end: usize,
+    ) -> Option<f64> {
+        let (first, rest) = match tokens.split_first() {
+            Some(split) => split,
+            None => return Some(if start == end { 1.0 } else { 0.0 }),
+        };
+
+        match first {
+            Token::Text(literal) => {
+                if self.text[start..end].starts_with(literal.as_ref()) {
+                    self.tokens(rule, rest, start + literal.len(), end)
+                } else {
+                    Some(0.0)
+                }
+            }
+            Token::Symbol(expression) => {
+                let symbol = token::symbol_name(&self.grammar.syntax, expression)
+                    .filter(|symbol| symbol == expression)?;
+
+                let text = self.text;
+                let mut total = 0.0;
+                for split in (start..=end).filter(|i| text.is_char_boundary(*i)) {
+                    let probability = self.symbol(symbol, start, split)?;
+                    if probability > 0.0 {
+                        total += probability * self.tokens(rule, rest, split, end)?;
+                    }
+                }
+                Some(total)
+            }
+            Token::Capture(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+
+    fn grammar(symbols: &[(&str, &[&str])]) -> Grammar<'static> {
+        symbols.iter().fold(
+            Grammar::new("testseed", GrammarSyntax::default()),
+            |grammar, (symbol, rules)| {
+                grammar.with_symbol(
+                    symbol.to_string(),
+                    rules.iter().map(|rule| rule.to_string()).collect(),
+                )
+            },
+        )
+    }
+
+    #[test]
+    fn ambiguous_derivations_add_up() {
+        let mut grammar = grammar(&[
+            ("root", &["{a}{b}"]),
+            ("a", &["x", "xy"]),
+            ("b", &["y", "z", "yz"]),
+        ]);
+
+        let probability = grammar.probability_of("root", "xyz").unwrap();
+        assert!((probability - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(grammar.probability_of("root", "xx"), Some(0.0));
+    }
+
+    #[test]
+    fn recursive_rules() {
+        let mut grammar = grammar(&[("list", &["item", "item, {list}"])]);
+        assert_eq!(
+            grammar.probability_of("list", "item, item, item"),
+            Some(0.125)
+        );
+    }
+
+    #[test]
+    fn many_ways_to_split_the_text() {
+        // Any 15 of the 30 symbols produce the text, in about 1.5e8 ways parsed once each
+        let rule = "{a}".repeat(30);
+        let mut grammar = grammar(&[("root", &[rule.as_str()]), ("a", &["", "x"])]);
+
+        let ways: f64 = (1..=15).map(|k| (15 + k) as f64 / k as f64).product();
+        let probability = grammar.probability_of("root", &"x".repeat(15)).unwrap();
+        assert!((probability - ways / 2f64.powi(30)).abs() < 1e-9);
+    }
+
+    #[test]
+    fn strategies_use_long_run_frequencies() {
+        use crate::grammar::RuleOptions;
+
+        let mut grammar = grammar(&[("coin", &["heads", "tails"])]).with_rule_options(
+            "coin",
+            0,
+            RuleOptions::default().with_weight(3.0),
+        );
+        assert_eq!(grammar.probability_of("coin", "heads"), Some(0.75));
+        assert_eq!(grammar.rule_probabilities("coin"), Some(vec![0.75, 0.25]));
+
+        grammar.set_strategy("coin", Strategy::ShuffleBag);
+        assert_eq!(grammar.probability_of("coin", "heads"), Some(0.5));
+        assert_eq!(grammar.rule_probabilities("coin"), Some(vec![0.5, 0.5]));
+    }
+
+    #[test]
+    fn unpredictable_expressions() {
+        let mut grammar = grammar(&[
+            ("root", &["A {name:upper}", "B {@hex:2}", "C {name}"]),
+            ("name", &["x"]),
+        ]);
+
+        assert_eq!(grammar.probability_of("root", "A X"), None);
+        assert_eq!(grammar.probability_of("root", "C x"), Some(1.0 / 3.0));
+        assert_eq!(grammar.probability_of("unknown", "x"), None);
+    }
+}
@@ -0,0 +1,193 @@
+use super::Grammar;
+use crate::error::VitrailError;
+use crate::report::GenerationResult;
+
+impl Grammar<'_> {
+    /// Expand the whole grammar from a given root symbol, and return the expanded string
+    /// along with metrics about its expansion.
+    ///
+    /// Returns the first expansion error the error handler does not recover from.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     error::{ExpansionError, VitrailError},
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["Hello {name}".to_string()]);
+    ///
+    /// let error = grammar.generate_from_root("root").unwrap_err();
+    /// assert!(matches!(
+    ///     &error,
+    ///     VitrailError::Expansion { error: ExpansionError::UnknownSymbol(name), .. } if name == "name"
+    /// ));
+    /// assert_eq!(
+    ///     error.to_string(),
+    ///     "Unable to expand. Symbol 'name' does not exist in the ruleset. (in root[0])"
+    /// );
+    /// ```
+    pub fn generate_from_root(&mut self, root: &str) -> Result<GenerationResult, VitrailError> {
+        self.catch_errors(|grammar| grammar.generate_from_root_unchecked(root))
+    }
+
+    /// Expand the whole grammar from the default root symbol, and return the expanded string
+    /// along with metrics about its expansion (depth, number of expansions, length)
+    ///
+    /// Returns `VitrailError::MissingRoot` if the grammar has no root symbol, as `flatten` does.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["Hello {name}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["world".to_string()]);
+    ///
+    /// let result = grammar.generate().unwrap();
+    /// assert_eq!(result.output, "Hello world");
+    /// assert_eq!(result.metrics.max_depth, 2);
+    /// assert_eq!(result.metrics.expansions, 2);
+    /// assert_eq!(result.metrics.output_length, 11);
+    /// ```
+    pub fn generate(&mut self) -> Result<GenerationResult, VitrailError> {
+        self.check_root(None)?;
+        let root = self.root().to_string();
+        self.generate_from_root(&root)
+    }
+
+    /// Expand the whole grammar from the default root symbol, until it reaches all terminal
+    /// symbols, and return the single expanded string, or the first expansion error the error handler
+    /// does not recover from.
+    ///
+    /// Returns `VitrailError::MissingRoot` if the grammar defines no conventional root symbol (`root` or `origin`)
+    /// and none is set with `with_root`, e.g. for a wordlist.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     error::VitrailError,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut colours = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("colour".to_string(), vec!["red".to_string()]);
+    ///
+    /// assert_eq!(colours.flatten(), Err(VitrailError::MissingRoot { path: None }));
+    /// assert_eq!(colours.flatten_from_root("colour").unwrap(), "red");
+    /// ```
+    pub fn flatten(&mut self) -> Result<String, VitrailError> {
+        self.check_root(None)?;
+        let root = self.root().to_string();
+        self.flatten_from_root(&root)
+    }
+
+    /// Expand the whole grammar from a given root symbol, until it reaches all terminal
+    /// symbols, and return the single expanded string, or the first expansion error the error handler
+    /// does not recover from
+    pub fn flatten_from_root(&mut self, root: &str) -> Result<String, VitrailError> {
+        self.generate_from_root(root).map(|result| result.output)
+    }
+
+    /// Look for a non-terminal symbol, and return one of its possible expansions in its raw form (without deriving its own value).
+    ///
+    /// The value of a captured variable, already expanded, is returned as is, while pinned values and the literal
+    /// text of rules are escaped according to the output profile.
+    ///
+    /// Returns an error if the symbol cannot be found in the grammar, unless the error handler decides otherwise.
+    pub fn derive_symbol(&mut self, symbol: &str) -> Result<String, VitrailError> {
+        self.catch_errors(|grammar| grammar.derive_symbol_unchecked(symbol))
+    }
+
+    /// Run an expansion, catching the first expansion error aborting it.
+    /// An error left over by an expansion which panicked is forgotten.
+    pub(super) fn catch_errors<T>(
+        &mut self,
+        expansion: impl FnOnce(&mut Self) -> T,
+    ) -> Result<T, VitrailError> {
+        self.caught_error = None;
+        self.catching_errors = true;
+        let result = expansion(self);
+        self.catching_errors = false;
+
+        match self.caught_error.take() {
+            Some(error) => Err(error),
+            None => Ok(result),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{GrammarSyntax, SandboxOptions};
+    use crate::error::{ErrorAction, ExpansionError};
+    use crate::grammar::Selection;
+
+    fn grammar() -> Grammar<'static> {
+        Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["{a} {missing} {b}".to_string()])
+            .with_symbol("a".to_string(), vec!["A".to_string()])
+            .with_symbol("b".to_string(), vec!["B".to_string()])
+    }
+
+    #[test]
+    fn first_error_is_returned() {
+        let mut grammar = grammar();
+        assert_eq!(
+            grammar.flatten(),
+            Err(VitrailError::Expansion {
+                error: ExpansionError::UnknownSymbol("missing".to_string()),
+                path: vec![Selection::new("root", 0)],
+            })
+        );
+        assert_eq!(grammar.flatten_from_root("a"), Ok("A".to_string()));
+        assert!(grammar.derive_symbol("missing").is_err());
+    }
+
+    #[test]
+    fn empty_symbols_are_returned() {
+        let mut grammar = grammar().with_symbol("missing".to_string(), Vec::new());
+        assert_eq!(
+            grammar.flatten(),
+            Err(VitrailError::Expansion {
+                error: ExpansionError::EmptySymbol("missing".to_string()),
+                path: vec![Selection::new("root", 0)],
+            })
+        );
+    }
+
+    #[test]
+    fn error_path_leads_to_the_faulty_rule() {
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["{a}, then {creature}".to_string()])
+            .with_symbol("a".to_string(), vec!["A".to_string()])
+            .with_symbol("creature".to_string(), vec!["{a} {colr} beast".to_string()]);
+
+        let error = grammar.flatten().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unable to expand. Symbol 'colr' does not exist in the ruleset. (in root[0] -> creature[0])"
+        );
+    }
+
+    #[test]
+    fn handled_errors_are_recovered() {
+        let mut grammar = grammar().on_error(|_| ErrorAction::Skip);
+        assert_eq!(grammar.flatten(), Ok("A  B".to_string()));
+
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["{root}".to_string()])
+            .with_sandbox(SandboxOptions::default().with_max_depth(5));
+        assert!(matches!(
+            grammar.flatten(),
+            Err(VitrailError::Expansion {
+                error: ExpansionError::LimitExceeded(_),
+                ..
+            })
+        ));
+    }
+}
@@ -0,0 +1,65 @@
+use serde::Deserialize;
+
+/// Settings of a single rule, beyond its text.
+///
+/// In a JSON grammar, a rule with options is written as an object: `{"rule": "...", "weight": 2, "tags": ["spooky"]}`.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuleOptions {
+    /// The relative likelihood of the rule being selected, compared to the other rules of its symbol
+    pub weight: f64,
+    /// The labels of the rule, used to bias the weights of related rules at runtime from a `Session`
+    pub tags: Vec<String>,
+    /// The number of subsequent flattens of a `Session` during which the rule is not selected again
+    pub cooldown: usize,
+    /// The smallest share of the selections of its symbol in a `Session` the rule must have, between 0 and 1
+    pub floor: Option<f64>,
+    /// The largest share of the selections of its symbol in a `Session` the rule may have, between 0 and 1
+    pub ceiling: Option<f64>,
+}
+
+impl Default for RuleOptions {
+    fn default() -> Self {
+        Self {
+            weight: 1.0,
+            tags: Vec::new(),
+            cooldown: 0,
+            floor: None,
+            ceiling: None,
+        }
+    }
+}
+
+impl RuleOptions {
+    /// Returns the options with the given weight
+    pub fn with_weight(mut self, weight: f64) -> Self {
+        self.weight = weight;
+        self
+    }
+
+    /// Returns the options with the given tag added
+    pub fn with_tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Returns the options with the given cooldown
+    pub fn with_cooldown(mut self, cooldown: usize) -> Self {
+        self.cooldown = cooldown;
+        self
+    }
+
+    /// Returns the options with the given floor, the smallest share of the selections of the symbol
+    /// the rule must have in a `Session`, e.g. 0.1 for 10%
+    pub fn with_floor(mut self, floor: f64) -> Self {
+        self.floor = Some(floor);
+        self
+    }
+
+    /// Returns the options with the given ceiling, the largest share of the selections of the symbol
+    /// the rule may have in a `Session`, e.g. 0.05 for 5%
+    pub fn with_ceiling(mut self, ceiling: f64) -> Self {
+        self.ceiling = Some(ceiling);
+        self
+    }
+}
@@ -0,0 +1,105 @@
+use super::Grammar;
+use crate::report::{Histogram, SampleReport};
+
+impl Grammar<'_> {
+    /// Expand the grammar `samples` times from the given root symbol, and report the distribution
+    /// of the outputs and of the rules chosen for the root, to tune a grammar with quantitative feedback.
+    ///
+    /// Each sample is generated as `generate_from_root` does, output profile included. A root symbol
+    /// derived without a rule (captured, pinned or dynamic) is counted under its output.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json(
+    ///     "test.json",
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    /// ).unwrap();
+    ///
+    /// let report = grammar.sample_report("verb", 100);
+    /// println!("{}", report.to_json());
+    /// ```
+    pub fn sample_report(&mut self, root: &str, samples: usize) -> SampleReport {
+        let mut outputs = Vec::with_capacity(samples);
+        let mut root_rules = Vec::with_capacity(samples);
+
+        for _ in 0..samples {
+            let output = self.generate_from_root_unchecked(root).output;
+            let rule = match self.selections.first() {
+                Some(selection) if selection.symbol() == root => {
+                    self.symbols[root][selection.rule()].clone()
+                }
+                _ => output.clone(),
+            };
+            outputs.push(output);
+            root_rules.push(rule);
+        }
+
+        SampleReport {
+            root: root.to_string(),
+            samples,
+            outputs: Histogram::from_values(outputs),
+            root_rules: Histogram::from_values(root_rules),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+
+    #[test]
+    fn sample_report_counts() {
+        let mut grammar = Grammar::from_json("test.json", "testseed", GrammarSyntax::default())
+            .unwrap()
+            .with_symbol(
+                "root".to_string(),
+                vec!["{animal}".to_string(), "nothing".to_string()],
+            )
+            .with_symbol(
+                "animal".to_string(),
+                vec!["cat".to_string(), "dog".to_string()],
+            );
+
+        let report = grammar.sample_report("root", 200);
+        assert_eq!(report.samples, 200);
+        assert_eq!(report.root_rules.counts.len(), 2);
+        assert_eq!(
+            report.outputs.count("cat") + report.outputs.count("dog"),
+            report.root_rules.count("{animal}")
+        );
+        assert!(report.outputs.entropy > 1.0);
+    }
+
+    #[test]
+    fn sandbox_budget_applies_to_each_sample() {
+        use crate::config::SandboxOptions;
+
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["{leaf}{leaf}".to_string()])
+            .with_symbol("leaf".to_string(), vec!["x".to_string()])
+            .with_sandbox(SandboxOptions::default().with_max_expansions(3));
+
+        let report = grammar.sample_report("root", 100);
+        assert_eq!(report.outputs.count("xx"), 100);
+    }
+
+    #[test]
+    fn samples_are_wrapped_by_the_output_profile() {
+        use crate::config::OutputProfile;
+
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["{a} & {a}".to_string()])
+            .with_symbol("a".to_string(), vec!["x".to_string()])
+            .with_output_profile(OutputProfile::ssml());
+
+        let report = grammar.sample_report("root", 10);
+        assert_eq!(report.outputs.count("<speak>x &amp; x</speak>"), 10);
+        assert_eq!(report.root_rules.count("{a} & {a}"), 10);
+    }
+}
@@ -0,0 +1,531 @@
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+use std::time::Instant;
+
+use super::token::{self, Token};
+use super::{loader, Grammar};
+use crate::config::{GrammarSyntax, PartialOutput, SandboxLimit, SandboxOptions};
+use crate::error::ExpansionError;
+
+/// Marks the start of a symbol left unexpanded by a partial output, until the output is finished.
+/// Private use characters cannot be mistaken for the grammar syntax.
+const PLACEHOLDER_START: char = '\u{E000}';
+/// Marks the end of a symbol left unexpanded by a partial output
+const PLACEHOLDER_END: char = '\u{E001}';
+
+/// The symbols left unexpanded by the expansion in progress, referenced by index from its text.
+///
+/// Each reference also carries a tag drawn anew for every expansion, so text coming from the rules, captures
+/// or host variables is left alone when finishing the output, even if it contains the marker characters.
+#[derive(Debug, Default)]
+pub(super) struct Placeholders {
+    tag: String,
+    symbols: Vec<String>,
+}
+
+impl Placeholders {
+    /// Forget the symbols left by the previous expansion, and draw a new tag
+    pub(super) fn reset(&mut self) {
+        self.tag = format!("{:016x}", RandomState::new().build_hasher().finish());
+        self.symbols.clear();
+    }
+
+    /// Returns the index of the placeholder found at the start of a text, after its start marker, along with
+    /// the length of the rest of the placeholder
+    fn parse(&self, text: &str) -> Option<(usize, usize)> {
+        let index_text = text.strip_prefix(self.tag.as_str())?;
+        let end = index_text.find(PLACEHOLDER_END)?;
+        let index: usize = index_text[..end].parse().ok()?;
+
+        (index < self.symbols.len())
+            .then_some((index, self.tag.len() + end + PLACEHOLDER_END.len_utf8()))
+    }
+}
+
+impl<'a> Grammar<'a> {
+    /// Create a Grammar instance from the content of an untrusted JSON grammar, e.g. submitted by the users
+    /// of a service, and apply the sandbox to it.
+    ///
+    /// Unlike `from_json`, an invalid grammar is reported rather than panicking. The grammar is also rejected
+    /// when it is larger than the sandbox allows, has a symbol without rules, references a banned built-in,
+    /// or a rules file while the sandbox does not allow them.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{GrammarSyntax, SandboxOptions},
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let submitted = r#"{"root": "Hello {name}", "name": ["Alice", "Bob"]}"#;
+    /// let mut grammar = Grammar::from_untrusted_json(
+    ///     submitted,
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    ///     SandboxOptions::default(),
+    /// )
+    /// .unwrap();
+    /// assert!(grammar.flatten().unwrap().starts_with("Hello"));
+    ///
+    /// let submitted = r#"{"root": "Running as {@env:USER}"}"#;
+    /// assert!(Grammar::from_untrusted_json(
+    ///     submitted,
+    ///     "anyrandomseed",
+    ///     GrammarSyntax::default(),
+    ///     SandboxOptions::default(),
+    /// )
+    /// .is_err());
+    /// ```
+    pub fn from_untrusted_json(
+        content: &str,
+        seed: &str,
+        syntax: GrammarSyntax,
+        sandbox: SandboxOptions,
+    ) -> Result<Self, String> {
+        if content.len() > sandbox.max_bytes {
+            return Err(format!(
+                "the grammar exceeds the sandbox limit of {} bytes",
+                sandbox.max_bytes
+            ));
+        }
+
+        let document = loader::parse_json(content.as_bytes())
+            .map_err(|error| format!("invalid JSON: {}", error))?;
+        let definitions = loader::parse_definitions(&document, Path::new(""))?;
+
+        if !sandbox.allow_file_symbols {
+            if let Some(symbol) = definitions.file_symbols.keys().min() {
+                return Err(format!(
+                    "symbol '{}' reads its rules from a file, which the sandbox does not allow",
+                    symbol
+                ));
+            }
+        }
+
+        let mut symbols: Vec<&String> = definitions.symbols.keys().collect();
+        symbols.sort();
+        for symbol in symbols {
+            if definitions.symbols[symbol].is_empty() {
+                return Err(format!("symbol '{}' has no rules", symbol));
+            }
+            for rule in &definitions.symbols[symbol] {
+                if let Some(builtin) = Self::banned_builtin(&syntax, &sandbox, rule) {
+                    return Err(format!(
+                        "a rule of symbol '{}' uses built-in '{}', which the sandbox does not allow",
+                        symbol, builtin
+                    ));
+                }
+            }
+        }
+
+        let grammar = Self {
+            symbols: definitions.symbols,
+            file_symbols: definitions.file_symbols,
+            rule_options: definitions.rule_options,
+            deprecations: definitions.deprecations,
+            strategies: definitions.strategies,
+            descriptions: definitions.descriptions,
+            length_hints: definitions.length_hints,
+            ..Self::new(seed, syntax)
+        };
+
+        Ok(grammar.with_sandbox(sandbox))
+    }
+
+    /// Apply sandbox limits to the following expansions of the Grammar: reaching one of them, or using a banned
+    /// built-in or a file-backed symbol while they are not allowed, is an expansion error.
+    ///
+    /// An expansion error panics without an error handler, so a service would typically install one
+    /// with `on_error`, which is called for every expression left once a limit is reached.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::{GrammarSyntax, SandboxOptions},
+    ///     error::ErrorAction,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["very {root}".to_string()])
+    ///     .with_sandbox(SandboxOptions::default().with_max_depth(3))
+    ///     .on_error(|_| ErrorAction::Skip);
+    ///
+    /// assert_eq!(grammar.flatten().unwrap(), "very very very ");
+    /// ```
+    pub fn with_sandbox(mut self, sandbox: SandboxOptions) -> Self {
+        self.sandbox = Some(sandbox);
+        self
+    }
+
+    /// Returns the sandbox limits applied to the expansions, if any
+    pub fn sandbox(&self) -> Option<&SandboxOptions> {
+        self.sandbox.as_ref()
+    }
+
+    /// Returns the first built-in used by a rule which the sandbox bans, if any
+    fn banned_builtin<'r>(
+        syntax: &GrammarSyntax,
+        sandbox: &SandboxOptions,
+        rule: &'r str,
+    ) -> Option<&'r str> {
+        token::tokenize(syntax, rule)
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Symbol(expression) if syntax.is_builtin(expression) => expression
+                    [syntax.builtin_prefix.len_utf8()..]
+                    .split(syntax.modifier_operator)
+                    .next(),
+                _ => None,
+            })
+            .find(|name| sandbox.bans_builtin(name))
+    }
+
+    /// Start the time limit of a new expansion, if the grammar is sandboxed
+    pub(super) fn start_deadline(&mut self) {
+        self.deadline = self
+            .sandbox
+            .as_ref()
+            .map(|sandbox| Instant::now() + sandbox.time_limit);
+    }
+
+    /// Check the depth, number of expansions and duration of the expansion in progress against the sandbox
+    /// and the expansion limits, before deriving one more symbol
+    pub(super) fn check_derivation_limits(&self) -> Result<(), ExpansionError> {
+        if self.sandbox.is_none() && self.limits.is_none() {
+            return Ok(());
+        }
+        let smallest = |sandbox: Option<usize>, limits: Option<usize>| match (sandbox, limits) {
+            (Some(sandbox), Some(limits)) => Some(sandbox.min(limits)),
+            (sandbox, limits) => sandbox.or(limits),
+        };
+        let max_depth = smallest(
+            self.sandbox.as_ref().map(|sandbox| sandbox.max_depth),
+            self.limits.as_ref().and_then(|limits| limits.max_depth),
+        );
+        let max_expansions = smallest(
+            self.sandbox.as_ref().map(|sandbox| sandbox.max_expansions),
+            self.limits
+                .as_ref()
+                .and_then(|limits| limits.max_expansions),
+        );
+
+        if let Some(limit) = self.limit_reached {
+            Err(ExpansionError::LimitExceeded(limit))
+        } else if let Some(max_depth) = max_depth.filter(|max_depth| self.depth >= *max_depth) {
+            Err(ExpansionError::LimitExceeded(SandboxLimit::Depth(
+                max_depth,
+            )))
+        } else if let Some(max_expansions) =
+            max_expansions.filter(|max_expansions| self.metrics.expansions >= *max_expansions)
+        {
+            Err(ExpansionError::LimitExceeded(SandboxLimit::Expansions(
+                max_expansions,
+            )))
+        } else if let Some(sandbox) = self.sandbox.as_ref().filter(|_| {
+            self.deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        }) {
+            Err(ExpansionError::LimitExceeded(SandboxLimit::Time(
+                sandbox.time_limit,
+            )))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Check the size of a text being expanded, or about to be generated, against the sandbox
+    pub(super) fn check_size_limit(&self, bytes: usize) -> Result<(), ExpansionError> {
+        match &self.sandbox {
+            Some(sandbox) if bytes > sandbox.max_bytes => Err(ExpansionError::LimitExceeded(
+                SandboxLimit::Bytes(sandbox.max_bytes),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Record a limit reached by the expansion in progress, when the sandbox produces partial outputs
+    /// or the expansion limits truncate it. Returns whether they do, so the symbols left are turned into
+    /// placeholders rather than errors.
+    pub(super) fn reach_limit(&mut self, error: &ExpansionError) -> bool {
+        let partial = self
+            .sandbox
+            .as_ref()
+            .is_some_and(|sandbox| sandbox.partial_output.is_some())
+            || self
+                .limits
+                .as_ref()
+                .is_some_and(|limits| limits.truncation_marker.is_some());
+
+        match error {
+            ExpansionError::LimitExceeded(limit) if partial => {
+                self.limit_reached.get_or_insert(*limit);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Returns the placeholder of a symbol left unexpanded, rendered by `finish_partial_output`
+    pub(super) fn placeholder(&mut self, symbol: &str) -> String {
+        let placeholders = &mut self.placeholders;
+        placeholders.symbols.push(symbol.to_string());
+
+        format!(
+            "{}{}{}{}",
+            PLACEHOLDER_START,
+            placeholders.tag,
+            placeholders.symbols.len() - 1,
+            PLACEHOLDER_END
+        )
+    }
+
+    /// Render the placeholders of a finished expansion, as configured by the sandbox, or else replace them
+    /// with the truncation marker of the expansion limits
+    pub(super) fn finish_partial_output(&self, expansion: &str) -> String {
+        if self.limit_reached.is_none() {
+            return expansion.to_string();
+        }
+        let partial_output = self
+            .sandbox
+            .as_ref()
+            .and_then(|sandbox| sandbox.partial_output);
+        let marker = self
+            .limits
+            .as_ref()
+            .and_then(|limits| limits.truncation_marker.as_deref());
+        if partial_output.is_none() && marker.is_none() {
+            return expansion.to_string();
+        }
+
+        let mut finished = String::with_capacity(expansion.len());
+        let mut rest = expansion;
+        while let Some(start) = rest.find(PLACEHOLDER_START) {
+            let after_start = start + PLACEHOLDER_START.len_utf8();
+            let (index, len) = match self.placeholders.parse(&rest[after_start..]) {
+                Some(placeholder) => placeholder,
+                None => {
+                    // The marker character comes from the text itself
+                    finished.push_str(&rest[..after_start]);
+                    rest = &rest[after_start..];
+                    continue;
+                }
+            };
+
+            finished.push_str(&rest[..start]);
+            match (partial_output, marker) {
+                (Some(PartialOutput::Placeholders), _) => {
+                    finished.push(self.syntax.symbol_start);
+                    finished.push_str(&self.placeholders.symbols[index]);
+                    finished.push(self.syntax.symbol_end);
+                }
+                (None, Some(marker)) => finished.push_str(marker),
+                _ => {}
+            }
+            rest = &rest[after_start + len..];
+        }
+        finished.push_str(rest);
+
+        finished
+    }
+
+    /// Check whether the sandbox allows a built-in
+    pub(super) fn check_builtin_allowed(&self, name: &str) -> Result<(), ExpansionError> {
+        match &self.sandbox {
+            Some(sandbox) if sandbox.bans_builtin(name) => {
+                Err(ExpansionError::ForbiddenBuiltin(name.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check whether the sandbox allows reading the rules of a file-backed symbol
+    pub(super) fn check_file_symbol_allowed(&self, symbol: &str) -> Result<(), ExpansionError> {
+        match &self.sandbox {
+            Some(sandbox) if !sandbox.allow_file_symbols => {
+                Err(ExpansionError::ForbiddenFileSymbol(symbol.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::error::ErrorAction;
+
+    fn sandboxed(rules: &[&str], sandbox: SandboxOptions) -> Grammar<'static> {
+        Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol(
+                "root".to_string(),
+                rules.iter().map(|rule| rule.to_string()).collect(),
+            )
+            .with_symbol("leaf".to_string(), vec!["x".to_string()])
+            .with_sandbox(sandbox)
+    }
+
+    #[test]
+    fn expansion_budget() {
+        let errors = RefCell::new(Vec::new());
+        let mut grammar = sandboxed(
+            &["{leaf}{leaf}{leaf}{leaf}"],
+            SandboxOptions::default().with_max_expansions(3),
+        )
+        .on_error(|error| {
+            errors.borrow_mut().push(error.clone());
+            ErrorAction::Skip
+        });
+
+        assert_eq!(grammar.flatten().unwrap(), "xx");
+        drop(grammar);
+        assert_eq!(
+            errors.into_inner(),
+            vec![ExpansionError::LimitExceeded(SandboxLimit::Expansions(3)); 2]
+        );
+    }
+
+    #[test]
+    fn partial_output() {
+        let sandbox = SandboxOptions::default().with_max_expansions(3);
+        let mut grammar = sandboxed(
+            &["{leaf}, {leaf}, {leaf:upper} and {leaf}"],
+            sandbox
+                .clone()
+                .with_partial_output(PartialOutput::Placeholders),
+        );
+        let result = grammar.generate().unwrap();
+        assert_eq!(result.output, "x, x, {leaf} and {leaf}");
+        assert!(result.partial);
+
+        let mut grammar = sandboxed(
+            &["{leaf}, {leaf}, {leaf:upper} and {leaf}"],
+            sandbox.with_partial_output(PartialOutput::Strip),
+        );
+        assert_eq!(grammar.flatten().unwrap(), "x, x,  and ");
+
+        let mut grammar = sandboxed(
+            &["{leaf}"],
+            SandboxOptions::default().with_partial_output(PartialOutput::Strip),
+        );
+        assert!(!grammar.generate().unwrap().partial);
+    }
+
+    #[test]
+    fn partial_output_keeps_marker_characters_of_the_text() {
+        let mut grammar = sandboxed(
+            &["\u{E000}{leaf}\u{E001}, \u{E000}0\u{E001} {leaf} {leaf}"],
+            SandboxOptions::default()
+                .with_max_expansions(2)
+                .with_partial_output(PartialOutput::Placeholders),
+        );
+
+        let result = grammar.generate().unwrap();
+        assert_eq!(
+            result.output,
+            "\u{E000}x\u{E001}, \u{E000}0\u{E001} {leaf} {leaf}"
+        );
+        assert!(result.partial);
+    }
+
+    #[test]
+    fn partial_output_of_size_limit() {
+        let mut grammar = sandboxed(
+            &["{long} {long} {long} {long}"],
+            SandboxOptions::default()
+                .with_max_bytes(64)
+                .with_partial_output(PartialOutput::Placeholders),
+        )
+        .with_symbol("long".to_string(), vec!["twenty bytes of text".to_string()]);
+
+        let result = grammar.generate().unwrap();
+        assert_eq!(
+            result.output,
+            "twenty bytes of text twenty bytes of text twenty bytes of text {long}"
+        );
+        assert!(result.partial);
+    }
+
+    #[test]
+    fn size_limit() {
+        let mut grammar = sandboxed(
+            &["{long}{long}{long}{long}"],
+            SandboxOptions::default().with_max_bytes(64),
+        )
+        .with_symbol("long".to_string(), vec!["twenty bytes of text".to_string()])
+        .on_error(|_| ErrorAction::Substitute("too long".to_string()));
+        assert_eq!(grammar.flatten().unwrap(), "too long");
+
+        let mut grammar = sandboxed(
+            &["{@hex:1000}"],
+            SandboxOptions::default().with_max_bytes(64),
+        )
+        .on_error(|_| ErrorAction::Skip);
+        assert_eq!(grammar.flatten().unwrap(), "");
+    }
+
+    #[test]
+    #[should_panic(expected = "Expansion exceeded the limit: duration of 0ns")]
+    fn time_limit() {
+        sandboxed(
+            &["{leaf}"],
+            SandboxOptions::default().with_time_limit(Duration::ZERO),
+        )
+        .flatten_unchecked();
+    }
+
+    #[test]
+    #[should_panic(expected = "Built-in symbol 'env' is not allowed by the sandbox")]
+    fn banned_builtin() {
+        let env = crate::host::EnvironmentVariables::allowing(&["USER"]);
+        sandboxed(&["{@env:USER}"], SandboxOptions::default())
+            .with_host_variables("env".to_string(), &env)
+            .flatten_unchecked();
+    }
+
+    #[test]
+    #[should_panic(expected = "File-backed symbol 'first_name' is not allowed by the sandbox")]
+    fn forbidden_file_symbol() {
+        sandboxed(&["{first_name}"], SandboxOptions::default())
+            .with_file_symbol("first_name".to_string(), "tests/data/names.txt")
+            .flatten_unchecked();
+    }
+
+    #[test]
+    fn untrusted_grammar_is_checked_at_load() {
+        let load = |content: &str, sandbox: SandboxOptions| {
+            Grammar::from_untrusted_json(content, "seed", GrammarSyntax::default(), sandbox)
+        };
+
+        assert!(load(r#"{"root": "{@uuid}"}"#, SandboxOptions::default()).is_ok());
+        assert_eq!(
+            load(
+                r#"{"root": "{none}", "none": []}"#,
+                SandboxOptions::default()
+            )
+            .err(),
+            Some("symbol 'none' has no rules".to_string())
+        );
+        assert!(load(r#"{"root": "{@env:HOME}"}"#, SandboxOptions::default()).is_err());
+        assert!(load(
+            r#"{"root": {"file": "/etc/passwd"}}"#,
+            SandboxOptions::default()
+        )
+        .is_err());
+        assert!(load(
+            r#"{"root": {"file": "names.txt"}}"#,
+            SandboxOptions::default().allowing_file_symbols(true)
+        )
+        .is_ok());
+        assert!(load(
+            r#"{"root": "long"}"#,
+            SandboxOptions::default().with_max_bytes(8)
+        )
+        .is_err());
+        assert!(load(r#"{"root": "#, SandboxOptions::default()).is_err());
+    }
+}
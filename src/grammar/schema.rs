@@ -0,0 +1,171 @@
+use std::collections::BTreeSet;
+
+use super::{block, builtin, Grammar};
+use crate::export::schema::{GrammarSchema, SymbolSchema, SyntaxSchema};
+
+impl Grammar<'_> {
+    /// Returns a machine-readable description of the grammar: its symbols, with their documentation, the variables
+    /// captured by its rules, its modifiers, built-ins and tags, and the delimiters and block keywords of its syntax,
+    /// e.g. for an editor plugin to offer autocompletion and hover documentation. `GrammarSchema::to_json` serializes
+    /// it.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["[name>hero]{hero:capitalize}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["ada".to_string(), "bo".to_string()])
+    ///     .with_standard_modifiers();
+    ///
+    /// let schema = grammar.export_schema();
+    /// assert_eq!(schema.symbols[0].name, "name");
+    /// assert_eq!(schema.symbols[0].rules, 2);
+    /// assert_eq!(schema.variables, ["hero"]);
+    /// assert!(schema.modifiers.contains(&"capitalize".to_string()));
+    /// assert_eq!(schema.syntax.symbol_start, '{');
+    /// assert!(schema.syntax.block_keywords.contains(&"switch".to_string()));
+    /// ```
+    pub fn export_schema(&self) -> GrammarSchema {
+        let names: BTreeSet<&String> = self
+            .symbols
+            .keys()
+            .chain(self.file_symbols.keys())
+            .chain(self.dynamic_symbols.keys())
+            .collect();
+
+        let symbols = names
+            .into_iter()
+            .map(|name| SymbolSchema {
+                name: name.to_string(),
+                description: self.description(name).map(str::to_string),
+                rules: self.symbols.get(name).map_or(0, Vec::len),
+                file: self
+                    .file_symbols
+                    .get(name)
+                    .map(|path| path.display().to_string()),
+                dynamic: self.dynamic_symbols.contains_key(name),
+                deprecated: self.deprecations.contains_key(name),
+                replacement_hint: self.deprecations.get(name).cloned().flatten(),
+                strategy: self.strategy(name).to_string(),
+                tags: sorted(self.symbol_tags(name)),
+            })
+            .collect();
+
+        let modifiers = self
+            .modifiers
+            .keys()
+            .chain(
+                self.locale_modifiers
+                    .values()
+                    .flat_map(|modifiers| modifiers.keys()),
+            )
+            .map(String::as_str);
+        let builtins = builtin::RANDOM_BUILTINS
+            .iter()
+            .copied()
+            .chain(self.host_variables.keys().map(String::as_str))
+            .chain(self.included_grammars.keys().map(String::as_str));
+        let tags = self
+            .symbols
+            .keys()
+            .flat_map(|symbol| self.symbol_tags(symbol));
+
+        GrammarSchema {
+            root: self.root().to_string(),
+            syntax: SyntaxSchema {
+                symbol_start: self.syntax.symbol_start,
+                symbol_end: self.syntax.symbol_end,
+                capture_start: self.syntax.capture_start,
+                capture_end: self.syntax.capture_end,
+                capture_operator: self.syntax.capture_operator,
+                modifier_operator: self.syntax.modifier_operator,
+                expression_prefix: self.syntax.expression_prefix,
+                builtin_prefix: self.syntax.builtin_prefix,
+                escape: self.syntax.escape,
+                block_keywords: block::BLOCK_KEYWORDS
+                    .iter()
+                    .map(|keyword| keyword.to_string())
+                    .collect(),
+            },
+            symbols,
+            variables: sorted(self.captured_variables()),
+            modifiers: sorted(modifiers),
+            builtins: sorted(builtins),
+            tags: sorted(tags),
+        }
+    }
+
+    /// Returns the tags of the rules of a symbol
+    fn symbol_tags(&self, symbol: &str) -> impl Iterator<Item = &str> {
+        self.rule_options
+            .get(symbol)
+            .into_iter()
+            .flatten()
+            .flat_map(|options| options.tags.iter().map(String::as_str))
+    }
+}
+
+/// Returns the given names sorted and without duplicates
+fn sorted<'n>(names: impl IntoIterator<Item = &'n str>) -> Vec<String> {
+    names
+        .into_iter()
+        .collect::<BTreeSet<&str>>()
+        .into_iter()
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::GrammarSyntax;
+    use crate::grammar::{Grammar, RuleOptions};
+
+    #[test]
+    fn schema_describes_symbols() {
+        let grammar = Grammar::from_json_str(
+            r#"{
+                "root": "[animal>pet]{pet:s} {@hex:4}",
+                "animal": {
+                    "description": "A pet",
+                    "rules": ["cat", {"rule": "owl", "tags": ["bird", "rare"]}],
+                    "strategy": "shuffleBag"
+                },
+                "beast": {"rules": ["wolf"], "deprecated": "use 'animal'"}
+            }"#,
+            "seed",
+            GrammarSyntax::default(),
+        )
+        .unwrap()
+        .with_rule_options("beast", 0, RuleOptions::default().with_tag("rare"));
+
+        let schema = grammar.export_schema();
+        let names: Vec<&str> = schema
+            .symbols
+            .iter()
+            .map(|symbol| symbol.name.as_str())
+            .collect();
+        assert_eq!(names, ["animal", "beast", "root"]);
+
+        let animal = &schema.symbols[0];
+        assert_eq!(animal.description.as_deref(), Some("A pet"));
+        assert_eq!(animal.strategy, "shuffleBag");
+        assert_eq!(animal.tags, ["bird", "rare"]);
+        assert_eq!(
+            schema.symbols[1].replacement_hint.as_deref(),
+            Some("use 'animal'")
+        );
+        assert!(schema.symbols[1].deprecated);
+
+        assert_eq!(schema.variables, ["pet"]);
+        assert_eq!(schema.tags, ["bird", "rare"]);
+        assert_eq!(schema.builtins, ["base32", "hex", "uuid"]);
+        assert!(schema.to_json().contains(r#""modifier_operator": ":""#));
+        assert_eq!(
+            schema.syntax.block_keywords,
+            ["switch", "case", "default", "if", "elif", "else", "end"]
+        );
+    }
+}
@@ -0,0 +1,562 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use super::{strategy::StrategyState, CaptureValue, Grammar, RuleOptions};
+use crate::rng::SeededRng;
+
+/// State shared by successive flattens, e.g. the posts of a bot, to enforce the cooldowns of the rules.
+///
+/// A session is independent from the grammar, and serializable, so it can be saved between runs.
+/// It also keeps the state of the grammar after its last flatten (captures, random generator, shuffle bags and
+/// the rules down-weighted by variety boosting),
+/// which `save_state` saves along with it, so a long-running bot goes on where it left off after a restart.
+///
+/// ```
+/// use vitrail::{
+///     config::GrammarSyntax,
+///     grammar::{Grammar, RuleOptions, Session},
+/// };
+///
+/// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+///     .with_symbol("root".to_string(), vec!["joke A".to_string(), "joke B".to_string()])
+///     .with_rule_options("root", 0, RuleOptions::default().with_cooldown(7))
+///     .with_rule_options("root", 1, RuleOptions::default().with_cooldown(7));
+///
+/// let mut session = Session::new();
+/// let first = session.flatten(&mut grammar);
+/// let second = session.flatten(&mut grammar);
+/// assert_ne!(first, second);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// The number of flattens performed in the session
+    flattens: usize,
+    /// The flatten in which each rule was last selected, expressed as a map of symbol => rule index => flatten number
+    last_selections: HashMap<String, HashMap<usize, usize>>,
+    /// The runtime adjustments of the weights of the rules having a tag, expressed as a map of tag => bias
+    tag_biases: HashMap<String, WeightBias>,
+    /// The runtime weights replacing the weights of the rules of a symbol, expressed as a map of symbol => weights by rule index
+    symbol_weights: HashMap<String, Vec<f64>>,
+    /// The position of the next rule of the symbols using the sequential strategy, expressed as a map of symbol => position
+    #[serde(default)]
+    sequence_positions: HashMap<String, usize>,
+    /// The number of times each rule was selected, expressed as a map of symbol => rule index => count
+    #[serde(default)]
+    selection_counts: HashMap<String, HashMap<usize, usize>>,
+    /// The captures of the grammar after the last flatten
+    #[serde(default)]
+    captures: HashMap<String, CaptureValue>,
+    /// The random generator of the grammar after the last flatten
+    #[serde(default)]
+    rng: Option<SeededRng>,
+    /// The selections kept by the strategies of the grammar after the last flatten, e.g. the rules left in
+    /// shuffle bags, expressed as a map of symbol => state
+    #[serde(default)]
+    strategy_states: HashMap<String, StrategyState>,
+    /// The weight factors of the rules down-weighted by the variety boosting of the grammar after the last flatten,
+    /// expressed as a map of symbol => factor of each rule, by rule index
+    #[serde(default)]
+    recent_selections: HashMap<String, Vec<f64>>,
+    /// The locale whose modifiers the grammar applies during the flattens of the session, instead of its own
+    #[serde(default)]
+    locale: Option<String>,
+    /// Whether the state of the grammar is restored from the session by the next flatten, once loaded
+    #[serde(skip)]
+    restore: bool,
+}
+
+/// A runtime adjustment of the weight of a rule
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum WeightBias {
+    /// Multiply the weight of the rule
+    Scale(f64),
+    /// Replace the weight of the rule
+    Override(f64),
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of flattens performed in the session
+    pub fn flattens(&self) -> usize {
+        self.flattens
+    }
+
+    /// Expand the grammar from its default root symbol, as part of the session
+    pub fn flatten(&mut self, grammar: &mut Grammar) -> String {
+        if std::mem::take(&mut self.restore) {
+            self.restore_grammar_state(grammar);
+        }
+
+        grammar.session = Some(std::mem::take(self));
+        let output = grammar.flatten_unchecked();
+        *self = grammar.session.take().unwrap_or_default();
+        self.flattens += 1;
+        self.keep_grammar_state(grammar);
+
+        output
+    }
+
+    /// Save the session to a JSON file, along with the state of the grammar after the last flatten:
+    /// its captures, random generator, shuffle bags and variety boosting.
+    ///
+    /// The session is written to a temporary file next to the given one, then renamed over it, so a crash
+    /// while saving leaves the previous state intact.
+    ///
+    /// ```no_run
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::{Grammar, Session},
+    /// };
+    ///
+    /// let mut grammar = Grammar::from_json("grammar.json", "anyrandomseed", GrammarSyntax::default()).unwrap();
+    /// let mut session = Session::load_state("bot.json").unwrap_or_default();
+    /// println!("{}", session.flatten(&mut grammar));
+    /// session.save_state("bot.json").unwrap();
+    /// ```
+    pub fn save_state<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let json = serde_json::to_vec(self).map_err(io::Error::from)?;
+
+        let mut temp_name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?
+            .to_os_string();
+        temp_name.push(format!(".{}.tmp", std::process::id()));
+        let temp_path = path.with_file_name(temp_name);
+
+        let saved = fs::write(&temp_path, json).and_then(|_| fs::rename(&temp_path, path));
+        if saved.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        saved
+    }
+
+    /// Load a session saved with `save_state`. The next flatten of the session restores the state of the grammar
+    /// it was saved with, so the grammar goes on with the same random sequence, captures, shuffle bags and
+    /// down-weighted rules.
+    pub fn load_state<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let json = fs::read(path)?;
+        let mut session: Self = serde_json::from_slice(&json).map_err(io::Error::from)?;
+        session.restore = true;
+
+        Ok(session)
+    }
+
+    /// Keep the state of the grammar after a flatten, to save it along with the session
+    fn keep_grammar_state(&mut self, grammar: &Grammar) {
+        self.captures = grammar.captures.clone();
+        self.rng = Some(grammar.rng.clone());
+        self.strategy_states = grammar.strategy_states.clone();
+        self.recent_selections = grammar
+            .recent_selections
+            .as_ref()
+            .map(|recent| recent.factors().clone())
+            .unwrap_or_default();
+    }
+
+    /// Restore the state of the grammar kept by the session, overriding its captures and strategy states of the
+    /// same names, and the rules down-weighted by its variety boosting if enabled
+    fn restore_grammar_state(&self, grammar: &mut Grammar) {
+        grammar.import_captures(&self.captures);
+        if let Some(rng) = &self.rng {
+            grammar.rng = rng.clone();
+        }
+        grammar.strategy_states.extend(
+            self.strategy_states
+                .iter()
+                .map(|(symbol, state)| (symbol.clone(), state.clone())),
+        );
+        if let Some(recent) = grammar.recent_selections.as_mut() {
+            recent.set_factors(self.recent_selections.clone());
+        }
+    }
+
+    /// Multiply the weight of the rules having the given tag, e.g. to make spooky rules more likely at night,
+    /// replacing any previous bias of the tag
+    ///
+    /// ```
+    /// use vitrail::grammar::Session;
+    ///
+    /// let mut session = Session::new();
+    /// session.bias_tag("spooky", 3.0);
+    /// ```
+    pub fn bias_tag(&mut self, tag: &str, factor: f64) {
+        self.tag_biases
+            .insert(tag.to_string(), WeightBias::Scale(factor));
+    }
+
+    /// Replace the weight of the rules having the given tag, e.g. 0 to disable them,
+    /// replacing any previous bias of the tag
+    pub fn override_tag(&mut self, tag: &str, weight: f64) {
+        self.tag_biases
+            .insert(tag.to_string(), WeightBias::Override(weight));
+    }
+
+    /// Replace the weights of the rules of a symbol, by rule index. Rules without a given weight keep theirs.
+    pub fn override_symbol(&mut self, symbol: &str, weights: Vec<f64>) {
+        self.symbol_weights.insert(symbol.to_string(), weights);
+    }
+
+    /// Remove every runtime adjustment of the weights
+    pub fn clear_biases(&mut self) {
+        self.tag_biases.clear();
+        self.symbol_weights.clear();
+    }
+
+    /// Returns the weight of the rule at `index` of the symbol in this session: its weight overridden for its symbol,
+    /// or else by its first overridden tag, then scaled by its scaled tags, or 0 while it is cooling down
+    pub(crate) fn rule_weight(&self, symbol: &str, index: usize, options: &RuleOptions) -> f64 {
+        if self.is_cooling_down(symbol, index, options.cooldown) {
+            return 0.0;
+        }
+
+        let biases: Vec<WeightBias> = options
+            .tags
+            .iter()
+            .filter_map(|tag| self.tag_biases.get(tag).copied())
+            .collect();
+
+        let overridden = self
+            .symbol_weights
+            .get(symbol)
+            .and_then(|weights| weights.get(index).copied())
+            .or_else(|| {
+                biases.iter().find_map(|bias| match bias {
+                    WeightBias::Override(weight) => Some(*weight),
+                    WeightBias::Scale(_) => None,
+                })
+            });
+
+        biases.iter().fold(
+            overridden.unwrap_or(options.weight),
+            |weight, bias| match bias {
+                WeightBias::Scale(factor) => weight * factor,
+                WeightBias::Override(_) => weight,
+            },
+        )
+    }
+
+    /// Adjust the weights of the rules of a symbol, so that the share of the selections of the symbol each rule has
+    /// in this session stays within its floor and ceiling once the next rule is selected: a rule which would exceed
+    /// its ceiling is excluded, and the rules which would fall below their floor are the only ones selectable
+    pub(crate) fn constrain_weights(
+        &self,
+        symbol: &str,
+        weights: &mut [f64],
+        options: &[&RuleOptions],
+    ) {
+        let counts = self.selection_counts.get(symbol);
+        let count = |index: usize| {
+            counts
+                .and_then(|counts| counts.get(&index))
+                .copied()
+                .unwrap_or(0) as f64
+        };
+        let next_total = counts.map_or(0, |counts| counts.values().sum::<usize>()) as f64 + 1.0;
+
+        let below_floor: Vec<usize> = (0..weights.len())
+            .filter(|index| {
+                options[*index]
+                    .floor
+                    .is_some_and(|floor| count(*index) / next_total < floor)
+            })
+            .collect();
+        for (index, weight) in weights.iter_mut().enumerate() {
+            let above_ceiling = options[index]
+                .ceiling
+                .is_some_and(|ceiling| (count(index) + 1.0) / next_total > ceiling);
+            let crowded_out = !below_floor.is_empty() && !below_floor.contains(&index);
+            if above_ceiling || crowded_out {
+                *weight = 0.0;
+            } else if below_floor.contains(&index) && *weight <= 0.0 {
+                // A rule needed to reach its floor is selectable whatever its weight
+                *weight = 1.0;
+            }
+        }
+    }
+
+    /// Returns whether the rule at `index` of the symbol was selected by one of the `cooldown` previous flattens
+    pub fn is_cooling_down(&self, symbol: &str, index: usize, cooldown: usize) -> bool {
+        self.last_selections
+            .get(symbol)
+            .and_then(|selections| selections.get(&index))
+            .is_some_and(|last| *last < self.flattens && self.flattens - last <= cooldown)
+    }
+
+    /// Set the locale whose modifiers the grammar applies during the flattens of the session, e.g. `fr` for
+    /// `{noun:s}` to pluralize French nouns, or `None` to apply the modifiers of the locale of the grammar
+    pub fn set_locale(&mut self, locale: Option<&str>) {
+        self.locale = locale.map(str::to_string);
+    }
+
+    /// Returns the locale whose modifiers the grammar applies during the flattens of the session, if it sets one
+    pub fn locale(&self) -> Option<&str> {
+        self.locale.as_deref()
+    }
+
+    /// Returns the position of the rule the symbol selects next, when it uses the sequential strategy.
+    /// It is the index following the rule selected last, or the position set by `set_sequence_position`,
+    /// and it is only wrapped around the number of rules when selecting: after the last rule, it equals
+    /// the number of rules.
+    pub fn sequence_position(&self, symbol: &str) -> usize {
+        self.sequence_positions.get(symbol).copied().unwrap_or(0)
+    }
+
+    /// Set the position of the rule the symbol selects next, when it uses the sequential strategy,
+    /// e.g. to show the tip of the day matching the number of days since a launch
+    pub fn set_sequence_position(&mut self, symbol: &str, position: usize) {
+        self.sequence_positions.insert(symbol.to_string(), position);
+    }
+
+    /// Record the selection of the rule at `index` of the symbol, by the current flatten
+    pub(crate) fn record(&mut self, symbol: &str, index: usize) {
+        self.last_selections
+            .entry(symbol.to_string())
+            .or_default()
+            .insert(index, self.flattens);
+        *self
+            .selection_counts
+            .entry(symbol.to_string())
+            .or_default()
+            .entry(index)
+            .or_default() += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+    use crate::grammar::RuleOptions;
+
+    /// Returns a path in the temporary directory unique to the test and the test process
+    fn temp_path(test: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("vitrail_{}_{}.json", test, std::process::id()))
+    }
+
+    #[test]
+    fn cooldown_window() {
+        let mut session = Session::new();
+        session.record("joke", 0);
+        assert!(!session.is_cooling_down("joke", 0, 2));
+
+        session.flattens = 2;
+        assert!(session.is_cooling_down("joke", 0, 2));
+        assert!(!session.is_cooling_down("joke", 1, 2));
+
+        session.flattens = 3;
+        assert!(!session.is_cooling_down("joke", 0, 2));
+    }
+
+    #[test]
+    fn rules_are_not_repeated_during_their_cooldown() {
+        let jokes: Vec<String> = (0..4).map(|i| format!("joke {}", i)).collect();
+        let mut grammar = Grammar::new("seed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), jokes.clone());
+        for index in 0..jokes.len() {
+            grammar =
+                grammar.with_rule_options("root", index, RuleOptions::default().with_cooldown(3));
+        }
+
+        let mut session = Session::new();
+        let outputs: Vec<String> = (0..12).map(|_| session.flatten(&mut grammar)).collect();
+        for window in outputs.windows(4) {
+            let mut distinct = window.to_vec();
+            distinct.sort();
+            distinct.dedup();
+            assert_eq!(distinct.len(), 4);
+        }
+        assert_eq!(session.flattens(), 12);
+    }
+
+    #[test]
+    fn shares_stay_within_floor_and_ceiling() {
+        let mut grammar = Grammar::new("seed", GrammarSyntax::default())
+            .with_symbol(
+                "root".to_string(),
+                vec!["sponsor".to_string(), "tip".to_string(), "news".to_string()],
+            )
+            .with_rule_options(
+                "root",
+                0,
+                RuleOptions::default().with_weight(50.0).with_ceiling(0.05),
+            )
+            .with_rule_options(
+                "root",
+                1,
+                RuleOptions::default().with_weight(0.01).with_floor(0.3),
+            );
+
+        let mut session = Session::new();
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for flattens in 1..=100 {
+            *counts.entry(session.flatten(&mut grammar)).or_default() += 1;
+            let share =
+                |rule: &str| counts.get(rule).copied().unwrap_or(0) as f64 / flattens as f64;
+            assert!(share("sponsor") <= 0.05);
+            assert!(share("tip") >= 0.3);
+        }
+        assert_eq!(counts["sponsor"], 5);
+    }
+
+    #[test]
+    fn biased_weights() {
+        let spooky = RuleOptions::default().with_weight(2.0).with_tag("spooky");
+        let mut session = Session::new();
+        assert_eq!(session.rule_weight("event", 0, &spooky), 2.0);
+
+        session.bias_tag("spooky", 3.0);
+        assert_eq!(session.rule_weight("event", 0, &spooky), 6.0);
+        assert_eq!(
+            session.rule_weight("event", 1, &RuleOptions::default()),
+            1.0
+        );
+
+        session.override_tag("spooky", 0.5);
+        assert_eq!(session.rule_weight("event", 0, &spooky), 0.5);
+
+        session.override_symbol("event", vec![4.0]);
+        assert_eq!(session.rule_weight("event", 0, &spooky), 4.0);
+
+        session.clear_biases();
+        assert_eq!(session.rule_weight("event", 0, &spooky), 2.0);
+    }
+
+    #[test]
+    fn biased_huge_weights_do_not_overflow() {
+        let mut grammar = Grammar::from_json_str(
+            r#"{"root": [{"rule": "a", "weight": 1e308}, {"rule": "b", "weight": 1e308}]}"#,
+            "seed",
+            GrammarSyntax::default(),
+        )
+        .unwrap()
+        .with_rule_options(
+            "root",
+            1,
+            RuleOptions::default().with_weight(1e308).with_tag("loud"),
+        );
+
+        assert!(["a", "b"].contains(&grammar.flatten().unwrap().as_str()));
+
+        let mut session = Session::new();
+        session.bias_tag("loud", 10.0);
+        for _ in 0..20 {
+            assert_eq!(session.flatten(&mut grammar), "b");
+        }
+    }
+
+    #[test]
+    fn bias_steers_generation() {
+        let mut grammar = Grammar::new("seed", GrammarSyntax::default())
+            .with_symbol(
+                "root".to_string(),
+                vec!["calm".to_string(), "ghost".to_string()],
+            )
+            .with_rule_options("root", 1, RuleOptions::default().with_tag("spooky"));
+
+        let mut session = Session::new();
+        session.override_tag("spooky", 0.0);
+        assert!((0..20).all(|_| session.flatten(&mut grammar) == "calm"));
+
+        session.clear_biases();
+        session.override_symbol("root", vec![0.0]);
+        assert!((0..20).all(|_| session.flatten(&mut grammar) == "ghost"));
+    }
+
+    #[test]
+    fn serializable() {
+        let mut session = Session::new();
+        session.record("joke", 3);
+        session.flattens = 1;
+
+        let json = serde_json::to_string(&session).unwrap();
+        assert_eq!(serde_json::from_str::<Session>(&json).unwrap(), session);
+    }
+
+    #[test]
+    fn saved_state_survives_a_restart() {
+        use crate::grammar::Strategy;
+
+        let cards: Vec<String> = (0..5).map(|i| format!("card {}", i)).collect();
+        let new_grammar = || {
+            let mut grammar = Grammar::new("seed", GrammarSyntax::default())
+                .with_symbol(
+                    "root".to_string(),
+                    vec!["[card>drawn]{drawn} {@hex:4}".to_string()],
+                )
+                .with_symbol("card".to_string(), cards.clone());
+            grammar.set_strategy("card", Strategy::ShuffleBag);
+            grammar
+        };
+        let path = temp_path("saved_state_survives_a_restart");
+
+        let mut grammar = new_grammar();
+        let mut session = Session::new();
+        session.flatten(&mut grammar);
+        session.flatten(&mut grammar);
+        session.save_state(&path).unwrap();
+        let expected: Vec<String> = (0..3).map(|_| session.flatten(&mut grammar)).collect();
+        let drawn = grammar.captures["drawn"].to_string();
+
+        let mut grammar = new_grammar();
+        let mut session = Session::load_state(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let outputs: Vec<String> = (0..3).map(|_| session.flatten(&mut grammar)).collect();
+        assert_eq!(outputs, expected);
+        assert_eq!(grammar.captures["drawn"].to_string(), drawn);
+        assert_eq!(session.flattens(), 5);
+    }
+
+    #[test]
+    fn saved_state_keeps_variety_boosting() {
+        use crate::config::VarietyBoost;
+
+        let new_grammar = || {
+            Grammar::new("seed", GrammarSyntax::default())
+                .with_symbol(
+                    "root".to_string(),
+                    (0..4).map(|i| format!("line {}", i)).collect(),
+                )
+                .with_variety_boost(VarietyBoost::new(0.0, 0.0))
+        };
+        let path = temp_path("saved_state_keeps_variety_boosting");
+
+        // Without recovery, the lines selected before the restart are never selected again
+        let mut grammar = new_grammar();
+        let mut session = Session::new();
+        let mut outputs: Vec<String> = (0..2).map(|_| session.flatten(&mut grammar)).collect();
+        session.save_state(&path).unwrap();
+
+        let mut grammar = new_grammar();
+        let mut session = Session::load_state(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        outputs.extend((0..2).map(|_| session.flatten(&mut grammar)));
+        outputs.sort();
+        assert_eq!(outputs, ["line 0", "line 1", "line 2", "line 3"]);
+    }
+
+    #[test]
+    fn saving_replaces_the_previous_state() {
+        let path = temp_path("saving_replaces_the_previous_state");
+        let mut session = Session::new();
+        session.save_state(&path).unwrap();
+        session.flattens = 3;
+        session.save_state(&path).unwrap();
+
+        let loaded = Session::load_state(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.flattens(), 3);
+        let dir = path.parent().unwrap();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(!fs::read_dir(dir)
+            .unwrap()
+            .filter_map(Result::ok)
+            .any(|entry| entry.file_name().to_string_lossy().starts_with(&name)));
+    }
+}
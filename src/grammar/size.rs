@@ -0,0 +1,123 @@
+use std::mem;
+
+use super::token::{self, Token};
+use super::{Grammar, RuleOptions};
+use crate::report::{SizeReport, SymbolSize};
+
+/// Number of symbols listed by a size report as the largest ones
+const LARGEST_SYMBOLS: usize = 10;
+
+impl Grammar<'_> {
+    /// Returns the number of symbols and rules of the grammar, the size of their text, and an estimate of the
+    /// memory they take, in total and for the largest symbols, e.g. to decide what to prune before targeting WASM
+    /// or a console. Rules files are loaded to be measured.
+    ///
+    /// The estimate counts the texts, the collections holding them, the rule options, and the tokens rules are
+    /// parsed into by analyses such as matching outputs or computing probabilities, but not the hash tables
+    /// indexing symbols, nor the memory allocator overhead. Expansion itself walks the text of the rules in a
+    /// single pass, without tokenizing them.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["Hello {name}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["Alice".to_string(), "Bob".to_string()]);
+    ///
+    /// let report = grammar.size_report();
+    /// assert_eq!((report.symbols, report.rules, report.text_bytes), (2, 3, 28));
+    /// assert_eq!(report.largest_symbols[0].symbol, "name");
+    /// assert!(report.estimated_memory > report.text_bytes);
+    /// ```
+    pub fn size_report(&mut self) -> SizeReport {
+        self.load_file_symbols();
+
+        let mut sizes: Vec<SymbolSize> = self
+            .symbols
+            .iter()
+            .map(|(symbol, rules)| {
+                let text_bytes = symbol.len() + rules.iter().map(String::len).sum::<usize>();
+                let tokens: usize = rules
+                    .iter()
+                    .map(|rule| token::tokenize(&self.syntax, rule).len())
+                    .sum();
+                let options: usize = self.rule_options.get(symbol).map_or(0, |options| {
+                    options
+                        .iter()
+                        .flat_map(|options| &options.tags)
+                        .map(|tag| mem::size_of::<String>() + tag.len())
+                        .sum::<usize>()
+                        + options.len() * mem::size_of::<RuleOptions>()
+                });
+
+                SymbolSize {
+                    symbol: symbol.to_string(),
+                    rules: rules.len(),
+                    text_bytes,
+                    estimated_memory: text_bytes
+                        + mem::size_of::<String>() * (rules.len() + 1)
+                        + mem::size_of::<Vec<String>>()
+                        + tokens * mem::size_of::<Token>()
+                        + options,
+                }
+            })
+            .collect();
+        sizes.sort_by(|a, b| {
+            b.estimated_memory
+                .cmp(&a.estimated_memory)
+                .then_with(|| a.symbol.cmp(&b.symbol))
+        });
+
+        SizeReport {
+            symbols: sizes.len(),
+            rules: sizes.iter().map(|size| size.rules).sum(),
+            text_bytes: sizes.iter().map(|size| size.text_bytes).sum(),
+            estimated_memory: sizes.iter().map(|size| size.estimated_memory).sum(),
+            largest_symbols: sizes.into_iter().take(LARGEST_SYMBOLS).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+
+    #[test]
+    fn largest_symbols_come_first() {
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["{a} {b}".to_string()])
+            .with_symbol("a".to_string(), vec!["x".repeat(500)])
+            .with_symbol("b".to_string(), vec!["y".to_string(), "z".to_string()])
+            .with_rule_options("b", 0, RuleOptions::default().with_tag("short"));
+        for index in 0..LARGEST_SYMBOLS {
+            grammar = grammar.with_symbol(format!("unused{}", index), vec!["w".to_string()]);
+        }
+
+        let report = grammar.size_report();
+        assert_eq!(report.symbols, 3 + LARGEST_SYMBOLS);
+        assert_eq!(report.largest_symbols.len(), LARGEST_SYMBOLS);
+
+        let largest: Vec<&str> = report
+            .largest_symbols
+            .iter()
+            .take(3)
+            .map(|size| size.symbol.as_str())
+            .collect();
+        assert_eq!(largest, vec!["a", "b", "root"]);
+        assert_eq!(report.largest_symbols[0].text_bytes, 501);
+        assert_eq!(
+            report.estimated_memory,
+            report
+                .largest_symbols
+                .iter()
+                .map(|size| size.estimated_memory)
+                .sum::<usize>()
+                + (LARGEST_SYMBOLS + 3 - report.largest_symbols.len())
+                    * report.largest_symbols[LARGEST_SYMBOLS - 1].estimated_memory
+        );
+    }
+}
@@ -0,0 +1,458 @@
+use serde::Serialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use super::{loader, Grammar, ValidationIssue};
+
+/// Where a symbol or a rule is written, so messages about it can point at the file to edit
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct SourceLocation {
+    /// The grammar or rules file
+    pub file: PathBuf,
+    /// The line in the file, from 1, or `None` if it could not be found
+    pub line: Option<usize>,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{}", self.file.display(), line),
+            None => write!(f, "{}", self.file.display()),
+        }
+    }
+}
+
+/// Where a symbol and each of its rules are written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct SymbolSource {
+    definition: SourceLocation,
+    rules: Vec<SourceLocation>,
+}
+
+impl SymbolSource {
+    /// Returns where the rules of a symbol are written once the rules of another definition are added after
+    /// its own
+    pub(super) fn append(mut self, other: SymbolSource) -> Self {
+        self.rules.extend(other.rules);
+        self
+    }
+}
+
+impl Grammar<'_> {
+    /// Returns where a symbol is defined, if it was read from a grammar file.
+    ///
+    /// The location is recorded when the grammar is read, and forgotten when the symbol is redefined.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::from_json("test.json", "anyrandomseed", GrammarSyntax::default()).unwrap();
+    /// assert_eq!(grammar.symbol_source("root").unwrap().to_string(), "test.json:2");
+    /// ```
+    pub fn symbol_source(&self, symbol: &str) -> Option<&SourceLocation> {
+        self.sources.get(symbol).map(|source| &source.definition)
+    }
+
+    /// Returns where the rule at `index` of a symbol is written, if it was read from a grammar or rules file
+    pub fn rule_source(&self, symbol: &str, index: usize) -> Option<&SourceLocation> {
+        self.sources
+            .get(symbol)
+            .and_then(|source| source.rules.get(index))
+    }
+
+    /// Returns where the problem reported by a validation issue is written: the rule it is about, if any,
+    /// or else its symbol
+    pub fn issue_source(&self, issue: &ValidationIssue) -> Option<&SourceLocation> {
+        issue
+            .rule()
+            .and_then(|rule| self.rule_source(issue.symbol(), rule))
+            .or_else(|| self.symbol_source(issue.symbol()))
+    }
+
+    /// Record where the symbols read from a grammar file and their rules are written in its content
+    pub(super) fn locate_symbols(&mut self, content: &str, file: &Path) {
+        let strings = json_strings(content);
+        let mut definitions: Vec<(usize, &String)> = self
+            .symbols
+            .keys()
+            .chain(self.file_symbols.keys())
+            .filter_map(|symbol| {
+                find_definition(content, strings.as_deref(), symbol).map(|offset| (offset, symbol))
+            })
+            .collect();
+        definitions.sort();
+
+        let mut sources = Vec::with_capacity(definitions.len());
+        for (index, (start, symbol)) in definitions.iter().enumerate() {
+            // The rules of a symbol are written before the definition of the next one
+            let end = definitions
+                .get(index + 1)
+                .map_or(content.len(), |(next, _)| *next);
+            let rules = self.symbols.get(*symbol).map_or(&[][..], Vec::as_slice);
+            let lines = locate_rules(
+                content,
+                strings.as_deref(),
+                *start + symbol.len()..end,
+                rules,
+            );
+
+            sources.push((
+                symbol.to_string(),
+                SymbolSource {
+                    definition: location(file, Some(line_of(content, *start))),
+                    rules: lines.into_iter().map(|line| location(file, line)).collect(),
+                },
+            ));
+        }
+
+        self.sources.extend(sources);
+    }
+
+    /// Record where the rules of a file-backed symbol are written in its rules file, keeping the location
+    /// of its definition in the grammar file
+    pub(super) fn locate_rules_file(&mut self, symbol: &str, content: &str, file: &Path) {
+        let rules = match self.symbols.get(symbol) {
+            Some(rules) => rules,
+            None => return,
+        };
+        let strings = json_strings(content);
+        let lines = locate_rules(content, strings.as_deref(), 0..content.len(), rules);
+
+        let rules = lines.into_iter().map(|line| location(file, line)).collect();
+        let definition = self
+            .symbol_source(symbol)
+            .cloned()
+            .unwrap_or_else(|| location(file, None));
+        self.sources
+            .insert(symbol.to_string(), SymbolSource { definition, rules });
+    }
+}
+
+fn location(file: &Path, line: Option<usize>) -> SourceLocation {
+    SourceLocation {
+        file: file.to_path_buf(),
+        line,
+    }
+}
+
+/// Returns the line, from 1, of a byte offset in a text
+fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset].matches('\n').count() + 1
+}
+
+/// A string of a JSON grammar or rules file
+struct JsonString {
+    /// The offset of its opening quote
+    offset: usize,
+    /// Its value, unescaped
+    value: String,
+    /// Whether it is a key of the top-level object, which defines a symbol
+    is_symbol: bool,
+    /// Whether it can be a rule: a value of the top-level object or of a `rule` or `rules` member,
+    /// or an item of a list of them
+    is_rule: bool,
+}
+
+/// Returns the strings of the content of a grammar or rules file, in order, if it is written in JSON
+fn json_strings(content: &str) -> Option<Vec<JsonString>> {
+    if !content.trim_start().starts_with(['{', '[']) {
+        return None;
+    }
+
+    let mut strings = Vec::new();
+    // The objects and lists the scanner is in, innermost last, with the key of the member being read for objects
+    let mut containers: Vec<(char, Option<String>)> = Vec::new();
+    let mut characters = content.char_indices();
+
+    while let Some((offset, character)) = characters.next() {
+        match character {
+            '{' | '[' => containers.push((character, None)),
+            '}' | ']' => {
+                containers.pop();
+            }
+            ',' => {
+                if let Some(('{', key)) = containers.last_mut() {
+                    *key = None;
+                }
+            }
+            '"' => {
+                let mut escaped = false;
+                let mut end = content.len();
+                for (index, character) in characters.by_ref() {
+                    match character {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        '"' => {
+                            end = index + 1;
+                            break;
+                        }
+                        _ => (),
+                    }
+                }
+                let value: String = serde_json::from_str(&content[offset..end]).unwrap_or_default();
+
+                if let Some(('{', key @ None)) = containers.last_mut() {
+                    *key = Some(value.clone());
+                    if containers.len() == 1 {
+                        strings.push(JsonString {
+                            offset,
+                            value,
+                            is_symbol: true,
+                            is_rule: false,
+                        });
+                    }
+                    continue;
+                }
+
+                let object = containers
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .find(|(_, (container, _))| *container == '{');
+                let is_rule = match object {
+                    None | Some((0, _)) => true,
+                    Some((_, (_, key))) => {
+                        matches!(key.as_deref(), Some(loader::RULE_KEY | loader::RULES_KEY))
+                    }
+                };
+                strings.push(JsonString {
+                    offset,
+                    value,
+                    is_symbol: false,
+                    is_rule,
+                });
+            }
+            _ => (),
+        }
+    }
+
+    Some(strings)
+}
+
+/// Returns the offset of the definition of a symbol in the content of a grammar file: a key of its top-level
+/// object (JSON), its name at the start of a line, quoted or not and followed by a key separator (YAML, TOML),
+/// or alone (plain text), or the header of a TOML table
+fn find_definition(content: &str, strings: Option<&[JsonString]>, symbol: &str) -> Option<usize> {
+    if let Some(strings) = strings {
+        return strings
+            .iter()
+            .find(|string| string.is_symbol && string.value == symbol)
+            .map(|string| string.offset);
+    }
+
+    // Once a TOML table starts, the keys at the start of a line are its own
+    let tables = content
+        .match_indices("\n[")
+        .map(|(offset, _)| offset + 1)
+        .chain(content.starts_with('[').then_some(0))
+        .min()
+        .unwrap_or(content.len());
+
+    content.match_indices(symbol).find_map(|(offset, _)| {
+        let before = &content[..offset];
+        let after = &content[offset + symbol.len()..];
+        let at_line_start = |before: &str| before.is_empty() || before.ends_with('\n');
+
+        let is_definition = match before.chars().next_back() {
+            Some('[') => at_line_start(&before[..before.len() - 1]) && after.starts_with(']'),
+            _ if offset >= tables => false,
+            Some(quote @ ('"' | '\'')) => {
+                at_line_start(&before[..before.len() - 1])
+                    && after.strip_prefix(quote).is_some_and(|after| {
+                        after
+                            .trim_start_matches([' ', '\t'])
+                            .starts_with([':', '='])
+                    })
+            }
+            None | Some('\n') => {
+                let rest = after.trim_start_matches([' ', '\t']);
+                rest.starts_with([':', '=', '\n', '\r', '(']) || rest.is_empty()
+            }
+            _ => false,
+        };
+        is_definition.then_some(offset)
+    })
+}
+
+/// Returns the line of each rule, searched in order in the given range of the content, or `None` for the rules
+/// which are not found. A rule is a whole string of a JSON file, or else a whole value, as written or escaped
+/// as a JSON string: only whitespace separates it from the line or the delimiters around it.
+fn locate_rules(
+    content: &str,
+    strings: Option<&[JsonString]>,
+    range: std::ops::Range<usize>,
+    rules: &[String],
+) -> Vec<Option<usize>> {
+    if let Some(strings) = strings {
+        let mut candidates = strings
+            .iter()
+            .filter(|string| string.is_rule && range.contains(&string.offset));
+        return rules
+            .iter()
+            .map(|rule| {
+                candidates
+                    .find(|string| string.value == *rule)
+                    .map(|string| line_of(content, string.offset))
+            })
+            .collect();
+    }
+
+    let mut cursor = range.start;
+    rules
+        .iter()
+        .map(|rule| {
+            let quoted = serde_json::to_string(rule).unwrap_or_default();
+            let escaped = &quoted[1..quoted.len() - 1];
+
+            let found = [rule.as_str(), escaped]
+                .iter()
+                .filter(|needle| !needle.is_empty())
+                .find_map(|needle| {
+                    content[cursor..range.end]
+                        .match_indices(*needle)
+                        .map(|(offset, _)| cursor + offset)
+                        .find(|offset| is_whole_value(content, *offset, *offset + needle.len()))
+                        .map(|offset| (offset, needle.len()))
+                });
+            found.map(|(offset, len)| {
+                cursor = offset + len;
+                line_of(content, offset)
+            })
+        })
+        .collect()
+}
+
+/// Returns whether the text at the given range of the content is a whole value: only whitespace separates it
+/// from the line or the quotes, list delimiters or key separators around it
+fn is_whole_value(content: &str, start: usize, end: usize) -> bool {
+    let before = &content[..start];
+    let spaced_before = before.trim_end_matches([' ', '\t']);
+    let after = content[end..].trim_start_matches([' ', '\t']);
+
+    let starts_value = spaced_before.is_empty()
+        || spaced_before.ends_with(['\n', '"', '\'', '[', ',', ':', '='])
+        // A YAML list item
+        || spaced_before.ends_with('-') && spaced_before.len() < before.len();
+    starts_value && (after.is_empty() || after.starts_with(['\r', '\n', '"', '\'', ',', ']', '#']))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+
+    #[test]
+    fn rules_are_located_in_their_file() {
+        let grammar =
+            Grammar::from_plain_text("test.txt", "testseed", GrammarSyntax::default()).unwrap();
+        let content = std::fs::read_to_string("test.txt").unwrap();
+
+        for (symbol, rules) in &grammar.symbols {
+            let definition = grammar.symbol_source(symbol).unwrap();
+            assert_eq!(definition.file, Path::new("test.txt"));
+            let line = content.lines().nth(definition.line.unwrap() - 1).unwrap();
+            assert!(line.starts_with(symbol.as_str()));
+
+            for (index, rule) in rules.iter().enumerate() {
+                let source = grammar.rule_source(symbol, index).unwrap();
+                let line = content.lines().nth(source.line.unwrap() - 1).unwrap();
+                assert_eq!(line.trim(), rule);
+            }
+        }
+    }
+
+    #[test]
+    fn keys_are_told_apart_from_rules() {
+        let content = r#"{
+    "root": "Say \"name\": {name}",
+    "name": ["Ada", "Bo"],
+    "other": "Ada"
+}"#;
+        let strings = json_strings(content);
+        assert_eq!(
+            find_definition(content, strings.as_deref(), "name")
+                .map(|offset| line_of(content, offset)),
+            Some(3)
+        );
+        assert_eq!(
+            locate_rules(
+                content,
+                strings.as_deref(),
+                0..content.len(),
+                &[
+                    "Say \"name\": {name}".to_string(),
+                    "Bo".to_string(),
+                    "Cy".to_string()
+                ]
+            ),
+            vec![Some(2), Some(3), None]
+        );
+
+        let mut grammar =
+            Grammar::from_json_str(content, "testseed", GrammarSyntax::default()).unwrap();
+        grammar.locate_symbols(content, Path::new("inline.json"));
+        assert_eq!(
+            grammar.rule_source("other", 0).unwrap().to_string(),
+            "inline.json:4"
+        );
+        assert_eq!(
+            grammar.rule_source("name", 1).unwrap().to_string(),
+            "inline.json:3"
+        );
+
+        let grammar = grammar.with_symbol("name".to_string(), vec!["Cy".to_string()]);
+        assert_eq!(grammar.symbol_source("name"), None);
+    }
+
+    #[test]
+    fn names_matching_settings_or_other_rules_are_told_apart() {
+        let content = r#"{
+    "root": {
+        "rules": ["root", "a"],
+        "strategy": "sequential"
+    },
+    "rules": ["sequential",
+        "root"]
+}"#;
+        let mut grammar =
+            Grammar::from_json_str(content, "testseed", GrammarSyntax::default()).unwrap();
+        grammar.locate_symbols(content, Path::new("inline.json"));
+        let line = |symbol: &str, rule: usize| grammar.rule_source(symbol, rule).unwrap().line;
+
+        assert_eq!(grammar.symbol_source("rules").unwrap().line, Some(6));
+        assert_eq!((line("root", 0), line("root", 1)), (Some(3), Some(3)));
+        assert_eq!((line("rules", 0), line("rules", 1)), (Some(6), Some(7)));
+    }
+
+    #[test]
+    fn rules_are_whole_values() {
+        let content = "start\n    start a\n    a\nrules\n    a\n";
+        assert_eq!(find_definition(content, None, "rules"), Some(24));
+        assert_eq!(
+            locate_rules(
+                content,
+                None,
+                5..24,
+                &["start a".to_string(), "a".to_string()]
+            ),
+            vec![Some(2), Some(3)]
+        );
+        assert_eq!(
+            locate_rules(content, None, 5..24, &["a".to_string()]),
+            vec![Some(3)]
+        );
+
+        let content = "name = [\"Ada\", \"Bo\"]\n[rules]\nrules = \"Ada Bo\"\n";
+        assert_eq!(
+            find_definition(content, None, "rules").map(|offset| line_of(content, offset)),
+            Some(2)
+        );
+        assert_eq!(
+            locate_rules(content, None, 0..20, &["Bo".to_string()]),
+            vec![Some(1)]
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+use super::Grammar;
+
+/// Name of the `Strategy::Uniform` strategy in grammar files
+const UNIFORM: &str = "uniform";
+/// Name of the `Strategy::Weighted` strategy in grammar files
+const WEIGHTED: &str = "weighted";
+/// Name of the `Strategy::ShuffleBag` strategy in grammar files
+const SHUFFLE_BAG: &str = "shuffleBag";
+/// Name of the `Strategy::NoRepeat` strategy in grammar files, followed by `:` and its number of rules
+const NO_REPEAT: &str = "noRepeat";
+/// Name of the `Strategy::Sequential` strategy in grammar files
+const SEQUENTIAL: &str = "sequential";
+
+/// How a grammar selects the rule of a symbol, each time it is derived.
+///
+/// In grammar files, a symbol defined by an object declares its strategy by name:
+/// `{"rules": [...], "strategy": "shuffleBag"}`, or `"noRepeat:2"`.
+///
+/// ```
+/// use vitrail::grammar::Strategy;
+///
+/// assert_eq!("noRepeat:2".parse(), Ok(Strategy::NoRepeat(2)));
+/// assert_eq!(Strategy::ShuffleBag.to_string(), "shuffleBag");
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Strategy {
+    /// Every rule is equally likely, regardless of its weight
+    Uniform,
+    /// Rules are drawn proportionally to their weight, as biased by the session and variety boosting
+    #[default]
+    Weighted,
+    /// Every rule is selected once, in a random order, before any rule is selected again
+    ShuffleBag,
+    /// Rules are drawn as `Weighted` ones, except the given number of rules selected last
+    NoRepeat(usize),
+    /// Rules are selected in order, starting over after the last one. The position is kept by the `Session`
+    /// the grammar is flattened in, if any, so it can be saved between runs.
+    Sequential,
+}
+
+impl fmt::Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Strategy::Uniform => write!(f, "{}", UNIFORM),
+            Strategy::Weighted => write!(f, "{}", WEIGHTED),
+            Strategy::ShuffleBag => write!(f, "{}", SHUFFLE_BAG),
+            Strategy::NoRepeat(count) => write!(f, "{}:{}", NO_REPEAT, count),
+            Strategy::Sequential => write!(f, "{}", SEQUENTIAL),
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            UNIFORM => Ok(Strategy::Uniform),
+            WEIGHTED => Ok(Strategy::Weighted),
+            SHUFFLE_BAG => Ok(Strategy::ShuffleBag),
+            SEQUENTIAL => Ok(Strategy::Sequential),
+            _ => name
+                .strip_prefix(NO_REPEAT)
+                .and_then(|count| count.strip_prefix(':'))
+                .and_then(|count| count.parse().ok())
+                .map(Strategy::NoRepeat)
+                .ok_or_else(|| format!("unknown selection strategy '{}'", name)),
+        }
+    }
+}
+
+/// The selections a strategy keeps track of, for a symbol
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct StrategyState {
+    /// The rules left in the bag, drawn from the end
+    bag: Vec<usize>,
+    /// The rules selected last, most recent last
+    recent: VecDeque<usize>,
+    /// The rule selected next in order
+    next: usize,
+}
+
+impl Grammar<'_> {
+    /// Set how the rules of a symbol are selected, replacing its previous strategy and forgetting its
+    /// previous selections (e.g. the rules left in its shuffle bag).
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::{Grammar, Strategy},
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["1".to_string(), "2".to_string()]);
+    /// grammar.set_strategy("root", Strategy::Sequential);
+    ///
+    /// assert_eq!(grammar.flatten().unwrap(), "1");
+    /// assert_eq!(grammar.flatten().unwrap(), "2");
+    /// assert_eq!(grammar.flatten().unwrap(), "1");
+    /// ```
+    pub fn set_strategy(&mut self, symbol: &str, strategy: Strategy) {
+        self.strategy_states.remove(symbol);
+        if strategy == Strategy::default() {
+            self.strategies.remove(symbol);
+        } else {
+            self.strategies.insert(symbol.to_string(), strategy);
+        }
+    }
+
+    /// Returns how the rules of a symbol are selected
+    pub fn strategy(&self, symbol: &str) -> Strategy {
+        self.strategies.get(symbol).copied().unwrap_or_default()
+    }
+
+    /// Select the index of a rule of the symbol, according to its strategy
+    pub(super) fn select_rule(&mut self, symbol: &str, rule_count: usize) -> usize {
+        let strategy = self.strategy(symbol);
+        if strategy == Strategy::Weighted {
+            return self.random_rule(symbol, rule_count);
+        }
+
+        let mut state = self.strategy_states.remove(symbol).unwrap_or_default();
+        let index = match strategy {
+            Strategy::Uniform => self.rng.index(rule_count),
+            Strategy::ShuffleBag => {
+                state.bag.retain(|index| *index < rule_count);
+                if state.bag.is_empty() {
+                    state.bag = (0..rule_count).collect();
+                    self.rng.shuffle(&mut state.bag);
+                }
+                state.bag.pop().unwrap_or_default()
+            }
+            Strategy::NoRepeat(count) => {
+                let mut weights = self.rule_weights(symbol, rule_count);
+                let excluded = state.recent.len().saturating_sub(count);
+                let is_available = |index: usize, weight: &f64| {
+                    *weight > 0.0 && !state.recent.iter().skip(excluded).any(|i| *i == index)
+                };
+                // Repeating is better than selecting no rule
+                if weights.iter().enumerate().any(|(i, w)| is_available(i, w)) {
+                    for (index, weight) in weights.iter_mut().enumerate() {
+                        if !is_available(index, weight) {
+                            *weight = 0.0;
+                        }
+                    }
+                }
+
+                let index = self.rng.weighted_index(&weights);
+                state.recent.push_back(index);
+                while state.recent.len() > count {
+                    state.recent.pop_front();
+                }
+                index
+            }
+            Strategy::Sequential => {
+                // A session keeps the position between runs, e.g. for a tip of the day
+                let position = match &self.session {
+                    Some(session) => session.sequence_position(symbol),
+                    None => state.next,
+                };
+                let index = position % rule_count;
+                state.next = index + 1;
+                if let Some(session) = self.session.as_mut() {
+                    session.set_sequence_position(symbol, index + 1);
+                }
+                index
+            }
+            Strategy::Weighted => unreachable!(),
+        };
+
+        self.strategy_states.insert(symbol.to_string(), state);
+        index
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+
+    fn strategic(rule_count: usize, strategy: Strategy) -> Grammar<'static> {
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::default()).with_symbol(
+            "root".to_string(),
+            (0..rule_count).map(|i| i.to_string()).collect(),
+        );
+        grammar.set_strategy("root", strategy);
+        grammar
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for strategy in [
+            Strategy::Uniform,
+            Strategy::Weighted,
+            Strategy::ShuffleBag,
+            Strategy::NoRepeat(3),
+            Strategy::Sequential,
+        ] {
+            assert_eq!(strategy.to_string().parse(), Ok(strategy));
+        }
+        assert!("noRepeat".parse::<Strategy>().is_err());
+        assert!("random".parse::<Strategy>().is_err());
+    }
+
+    #[test]
+    fn shuffle_bag_selects_every_rule_once_per_round() {
+        let mut grammar = strategic(5, Strategy::ShuffleBag);
+        for _ in 0..3 {
+            let mut round: Vec<String> = (0..5).map(|_| grammar.flatten().unwrap()).collect();
+            round.sort();
+            assert_eq!(round, vec!["0", "1", "2", "3", "4"]);
+        }
+    }
+
+    #[test]
+    fn no_repeat_skips_recent_rules() {
+        let mut grammar = strategic(3, Strategy::NoRepeat(2));
+        let outputs: Vec<String> = (0..30).map(|_| grammar.flatten().unwrap()).collect();
+        for window in outputs.windows(3) {
+            assert!(window[0] != window[1] && window[0] != window[2] && window[1] != window[2]);
+        }
+
+        let mut grammar = strategic(1, Strategy::NoRepeat(1));
+        assert_eq!(grammar.flatten().unwrap(), "0");
+        assert_eq!(grammar.flatten().unwrap(), "0");
+    }
+
+    #[test]
+    fn sequential_cycles_through_rules() {
+        let mut grammar = strategic(3, Strategy::Sequential);
+        let outputs: Vec<String> = (0..4).map(|_| grammar.flatten().unwrap()).collect();
+        assert_eq!(outputs, vec!["0", "1", "2", "0"]);
+
+        grammar.set_strategy("root", Strategy::Sequential);
+        assert_eq!(grammar.flatten().unwrap(), "0");
+    }
+
+    #[test]
+    fn sequence_position_is_kept_by_session() {
+        use crate::grammar::Session;
+
+        let mut grammar = strategic(3, Strategy::Sequential);
+        let mut session = Session::new();
+        session.set_sequence_position("root", 4);
+        assert_eq!(session.flatten(&mut grammar), "1");
+        assert_eq!(session.sequence_position("root"), 2);
+
+        let saved = serde_json::to_string(&session).unwrap();
+        let mut grammar = strategic(3, Strategy::Sequential);
+        let mut session: Session = serde_json::from_str(&saved).unwrap();
+        assert_eq!(session.flatten(&mut grammar), "2");
+        assert_eq!(session.flatten(&mut grammar), "0");
+    }
+
+    #[test]
+    fn uniform_ignores_weights() {
+        use crate::grammar::RuleOptions;
+
+        let mut grammar = strategic(2, Strategy::Uniform).with_rule_options(
+            "root",
+            0,
+            RuleOptions::default().with_weight(0.0),
+        );
+        assert!((0..50).any(|_| grammar.flatten().unwrap() == "0"));
+    }
+}
@@ -0,0 +1,253 @@
+use std::borrow::Cow;
+
+use crate::config::GrammarSyntax;
+
+/// A top-level part of a rule, as read by the analysis and export tools
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Token<'a> {
+    /// Literal text, without its escape characters
+    Text(Cow<'a, str>),
+    /// The content of a symbol expression, between the symbol delimiters (symbol, built-in, expression or block keyword)
+    Symbol(&'a str),
+    /// The content of a capture expression, between the capture delimiters
+    Capture(&'a str),
+}
+
+/// Split a rule into its literal text, symbol and capture expressions, in order.
+/// An unterminated expression is read as literal text.
+pub(crate) fn tokenize<'a>(syntax: &GrammarSyntax, rule: &'a str) -> Vec<Token<'a>> {
+    let mut tokens = Vec::new();
+    let mut literal_start = 0;
+    let mut cursor = 0;
+
+    while let Some((start, opening)) = syntax_chars(syntax, &rule[cursor..])
+        .find(|(_, c)| *c == syntax.symbol_start || *c == syntax.capture_start)
+        .map(|(i, c)| (cursor + i, c))
+    {
+        let closing = if opening == syntax.symbol_start {
+            syntax.symbol_end
+        } else {
+            syntax.capture_end
+        };
+        let content_start = start + opening.len_utf8();
+        let is_symbol = opening == syntax.symbol_start;
+
+        let length = if is_symbol && syntax.symbol_start == syntax.symbol_end {
+            find_symbol_end(syntax, &rule[content_start..])
+        } else if is_symbol {
+            find_nested_symbol_end(syntax, &rule[content_start..])
+                .or_else(|| find_unescaped(syntax, &rule[content_start..], closing))
+        } else {
+            find_unescaped(syntax, &rule[content_start..], closing)
+        };
+        match length {
+            Some(length) => {
+                if literal_start < start {
+                    tokens.push(Token::Text(unescape(syntax, &rule[literal_start..start])));
+                }
+
+                let mut content = &rule[content_start..content_start + length];
+                if is_symbol {
+                    // Captures leading a symbol expression (`#[hero:#name#]story#`) are executed before it
+                    while let Some((capture, rest)) = content
+                        .strip_prefix(syntax.capture_start)
+                        .and_then(|content| content.split_once(syntax.capture_end))
+                    {
+                        tokens.push(Token::Capture(capture));
+                        content = rest;
+                    }
+                    tokens.push(Token::Symbol(content));
+                } else {
+                    tokens.push(Token::Capture(content));
+                }
+
+                cursor = content_start + length + closing.len_utf8();
+                literal_start = cursor;
+            }
+            None => break,
+        }
+    }
+
+    if literal_start < rule.len() {
+        tokens.push(Token::Text(unescape(syntax, &rule[literal_start..])));
+    }
+
+    tokens
+}
+
+/// Returns the position of the delimiter ending a symbol expression, skipping the symbol expressions nested
+/// in its name (`{{gender}_title}`)
+fn find_nested_symbol_end(syntax: &GrammarSyntax, content: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, character) in syntax_chars(syntax, content) {
+        if character == syntax.symbol_start {
+            depth += 1;
+        } else if character == syntax.symbol_end {
+            if depth == 0 {
+                return Some(i);
+            }
+            depth -= 1;
+        }
+    }
+
+    None
+}
+
+/// Returns the position of the delimiter ending a symbol expression whose delimiters are identical,
+/// skipping the ones of the symbols within its captures
+fn find_symbol_end(syntax: &GrammarSyntax, content: &str) -> Option<usize> {
+    let mut in_capture = false;
+    for (i, character) in syntax_chars(syntax, content) {
+        if character == syntax.capture_start {
+            in_capture = true;
+        } else if character == syntax.capture_end {
+            in_capture = false;
+        } else if character == syntax.symbol_end && !in_capture {
+            return Some(i);
+        }
+    }
+
+    None
+}
+
+/// Returns the characters of a text which can be part of the syntax, with their position: all of them but the
+/// escaped ones and the escape characters before them
+pub(crate) fn syntax_chars<'t>(
+    syntax: &'t GrammarSyntax,
+    text: &'t str,
+) -> impl Iterator<Item = (usize, char)> + 't {
+    let mut characters = text.char_indices().peekable();
+    std::iter::from_fn(move || loop {
+        let (i, character) = characters.next()?;
+        let escapes = character == syntax.escape
+            && characters
+                .peek()
+                .is_some_and(|(_, next)| syntax.is_escapable(*next));
+        if !escapes {
+            return Some((i, character));
+        }
+        characters.next();
+    })
+}
+
+/// Returns the position of the first unescaped occurrence of a character in a text
+fn find_unescaped(syntax: &GrammarSyntax, text: &str, character: char) -> Option<usize> {
+    syntax_chars(syntax, text)
+        .find(|(_, c)| *c == character)
+        .map(|(i, _)| i)
+}
+
+/// Returns literal text without the escape characters before the characters they make literal
+pub(crate) fn unescape<'t>(syntax: &GrammarSyntax, text: &'t str) -> Cow<'t, str> {
+    if !text.contains(syntax.escape) {
+        return Cow::Borrowed(text);
+    }
+
+    let mut unescaped = String::with_capacity(text.len());
+    let mut characters = text.chars().peekable();
+    while let Some(character) = characters.next() {
+        match characters.peek() {
+            Some(next) if character == syntax.escape && syntax.is_escapable(*next) => {
+                unescaped.push(*next);
+                characters.next();
+            }
+            _ => unescaped.push(character),
+        }
+    }
+
+    Cow::Owned(unescaped)
+}
+
+/// Returns the name of the symbol referenced by a symbol expression, without its modifiers,
+/// or `None` if the expression is a built-in, an arithmetic expression, or a block keyword
+pub(crate) fn symbol_name<'a>(syntax: &GrammarSyntax, expression: &'a str) -> Option<&'a str> {
+    if syntax.is_builtin(expression)
+        || syntax.is_expression(expression)
+        || super::block::is_keyword(syntax, expression)
+    {
+        return None;
+    }
+
+    expression.split(syntax.modifier_operator).next()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_rule() {
+        let syntax = GrammarSyntax::default();
+        assert_eq!(
+            tokenize(&syntax, "[a>b]Hello {name:capitalize}!"),
+            vec![
+                Token::Capture("a>b"),
+                Token::Text("Hello ".into()),
+                Token::Symbol("name:capitalize"),
+                Token::Text("!".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_unterminated() {
+        let syntax = GrammarSyntax::default();
+        assert_eq!(
+            tokenize(&syntax, "{a}{b"),
+            vec![Token::Symbol("a"), Token::Text("{b".into())]
+        );
+    }
+
+    #[test]
+    fn tokenize_nested_symbol_names() {
+        let syntax = GrammarSyntax::default();
+        assert_eq!(
+            tokenize(&syntax, "{{gender}_title} {a {b}"),
+            vec![
+                Token::Symbol("{gender}_title"),
+                Token::Text(" ".into()),
+                Token::Symbol("a {b"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_identical_delimiters() {
+        let syntax = GrammarSyntax::tracery();
+        assert_eq!(
+            tokenize(&syntax, "#[hero:#name#]story# and #a.s#"),
+            vec![
+                Token::Capture("hero:#name#"),
+                Token::Symbol("story"),
+                Token::Text(" and ".into()),
+                Token::Symbol("a.s"),
+            ]
+        );
+    }
+
+    #[test]
+    fn tokenize_escaped_delimiters() {
+        let syntax = GrammarSyntax::default();
+        assert_eq!(
+            tokenize(&syntax, r"\{a\}{b}\\{c}\[d] \n"),
+            vec![
+                Token::Text("{a}".into()),
+                Token::Symbol("b"),
+                Token::Text("\\".into()),
+                Token::Symbol("c"),
+                Token::Text(r"[d] \n".into()),
+            ]
+        );
+        assert_eq!(tokenize(&syntax, r"{a\}b}"), vec![Token::Symbol(r"a\}b")]);
+    }
+
+    #[test]
+    fn symbol_names() {
+        let syntax = GrammarSyntax::default();
+        assert_eq!(symbol_name(&syntax, "name:capitalize"), Some("name"));
+        assert_eq!(symbol_name(&syntax, "@uuid"), None);
+        assert_eq!(symbol_name(&syntax, "#a+1"), None);
+        assert_eq!(symbol_name(&syntax, "switch:weather"), None);
+        assert_eq!(symbol_name(&syntax, "end"), None);
+    }
+}
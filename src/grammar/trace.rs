@@ -0,0 +1,542 @@
+use std::fmt;
+use std::ops::Range;
+
+use serde::Serialize;
+
+use super::{Grammar, SourceLocation};
+use crate::choice::ChoiceMode;
+use crate::rng::SeededRng;
+
+/// A derived symbol, in the trace of a flatten built by `Grammar::flatten_traced`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DerivationNode {
+    /// The position of the node in the tree, numbered from 0 for the root, parents before their children
+    pub id: usize,
+    /// The derived symbol
+    pub symbol: String,
+    /// The index of the rule selected for the symbol, or `None` if its value was captured, pinned or dynamic
+    pub rule: Option<usize>,
+    /// The rule or value the symbol was derived to, before the expansion of its own symbols
+    pub derivation: String,
+    /// The modifiers applied to the expansion of the symbol, in order, with their arguments as written: `pad(12,'.')`
+    pub modifiers: Vec<String>,
+    /// The expansion of the symbol, after its modifiers and before its output decoration
+    pub output: String,
+    /// Where the output of the symbol is, as a byte range in the output of the root node, e.g. to highlight
+    /// which part of a text came from which symbol. `None` if it is not part of the text as is: captured,
+    /// used by a switch block, an arithmetic expression or the name of another symbol, or derived
+    /// within a symbol whose modifiers changed its text.
+    pub span: Option<Range<usize>>,
+    /// Where the selected rule is written, if it was read from a file
+    pub source: Option<SourceLocation>,
+    /// The symbols derived while expanding this one, in order
+    pub children: Vec<DerivationNode>,
+}
+
+impl DerivationNode {
+    fn new(symbol: &str) -> Self {
+        Self {
+            id: 0,
+            symbol: symbol.to_string(),
+            rule: None,
+            derivation: String::new(),
+            modifiers: Vec::new(),
+            output: String::new(),
+            span: None,
+            source: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Returns the node with the given id, among this one and its descendants
+    pub fn find(&self, id: usize) -> Option<&DerivationNode> {
+        if self.id == id {
+            return Some(self);
+        }
+
+        self.children.iter().find_map(|child| child.find(id))
+    }
+
+    /// Returns the number of nodes in the subtree of this node, itself included
+    pub fn size(&self) -> usize {
+        1 + self
+            .children
+            .iter()
+            .map(DerivationNode::size)
+            .sum::<usize>()
+    }
+
+    /// Add the rules selected in the subtree of this node to the given list, parents before their children,
+    /// along with the id of their node
+    fn collect_rules(&self, rules: &mut Vec<(usize, usize)>) {
+        if let Some(rule) = self.rule {
+            rules.push((self.id, rule));
+        }
+        for child in &self.children {
+            child.collect_rules(rules);
+        }
+    }
+
+    /// Number the node and its descendants from the given id, parents before their children,
+    /// and return the id following the last one
+    fn number(&mut self, id: usize) -> usize {
+        self.id = id;
+        self.children
+            .iter_mut()
+            .fold(id + 1, |next, child| child.number(next))
+    }
+
+    /// Turn the spans of the children, relative to the output of this node while the tree is built,
+    /// into spans in the output of the root node
+    fn resolve_spans(&mut self) {
+        for child in &mut self.children {
+            child.span = match (&self.span, &child.span) {
+                (Some(span), Some(relative)) => {
+                    Some(span.start + relative.start..span.start + relative.end)
+                }
+                _ => None,
+            };
+            child.resolve_spans();
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter<'_>, indent: usize) -> fmt::Result {
+        let rule = match self.rule {
+            Some(rule) => format!("[{}]", rule),
+            None => String::new(),
+        };
+        write!(
+            f,
+            "{:indent$}#{} {}{} -> {:?}",
+            "",
+            self.id,
+            self.symbol,
+            rule,
+            self.output,
+            indent = indent * 2,
+        )?;
+        match &self.source {
+            Some(source) => writeln!(f, " ({})", source)?,
+            None => writeln!(f)?,
+        }
+
+        self.children
+            .iter()
+            .try_for_each(|child| child.write(f, indent + 1))
+    }
+}
+
+/// The symbols derived by a flatten, each with the rule selected for it and the symbols derived by its expansion,
+/// so grammar authors can see why an output came out the way it did
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DerivationTree {
+    /// The node of the root symbol
+    pub root: DerivationNode,
+}
+
+impl DerivationTree {
+    /// Returns the node with the given id, if any
+    pub fn node(&self, id: usize) -> Option<&DerivationNode> {
+        self.root.find(id)
+    }
+}
+
+impl fmt::Display for DerivationTree {
+    /// One node per line, indented under its parent: `#1 name[0] -> "world"`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.root.write(f, 0)
+    }
+}
+
+/// The derivation tree of the flatten in progress, while it is being traced
+#[derive(Debug, Default)]
+pub(super) struct TraceBuilder {
+    /// The nodes of the symbols being expanded, outermost first, with their children completed so far
+    open: Vec<DerivationNode>,
+    /// The completed nodes derived outside of any other, i.e. the root
+    completed: Vec<DerivationNode>,
+    /// The number of nodes opened so far, so the id of the next one, discarded nodes excepted
+    opened: usize,
+    /// The id of the node whose subtree is derived with fresh random choices, along with the seed to draw them from
+    reroll: Option<(usize, String)>,
+}
+
+impl TraceBuilder {
+    /// Returns the completed children of the innermost node being expanded
+    fn children(&mut self) -> &mut Vec<DerivationNode> {
+        match self.open.last_mut() {
+            Some(node) => &mut node.children,
+            None => &mut self.completed,
+        }
+    }
+}
+
+impl Grammar<'_> {
+    /// Expand the whole grammar from the default root symbol, and return the expanded string along with
+    /// the tree of its derivations: the rule selected for each symbol, and the symbols derived by its expansion.
+    ///
+    /// The tree prints as an indented outline, to debug the choices made by a grammar.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["Hello {name}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["world".to_string()]);
+    ///
+    /// let (output, tree) = grammar.flatten_traced();
+    /// assert_eq!(output, "Hello world");
+    /// assert_eq!(tree.root.children[0].symbol, "name");
+    /// assert_eq!(tree.to_string(), "#0 root[0] -> \"Hello world\"\n  #1 name[0] -> \"world\"\n");
+    /// ```
+    pub fn flatten_traced(&mut self) -> (String, DerivationTree) {
+        let root = self.root().to_string();
+        self.flatten_traced_from(&root, TraceBuilder::default())
+    }
+
+    /// Expand the grammar from a given root symbol, and return the tree of its derivations rather than the text,
+    /// e.g. for tooling highlighting which part of a sentence came from which symbol. The text is the output of
+    /// the root node, before the decoration of the whole document.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("greeting".to_string(), vec!["Hello {name:capitalize}!".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["world".to_string()])
+    ///     .with_standard_modifiers();
+    ///
+    /// let tree = grammar.derive_tree("greeting");
+    /// let name = &tree.root.children[0];
+    /// assert_eq!(name.derivation, "world");
+    /// assert_eq!(name.modifiers, ["capitalize"]);
+    /// assert_eq!(&tree.root.output[name.span.clone().unwrap()], "World");
+    /// ```
+    pub fn derive_tree(&mut self, root: &str) -> DerivationTree {
+        self.flatten_traced_from(root, TraceBuilder::default()).1
+    }
+
+    /// Expand the grammar again as it was in a traced flatten, except the subtree of the given node, whose symbol
+    /// is derived with fresh random choices, e.g. to change an adjective in an otherwise liked sentence.
+    /// Returns the new output with its own derivation tree, or `None` if the tree has no such node.
+    ///
+    /// The rules outside of the subtree are replayed from the tree, like a choice script, so the same caveats apply:
+    /// values of dynamic symbols and random built-ins are not kept, and the replay panics if the new subtree changes
+    /// which symbols are derived after it, e.g. through a switch block, unless the error handler decides otherwise.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["A {adjective} {animal}".to_string()])
+    ///     .with_symbol("adjective".to_string(), vec!["red".to_string(), "shy".to_string(), "tiny".to_string()])
+    ///     .with_symbol("animal".to_string(), vec!["fox".to_string(), "owl".to_string(), "cat".to_string()]);
+    ///
+    /// let (output, tree) = grammar.flatten_traced();
+    /// let adjective = tree.root.children[0].id;
+    /// let (rerolled, _) = grammar.reroll_subtree(&tree, adjective).unwrap();
+    /// assert_eq!(rerolled.split(' ').last(), output.split(' ').last());
+    /// ```
+    pub fn reroll_subtree(
+        &mut self,
+        tree: &DerivationTree,
+        node_id: usize,
+    ) -> Option<(String, DerivationTree)> {
+        let subtree = tree.node(node_id)?.size();
+        let mut rules = Vec::new();
+        tree.root.collect_rules(&mut rules);
+        let replayed: Vec<usize> = rules
+            .into_iter()
+            .filter(|(id, _)| *id < node_id || *id >= node_id + subtree)
+            .map(|(_, rule)| rule)
+            .collect();
+
+        let seed = format!("{}#{}", self.seed, self.rng.hex(16));
+        let builder = TraceBuilder {
+            reroll: Some((node_id, seed)),
+            ..TraceBuilder::default()
+        };
+        let mut grammar = self.choosing(ChoiceMode::Replaying(replayed.into_iter()), None);
+
+        Some(grammar.flatten_traced_from(&tree.root.symbol, builder))
+    }
+
+    /// Expand the grammar from a root symbol, building its derivation tree with the given builder
+    fn flatten_traced_from(
+        &mut self,
+        root: &str,
+        builder: TraceBuilder,
+    ) -> (String, DerivationTree) {
+        self.trace = Some(builder);
+        let output = self.flatten_from_root_unchecked(root);
+        let builder = self.trace.take().unwrap_or_default();
+
+        let mut root = builder
+            .completed
+            .into_iter()
+            .next()
+            .unwrap_or_else(|| DerivationNode::new(root));
+        root.number(0);
+        root.span = Some(0..root.output.len());
+        root.resolve_spans();
+
+        (output, DerivationTree { root })
+    }
+
+    /// Derive a symbol and expand its derivation, adding it to the derivation tree when the flatten is traced
+    pub(super) fn expand_symbol(&mut self, symbol: &str) -> String {
+        let first_selection = self.selections.len();
+        let mut reroll = None;
+        if let Some(trace) = self.trace.as_mut() {
+            trace.open.push(DerivationNode::new(symbol));
+            match &trace.reroll {
+                Some((id, seed)) if *id == trace.opened => reroll = Some(SeededRng::new(seed)),
+                _ => {}
+            }
+            trace.opened += 1;
+        }
+
+        let (derivation, expansion) = match reroll {
+            Some(rng) => {
+                let mut grammar = self.choosing(ChoiceMode::Random, Some(rng));
+                let derivation = grammar.derive_symbol_unchecked(symbol);
+                let expansion = grammar.expand(&derivation);
+                (derivation, expansion)
+            }
+            None => {
+                let derivation = self.derive_symbol_unchecked(symbol);
+                let expansion = self.expand(&derivation);
+                (derivation, expansion)
+            }
+        };
+
+        let rule = match self.selections.get(first_selection) {
+            Some(selection) if selection.symbol() == symbol => Some(selection.rule()),
+            _ => None,
+        };
+        let source = match (&self.trace, rule) {
+            (Some(_), Some(rule)) => self.rule_source(symbol, rule).cloned(),
+            _ => None,
+        };
+        if let Some(trace) = self.trace.as_mut() {
+            if let Some(mut node) = trace.open.pop() {
+                node.rule = rule;
+                node.source = source;
+                node.derivation = derivation;
+                node.output = expansion.clone();
+                trace.children().push(node);
+            }
+        }
+
+        expansion
+    }
+
+    /// Returns the number of nodes completed so far under the node being expanded, when the flatten is traced
+    pub(super) fn traced_children(&mut self) -> usize {
+        self.trace
+            .as_mut()
+            .map_or(0, |trace| trace.children().len())
+    }
+
+    /// Update the node completed last once its modifiers are applied. The text of its descendants
+    /// is no longer found in its output if they changed it.
+    pub(super) fn trace_modifiers(&mut self, modifiers: &[&str], output: &str) {
+        if let Some(node) = self
+            .trace
+            .as_mut()
+            .and_then(|trace| trace.children().last_mut())
+        {
+            node.modifiers = modifiers.iter().map(|name| name.to_string()).collect();
+            if node.output != output {
+                node.output = output.to_string();
+                for child in &mut node.children {
+                    child.span = None;
+                }
+            }
+        }
+    }
+
+    /// Set where the output of the symbol completed last is in the expansion of its parent, once the expansion
+    /// of its expression, decoration included, is added at the given position. Nothing is set unless the node
+    /// was completed after the given number of nodes, by that expression.
+    pub(super) fn trace_span(&mut self, completed_before: usize, position: usize) {
+        let trace = match self.trace.as_mut() {
+            Some(trace) => trace,
+            None => return,
+        };
+        let children = trace.children();
+        if children.len() <= completed_before {
+            return;
+        }
+
+        if let Some(node) = children.last_mut() {
+            let start = position
+                + self
+                    .output_profile
+                    .decorations
+                    .get(&node.symbol)
+                    .map_or(0, |decoration| decoration.prefix.len());
+            node.span = Some(start..start + node.output.len());
+        }
+    }
+
+    /// Remove the nodes completed from the given position on, except the one at the given offset from it,
+    /// e.g. to keep only the expansion kept among the attempts to fit a length hint
+    pub(super) fn keep_traced_attempt(&mut self, first: usize, kept: usize) {
+        if let Some(trace) = self.trace.as_mut() {
+            let children = trace.children();
+            if first + kept < children.len() {
+                let node = children.swap_remove(first + kept);
+                let discarded: usize = children.drain(first..).map(|node| node.size()).sum();
+                children.push(node);
+                trace.opened -= discarded;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Decoration, GrammarSyntax, OutputProfile};
+    use std::collections::HashSet;
+    use std::panic::{self, AssertUnwindSafe};
+
+    fn grammar() -> Grammar<'static> {
+        Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol(
+                "root".to_string(),
+                vec!["[hero>name]{name} met {creature:capitalize}".to_string()],
+            )
+            .with_symbol("hero".to_string(), vec!["Ada".to_string()])
+            .with_symbol(
+                "creature".to_string(),
+                vec!["a {size} troll".to_string(), "an {size} owl".to_string()],
+            )
+            .with_symbol("size".to_string(), vec!["odd".to_string()])
+            .with_standard_modifiers()
+    }
+
+    #[test]
+    fn traced_selections_and_captures() {
+        let mut grammar = grammar();
+        let (output, tree) = grammar.flatten_traced();
+        assert!(output.starts_with("Ada met A"), "{}", output);
+
+        let root = &tree.root;
+        assert_eq!((root.symbol.as_str(), root.rule), ("root", Some(0)));
+        let symbols: Vec<&str> = root
+            .children
+            .iter()
+            .map(|node| node.symbol.as_str())
+            .collect();
+        assert_eq!(symbols, ["hero", "name", "creature"]);
+
+        // The captured variable has no rule, and the modifier applies to the output of its symbol
+        assert_eq!(root.children[1].rule, None);
+        assert_eq!(root.children[1].output, "Ada");
+        let creature = &root.children[2];
+        assert!(creature.output.starts_with('A'));
+        assert_eq!(creature.children[0].symbol, "size");
+        assert_eq!(tree.node(4), Some(&creature.children[0]));
+        assert_eq!(tree.node(5), None);
+    }
+
+    #[test]
+    fn spans_locate_the_outputs_of_symbols() {
+        let mut grammar = grammar().with_output_profile(
+            OutputProfile::default().with_decoration("size", Decoration::html("em")),
+        );
+        grammar.symbols.insert(
+            "root".to_string(),
+            vec!["[hero>name]{name}, {creature} and {creature:capitalize}".to_string()],
+        );
+        let tree = grammar.derive_tree("root");
+        let root = &tree.root;
+        let text = |node: &DerivationNode| node.span.clone().map(|span| &root.output[span]);
+
+        assert_eq!(text(root), Some(root.output.as_str()));
+        // The captured derivation is not part of the text, its variable is
+        assert_eq!(text(&root.children[0]), None);
+        assert_eq!(text(&root.children[1]), Some("Ada"));
+
+        // The decoration is around the span of the decorated symbol
+        let creature = &root.children[2];
+        assert_eq!(text(creature), Some(creature.output.as_str()));
+        assert_eq!(text(&creature.children[0]), Some("odd"));
+        assert!(creature.output.contains("<em>odd</em>"));
+
+        // A modifier changing the text of a symbol leaves its descendants without span
+        let capitalized = &root.children[3];
+        assert_eq!(capitalized.modifiers, ["capitalize"]);
+        assert_eq!(text(capitalized), Some(capitalized.output.as_str()));
+        assert_eq!(capitalized.children[0].span, None);
+    }
+
+    #[test]
+    fn untraced_flattens_build_no_tree() {
+        let mut grammar = grammar();
+        grammar.flatten_traced();
+        grammar.flatten().unwrap();
+        assert!(grammar.trace.is_none());
+    }
+
+    #[test]
+    fn reroll_keeps_everything_outside_the_subtree() {
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["{line}|{line}|{line}".to_string()])
+            .with_symbol(
+                "line".to_string(),
+                vec!["{a} {b}".to_string(), "{b}".to_string()],
+            )
+            .with_symbol("a".to_string(), (0..10).map(|n| n.to_string()).collect())
+            .with_symbol("b".to_string(), (10..20).map(|n| n.to_string()).collect());
+        let (output, tree) = grammar.flatten_traced();
+        let middle = &tree.root.children[1];
+        assert_eq!(grammar.reroll_subtree(&tree, tree.root.size()), None);
+
+        let mut changed = false;
+        for _ in 0..10 {
+            let (rerolled, new_tree) = grammar.reroll_subtree(&tree, middle.id).unwrap();
+            let before: Vec<&str> = output.split('|').collect();
+            let after: Vec<&str> = rerolled.split('|').collect();
+            assert_eq!((before[0], before[2]), (after[0], after[2]));
+            assert_eq!(new_tree.root.children[0], tree.root.children[0]);
+            changed |= before[1] != after[1];
+        }
+        assert!(changed);
+
+        // The grammar chooses randomly again afterwards
+        let outputs: HashSet<String> = (0..10).map(|_| grammar.flatten().unwrap()).collect();
+        assert!(outputs.len() > 1);
+    }
+
+    #[test]
+    fn panicking_rerolls_restore_the_choice_mode() {
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("root".to_string(), vec!["{a} {b}".to_string()])
+            .with_symbol("a".to_string(), vec!["x".to_string(), "y".to_string()])
+            .with_symbol("b".to_string(), vec!["z".to_string()]);
+        let (_, tree) = grammar.flatten_traced();
+        grammar
+            .symbols
+            .insert("b".to_string(), vec!["{missing}".to_string()]);
+        // Only the seed of the rerolled node is drawn from the random generator of the grammar
+        let mut rng = grammar.rng.clone();
+        rng.hex(16);
+
+        let rerolled = panic::catch_unwind(AssertUnwindSafe(|| grammar.reroll_subtree(&tree, 2)));
+        assert!(rerolled.is_err());
+        assert!(matches!(grammar.choice_mode, ChoiceMode::Random));
+        assert_eq!(grammar.rng, rng);
+    }
+}
@@ -0,0 +1,1382 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+use super::block;
+use super::capture;
+use super::loader;
+use super::token::{self, Token};
+use super::{Grammar, RuleOptions};
+use crate::config::CaptureForm;
+use crate::error::ExpansionError;
+use crate::modifier::call;
+use crate::text;
+
+/// Rules are near-duplicates when they are at most one edit apart per this number of characters
+/// of the shorter one
+const NEAR_DUPLICATE_LENGTH_PER_EDIT: usize = 10;
+
+/// A grammar defining no conventional root symbol (`root` or `origin`), with none set with `Grammar::with_root`,
+/// which `Grammar::flatten` cannot expand
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingRoot {
+    /// The root symbol expanded by default, `root`
+    pub symbol: String,
+}
+
+impl fmt::Display for MissingRoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "No root symbol ('{}') and none configured",
+            loader::ROOT_CONVENTIONS.join("' or '")
+        )
+    }
+}
+
+/// A symbol referenced by a rule, but neither defined by the grammar nor captured by any of its rules
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UndefinedSymbol {
+    /// The symbol owning the rule
+    pub symbol: String,
+    /// The index of the rule, among the rules of the symbol
+    pub rule: usize,
+    /// The name of the undefined symbol, as written in the rule
+    pub name: String,
+    /// The description of the symbol owning the rule, if it has one
+    pub description: Option<String>,
+}
+
+impl fmt::Display for UndefinedSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_rule(f, &self.symbol, self.rule, self.description.as_deref())?;
+        write!(f, " references undefined symbol '{}'", self.name)
+    }
+}
+
+/// A capture expression which cannot be executed, lacking its operator, source or variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MalformedCapture {
+    /// The symbol owning the rule
+    pub symbol: String,
+    /// The index of the rule, among the rules of the symbol
+    pub rule: usize,
+    /// The content of the capture expression, between its delimiters
+    pub capture: String,
+    /// The description of the symbol owning the rule, if it has one
+    pub description: Option<String>,
+}
+
+impl fmt::Display for MalformedCapture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_rule(f, &self.symbol, self.rule, self.description.as_deref())?;
+        write!(f, " has malformed capture '{}'", self.capture)
+    }
+}
+
+/// A symbol without any rule, whose derivation is an expansion error
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmptySymbol {
+    /// The symbol without rules
+    pub symbol: String,
+    /// The description of the symbol, if it has one
+    pub description: Option<String>,
+}
+
+impl fmt::Display for EmptySymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_symbol(f, &self.symbol, self.description.as_deref())?;
+        write!(f, " has no rules")
+    }
+}
+
+/// A symbol which no expansion from the root symbol can derive
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnreachableSymbol {
+    /// The unreachable symbol
+    pub symbol: String,
+    /// The root symbol of the grammar
+    pub root: String,
+    /// The description of the symbol, if it has one
+    pub description: Option<String>,
+}
+
+impl fmt::Display for UnreachableSymbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_symbol(f, &self.symbol, self.description.as_deref())?;
+        write!(f, " is not reachable from root symbol '{}'", self.root)
+    }
+}
+
+/// A modifier referenced by a rule, but not registered on the grammar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownModifier {
+    /// The symbol owning the rule
+    pub symbol: String,
+    /// The index of the rule, among the rules of the symbol
+    pub rule: usize,
+    /// The name of the modifier, as written in the rule
+    pub name: String,
+    /// The name of the registered modifier closest to the unknown one, if any is close enough
+    pub suggestion: Option<String>,
+    /// The description of the symbol owning the rule, if it has one
+    pub description: Option<String>,
+}
+
+impl fmt::Display for UnknownModifier {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_rule(f, &self.symbol, self.rule, self.description.as_deref())?;
+        write!(
+            f,
+            ": {}",
+            ExpansionError::UnknownModifier {
+                name: self.name.to_string(),
+                suggestion: self.suggestion.clone(),
+            }
+        )
+    }
+}
+
+/// A reference to a deprecated symbol, made by a rule
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeprecatedReference {
+    /// The symbol owning the rule
+    pub symbol: String,
+    /// The index of the rule, among the rules of the symbol
+    pub rule: usize,
+    /// The deprecated symbol referenced by the rule
+    pub deprecated: String,
+    /// The hint about the replacement of the deprecated symbol, if any
+    pub hint: Option<String>,
+    /// The description of the symbol owning the rule, if it has one
+    pub description: Option<String>,
+}
+
+impl fmt::Display for DeprecatedReference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_rule(f, &self.symbol, self.rule, self.description.as_deref())?;
+        write!(f, " references deprecated symbol '{}'", self.deprecated)?;
+        match &self.hint {
+            Some(hint) => write!(f, ": {}", hint),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A rule identical or nearly identical to a previous rule of its symbol, which skews the selection
+/// probabilities of the symbol towards their shared text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateRule {
+    /// The symbol owning the rules
+    pub symbol: String,
+    /// The index of the duplicate rule, among the rules of the symbol
+    pub rule: usize,
+    /// The index of the previous rule it duplicates
+    pub original: usize,
+    /// The edit distance between both rules, 0 for an exact duplicate
+    pub distance: usize,
+    /// The description of the symbol owning the rules, if it has one
+    pub description: Option<String>,
+}
+
+impl fmt::Display for DuplicateRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_rule(f, &self.symbol, self.rule, self.description.as_deref())?;
+        match self.distance {
+            0 => write!(f, " duplicates rule {}", self.original),
+            1 => write!(
+                f,
+                " nearly duplicates rule {} (1 edit apart)",
+                self.original
+            ),
+            distance => write!(
+                f,
+                " nearly duplicates rule {} ({} edits apart)",
+                self.original, distance
+            ),
+        }
+    }
+}
+
+/// A rule whose weight is negative, infinite or not a number, which is ignored as if it were zero, or a rule whose
+/// weight is so much larger than the weights of the other rules of its symbol it is likely a typo
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidWeight {
+    /// The symbol owning the rule
+    pub symbol: String,
+    /// The index of the rule, among the rules of the symbol
+    pub rule: usize,
+    /// The weight of the rule
+    pub weight: f64,
+    /// The description of the symbol owning the rule, if it has one
+    pub description: Option<String>,
+}
+
+impl fmt::Display for InvalidWeight {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_rule(f, &self.symbol, self.rule, self.description.as_deref())?;
+        if is_valid_weight(self.weight) {
+            write!(
+                f,
+                " has an outlying weight ({}), over {} times the typical weight of the rules of its symbol",
+                self.weight, WEIGHT_OUTLIER_RATIO
+            )
+        } else if self.weight.is_finite() {
+            write!(f, " has a negative weight ({})", self.weight)
+        } else {
+            write!(f, " has a non-finite weight ({})", self.weight)
+        }
+    }
+}
+
+/// A symbol whose expansions can be longer than its length hint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LengthHintOverrun {
+    /// The symbol declaring the hint
+    pub symbol: String,
+    /// The maximum expansion length declared by the hint, in graphemes
+    pub max_len: usize,
+    /// The length of the longest expansion of the symbol, or `None` if its expansions can grow without bound
+    pub longest: Option<usize>,
+    /// The description of the symbol, if it has one
+    pub description: Option<String>,
+}
+
+impl fmt::Display for LengthHintOverrun {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_symbol(f, &self.symbol, self.description.as_deref())?;
+        match self.longest {
+            Some(longest) => write!(
+                f,
+                " can expand to {} graphemes, over its hint of {}",
+                longest, self.max_len
+            ),
+            None => write!(
+                f,
+                " can expand without bound, over its hint of {} graphemes",
+                self.max_len
+            ),
+        }
+    }
+}
+
+/// A problem found in a grammar by `Grammar::validate`
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationIssue {
+    MissingRoot(MissingRoot),
+    UndefinedSymbol(UndefinedSymbol),
+    MalformedCapture(MalformedCapture),
+    EmptySymbol(EmptySymbol),
+    UnreachableSymbol(UnreachableSymbol),
+    UnknownModifier(UnknownModifier),
+    DeprecatedReference(DeprecatedReference),
+    DuplicateRule(DuplicateRule),
+    InvalidWeight(InvalidWeight),
+    LengthHintOverrun(LengthHintOverrun),
+}
+
+impl ValidationIssue {
+    /// Returns the symbol the issue is about
+    pub fn symbol(&self) -> &str {
+        match self {
+            ValidationIssue::MissingRoot(issue) => &issue.symbol,
+            ValidationIssue::UndefinedSymbol(issue) => &issue.symbol,
+            ValidationIssue::MalformedCapture(issue) => &issue.symbol,
+            ValidationIssue::EmptySymbol(issue) => &issue.symbol,
+            ValidationIssue::UnreachableSymbol(issue) => &issue.symbol,
+            ValidationIssue::UnknownModifier(issue) => &issue.symbol,
+            ValidationIssue::DeprecatedReference(issue) => &issue.symbol,
+            ValidationIssue::DuplicateRule(issue) => &issue.symbol,
+            ValidationIssue::InvalidWeight(issue) => &issue.symbol,
+            ValidationIssue::LengthHintOverrun(issue) => &issue.symbol,
+        }
+    }
+
+    /// Returns the index of the rule the issue is about, among the rules of its symbol, or `None` if the issue
+    /// is about the symbol as a whole
+    pub fn rule(&self) -> Option<usize> {
+        match self {
+            ValidationIssue::UndefinedSymbol(issue) => Some(issue.rule),
+            ValidationIssue::MalformedCapture(issue) => Some(issue.rule),
+            ValidationIssue::UnknownModifier(issue) => Some(issue.rule),
+            ValidationIssue::DeprecatedReference(issue) => Some(issue.rule),
+            ValidationIssue::DuplicateRule(issue) => Some(issue.rule),
+            ValidationIssue::InvalidWeight(issue) => Some(issue.rule),
+            ValidationIssue::MissingRoot(_)
+            | ValidationIssue::EmptySymbol(_)
+            | ValidationIssue::UnreachableSymbol(_)
+            | ValidationIssue::LengthHintOverrun(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationIssue::MissingRoot(issue) => issue.fmt(f),
+            ValidationIssue::UndefinedSymbol(issue) => issue.fmt(f),
+            ValidationIssue::MalformedCapture(issue) => issue.fmt(f),
+            ValidationIssue::EmptySymbol(issue) => issue.fmt(f),
+            ValidationIssue::UnreachableSymbol(issue) => issue.fmt(f),
+            ValidationIssue::UnknownModifier(issue) => issue.fmt(f),
+            ValidationIssue::DeprecatedReference(issue) => issue.fmt(f),
+            ValidationIssue::DuplicateRule(issue) => issue.fmt(f),
+            ValidationIssue::InvalidWeight(issue) => issue.fmt(f),
+            ValidationIssue::LengthHintOverrun(issue) => issue.fmt(f),
+        }
+    }
+}
+
+/// The problems found in a grammar by `Grammar::validate`, one per line when displayed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ValidationReport {
+    /// The problems found, grouped by kind, then sorted by symbol and rule
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    /// Returns whether no problem was found
+    pub fn is_empty(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for issue in &self.issues {
+            writeln!(f, "{}", issue)?;
+        }
+        Ok(())
+    }
+}
+
+/// Write the rule a validation message is about, with the description of its symbol, so the message
+/// can be understood without looking the symbol up
+fn write_rule(
+    f: &mut fmt::Formatter<'_>,
+    symbol: &str,
+    rule: usize,
+    description: Option<&str>,
+) -> fmt::Result {
+    write!(f, "Rule {} of symbol '{}'", rule, symbol)?;
+    match description {
+        Some(description) => write!(f, " ({})", description),
+        None => Ok(()),
+    }
+}
+
+/// Write the symbol a validation message is about, with its description
+fn write_symbol(
+    f: &mut fmt::Formatter<'_>,
+    symbol: &str,
+    description: Option<&str>,
+) -> fmt::Result {
+    write!(f, "Symbol '{}'", symbol)?;
+    match description {
+        Some(description) => write!(f, " ({})", description),
+        None => Ok(()),
+    }
+}
+
+/// How many times the median weight of the other rules of a symbol a rule weight must exceed to be reported as
+/// an outlier
+const WEIGHT_OUTLIER_RATIO: f64 = 50.0;
+
+/// Returns whether a rule weight can be used to select rules: finite, and not negative
+pub(super) fn is_valid_weight(weight: f64) -> bool {
+    weight.is_finite() && weight >= 0.0
+}
+
+/// Returns whether a valid rule weight is over `WEIGHT_OUTLIER_RATIO` times the median of the positive, valid
+/// weights of the other rules of its symbol
+fn is_outlying_weight(weight: f64, others: &[f64]) -> bool {
+    let mut others: Vec<f64> = others
+        .iter()
+        .copied()
+        .filter(|other| is_valid_weight(*other) && *other > 0.0)
+        .collect();
+    if others.is_empty() {
+        return false;
+    }
+    others.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let median = others[others.len() / 2];
+
+    weight > median * WEIGHT_OUTLIER_RATIO
+}
+
+/// The names a rule refers to
+#[derive(Default)]
+struct RuleReferences<'r> {
+    /// The symbols (or variables) expanded or captured by the rule
+    symbols: Vec<&'r str>,
+    /// The symbol names made of other symbols (`{gender}_title`), which can expand any symbol they match
+    patterns: Vec<&'r str>,
+    /// The variables captured by the rule
+    variables: Vec<&'r str>,
+    /// The content of the capture expressions which cannot be executed
+    malformed_captures: Vec<&'r str>,
+}
+
+impl Grammar<'_> {
+    /// Check the grammar for problems: the ones making some of its expansions fail (a missing root symbol, undefined
+    /// symbols, malformed captures, symbols without rules), and the ones which do not prevent it from being expanded, but are likely
+    /// mistakes (unreachable symbols, unknown modifiers, references to deprecated symbols, duplicate rules,
+    /// invalid rule weights, and symbols whose expansions can exceed their length hint).
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["Hello {name}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["Alice".to_string(), "Bob".to_string()]);
+    ///
+    /// assert!(grammar.validate().is_empty());
+    /// ```
+    pub fn validate(&self) -> ValidationReport {
+        // Without a root, every symbol would be reported as unreachable from it
+        let missing_root = if self.has_root() {
+            None
+        } else {
+            Some(MissingRoot {
+                symbol: self.root().to_string(),
+            })
+        };
+        let undefined_symbols = self
+            .undefined_symbols()
+            .into_iter()
+            .map(ValidationIssue::UndefinedSymbol);
+        let malformed_captures = self
+            .malformed_captures()
+            .into_iter()
+            .map(ValidationIssue::MalformedCapture);
+        let empty_symbols = self
+            .empty_symbols()
+            .into_iter()
+            .map(ValidationIssue::EmptySymbol);
+        let unreachable_symbols = match missing_root {
+            Some(_) => Vec::new(),
+            None => self.unreachable_symbols(),
+        }
+        .into_iter()
+        .map(ValidationIssue::UnreachableSymbol);
+        let unknown_modifiers = self
+            .unknown_modifiers()
+            .into_iter()
+            .map(ValidationIssue::UnknownModifier);
+        let deprecated_references = self
+            .deprecated_references()
+            .into_iter()
+            .map(ValidationIssue::DeprecatedReference);
+        let duplicate_rules = self
+            .duplicate_rules()
+            .into_iter()
+            .map(ValidationIssue::DuplicateRule);
+        let invalid_weights = self
+            .invalid_weights()
+            .into_iter()
+            .map(ValidationIssue::InvalidWeight);
+        let length_hint_overruns = self
+            .length_hint_overruns()
+            .into_iter()
+            .map(ValidationIssue::LengthHintOverrun);
+
+        ValidationReport {
+            issues: missing_root
+                .map(ValidationIssue::MissingRoot)
+                .into_iter()
+                .chain(undefined_symbols)
+                .chain(malformed_captures)
+                .chain(empty_symbols)
+                .chain(unreachable_symbols)
+                .chain(unknown_modifiers)
+                .chain(deprecated_references)
+                .chain(duplicate_rules)
+                .chain(invalid_weights)
+                .chain(length_hint_overruns)
+                .collect(),
+        }
+    }
+
+    /// Returns the references made by the rules of the grammar to symbols it does not define, and which are not
+    /// variables captured by any of its rules, or set on the grammar, sorted by symbol and rule.
+    ///
+    /// The rules of file-backed symbols are only checked once they are loaded.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["[name>hero]{hero} meets {colr} {animal}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["Ada".to_string()])
+    ///     .with_symbol("animal".to_string(), vec!["fox".to_string()]);
+    ///
+    /// assert_eq!(
+    ///     grammar.undefined_symbols()[0].to_string(),
+    ///     "Rule 0 of symbol 'root' references undefined symbol 'colr'"
+    /// );
+    /// ```
+    pub fn undefined_symbols(&self) -> Vec<UndefinedSymbol> {
+        let mut symbols: Vec<&String> = self.symbols.keys().collect();
+        symbols.sort();
+
+        let captured = self.captured_variables();
+
+        let mut undefined = Vec::new();
+        for symbol in symbols {
+            for (index, rule) in self.symbols[symbol].iter().enumerate() {
+                let mut reported: HashSet<&str> = HashSet::new();
+                for name in self.rule_references(rule).symbols {
+                    if !self.has_symbol(name) && !captured.contains(name) && reported.insert(name) {
+                        undefined.push(UndefinedSymbol {
+                            symbol: symbol.to_string(),
+                            rule: index,
+                            name: name.to_string(),
+                            description: self.descriptions.get(symbol).cloned(),
+                        });
+                    }
+                }
+            }
+        }
+
+        undefined
+    }
+
+    /// Returns the capture expressions of the rules of the grammar which cannot be executed, lacking their
+    /// operator, source or variable, sorted by symbol and rule.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["[name]{name}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["Ada".to_string()]);
+    ///
+    /// assert_eq!(grammar.malformed_captures()[0].capture, "name");
+    /// ```
+    pub fn malformed_captures(&self) -> Vec<MalformedCapture> {
+        let mut symbols: Vec<&String> = self.symbols.keys().collect();
+        symbols.sort();
+
+        let mut malformed = Vec::new();
+        for symbol in symbols {
+            for (index, rule) in self.symbols[symbol].iter().enumerate() {
+                for capture in self.rule_references(rule).malformed_captures {
+                    malformed.push(MalformedCapture {
+                        symbol: symbol.to_string(),
+                        rule: index,
+                        capture: capture.to_string(),
+                        description: self.descriptions.get(symbol).cloned(),
+                    });
+                }
+            }
+        }
+
+        malformed
+    }
+
+    /// Returns the symbols of the grammar without any rule, sorted by symbol
+    pub fn empty_symbols(&self) -> Vec<EmptySymbol> {
+        let mut empty: Vec<EmptySymbol> = self
+            .symbols
+            .iter()
+            .filter(|(_, rules)| rules.is_empty())
+            .map(|(symbol, _)| EmptySymbol {
+                symbol: symbol.to_string(),
+                description: self.descriptions.get(symbol).cloned(),
+            })
+            .collect();
+        empty.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+        empty
+    }
+
+    /// Returns the symbols of the grammar which no expansion from its root symbol can derive, sorted by symbol.
+    ///
+    /// Symbols can also be derived from code, or by the rules of file-backed symbols: nothing is reported
+    /// while a file-backed symbol reachable from the root is not loaded. A symbol name made of other symbols
+    /// (`{{gender}_title}`) reaches every symbol it can match.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["Hello {name}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["Ada".to_string()])
+    ///     .with_symbol("nmae".to_string(), vec!["Bob".to_string()]);
+    ///
+    /// assert_eq!(
+    ///     grammar.unreachable_symbols()[0].to_string(),
+    ///     "Symbol 'nmae' is not reachable from root symbol 'root'"
+    /// );
+    /// ```
+    pub fn unreachable_symbols(&self) -> Vec<UnreachableSymbol> {
+        let mut reached: HashSet<&str> = HashSet::new();
+        let mut pending: VecDeque<&str> = VecDeque::new();
+        pending.push_back(self.root());
+        while let Some(symbol) = pending.pop_front() {
+            if !reached.insert(symbol) {
+                continue;
+            }
+            if self.file_symbols.contains_key(symbol) {
+                return Vec::new();
+            }
+
+            for rule in self.symbols.get(symbol).into_iter().flatten() {
+                let references = self.rule_references(rule);
+                pending.extend(references.symbols);
+                for pattern in references.patterns {
+                    pending.extend(
+                        self.symbols
+                            .keys()
+                            .chain(self.file_symbols.keys())
+                            .filter(|symbol| self.matches_pattern(pattern, symbol))
+                            .map(String::as_str),
+                    );
+                }
+            }
+        }
+
+        let mut unreachable: Vec<UnreachableSymbol> = self
+            .symbols
+            .keys()
+            .chain(self.file_symbols.keys())
+            .filter(|symbol| !reached.contains(symbol.as_str()))
+            .map(|symbol| UnreachableSymbol {
+                symbol: symbol.to_string(),
+                root: self.root().to_string(),
+                description: self.descriptions.get(symbol).cloned(),
+            })
+            .collect();
+        unreachable.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+
+        unreachable
+    }
+
+    /// Returns the variables captured by any rule of the grammar
+    pub(super) fn captured_variables(&self) -> HashSet<&str> {
+        self.symbols
+            .values()
+            .flatten()
+            .flat_map(|rule| self.rule_references(rule).variables)
+            .collect()
+    }
+
+    /// Returns whether a symbol name made of other symbols (`{gender}_title`) can expand to the given symbol:
+    /// the symbol holds its literal parts in order, anything being allowed in place of its nested symbols
+    fn matches_pattern(&self, pattern: &str, symbol: &str) -> bool {
+        let mut parts: Vec<String> = vec![String::new()];
+        for token in token::tokenize(&self.syntax, pattern) {
+            match token {
+                Token::Text(text) => parts.last_mut().unwrap().push_str(&text),
+                Token::Symbol(_) | Token::Capture(_) => parts.push(String::new()),
+            }
+        }
+
+        let (first, last) = (&parts[0], &parts[parts.len() - 1]);
+        let mut rest = match symbol.strip_prefix(first.as_str()) {
+            Some(rest) if rest.len() >= last.len() || parts.len() == 1 => rest,
+            _ => return false,
+        };
+        if parts.len() == 1 {
+            return rest.is_empty();
+        }
+        if !rest.ends_with(last.as_str()) {
+            return false;
+        }
+        rest = &rest[..rest.len() - last.len()];
+
+        parts[1..parts.len() - 1]
+            .iter()
+            .all(|part| match rest.find(part.as_str()) {
+                Some(offset) => {
+                    rest = &rest[offset + part.len()..];
+                    true
+                }
+                None => false,
+            })
+    }
+
+    /// Returns the names a rule refers to, including the ones nested in a symbol name made of other symbols
+    /// (`{{gender}_title}`), or in the rule captured with `CaptureForm::VariableFirst`
+    fn rule_references<'r>(&self, rule: &'r str) -> RuleReferences<'r> {
+        let mut references = RuleReferences::default();
+        self.collect_references(rule, &mut references);
+        references
+    }
+
+    fn collect_references<'r>(&self, rule: &'r str, references: &mut RuleReferences<'r>) {
+        let add_symbol = |name: &'r str, references: &mut RuleReferences<'r>| {
+            if name.contains(self.syntax.symbol_start) {
+                references.patterns.push(name);
+                self.collect_references(name, references);
+            } else {
+                references.symbols.push(name);
+            }
+        };
+
+        for token in token::tokenize(&self.syntax, rule) {
+            match token {
+                Token::Symbol(expression) => {
+                    if let Some(name) = token::symbol_name(&self.syntax, expression)
+                        .or_else(|| block::condition_variable(&self.syntax, expression))
+                    {
+                        add_symbol(name, references);
+                    }
+                }
+                Token::Capture(expression) => {
+                    let (source, variable) = match capture::parse_capture(&self.syntax, expression)
+                    {
+                        Some((source, variable, _))
+                            if !source.is_empty() && !variable.is_empty() =>
+                        {
+                            (source, variable)
+                        }
+                        _ => {
+                            references.malformed_captures.push(expression);
+                            continue;
+                        }
+                    };
+
+                    references.variables.push(variable);
+                    if self.syntax.capture_form == CaptureForm::VariableFirst {
+                        self.collect_references(source, references);
+                    } else if !self.syntax.is_expression(source) {
+                        add_symbol(source, references);
+                    }
+                }
+                Token::Text(_) => {}
+            }
+        }
+    }
+
+    /// Returns the rules identical or nearly identical to a previous rule of their symbol, sorted by symbol
+    /// and rule. Rules are nearly identical when they are at most one edit apart per ten characters
+    /// of the shorter one, e.g. when they only differ by a typo or punctuation.
+    ///
+    /// Each duplicate is reported once, along with the closest previous rule.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol(
+    ///         "greeting".to_string(),
+    ///         vec!["Well met, traveller".to_string(), "Well met, traveler".to_string()],
+    ///     );
+    ///
+    /// assert_eq!(
+    ///     grammar.duplicate_rules()[0].to_string(),
+    ///     "Rule 1 of symbol 'greeting' nearly duplicates rule 0 (1 edit apart)"
+    /// );
+    /// ```
+    pub fn duplicate_rules(&self) -> Vec<DuplicateRule> {
+        let mut symbols: Vec<&String> = self.symbols.keys().collect();
+        symbols.sort();
+
+        let mut duplicates = Vec::new();
+        for symbol in symbols {
+            let rules = &self.symbols[symbol];
+            let lengths: Vec<usize> = rules.iter().map(|rule| rule.chars().count()).collect();
+
+            for (index, rule) in rules.iter().enumerate() {
+                let closest = (0..index)
+                    .filter_map(|previous| {
+                        let shorter = lengths[index].min(lengths[previous]);
+                        let max_distance = shorter / NEAR_DUPLICATE_LENGTH_PER_EDIT;
+                        if lengths[index].abs_diff(lengths[previous]) > max_distance {
+                            return None;
+                        }
+
+                        let distance = text::edit_distance(rule, &rules[previous]);
+                        (distance <= max_distance).then_some((distance, previous))
+                    })
+                    .min();
+
+                if let Some((distance, original)) = closest {
+                    duplicates.push(DuplicateRule {
+                        symbol: symbol.to_string(),
+                        rule: index,
+                        original,
+                        distance,
+                        description: self.descriptions.get(symbol).cloned(),
+                    });
+                }
+            }
+        }
+
+        duplicates
+    }
+
+    /// Returns the rules whose weight is negative, infinite or not a number, sorted by symbol and rule.
+    /// Such weights are ignored when selecting rules, as if they were zero. Rules whose weight is over 50 times
+    /// the median weight of the other rules of their symbol, like a `100` mistyped for `1.0`, are reported too.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::{Grammar, RuleOptions},
+    /// };
+    ///
+    /// let grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("coin".to_string(), vec!["heads".to_string(), "tails".to_string()])
+    ///     .with_rule_options("coin", 1, RuleOptions::default().with_weight(-1.0))
+    ///     .with_symbol("dice".to_string(), vec!["1".to_string(), "2".to_string(), "3".to_string()])
+    ///     .with_rule_options("dice", 2, RuleOptions::default().with_weight(100.0));
+    ///
+    /// assert_eq!(
+    ///     grammar.invalid_weights()[0].to_string(),
+    ///     "Rule 1 of symbol 'coin' has a negative weight (-1)"
+    /// );
+    /// assert_eq!(
+    ///     grammar.invalid_weights()[1].to_string(),
+    ///     "Rule 2 of symbol 'dice' has an outlying weight (100), over 50 times the typical weight of the rules of \
+    ///      its symbol"
+    /// );
+    /// ```
+    pub fn invalid_weights(&self) -> Vec<InvalidWeight> {
+        let mut symbols: Vec<&String> = self.rule_options.keys().collect();
+        symbols.sort();
+
+        let mut invalid = Vec::new();
+        for symbol in symbols {
+            let rules = self.symbols.get(symbol).map_or(0, Vec::len);
+            let mut weights: Vec<f64> = self.rule_options[symbol]
+                .iter()
+                .map(|options| options.weight)
+                .collect();
+            if weights.len() < rules {
+                weights.resize(rules, RuleOptions::default().weight);
+            }
+
+            for (index, &weight) in weights.iter().enumerate() {
+                let others: Vec<f64> = weights
+                    .iter()
+                    .enumerate()
+                    .filter(|(other, _)| *other != index)
+                    .map(|(_, weight)| *weight)
+                    .collect();
+                if !is_valid_weight(weight) || is_outlying_weight(weight, &others) {
+                    invalid.push(InvalidWeight {
+                        symbol: symbol.to_string(),
+                        rule: index,
+                        weight,
+                        description: self.descriptions.get(symbol).cloned(),
+                    });
+                }
+            }
+        }
+
+        invalid
+    }
+
+    /// Returns the references made by the rules of the grammar to deprecated symbols, either expanded
+    /// or captured, sorted by symbol and rule.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["A {colour} door".to_string()])
+    ///     .with_symbol("colour".to_string(), vec!["red".to_string()])
+    ///     .with_deprecation("colour", Some("use 'color'"));
+    ///
+    /// let references = grammar.deprecated_references();
+    /// assert_eq!(
+    ///     references[0].to_string(),
+    ///     "Rule 0 of symbol 'root' references deprecated symbol 'colour': use 'color'"
+    /// );
+    /// ```
+    pub fn deprecated_references(&self) -> Vec<DeprecatedReference> {
+        let mut symbols: Vec<&String> = self.symbols.keys().collect();
+        symbols.sort();
+
+        let mut references = Vec::new();
+        for symbol in symbols {
+            for (index, rule) in self.symbols[symbol].iter().enumerate() {
+                // The sources of the captures are parsed according to the capture form of the syntax
+                for name in self.rule_references(rule).symbols {
+                    if let Some((deprecated, hint)) = self.deprecations.get_key_value(name) {
+                        references.push(DeprecatedReference {
+                            symbol: symbol.to_string(),
+                            rule: index,
+                            deprecated: deprecated.to_string(),
+                            hint: hint.clone(),
+                            description: self.descriptions.get(symbol).cloned(),
+                        });
+                    }
+                }
+            }
+        }
+
+        references
+    }
+
+    /// Returns the modifiers referenced by the rules of the grammar without being registered, which are
+    /// otherwise silently ignored at expansion time, sorted by symbol and rule.
+    ///
+    /// The rules of file-backed symbols are only checked once they are loaded.
+    ///
+    /// ```
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["{name:capitalise}".to_string()])
+    ///     .with_standard_modifiers();
+    ///
+    /// let unknown = grammar.unknown_modifiers();
+    /// assert_eq!(unknown[0].name, "capitalise");
+    /// assert_eq!(unknown[0].suggestion.as_deref(), Some("capitalize"));
+    /// ```
+    pub fn unknown_modifiers(&self) -> Vec<UnknownModifier> {
+        let mut symbols: Vec<&String> = self.symbols.keys().collect();
+        symbols.sort();
+
+        let mut unknown = Vec::new();
+        for symbol in symbols {
+            for (index, rule) in self.symbols[symbol].iter().enumerate() {
+                for token in token::tokenize(&self.syntax, rule) {
+                    let expression = match token {
+                        Token::Symbol(expression) => expression,
+                        _ => continue,
+                    };
+                    if token::symbol_name(&self.syntax, expression).is_none() {
+                        continue;
+                    }
+
+                    let calls = call::split_calls(expression, self.syntax.modifier_operator);
+                    for name in calls.into_iter().skip(1).map(call::call_name) {
+                        if !self.is_modifier_registered(name) {
+                            unknown.push(UnknownModifier {
+                                symbol: symbol.to_string(),
+                                rule: index,
+                                name: name.to_string(),
+                                suggestion: self.suggest_modifier(name),
+                                description: self.descriptions.get(symbol).cloned(),
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        unknown
+    }
+
+    /// Enable strict modifiers: referencing a modifier which is not registered is an expansion error,
+    /// replacing the whole symbol expression, instead of the modifier being ignored.
+    ///
+    /// The method returns the Grammar instance, so you can build upon it.
+    ///
+    /// ```should_panic
+    /// use vitrail::{
+    ///     config::GrammarSyntax,
+    ///     grammar::Grammar,
+    /// };
+    ///
+    /// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+    ///     .with_symbol("root".to_string(), vec!["{name:capitalise}".to_string()])
+    ///     .with_symbol("name".to_string(), vec!["alice".to_string()])
+    ///     .with_standard_modifiers()
+    ///     .with_strict_modifiers();
+    ///
+    /// // panics with "Unknown modifier 'capitalise', did you mean 'capitalize'?"
+    /// grammar.flatten().unwrap();
+    /// ```
+    pub fn with_strict_modifiers(mut self) -> Self {
+        self.strict_modifiers = true;
+        self
+    }
+
+    /// Check that the modifiers applied to a symbol expression are registered, in strict mode
+    pub(super) fn check_modifiers(&self, modifier_names: &[&str]) -> Result<(), ExpansionError> {
+        if !self.strict_modifiers {
+            return Ok(());
+        }
+
+        match modifier_names
+            .iter()
+            .find(|name| self.modifier(name).is_none())
+        {
+            Some(name) => Err(ExpansionError::UnknownModifier {
+                name: name.to_string(),
+                suggestion: self.suggest_modifier(name),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the name of the registered modifier closest to an unknown one, if it is within an edit distance
+    /// of a third of its length (at least 1). Ties are broken alphabetically.
+    fn suggest_modifier(&self, name: &str) -> Option<String> {
+        let max_distance = (name.chars().count() / 3).max(1);
+
+        self.modifiers
+            .keys()
+            .chain(self.locale_modifiers.values().flat_map(HashMap::keys))
+            .map(|candidate| (text::edit_distance(name, candidate), candidate))
+            .filter(|(distance, _)| *distance <= max_distance)
+            .min()
+            .map(|(_, candidate)| candidate.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+    use crate::error::ErrorAction;
+    use crate::grammar::RuleOptions;
+
+    fn grammar() -> Grammar<'static> {
+        Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol(
+                "root".to_string(),
+                vec![
+                    "{name:capitalise} {@hex:4} {switch:name}{case:bob}{end}".to_string(),
+                    "{name:upper:zzz}".to_string(),
+                ],
+            )
+            .with_symbol("name".to_string(), vec!["bob".to_string()])
+            .with_standard_modifiers()
+    }
+
+    #[test]
+    fn missing_root_is_reported_instead_of_unreachable_symbols() {
+        let colours = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol("colour".to_string(), vec!["red".to_string()]);
+
+        let issues = colours.validate().issues;
+        assert_eq!(
+            issues,
+            vec![ValidationIssue::MissingRoot(MissingRoot {
+                symbol: "root".to_string(),
+            })]
+        );
+        assert_eq!(
+            issues[0].to_string(),
+            "No root symbol ('root' or 'origin') and none configured"
+        );
+        assert!(colours.with_root("colour").validate().is_empty());
+    }
+
+    #[test]
+    fn unknown_modifiers_with_suggestions() {
+        let unknown = grammar().unknown_modifiers();
+        assert_eq!(
+            unknown,
+            vec![
+                UnknownModifier {
+                    symbol: "root".to_string(),
+                    rule: 0,
+                    name: "capitalise".to_string(),
+                    suggestion: Some("capitalize".to_string()),
+                    description: None,
+                },
+                UnknownModifier {
+                    symbol: "root".to_string(),
+                    rule: 1,
+                    name: "zzz".to_string(),
+                    suggestion: None,
+                    description: None,
+                },
+            ]
+        );
+        assert_eq!(
+            unknown[0].to_string(),
+            "Rule 0 of symbol 'root': Unknown modifier 'capitalise', did you mean 'capitalize'?"
+        );
+    }
+
+    #[test]
+    fn deprecated_references_in_symbols_and_captures() {
+        let grammar = grammar()
+            .with_symbol(
+                "story".to_string(),
+                vec!["[name>hero]{hero}".to_string(), "{name:upper}".to_string()],
+            )
+            .with_deprecation("name", None);
+
+        let references: Vec<(String, usize)> = grammar
+            .deprecated_references()
+            .into_iter()
+            .map(|reference| (reference.symbol, reference.rule))
+            .collect();
+        assert_eq!(
+            references,
+            vec![
+                ("root".to_string(), 0),
+                ("root".to_string(), 1),
+                ("story".to_string(), 0),
+                ("story".to_string(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn deprecated_references_in_variable_first_captures() {
+        let grammar = Grammar::new("testseed", GrammarSyntax::tracery())
+            .with_symbol(
+                "root".to_string(),
+                vec![
+                    "[hero:#name#]#hero#".to_string(),
+                    "[name:#other#]done".to_string(),
+                ],
+            )
+            .with_symbol("name".to_string(), vec!["Ada".to_string()])
+            .with_symbol("other".to_string(), vec!["Bob".to_string()])
+            .with_deprecation("name", None);
+
+        let references: Vec<(String, usize)> = grammar
+            .deprecated_references()
+            .into_iter()
+            .map(|reference| (reference.symbol, reference.rule))
+            .collect();
+        // A variable named like the deprecated symbol is captured, not derived
+        assert_eq!(references, vec![("root".to_string(), 0)]);
+    }
+
+    #[test]
+    fn messages_describe_the_symbol() {
+        let grammar = grammar()
+            .with_description("root", "the opening line")
+            .with_deprecation("name", Some("use 'hero'"));
+
+        assert_eq!(
+            grammar.unknown_modifiers()[1].to_string(),
+            "Rule 1 of symbol 'root' (the opening line): Unknown modifier 'zzz'"
+        );
+        assert_eq!(
+            grammar.deprecated_references()[0].to_string(),
+            "Rule 0 of symbol 'root' (the opening line) references deprecated symbol 'name': use 'hero'"
+        );
+    }
+
+    #[test]
+    fn duplicate_and_near_duplicate_rules() {
+        let grammar = grammar()
+            .with_symbol(
+                "animal".to_string(),
+                vec![
+                    "cat".to_string(),
+                    "bat".to_string(),
+                    "cat".to_string(),
+                    "a sleepy old dragon".to_string(),
+                    "a sleepy old dragon!".to_string(),
+                    "a sleepy young dragon".to_string(),
+                ],
+            )
+            .with_description("animal", "a creature of the forest");
+
+        let duplicates: Vec<(usize, usize, usize)> = grammar
+            .duplicate_rules()
+            .into_iter()
+            .map(|duplicate| (duplicate.rule, duplicate.original, duplicate.distance))
+            .collect();
+        assert_eq!(duplicates, vec![(2, 0, 0), (4, 3, 1)]);
+
+        let report = grammar.validate();
+        assert_eq!(report.issues.len(), 5);
+        assert_eq!(
+            report.to_string().lines().last(),
+            Some("Rule 4 of symbol 'animal' (a creature of the forest) nearly duplicates rule 3 (1 edit apart)")
+        );
+    }
+
+    #[test]
+    fn invalid_weights_are_reported_and_ignored() {
+        let mut grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol(
+                "coin".to_string(),
+                vec!["heads".to_string(), "tails".to_string(), "edge".to_string()],
+            )
+            .with_rule_options("coin", 1, RuleOptions::default().with_weight(f64::INFINITY))
+            .with_rule_options("coin", 2, RuleOptions::default().with_weight(f64::NAN))
+            .with_root("coin");
+
+        let invalid: Vec<usize> = grammar
+            .invalid_weights()
+            .into_iter()
+            .map(|issue| issue.rule)
+            .collect();
+        assert_eq!(invalid, vec![1, 2]);
+        assert_eq!(
+            grammar.validate().to_string(),
+            "Rule 1 of symbol 'coin' has a non-finite weight (inf)\n\
+             Rule 2 of symbol 'coin' has a non-finite weight (NaN)\n"
+        );
+
+        for _ in 0..10 {
+            assert_eq!(grammar.flatten().unwrap(), "heads");
+        }
+    }
+
+    #[test]
+    fn outlying_weights_are_reported() {
+        let grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol(
+                "coin".to_string(),
+                vec!["heads".to_string(), "tails".to_string()],
+            )
+            .with_rule_options("coin", 1, RuleOptions::default().with_weight(100.0))
+            .with_symbol(
+                "loot".to_string(),
+                vec!["gold".to_string(), "gem".to_string(), "relic".to_string()],
+            )
+            .with_rule_options("loot", 0, RuleOptions::default().with_weight(20.0))
+            .with_rule_options("loot", 1, RuleOptions::default().with_weight(0.0))
+            .with_rule_options("loot", 2, RuleOptions::default().with_weight(0.5));
+
+        let invalid: Vec<(String, usize)> = grammar
+            .invalid_weights()
+            .into_iter()
+            .map(|issue| (issue.symbol, issue.rule))
+            .collect();
+        assert_eq!(invalid, vec![("coin".to_string(), 1)]);
+
+        let grammar = grammar.with_rule_options("loot", 2, RuleOptions::default().with_weight(0.1));
+        assert_eq!(grammar.invalid_weights().len(), 2);
+    }
+
+    #[test]
+    fn undefined_unreachable_and_empty_symbols() {
+        let grammar = grammar()
+            .with_symbol(
+                "story".to_string(),
+                vec![
+                    "[name>hero]{hero} {{gender}_title} {missing} {missing}".to_string(),
+                    "[story]{hero:upper}[>x][#1+1>count]".to_string(),
+                ],
+            )
+            .with_symbol("f_title".to_string(), Vec::new());
+
+        let undefined: Vec<(usize, String)> = grammar
+            .undefined_symbols()
+            .into_iter()
+            .map(|undefined| (undefined.rule, undefined.name))
+            .collect();
+        assert_eq!(
+            undefined,
+            vec![(0, "gender".to_string()), (0, "missing".to_string())]
+        );
+
+        let malformed: Vec<String> = grammar
+            .malformed_captures()
+            .into_iter()
+            .map(|malformed| malformed.capture)
+            .collect();
+        assert_eq!(malformed, vec!["story", ">x"]);
+
+        assert_eq!(
+            grammar.empty_symbols()[0].to_string(),
+            "Symbol 'f_title' has no rules"
+        );
+        let unreachable: Vec<String> = grammar
+            .unreachable_symbols()
+            .into_iter()
+            .map(|unreachable| unreachable.symbol)
+            .collect();
+        assert_eq!(unreachable, vec!["f_title", "story"]);
+
+        // f_title is reached through {{gender}_title}, while f_name matches no name of the rules
+        let grammar = grammar
+            .with_symbol("root".to_string(), vec!["{story}".to_string()])
+            .with_symbol("f_name".to_string(), vec!["Ada".to_string()]);
+        let unreachable: Vec<String> = grammar
+            .unreachable_symbols()
+            .into_iter()
+            .map(|unreachable| unreachable.symbol)
+            .collect();
+        assert_eq!(unreachable, vec!["f_name"]);
+        let grammar = grammar.with_file_symbol("story".to_string(), "tests/data/names.txt");
+        assert!(grammar.unreachable_symbols().is_empty());
+    }
+
+    #[test]
+    fn symbol_patterns() {
+        let grammar = Grammar::new("testseed", GrammarSyntax::default());
+        assert!(grammar.matches_pattern("{gender}_title", "f_title"));
+        assert!(grammar.matches_pattern("{a}_{b}", "x_y"));
+        assert!(grammar.matches_pattern("pre{a}mid{b}post", "pre1mid2post"));
+        assert!(!grammar.matches_pattern("pre{a}mid{b}post", "premipost"));
+        assert!(!grammar.matches_pattern("{gender}_title", "f_name"));
+        assert!(!grammar.matches_pattern("ab{x}ba", "aba"));
+    }
+
+    #[test]
+    fn condition_variables_are_references() {
+        let grammar = Grammar::new("testseed", GrammarSyntax::default())
+            .with_symbol(
+                "root".to_string(),
+                vec!["{if:a=b}x{elif:mood}y{end}".to_string()],
+            )
+            .with_symbol("mood".to_string(), vec!["calm".to_string()]);
+
+        let undefined: Vec<String> = grammar
+            .undefined_symbols()
+            .into_iter()
+            .map(|undefined| undefined.name)
+            .collect();
+        assert_eq!(undefined, vec!["a"]);
+        assert!(grammar.unreachable_symbols().is_empty());
+    }
+
+    #[test]
+    fn variable_first_captures_reference_their_rule() {
+        let grammar = Grammar::new("testseed", GrammarSyntax::tracery())
+            .with_symbol(
+                "origin".to_string(),
+                vec!["#[hero:#name#]story#".to_string()],
+            )
+            .with_symbol("name".to_string(), vec!["Ada".to_string()])
+            .with_symbol("story".to_string(), vec!["#hero# #nmae#".to_string()])
+            .with_root("origin");
+
+        let undefined = grammar.undefined_symbols();
+        assert_eq!(undefined.len(), 1);
+        assert_eq!(undefined[0].name, "nmae");
+        assert!(grammar.unreachable_symbols().is_empty());
+    }
+
+    #[test]
+    fn lenient_expansion_ignores_unknown_modifiers() {
+        let mut grammar =
+            grammar().with_symbol("root".to_string(), vec!["{name:capitalise}".to_string()]);
+        assert_eq!(grammar.flatten().unwrap(), "bob");
+    }
+
+    #[test]
+    fn strict_expansion_reports_unknown_modifiers() {
+        let mut grammar = grammar()
+            .with_symbol(
+                "root".to_string(),
+                vec!["{name:upper}, {name:capitalise}".to_string()],
+            )
+            .with_strict_modifiers()
+            .on_error(|error| ErrorAction::Substitute(format!("<{}>", error)));
+
+        assert_eq!(
+            grammar.flatten().unwrap(),
+            "BOB, <Unknown modifier 'capitalise', did you mean 'capitalize'?>"
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::token::{self, Token};
+use super::Grammar;
+use crate::config::{VarietyBoost, VocabularyDampening};
+use crate::text;
+
+/// The weight factors of the rules selected recently, when variety boosting is enabled
+#[derive(Debug, Clone)]
+pub(crate) struct RecentSelections {
+    pub(crate) boost: VarietyBoost,
+    /// The weight factor of the penalized rules, expressed as a map of symbol => factor of each rule, by rule index
+    factors: HashMap<String, Vec<f64>>,
+}
+
+/// Factor above which a rule is considered back to its normal weight
+const RECOVERED_FACTOR: f64 = 0.999;
+
+impl RecentSelections {
+    pub(crate) fn new(boost: VarietyBoost) -> Self {
+        Self {
+            boost,
+            factors: HashMap::new(),
+        }
+    }
+
+    /// Penalize the weight of a rule which has just been selected
+    pub(crate) fn penalize(&mut self, symbol: &str, index: usize) {
+        let factors = self.factors.entry(symbol.to_string()).or_default();
+        if factors.len() <= index {
+            factors.resize(index + 1, 1.0);
+        }
+        factors[index] *= self.boost.penalty;
+    }
+
+    /// Let every penalized rule recover part of its weight, after a flatten
+    pub(crate) fn recover(&mut self) {
+        let boost = self.boost;
+        self.factors.retain(|_, factors| {
+            for factor in factors.iter_mut() {
+                *factor = boost.recover(*factor);
+            }
+            factors.iter().any(|factor| *factor < RECOVERED_FACTOR)
+        });
+    }
+
+    /// Returns the weight factors of the penalized rules, by symbol
+    pub(crate) fn factors(&self) -> &HashMap<String, Vec<f64>> {
+        &self.factors
+    }
+
+    /// Replace the weight factors of the penalized rules, e.g. with the ones saved by a session
+    pub(crate) fn set_factors(&mut self, factors: HashMap<String, Vec<f64>>) {
+        self.factors = factors;
+    }
+
+    /// Returns the weight factor of a rule
+    pub(crate) fn factor(&self, symbol: &str, index: usize) -> f64 {
+        self.factors
+            .get(symbol)
+            .and_then(|factors| factors.get(index))
+            .copied()
+            .unwrap_or(1.0)
+    }
+}
+
+/// The number of uses of the words output so far, when vocabulary dampening is enabled
+#[derive(Debug, Clone)]
+pub(crate) struct WordFrequencies {
+    pub(crate) dampening: VocabularyDampening,
+    /// The number of uses of each tracked word, lowercased
+    uses: HashMap<String, usize>,
+}
+
+impl WordFrequencies {
+    pub(crate) fn new(dampening: VocabularyDampening) -> Self {
+        Self {
+            dampening,
+            uses: HashMap::new(),
+        }
+    }
+
+    /// Returns the tracked words of a text, lowercased
+    fn words<'t>(&self, text: &'t str) -> impl Iterator<Item = String> + 't {
+        let min_word_len = self.dampening.min_word_len;
+        text.unicode_words()
+            .filter(move |word| text::grapheme_len(word) >= min_word_len)
+            .map(str::to_lowercase)
+    }
+
+    /// Count the words of a flatten output
+    pub(crate) fn record(&mut self, output: &str) {
+        let words: Vec<String> = self.words(output).collect();
+        for word in words {
+            *self.uses.entry(word).or_default() += 1;
+        }
+    }
+
+    /// Returns the weight factor of a rule having the given literal text
+    pub(crate) fn factor(&self, literal_text: &str) -> f64 {
+        let (words, uses) = self
+            .words(literal_text)
+            .fold((0, 0), |(words, uses), word| {
+                (words + 1, uses + self.uses.get(&word).copied().unwrap_or(0))
+            });
+        if words == 0 {
+            return 1.0;
+        }
+
+        self.dampening.factor(uses as f64 / words as f64)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.uses.clear();
+    }
+}
+
+impl Grammar<'_> {
+    /// Returns the weight factor of a rule from the uses of the words of its literal text, when vocabulary
+    /// dampening is enabled
+    pub(super) fn vocabulary_factor(&self, symbol: &str, index: usize) -> f64 {
+        let (frequencies, rule) = match (
+            &self.word_frequencies,
+            self.symbols.get(symbol).and_then(|rules| rules.get(index)),
+        ) {
+            (Some(frequencies), Some(rule)) => (frequencies, rule),
+            _ => return 1.0,
+        };
+
+        let literal_text: Vec<_> = token::tokenize(&self.syntax, rule)
+            .into_iter()
+            .filter_map(|token| match token {
+                Token::Text(text) => Some(text),
+                _ => None,
+            })
+            .collect();
+        frequencies.factor(&literal_text.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selected_rules_are_penalized_then_recover() {
+        let mut recent = RecentSelections::new(VarietyBoost::new(0.25, 0.5));
+        let index = 2;
+
+        recent.penalize("adjective", index);
+        assert_eq!(recent.factor("adjective", index), 0.25);
+        assert_eq!(recent.factor("adjective", 0), 1.0);
+
+        recent.recover();
+        assert_eq!(recent.factor("adjective", index), 0.625);
+
+        for _ in 0..20 {
+            recent.recover();
+        }
+        assert_eq!(recent.factor("adjective", index), 1.0);
+    }
+
+    #[test]
+    fn used_words_dampen_rules() {
+        let mut frequencies = WordFrequencies::new(VocabularyDampening::new(1.0));
+        frequencies.record("The Storm rages, the storm howls");
+
+        assert_eq!(frequencies.factor("a storm"), 1.0 / 3.0);
+        assert_eq!(frequencies.factor("storm clouds"), 0.5);
+        assert_eq!(frequencies.factor("the sun"), 1.0);
+
+        frequencies.clear();
+        assert_eq!(frequencies.factor("a storm"), 1.0);
+    }
+}
@@ -0,0 +1,95 @@
+use std::collections::{HashMap, HashSet};
+use std::env;
+
+use crate::grammar::ExpansionContext;
+
+/// A source of values provided by the host application, exposed to the grammar as built-in symbols.
+///
+/// A provider registered under the name `env` answers the `{@env:NAME}` symbols.
+pub trait HostVariables {
+    /// Returns the value of the given variable, or `None` if it is not available
+    fn get(&self, name: &str) -> Option<String>;
+
+    /// Same as `get`, with access to the state of the expansion in progress. `Grammar` reads variables
+    /// through this method.
+    ///
+    /// The default implementation ignores the context, through `get`.
+    fn get_in_context(&self, name: &str, _context: &ExpansionContext) -> Option<String> {
+        self.get(name)
+    }
+}
+
+impl HostVariables for HashMap<String, String> {
+    fn get(&self, name: &str) -> Option<String> {
+        HashMap::get(self, name).cloned()
+    }
+}
+
+/// Exposes process environment variables, restricted to an explicit allowlist so that grammars
+/// cannot read arbitrary values from the host environment
+///
+/// ```
+/// use vitrail::host::{EnvironmentVariables, HostVariables};
+///
+/// let env = EnvironmentVariables::allowing(&["USER", "HOSTNAME"]);
+/// assert_eq!(env.get("PATH"), None);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct EnvironmentVariables {
+    allowlist: HashSet<String>,
+}
+
+impl EnvironmentVariables {
+    /// Create a provider exposing only the given environment variables
+    pub fn allowing(names: &[&str]) -> Self {
+        Self {
+            allowlist: names.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+}
+
+impl HostVariables for EnvironmentVariables {
+    fn get(&self, name: &str) -> Option<String> {
+        if self.allowlist.contains(name) {
+            env::var(name).ok()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_variables() {
+        let mut variables = HashMap::new();
+        variables.insert("region".to_string(), "eu-west".to_string());
+
+        assert_eq!(
+            HostVariables::get(&variables, "region"),
+            Some("eu-west".to_string())
+        );
+        assert_eq!(HostVariables::get(&variables, "zone"), None);
+    }
+
+    #[test]
+    fn environment_allowed() {
+        env::set_var("VITRAIL_TEST_ALLOWED", "yes");
+        let provider = EnvironmentVariables::allowing(&["VITRAIL_TEST_ALLOWED"]);
+
+        assert_eq!(
+            provider.get("VITRAIL_TEST_ALLOWED"),
+            Some("yes".to_string())
+        );
+    }
+
+    #[test]
+    fn environment_not_allowed() {
+        env::set_var("VITRAIL_TEST_DENIED", "no");
+        let provider = EnvironmentVariables::allowing(&[]);
+
+        assert_eq!(provider.get("VITRAIL_TEST_DENIED"), None);
+    }
+}
@@ -0,0 +1,3 @@
+//! Importers translating grammars written for other tools into vitrail rules.
+
+pub mod rant;
@@ -0,0 +1,317 @@
+//! Translation of the common subset of Rant patterns into rules:
+//!
+//! - inline blocks `{a|b|c}`, which may be nested, become symbols of their own
+//! - queries `<noun>` reference the symbol of the same name, and `<noun.pl>` its plural (with the `s` modifier)
+//! - `[num:1;6]` draws a whole number between both bounds
+//! - `[rep:3]{...}` repeats the following block, with the separator set by `[sep:, ]` if any
+//! - escape sequences `\n`, `\t`, `\s` (space), and escaped characters such as `\|`
+//!
+//! Other functions are reported as errors, rather than silently producing a different text.
+
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::config::GrammarSyntax;
+
+/// Largest number of values `[num:a;b]` can draw from, as each one becomes a rule
+const MAX_NUMBER_RANGE: i64 = 1000;
+/// Subtype of a query selecting the plural form of a word
+const PLURAL_SUBTYPE: &str = "pl";
+/// Modifier producing the plural form of a word, as registered by the standard modifiers
+const PLURAL_MODIFIER: &str = "s";
+
+/// Translate the rules of a symbol written as Rant patterns into rules of the given syntax.
+///
+/// Returns the translated symbol, along with the symbols created for its inline blocks, named after it
+/// (`weather_block1`, ...). Plural queries rely on the standard modifiers.
+///
+/// ```
+/// use vitrail::{config::GrammarSyntax, grammar::Grammar, import::rant};
+///
+/// let syntax = GrammarSyntax::default();
+/// let symbols = rant::translate("root", &["The <animal> is {very|quite} {calm|angry}."], &syntax).unwrap();
+/// assert_eq!(symbols["root"], vec!["The {animal} is {root_block1} {root_block2}."]);
+/// assert_eq!(symbols["root_block1"], vec!["very", "quite"]);
+///
+/// let mut grammar = symbols.into_iter().fold(
+///     Grammar::new("anyrandomseed", syntax),
+///     |grammar, (symbol, rules)| grammar.with_symbol(symbol, rules),
+/// );
+/// grammar = grammar.with_symbol("animal".to_string(), vec!["cat".to_string()]);
+/// assert!(grammar.flatten().unwrap().starts_with("The cat is "));
+/// ```
+pub fn translate(
+    symbol: &str,
+    patterns: &[&str],
+    syntax: &GrammarSyntax,
+) -> Result<HashMap<String, Vec<String>>, String> {
+    let mut translation = Translation {
+        symbol,
+        syntax,
+        symbols: HashMap::new(),
+    };
+
+    let rules = patterns
+        .iter()
+        .map(|pattern| translation.pattern(pattern))
+        .collect::<Result<Vec<String>, String>>()?;
+    translation.symbols.insert(symbol.to_string(), rules);
+
+    Ok(translation.symbols)
+}
+
+/// The translation of the patterns of a symbol in progress
+struct Translation<'t> {
+    /// The symbol whose patterns are translated
+    symbol: &'t str,
+    syntax: &'t GrammarSyntax,
+    /// The symbols created so far, for inline blocks and number ranges
+    symbols: HashMap<String, Vec<String>>,
+}
+
+impl Translation<'_> {
+    fn pattern(&mut self, pattern: &str) -> Result<String, String> {
+        let mut chars = pattern.chars().peekable();
+        let rule = self.sequence(&mut chars, false)?;
+
+        match chars.next() {
+            Some(c) => Err(format!("unexpected '{}' outside of a block", c)),
+            None => Ok(rule),
+        }
+    }
+
+    /// Translate a sequence of text, blocks, queries and functions, up to the end of the pattern,
+    /// or to the end of the branch when inside a block
+    fn sequence(&mut self, chars: &mut Peekable<Chars>, in_block: bool) -> Result<String, String> {
+        let mut rule = String::new();
+        let mut separator = String::new();
+
+        while let Some(&c) = chars.peek() {
+            if c == '}' || (in_block && c == '|') {
+                break;
+            }
+            chars.next();
+
+            match c {
+                '{' => {
+                    let block = self.block(chars)?;
+                    rule.push_str(&self.reference(&block));
+                }
+                '<' => {
+                    let query = read_until(chars, '>')?;
+                    rule.push_str(&self.query(&query)?);
+                }
+                '[' => {
+                    let function = read_until(chars, ']')?;
+                    let (name, arguments) = function.split_once(':').unwrap_or((&function, ""));
+                    match name.trim() {
+                        "num" => rule.push_str(&self.number(arguments)?),
+                        "sep" => separator = arguments.to_string(),
+                        "rep" => {
+                            let count: usize = arguments
+                                .trim()
+                                .parse()
+                                .map_err(|_| format!("invalid repetition count '{}'", arguments))?;
+                            rule.push_str(&self.repetition(chars, count, &separator)?);
+                        }
+                        _ => return Err(format!("unsupported Rant function '[{}]'", function)),
+                    }
+                }
+                '\\' => {
+                    let escaped = chars
+                        .next()
+                        .ok_or_else(|| "unterminated escape sequence".to_string())?;
+                    rule.push(self.escape(escaped)?);
+                }
+                _ => rule.push(c),
+            }
+        }
+
+        Ok(rule)
+    }
+
+    /// Translate the branches of a block, whose opening brace was read, into a new symbol, and return its name
+    fn block(&mut self, chars: &mut Peekable<Chars>) -> Result<String, String> {
+        let mut branches = Vec::new();
+        loop {
+            branches.push(self.sequence(chars, true)?);
+            match chars.next() {
+                Some('|') => continue,
+                Some(_) => break,
+                None => return Err("unterminated block".to_string()),
+            }
+        }
+
+        Ok(self.new_symbol(branches))
+    }
+
+    /// Translate `[rep:count]`, followed by the block to repeat
+    fn repetition(
+        &mut self,
+        chars: &mut Peekable<Chars>,
+        count: usize,
+        separator: &str,
+    ) -> Result<String, String> {
+        if chars.next() != Some('{') {
+            return Err("[rep] must be followed by a block".to_string());
+        }
+
+        let block = self.block(chars)?;
+        let separator = self.pattern(separator)?;
+        Ok(vec![self.reference(&block); count].join(&separator))
+    }
+
+    /// Translate the arguments of `[num:min;max]` into a symbol drawing one of the numbers
+    fn number(&mut self, arguments: &str) -> Result<String, String> {
+        let bounds = arguments
+            .split_once(';')
+            .and_then(|(min, max)| Some((min.trim().parse().ok()?, max.trim().parse().ok()?)));
+        let (min, max): (i64, i64) = match bounds {
+            Some((min, max)) if min <= max && max - min < MAX_NUMBER_RANGE => (min, max),
+            _ => {
+                return Err(format!(
+                    "[num] needs two ordered bounds, at most {} apart: '{}'",
+                    MAX_NUMBER_RANGE - 1,
+                    arguments
+                ))
+            }
+        };
+
+        let symbol = self.new_symbol((min..=max).map(|n| n.to_string()).collect());
+        Ok(self.reference(&symbol))
+    }
+
+    /// Translate the content of a query (`noun`, `noun.pl`) into a symbol expression
+    fn query(&self, query: &str) -> Result<String, String> {
+        let query = query.trim();
+        let (name, subtype) = match query.split_once('.') {
+            Some((name, subtype)) => (name, Some(subtype)),
+            None => (query, None),
+        };
+
+        // Class filters (`<noun-animal>`) and other query options have no equivalent
+        let is_name = !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+        if !is_name {
+            return Err(format!("unsupported Rant query '<{}>'", query));
+        }
+
+        match subtype {
+            None => Ok(self.reference(name)),
+            Some(PLURAL_SUBTYPE) => Ok(format!(
+                "{}{}{}{}{}",
+                self.syntax.symbol_start,
+                name,
+                self.syntax.modifier_operator,
+                PLURAL_MODIFIER,
+                self.syntax.symbol_end
+            )),
+            Some(subtype) => Err(format!(
+                "unsupported subtype '{}' of Rant query '<{}>'",
+                subtype, query
+            )),
+        }
+    }
+
+    /// Returns the character of an escape sequence, if the syntax can hold it as literal text
+    fn escape(&self, escaped: char) -> Result<char, String> {
+        let character = match escaped {
+            'n' => '\n',
+            't' => '\t',
+            's' => ' ',
+            c => c,
+        };
+
+        let syntax = self.syntax;
+        if [
+            syntax.symbol_start,
+            syntax.symbol_end,
+            syntax.capture_start,
+            syntax.capture_end,
+        ]
+        .contains(&character)
+        {
+            return Err(format!(
+                "'{}' cannot be written as literal text in the grammar syntax",
+                character
+            ));
+        }
+        Ok(character)
+    }
+
+    fn new_symbol(&mut self, rules: Vec<String>) -> String {
+        let name = format!("{}_block{}", self.symbol, self.symbols.len() + 1);
+        self.symbols.insert(name.to_string(), rules);
+        name
+    }
+
+    fn reference(&self, symbol: &str) -> String {
+        format!(
+            "{}{}{}",
+            self.syntax.symbol_start, symbol, self.syntax.symbol_end
+        )
+    }
+}
+
+/// Read the characters up to the given delimiter, which is consumed
+fn read_until(chars: &mut Peekable<Chars>, delimiter: char) -> Result<String, String> {
+    let mut content = String::new();
+    for c in chars.by_ref() {
+        if c == delimiter {
+            return Ok(content);
+        }
+        content.push(c);
+    }
+
+    Err(format!("missing '{}'", delimiter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn translate_one(pattern: &str) -> Result<HashMap<String, Vec<String>>, String> {
+        translate("root", &[pattern], &GrammarSyntax::default())
+    }
+
+    #[test]
+    fn nested_blocks() {
+        let symbols = translate_one("{a|b{c|d}|}!").unwrap();
+        assert_eq!(symbols["root"], vec!["{root_block2}!"]);
+        assert_eq!(symbols["root_block2"], vec!["a", "b{root_block1}", ""]);
+        assert_eq!(symbols["root_block1"], vec!["c", "d"]);
+    }
+
+    #[test]
+    fn functions() {
+        let symbols = translate_one("Rolled [num:1;3]: [sep:, ][rep:3]{<die.pl>|x}").unwrap();
+        assert_eq!(
+            symbols["root"],
+            vec!["Rolled {root_block1}: {root_block2}, {root_block2}, {root_block2}"]
+        );
+        assert_eq!(symbols["root_block1"], vec!["1", "2", "3"]);
+        assert_eq!(symbols["root_block2"], vec!["{die:s}", "x"]);
+    }
+
+    #[test]
+    fn escapes() {
+        let symbols = translate_one(r"a\sb\nc\|d").unwrap();
+        assert_eq!(symbols["root"], vec!["a b\nc|d"]);
+        assert!(translate_one(r"\{").is_err());
+    }
+
+    #[test]
+    fn unsupported_patterns() {
+        for pattern in [
+            "{a|b",
+            "a}",
+            "[case:upper]word",
+            "<noun-animal>",
+            "<noun.past>",
+            "[num:5;1]",
+            "[rep:2]x",
+        ] {
+            assert!(translate_one(pattern).is_err(), "{}", pattern);
+        }
+    }
+}
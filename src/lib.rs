@@ -1,3 +1,22 @@
+//! Procedural text generation from Tracery-like grammars.
+//!
+//! The `vitrail` binary is a thin CLI over this library. The crates only it uses, `clap` and `ratatui`,
+//! are optional dependencies of the package, enabled by the `cli` (default) and `tui` features: the library
+//! itself never uses them, so it can be depended upon with `default-features = false`.
+
+pub mod choice;
 pub mod config;
+pub mod container;
+pub mod error;
+pub mod export;
 pub mod grammar;
+pub mod host;
+pub mod import;
+pub mod markup;
 pub mod modifier;
+pub mod pipeline;
+pub mod report;
+pub mod reproducibility;
+pub mod rng;
+pub mod ssml;
+pub mod text;
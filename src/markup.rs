@@ -0,0 +1,215 @@
+//! Balancing of the inline markup understood by game engines (Unity rich text, BBCode), so generated text
+//! never emits unbalanced tags when rules contain opening or closing fragments.
+
+/// Tags of Unity rich text (and TextMesh Pro) which have no closing tag
+const RICH_TEXT_VOID_TAGS: [&str; 4] = ["br", "page", "quad", "sprite"];
+/// Tags of BBCode which have no closing tag
+const BBCODE_VOID_TAGS: [&str; 3] = ["*", "br", "hr"];
+
+/// An inline markup language
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Markup {
+    /// Unity rich text: `<b>`, `<color=#ff0000>`, `</color>`
+    RichText,
+    /// BBCode, as used by Godot's `RichTextLabel` or forums: `[b]`, `[color=red]`, `[/color]`
+    BBCode,
+}
+
+/// A tag read from a text
+struct Tag<'a> {
+    /// The lowercase name of the tag, without its value or attributes
+    name: String,
+    /// Whether the tag closes an element
+    closing: bool,
+    /// The tag as written, delimiters included
+    text: &'a str,
+}
+
+impl Markup {
+    fn delimiters(&self) -> (char, char) {
+        match self {
+            Markup::RichText => ('<', '>'),
+            Markup::BBCode => ('[', ']'),
+        }
+    }
+
+    fn is_void(&self, name: &str) -> bool {
+        match self {
+            Markup::RichText => RICH_TEXT_VOID_TAGS.contains(&name),
+            Markup::BBCode => BBCODE_VOID_TAGS.contains(&name),
+        }
+    }
+
+    /// Returns the closing tag of the element with the given name
+    fn closing_tag(&self, name: &str) -> String {
+        let (start, end) = self.delimiters();
+        format!("{}/{}{}", start, name, end)
+    }
+
+    /// Split a text into its literal parts and tags. Delimited text which does not look like a tag,
+    /// such as `[1]`, is kept literal.
+    fn parse<'a>(&self, text: &'a str) -> Vec<Result<Tag<'a>, &'a str>> {
+        let (start, end) = self.delimiters();
+        let mut parts = Vec::new();
+        let mut rest = text;
+
+        while let Some(tag_start) = rest.find(start) {
+            let tag_end = match rest[tag_start..].find(end) {
+                Some(length) => tag_start + length + end.len_utf8(),
+                None => break,
+            };
+
+            match Self::read_tag(&rest[tag_start..tag_end]) {
+                Some(tag) => {
+                    if tag_start > 0 {
+                        parts.push(Err(&rest[..tag_start]));
+                    }
+                    parts.push(Ok(tag));
+                    rest = &rest[tag_end..];
+                }
+                None => {
+                    // The delimiter is literal, but a tag may start right after it
+                    let literal_end = tag_start + start.len_utf8();
+                    parts.push(Err(&rest[..literal_end]));
+                    rest = &rest[literal_end..];
+                }
+            }
+        }
+
+        if !rest.is_empty() {
+            parts.push(Err(rest));
+        }
+        parts
+    }
+
+    fn read_tag(text: &str) -> Option<Tag<'_>> {
+        let content = &text[1..text.len() - 1];
+        let (closing, content) = match content.strip_prefix('/') {
+            Some(content) => (true, content),
+            None => (false, content),
+        };
+        let name = content
+            .split(|c: char| c == '=' || c.is_whitespace())
+            .next()
+            .unwrap_or_default();
+
+        let is_name = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '*' || c == '-' || c == '_');
+        if !is_name || name.starts_with(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+
+        Some(Tag {
+            name: name.to_ascii_lowercase(),
+            closing,
+            text,
+        })
+    }
+
+    /// Returns the text with its markup balanced: closing tags without a matching opening tag are removed,
+    /// elements left open inside a closed one are closed with it, and elements left open at the end are closed.
+    ///
+    /// ```
+    /// use vitrail::markup::Markup;
+    ///
+    /// assert_eq!(
+    ///     Markup::RichText.balance("<color=red>The <b>dragon</color> roars</i>"),
+    ///     "<color=red>The <b>dragon</b></color> roars"
+    /// );
+    /// assert_eq!(Markup::BBCode.balance("[i]whispers"), "[i]whispers[/i]");
+    /// ```
+    pub fn balance(&self, text: &str) -> String {
+        let mut balanced = String::with_capacity(text.len());
+        let mut open: Vec<String> = Vec::new();
+
+        for part in self.parse(text) {
+            let tag = match part {
+                Ok(tag) => tag,
+                Err(literal) => {
+                    balanced.push_str(literal);
+                    continue;
+                }
+            };
+
+            if !tag.closing {
+                balanced.push_str(tag.text);
+                if !self.is_void(&tag.name) {
+                    open.push(tag.name);
+                }
+            } else if let Some(position) = open.iter().rposition(|name| *name == tag.name) {
+                for name in open.drain(position + 1..).rev() {
+                    balanced.push_str(&self.closing_tag(&name));
+                }
+                open.pop();
+                balanced.push_str(tag.text);
+            }
+        }
+
+        for name in open.iter().rev() {
+            balanced.push_str(&self.closing_tag(name));
+        }
+
+        balanced
+    }
+
+    /// Check that every element of the text is properly closed, in the right order.
+    ///
+    /// Returns the name of the first mismatched or unclosed tag as error.
+    ///
+    /// ```
+    /// use vitrail::markup::Markup;
+    ///
+    /// assert!(Markup::BBCode.validate("[b]bold [color=red]red[/color][/b]").is_ok());
+    /// assert_eq!(Markup::BBCode.validate("[b]bold"), Err("b".to_string()));
+    /// ```
+    pub fn validate(&self, text: &str) -> Result<(), String> {
+        let mut open: Vec<String> = Vec::new();
+
+        for tag in self.parse(text).into_iter().filter_map(Result::ok) {
+            if !tag.closing {
+                if !self.is_void(&tag.name) {
+                    open.push(tag.name);
+                }
+            } else if open.pop().as_ref() != Some(&tag.name) {
+                return Err(tag.name);
+            }
+        }
+
+        match open.pop() {
+            Some(unclosed) => Err(unclosed),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn balance_keeps_balanced_text() {
+        let text = "<size=20><b>Title</b></size><br>body<sprite=3> <3";
+        assert_eq!(Markup::RichText.balance(text), text);
+        assert!(Markup::RichText.validate(text).is_ok());
+    }
+
+    #[test]
+    fn balance_bbcode() {
+        assert_eq!(
+            Markup::BBCode.balance("[list][*]one[/b][*]two[URL=x]link"),
+            "[list][*]one[*]two[URL=x]link[/url][/list]"
+        );
+        assert_eq!(Markup::BBCode.balance("[1] note"), "[1] note");
+        assert_eq!(Markup::BBCode.balance("[[b]x"), "[[b]x[/b]");
+    }
+
+    #[test]
+    fn validate_mismatched_tag() {
+        assert_eq!(
+            Markup::RichText.validate("<b><i>x</b></i>"),
+            Err("b".to_string())
+        );
+    }
+}
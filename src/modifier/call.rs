@@ -0,0 +1,144 @@
+/// Characters quoting an argument of a modifier, e.g. to pass a comma, a space or a parenthesis
+const QUOTES: [char; 2] = ['\'', '"'];
+/// Separator of the arguments of a modifier
+const ARGUMENT_SEPARATOR: char = ',';
+
+/// A modifier applied to a symbol, with the arguments it is given between parentheses, if any: `pad(12,'.')`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct ModifierCall<'e> {
+    pub(crate) name: &'e str,
+    pub(crate) arguments: Vec<String>,
+}
+
+/// Split the modifiers following a symbol on the modifier operator, except within the parentheses of their
+/// arguments, or their quotes: `pad(5,':'):upper` => `pad(5,':')`, `upper`
+pub(crate) fn split_calls(modifiers: &str, operator: char) -> Vec<&str> {
+    let mut calls = Vec::new();
+    let mut start = 0;
+    let mut in_arguments = false;
+    let mut quote = None;
+    for (index, character) in modifiers.char_indices() {
+        match (quote, character) {
+            (Some(open), _) if character == open => quote = None,
+            (Some(_), _) => {}
+            (None, '(') => in_arguments = true,
+            (None, ')') => in_arguments = false,
+            (None, _) if in_arguments && QUOTES.contains(&character) => quote = Some(character),
+            (None, _) if !in_arguments && character == operator => {
+                calls.push(&modifiers[start..index]);
+                start = index + operator.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    calls.push(&modifiers[start..]);
+
+    calls
+}
+
+/// Returns the name of the modifier of a call, without its arguments
+pub(crate) fn call_name(call: &str) -> &str {
+    call.split('(').next().unwrap_or_default()
+}
+
+/// Parse a modifier call: the name of the modifier, optionally followed by its arguments between parentheses,
+/// separated by commas. Arguments are trimmed, unless they are quoted with `'` or `"`.
+///
+/// Returns an error message when the parentheses or quotes are not closed.
+pub(crate) fn parse_call(call: &str) -> Result<ModifierCall<'_>, String> {
+    let (name, arguments) = match call.find('(') {
+        Some(open) => (&call[..open], &call[open + 1..]),
+        None => {
+            return Ok(ModifierCall {
+                name: call,
+                arguments: Vec::new(),
+            })
+        }
+    };
+    let arguments = arguments
+        .strip_suffix(')')
+        .ok_or_else(|| format!("missing ')' after the arguments of '{}'", call))?;
+
+    let mut parsed = Vec::new();
+    let mut argument = String::new();
+    let mut quoted = false;
+    let mut quote = None;
+    for character in arguments.chars() {
+        match quote {
+            Some(open) if character == open => quote = None,
+            Some(_) => argument.push(character),
+            None if QUOTES.contains(&character) && argument.trim().is_empty() => {
+                argument.clear();
+                quoted = true;
+                quote = Some(character);
+            }
+            None if character == ARGUMENT_SEPARATOR => {
+                parsed.push(finish_argument(&argument, quoted));
+                argument.clear();
+                quoted = false;
+            }
+            None if quoted => {
+                if !character.is_whitespace() {
+                    return Err(format!(
+                        "unexpected text after a quoted argument of '{}'",
+                        call
+                    ));
+                }
+            }
+            None => argument.push(character),
+        }
+    }
+    if quote.is_some() {
+        return Err(format!("unterminated quote in the arguments of '{}'", call));
+    }
+    if quoted || !argument.trim().is_empty() || !parsed.is_empty() {
+        parsed.push(finish_argument(&argument, quoted));
+    }
+
+    Ok(ModifierCall {
+        name,
+        arguments: parsed,
+    })
+}
+
+/// Returns an argument as given, when it is quoted, or trimmed otherwise
+fn finish_argument(argument: &str, quoted: bool) -> String {
+    if quoted {
+        argument.to_string()
+    } else {
+        argument.trim().to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calls_are_split_outside_arguments() {
+        assert_eq!(split_calls("upper", ':'), ["upper"]);
+        assert_eq!(
+            split_calls("pad(5,':'):upper:truncate(3, ':)')", ':'),
+            ["pad(5,':')", "upper", "truncate(3, ':)')"]
+        );
+    }
+
+    #[test]
+    fn arguments_are_trimmed_unless_quoted() {
+        let call = parse_call("pad( 12 , ' ', right)").unwrap();
+        assert_eq!(call.name, "pad");
+        assert_eq!(call.arguments, ["12", " ", "right"]);
+
+        assert_eq!(parse_call("trim").unwrap().arguments, Vec::<String>::new());
+        assert_eq!(parse_call("f()").unwrap().arguments, Vec::<String>::new());
+        assert_eq!(parse_call("f(\",\")").unwrap().arguments, [","]);
+        assert_eq!(parse_call("f(a,)").unwrap().arguments, ["a", ""]);
+    }
+
+    #[test]
+    fn malformed_calls() {
+        assert!(parse_call("pad(12").is_err());
+        assert!(parse_call("pad(12,'.)").is_err());
+        assert!(parse_call("pad('.'x)").is_err());
+    }
+}
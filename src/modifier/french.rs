@@ -0,0 +1,59 @@
+use super::Modifier;
+
+/// Nouns ending in "al" which take an "s" in the plural, instead of "aux": "des festivals"
+const AL_EXCEPTIONS: [&str; 6] = ["bal", "carnaval", "chacal", "festival", "récital", "régal"];
+/// Nouns ending in "au" or "eu" which take an "s" in the plural, instead of an "x": "des pneus"
+const X_EXCEPTIONS: [&str; 3] = ["bleu", "landau", "pneu"];
+/// Nouns ending in "ou" which take an "x" in the plural, instead of an "s": "des hiboux"
+const OU_EXCEPTIONS: [&str; 7] = [
+    "bijou", "caillou", "chou", "genou", "hibou", "joujou", "pou",
+];
+
+/// Pluralizes a French noun, e.g. registered as the `s` modifier of the `fr` locale with
+/// `Grammar::with_locale_modifier`: "cheval" => "chevaux", "château" => "châteaux", "souris" => "souris"
+pub struct FrenchPluralizeModifier {}
+
+impl Modifier for FrenchPluralizeModifier {
+    fn apply(&self, source: &str) -> String {
+        let word = source.to_lowercase();
+        let is_exception = |exceptions: &[&str]| exceptions.contains(&word.as_str());
+
+        if source.is_empty() || word.ends_with(['s', 'x', 'z']) {
+            source.to_string()
+        } else if word.ends_with("al") && !is_exception(&AL_EXCEPTIONS) {
+            format!("{}aux", &source[..source.len() - 2])
+        } else if (word.ends_with("au") || word.ends_with("eu")) && !is_exception(&X_EXCEPTIONS)
+            || word.ends_with("ou") && is_exception(&OU_EXCEPTIONS)
+        {
+            format!("{}x", source)
+        } else {
+            format!("{}s", source)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn french_plurals() {
+        let modifier = FrenchPluralizeModifier {};
+        let plurals = [
+            ("chat", "chats"),
+            ("souris", "souris"),
+            ("nez", "nez"),
+            ("cheval", "chevaux"),
+            ("festival", "festivals"),
+            ("château", "châteaux"),
+            ("jeu", "jeux"),
+            ("pneu", "pneus"),
+            ("hibou", "hiboux"),
+            ("trou", "trous"),
+        ];
+
+        for (singular, plural) in plurals {
+            assert_eq!(modifier.apply(singular), plural);
+        }
+    }
+}
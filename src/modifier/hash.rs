@@ -0,0 +1,95 @@
+use super::Modifier;
+
+/// Offset basis of the 64-bit FNV-1a hash
+const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+/// Prime of the 64-bit FNV-1a hash
+const FNV_PRIME: u64 = 0x0100_0000_01b3;
+/// Number of hexadecimal digits of a 64-bit hash
+const MAX_HASH_LENGTH: usize = 16;
+
+/// Returns the 64-bit FNV-1a hash of the text, which is the same on every platform and Rust version
+/// (unlike the standard library hasher)
+fn stable_hash(text: &str) -> u64 {
+    text.bytes().fold(FNV_OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(FNV_PRIME)
+    })
+}
+
+/// Maps its input to a short hexadecimal hash of the given length (at most 16 digits), which is always
+/// the same for the same input, e.g. to build a stable identifier for a generated entity
+///
+/// ```
+/// use vitrail::modifier::{HashModifier, Modifier};
+///
+/// let modifier = HashModifier { length: 6 };
+/// assert_eq!(modifier.apply("Aldric the Bold"), modifier.apply("Aldric the Bold"));
+/// assert_eq!(modifier.apply("Aldric the Bold").len(), 6);
+/// ```
+pub struct HashModifier {
+    pub length: usize,
+}
+
+impl Modifier for HashModifier {
+    fn apply(&self, source: &str) -> String {
+        let hash = format!("{:016x}", stable_hash(source));
+        hash[..self.length.min(MAX_HASH_LENGTH)].to_string()
+    }
+}
+
+/// Maps its input to one of the given items, chosen by its hash, so the same input is always given
+/// the same item, e.g. to always assign the same colour to the same generated name.
+/// Returns an empty text when there is no item.
+///
+/// ```
+/// use vitrail::modifier::{HashPickModifier, Modifier};
+///
+/// let colour = HashPickModifier::new(&["red", "green", "blue"]);
+/// assert_eq!(colour.apply("Aldric"), colour.apply("Aldric"));
+/// ```
+pub struct HashPickModifier {
+    pub items: Vec<String>,
+}
+
+impl HashPickModifier {
+    /// Create a modifier picking among the given items
+    pub fn new(items: &[&str]) -> Self {
+        Self {
+            items: items.iter().map(|item| item.to_string()).collect(),
+        }
+    }
+}
+
+impl Modifier for HashPickModifier {
+    fn apply(&self, source: &str) -> String {
+        if self.items.is_empty() {
+            return String::new();
+        }
+
+        let index = stable_hash(source) % self.items.len() as u64;
+        self.items[index as usize].to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_is_stable() {
+        assert_eq!(stable_hash(""), FNV_OFFSET_BASIS);
+        assert_eq!(stable_hash("a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(HashModifier { length: 40 }.apply("a"), "af63dc4c8601ec8c");
+        assert_eq!(HashModifier { length: 4 }.apply("a"), "af63");
+    }
+
+    #[test]
+    fn hash_pick() {
+        let modifier = HashPickModifier::new(&["red", "green", "blue"]);
+        let picks: Vec<String> = ["Al", "Bea", "Cy", "Dee", "Ed"]
+            .iter()
+            .map(|name| modifier.apply(name))
+            .collect();
+        assert!(picks.iter().all(|pick| modifier.items.contains(pick)));
+        assert_eq!(HashPickModifier::new(&[]).apply("Al"), "");
+    }
+}
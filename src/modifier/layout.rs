@@ -0,0 +1,225 @@
+use std::borrow::Cow;
+
+use super::Modifier;
+use crate::grammar::ExpansionContext;
+use crate::text;
+
+/// Side of the padding added by a `PadModifier`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Alignment {
+    /// The text is kept on the left, and padded on the right
+    #[default]
+    Left,
+    /// The text is kept on the right, and padded on the left
+    Right,
+    /// The text is centered, with any odd padding character on the right
+    Center,
+}
+
+/// Pads its input with the fill character up to the given width, in grapheme clusters, for fixed-width output.
+/// Longer inputs are left unchanged.
+///
+/// In a rule, its arguments override the width, then the fill character and the alignment (`left`, `right`
+/// or `center`): `{name:pad(12,'.')}`, `{score:pad(6,' ',right)}`.
+///
+/// ```
+/// use vitrail::modifier::{Alignment, Modifier, PadModifier};
+///
+/// assert_eq!(PadModifier::new(6, '.').apply("abc"), "abc...");
+/// assert_eq!(PadModifier::new(6, ' ').aligned(Alignment::Right).apply("42"), "    42");
+/// ```
+pub struct PadModifier {
+    pub width: usize,
+    pub fill: char,
+    pub alignment: Alignment,
+}
+
+impl PadModifier {
+    /// Create a modifier padding on the right, up to the given width
+    pub fn new(width: usize, fill: char) -> Self {
+        Self {
+            width,
+            fill,
+            alignment: Alignment::default(),
+        }
+    }
+
+    /// Returns the modifier with the given alignment
+    pub fn aligned(mut self, alignment: Alignment) -> Self {
+        self.alignment = alignment;
+        self
+    }
+}
+
+impl Modifier for PadModifier {
+    fn apply(&self, source: &str) -> String {
+        self.apply_cow(Cow::Borrowed(source)).into_owned()
+    }
+
+    fn apply_cow<'s>(&self, source: Cow<'s, str>) -> Cow<'s, str> {
+        let missing = self.width.saturating_sub(text::grapheme_len(&source));
+        if missing == 0 {
+            return source;
+        }
+
+        let (left, right) = match self.alignment {
+            Alignment::Left => (0, missing),
+            Alignment::Right => (missing, 0),
+            Alignment::Center => (missing / 2, missing - missing / 2),
+        };
+        let fill = |count: usize| self.fill.to_string().repeat(count);
+
+        Cow::Owned(format!("{}{}{}", fill(left), source, fill(right)))
+    }
+
+    fn apply_with_arguments<'s>(
+        &self,
+        source: Cow<'s, str>,
+        arguments: &[String],
+        _context: &ExpansionContext,
+    ) -> Result<Cow<'s, str>, String> {
+        let (width, fill, alignment) = match arguments {
+            [] => return Ok(self.apply_cow(source)),
+            [width] => (width, None, None),
+            [width, fill] => (width, Some(fill), None),
+            [width, fill, alignment] => (width, Some(fill), Some(alignment)),
+            _ => return Err("expected a width, a fill character and an alignment".to_string()),
+        };
+
+        let modifier = PadModifier {
+            width: parse_length("width", width)?,
+            fill: match fill {
+                None => self.fill,
+                Some(fill) => {
+                    let mut characters = fill.chars();
+                    match (characters.next(), characters.next()) {
+                        (Some(character), None) => character,
+                        _ => return Err(format!("invalid fill character '{}'", fill)),
+                    }
+                }
+            },
+            alignment: match alignment.map(String::as_str) {
+                None => self.alignment,
+                Some("left") => Alignment::Left,
+                Some("right") => Alignment::Right,
+                Some("center") => Alignment::Center,
+                Some(alignment) => return Err(format!("invalid alignment '{}'", alignment)),
+            },
+        };
+        Ok(modifier.apply_cow(source))
+    }
+}
+
+/// Truncates its input to at most the given length, in grapheme clusters, ellipsis included,
+/// cutting at the last word boundary when there is one. Inputs which fit are left unchanged.
+///
+/// In a rule, its arguments override the length, then the ellipsis: `{title:truncate(40)}`, `{title:truncate(8,'')}`.
+///
+/// ```
+/// use vitrail::modifier::{Modifier, TruncateModifier};
+///
+/// let modifier = TruncateModifier::new(12, "…");
+/// assert_eq!(modifier.apply("The quick brown fox"), "The quick…");
+/// ```
+pub struct TruncateModifier {
+    pub length: usize,
+    pub ellipsis: String,
+}
+
+impl TruncateModifier {
+    /// Create a modifier truncating to the given length, ending truncated inputs with the ellipsis
+    pub fn new(length: usize, ellipsis: &str) -> Self {
+        Self {
+            length,
+            ellipsis: ellipsis.to_string(),
+        }
+    }
+}
+
+impl Modifier for TruncateModifier {
+    fn apply(&self, source: &str) -> String {
+        text::truncate(source, self.length, &self.ellipsis)
+    }
+
+    fn apply_cow<'s>(&self, source: Cow<'s, str>) -> Cow<'s, str> {
+        if text::grapheme_len(&source) <= self.length {
+            source
+        } else {
+            Cow::Owned(self.apply(&source))
+        }
+    }
+
+    fn apply_with_arguments<'s>(
+        &self,
+        source: Cow<'s, str>,
+        arguments: &[String],
+        _context: &ExpansionContext,
+    ) -> Result<Cow<'s, str>, String> {
+        let modifier = match arguments {
+            [] => return Ok(self.apply_cow(source)),
+            [length] => TruncateModifier::new(parse_length("length", length)?, &self.ellipsis),
+            [length, ellipsis] => TruncateModifier::new(parse_length("length", length)?, ellipsis),
+            _ => return Err("expected a length and an ellipsis".to_string()),
+        };
+        Ok(modifier.apply_cow(source))
+    }
+}
+
+/// Parse a length given as an argument to a modifier
+fn parse_length(name: &str, argument: &str) -> Result<usize, String> {
+    argument
+        .parse()
+        .map_err(|_| format!("invalid {} '{}'", name, argument))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pad_alignments() {
+        assert_eq!(PadModifier::new(5, '-').apply("ab"), "ab---");
+        assert_eq!(
+            PadModifier::new(5, '-')
+                .aligned(Alignment::Center)
+                .apply("ab"),
+            "-ab--"
+        );
+        assert_eq!(PadModifier::new(3, '-').apply("café!"), "café!");
+        assert_eq!(PadModifier::new(3, '-').apply("é"), "é--");
+    }
+
+    #[test]
+    fn arguments_override_the_configuration() {
+        let grammar = crate::grammar::Grammar::default();
+        let context = grammar.context();
+        let apply = |modifier: &dyn Modifier, arguments: &[&str]| {
+            let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+            modifier
+                .apply_with_arguments(Cow::Borrowed("ab"), &arguments, &context)
+                .map(Cow::into_owned)
+        };
+
+        let pad = PadModifier::new(3, '-');
+        assert_eq!(apply(&pad, &[]).unwrap(), "ab-");
+        assert_eq!(apply(&pad, &["4"]).unwrap(), "ab--");
+        assert_eq!(apply(&pad, &["4", "*", "center"]).unwrap(), "*ab*");
+        assert!(apply(&pad, &["4", "**"]).is_err());
+        assert!(apply(&pad, &["4", "*", "up"]).is_err());
+
+        let truncate = TruncateModifier::new(10, ".");
+        assert_eq!(apply(&truncate, &["1"]).unwrap(), ".");
+        assert_eq!(apply(&truncate, &["1", ""]).unwrap(), "a");
+        assert!(apply(&truncate, &["-1"]).is_err());
+    }
+
+    #[test]
+    fn truncate_keeps_fitting_input() {
+        let modifier = TruncateModifier::new(5, "...");
+        assert!(matches!(
+            modifier.apply_cow(Cow::Borrowed("short")),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(modifier.apply("longer"), "lo...");
+    }
+}
@@ -0,0 +1,546 @@
+use std::borrow::Cow;
+
+pub(crate) mod call;
+mod french;
+mod hash;
+mod layout;
+mod ssml;
+mod transliteration;
+
+pub use self::french::FrenchPluralizeModifier;
+pub use self::hash::{HashModifier, HashPickModifier};
+pub use self::layout::{Alignment, PadModifier, TruncateModifier};
+pub use self::ssml::{SsmlBreakModifier, SsmlEmphasisModifier, SsmlProsodyModifier};
+pub use self::transliteration::{StripDiacriticsModifier, TransliterationModifier};
+
+use crate::grammar::ExpansionContext;
+
+/// Tag of the rules of mass nouns, which the `a` modifier leaves without an article: "water"
+pub const MASS_NOUN_TAG: &str = "mass";
+/// Tag of the rules of plural nouns, which the `a` modifier prefixes with "some": "some apples"
+pub const PLURAL_NOUN_TAG: &str = "plural";
+/// Determiner of plural nouns
+const PLURAL_DETERMINER: &str = "some";
+
+/// Beginnings of words whose first vowel sounds like a consonant, which take "a": "a unicorn", "a European"
+const CONSONANT_SOUND_PREFIXES: [&str; 12] = [
+    "eu", "ewe", "unicorn", "unif", "union", "uniq", "unit", "univers", "usa", "use", "usu", "uto",
+];
+/// Words whose first vowel sounds like a consonant, which take "a": "a one"
+const CONSONANT_SOUND_WORDS: [&str; 2] = ["one", "once"];
+/// Beginnings of words starting with a silent "h", which take "an": "an hour"
+const SILENT_H_PREFIXES: [&str; 5] = ["heir", "honest", "honor", "honour", "hour"];
+
+/// Short words left out of acronyms, unless they are kept explicitly
+const ACRONYM_STOP_WORDS: [&str; 12] = [
+    "a", "an", "and", "at", "by", "for", "in", "of", "on", "or", "the", "to",
+];
+
+pub trait Modifier {
+    fn apply(&self, source: &str) -> String;
+
+    /// Same as `apply`, taking and returning a `Cow`, so modifiers leaving their input unchanged
+    /// can return it without allocating.
+    ///
+    /// The default implementation always allocates through `apply`.
+    fn apply_cow<'s>(&self, source: Cow<'s, str>) -> Cow<'s, str> {
+        Cow::Owned(self.apply(&source))
+    }
+
+    /// Same as `apply_cow`, with access to the state of the expansion in progress, e.g. its captured variables.
+    ///
+    /// The default implementation ignores the context, through `apply_cow`.
+    fn apply_in_context<'s>(
+        &self,
+        source: Cow<'s, str>,
+        _context: &ExpansionContext,
+    ) -> Cow<'s, str> {
+        self.apply_cow(source)
+    }
+
+    /// Same as `apply_in_context`, with the arguments the modifier is given between parentheses in the rule:
+    /// `{name:pad(12,'.')}`. `Grammar` chains modifiers through this method. An error, describing invalid
+    /// arguments, is reported as an `ExpansionError::InvalidModifierArguments`.
+    ///
+    /// The default implementation rejects any argument, and goes through `apply_in_context` otherwise.
+    fn apply_with_arguments<'s>(
+        &self,
+        source: Cow<'s, str>,
+        arguments: &[String],
+        context: &ExpansionContext,
+    ) -> Result<Cow<'s, str>, String> {
+        if arguments.is_empty() {
+            Ok(self.apply_in_context(source, context))
+        } else {
+            Err("the modifier takes no arguments".to_string())
+        }
+    }
+}
+
+impl<M: Modifier + ?Sized> Modifier for &M {
+    fn apply(&self, source: &str) -> String {
+        (**self).apply(source)
+    }
+
+    fn apply_cow<'s>(&self, source: Cow<'s, str>) -> Cow<'s, str> {
+        (**self).apply_cow(source)
+    }
+
+    fn apply_in_context<'s>(
+        &self,
+        source: Cow<'s, str>,
+        context: &ExpansionContext,
+    ) -> Cow<'s, str> {
+        (**self).apply_in_context(source, context)
+    }
+
+    fn apply_with_arguments<'s>(
+        &self,
+        source: Cow<'s, str>,
+        arguments: &[String],
+        context: &ExpansionContext,
+    ) -> Result<Cow<'s, str>, String> {
+        (**self).apply_with_arguments(source, arguments, context)
+    }
+}
+
+/// Adapter registering a closure as a modifier, see `Grammar::with_modifier_fn`
+pub(crate) struct FnModifier<F>(pub(crate) F);
+impl<F: Fn(&str) -> String> Modifier for FnModifier<F> {
+    fn apply(&self, source: &str) -> String {
+        (self.0)(source)
+    }
+}
+
+pub struct CapitalizeModifier {}
+impl Modifier for CapitalizeModifier {
+    fn apply(&self, source: &str) -> String {
+        let mut chars = source.chars();
+        match chars.next() {
+            None => String::new(),
+            Some(first) => first.to_uppercase().collect::<String>() + chars.as_str(),
+        }
+    }
+
+    fn apply_cow<'s>(&self, source: Cow<'s, str>) -> Cow<'s, str> {
+        match source.chars().next() {
+            Some(first) if !first.is_lowercase() => source,
+            None => source,
+            Some(_) => Cow::Owned(self.apply(&source)),
+        }
+    }
+}
+
+pub struct PluralizeModifier {}
+impl PluralizeModifier {
+    fn is_vowel(c: char) -> bool {
+        ['a', 'e', 'i', 'o', 'u'].contains(&c.to_ascii_lowercase())
+    }
+}
+
+impl Modifier for PluralizeModifier {
+    fn apply(&self, source: &str) -> String {
+        match source.chars().last() {
+            None => String::new(),
+            Some('s') | Some('h') | Some('x') => format!("{}es", source),
+            Some('y') => {
+                let root = &source[..source.len() - 1];
+                match root.chars().last() {
+                    Some(c) if !PluralizeModifier::is_vowel(c) => format!("{}ies", root),
+                    _ => format!("{}s", source),
+                }
+            }
+            Some(_) => format!("{}s", source),
+        }
+    }
+}
+
+/// Capitalizes the first letter of every word: "the red door" => "The Red Door"
+pub struct CapitalizeAllModifier {}
+impl Modifier for CapitalizeAllModifier {
+    fn apply(&self, source: &str) -> String {
+        source
+            .split(' ')
+            .map(|word| CapitalizeModifier {}.apply(word))
+            .collect::<Vec<String>>()
+            .join(" ")
+    }
+}
+
+/// Converts the whole text to uppercase
+pub struct UppercaseModifier {}
+impl Modifier for UppercaseModifier {
+    fn apply(&self, source: &str) -> String {
+        source.to_uppercase()
+    }
+
+    fn apply_cow<'s>(&self, source: Cow<'s, str>) -> Cow<'s, str> {
+        if source.chars().any(char::is_lowercase) {
+            Cow::Owned(self.apply(&source))
+        } else {
+            source
+        }
+    }
+}
+
+/// Converts the whole text to lowercase
+pub struct LowercaseModifier {}
+impl Modifier for LowercaseModifier {
+    fn apply(&self, source: &str) -> String {
+        source.to_lowercase()
+    }
+
+    fn apply_cow<'s>(&self, source: Cow<'s, str>) -> Cow<'s, str> {
+        if source.chars().any(char::is_uppercase) {
+            Cow::Owned(self.apply(&source))
+        } else {
+            source
+        }
+    }
+}
+
+/// Removes leading and trailing whitespace
+pub struct TrimModifier {}
+impl Modifier for TrimModifier {
+    fn apply(&self, source: &str) -> String {
+        source.trim().to_string()
+    }
+
+    fn apply_cow<'s>(&self, source: Cow<'s, str>) -> Cow<'s, str> {
+        match source {
+            Cow::Borrowed(text) => Cow::Borrowed(text.trim()),
+            Cow::Owned(text) if text.trim().len() == text.len() => Cow::Owned(text),
+            Cow::Owned(text) => Cow::Owned(text.trim().to_string()),
+        }
+    }
+}
+
+/// Prefixes a word with the indefinite article matching its initial sound: "apple" => "an apple",
+/// "hour" => "an hour", "unicorn" => "a unicorn".
+///
+/// The sound is read from the first letter, except for the common words listed as exceptions,
+/// such as the ones starting with a silent "h", or with a "u" pronounced "you".
+///
+/// In a grammar, the determiner agrees with the tags of the rule selected for the modified symbol, or for the
+/// captured variable: mass nouns (`MASS_NOUN_TAG`) take no article, and plural nouns (`PLURAL_NOUN_TAG`) take "some".
+pub struct ArticleModifier {}
+impl Modifier for ArticleModifier {
+    fn apply(&self, source: &str) -> String {
+        if source.is_empty() {
+            String::new()
+        } else if Self::takes_an(source) {
+            format!("an {}", source)
+        } else {
+            format!("a {}", source)
+        }
+    }
+
+    fn apply_in_context<'s>(
+        &self,
+        source: Cow<'s, str>,
+        context: &ExpansionContext,
+    ) -> Cow<'s, str> {
+        let has_tag = |tag: &str| context.tags().iter().any(|t| t == tag);
+        if source.is_empty() || has_tag(MASS_NOUN_TAG) {
+            source
+        } else if has_tag(PLURAL_NOUN_TAG) {
+            Cow::Owned(format!("{} {}", PLURAL_DETERMINER, source))
+        } else {
+            self.apply_cow(source)
+        }
+    }
+}
+
+impl ArticleModifier {
+    /// Returns whether the given text starts with a vowel sound
+    fn takes_an(text: &str) -> bool {
+        let word = text.to_lowercase();
+        let first_word = word
+            .split(|c: char| !c.is_alphabetic())
+            .next()
+            .unwrap_or("");
+
+        if SILENT_H_PREFIXES
+            .iter()
+            .any(|prefix| word.starts_with(prefix))
+        {
+            true
+        } else if CONSONANT_SOUND_WORDS.contains(&first_word)
+            || CONSONANT_SOUND_PREFIXES
+                .iter()
+                .any(|prefix| word.starts_with(prefix))
+        {
+            false
+        } else {
+            word.chars().next().is_some_and(PluralizeModifier::is_vowel)
+        }
+    }
+}
+
+/// Turns a regular verb into its past tense: "walk" => "walked", "carry" => "carried"
+pub struct PastTenseModifier {}
+impl Modifier for PastTenseModifier {
+    fn apply(&self, source: &str) -> String {
+        let mut chars = source.chars().rev();
+        match (chars.next(), chars.next()) {
+            (None, _) => String::new(),
+            (Some('e'), _) => format!("{}d", source),
+            (Some('y'), Some(before)) if !PluralizeModifier::is_vowel(before) => {
+                format!("{}ied", &source[..source.len() - 1])
+            }
+            (Some(_), _) => format!("{}ed", source),
+        }
+    }
+}
+
+/// Wraps the text in double quotes
+pub struct QuoteModifier {}
+impl Modifier for QuoteModifier {
+    fn apply(&self, source: &str) -> String {
+        format!("\"{}\"", source)
+    }
+}
+
+/// Appends a comma, unless the text already ends with a punctuation mark
+pub struct CommaModifier {}
+impl Modifier for CommaModifier {
+    fn apply(&self, source: &str) -> String {
+        self.apply_cow(Cow::Borrowed(source)).into_owned()
+    }
+
+    fn apply_cow<'s>(&self, source: Cow<'s, str>) -> Cow<'s, str> {
+        match source.chars().last() {
+            None | Some(',') | Some('.') | Some('?') | Some('!') => source,
+            Some(_) => Cow::Owned(format!("{},", source)),
+        }
+    }
+}
+
+/// Builds an acronym from the first letter of each word, hyphenated parts included, optionally keeping
+/// short stop words like "of" or "the": "Galactic Trade Federation" => "GTF"
+pub struct AcronymModifier {
+    pub keep_stop_words: bool,
+}
+
+impl Modifier for AcronymModifier {
+    fn apply(&self, source: &str) -> String {
+        source
+            .split(|c: char| c.is_whitespace() || c == '-')
+            .filter(|word| {
+                self.keep_stop_words || !ACRONYM_STOP_WORDS.contains(&word.to_lowercase().as_str())
+            })
+            .filter_map(|word| word.chars().find(|c| c.is_alphanumeric()))
+            .flat_map(char::to_uppercase)
+            .collect()
+    }
+}
+
+/// Returns the standard modifiers, under their documented names, matching Tracery's where applicable:
+///
+/// | Name | Modifier |
+/// |---|---|
+/// | `capitalize` | `CapitalizeModifier` |
+/// | `capitalizeAll` | `CapitalizeAllModifier` |
+/// | `s` | `PluralizeModifier` |
+/// | `a` | `ArticleModifier` |
+/// | `ed` | `PastTenseModifier` |
+/// | `inQuotes` | `QuoteModifier` |
+/// | `comma` | `CommaModifier` |
+/// | `upper` | `UppercaseModifier` |
+/// | `lower` | `LowercaseModifier` |
+/// | `trim` | `TrimModifier` |
+/// | `acronym` | `AcronymModifier`, without stop words |
+/// | `pad(width, fill, alignment)` | `PadModifier`, filling with spaces on the right by default |
+/// | `truncate(length, ellipsis)` | `TruncateModifier`, ending with "…" by default |
+pub fn standard_modifiers() -> Vec<(String, Box<dyn Modifier>)> {
+    let modifiers: Vec<(&str, Box<dyn Modifier>)> = vec![
+        ("capitalize", Box::new(CapitalizeModifier {})),
+        ("capitalizeAll", Box::new(CapitalizeAllModifier {})),
+        ("s", Box::new(PluralizeModifier {})),
+        ("a", Box::new(ArticleModifier {})),
+        ("ed", Box::new(PastTenseModifier {})),
+        ("inQuotes", Box::new(QuoteModifier {})),
+        ("comma", Box::new(CommaModifier {})),
+        ("upper", Box::new(UppercaseModifier {})),
+        ("lower", Box::new(LowercaseModifier {})),
+        ("trim", Box::new(TrimModifier {})),
+        (
+            "acronym",
+            Box::new(AcronymModifier {
+                keep_stop_words: false,
+            }),
+        ),
+        ("pad", Box::new(PadModifier::new(0, ' '))),
+        ("truncate", Box::new(TruncateModifier::new(usize::MAX, "…"))),
+    ];
+
+    modifiers
+        .into_iter()
+        .map(|(name, modifier)| (name.to_string(), modifier))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capitalize_all() {
+        let modifier = CapitalizeAllModifier {};
+        assert_eq!(modifier.apply("the red  door"), "The Red  Door");
+    }
+
+    #[test]
+    fn upper_and_lower_case() {
+        assert_eq!(UppercaseModifier {}.apply("Hello"), "HELLO");
+        assert_eq!(LowercaseModifier {}.apply("Hello"), "hello");
+    }
+
+    #[test]
+    fn trim() {
+        assert_eq!(TrimModifier {}.apply("  spaced out "), "spaced out");
+    }
+
+    #[test]
+    fn article() {
+        let modifier = ArticleModifier {};
+        assert_eq!(modifier.apply("apple"), "an apple");
+        assert_eq!(modifier.apply("banana"), "a banana");
+        assert_eq!(modifier.apply(""), "");
+        assert_eq!(modifier.apply("hour"), "an hour");
+        assert_eq!(modifier.apply("Honest man"), "an Honest man");
+        assert_eq!(modifier.apply("horse"), "a horse");
+        assert_eq!(modifier.apply("unicorn"), "a unicorn");
+        assert_eq!(modifier.apply("uninvited guest"), "an uninvited guest");
+        assert_eq!(modifier.apply("European"), "a European");
+        assert_eq!(modifier.apply("one-eyed cat"), "a one-eyed cat");
+        assert_eq!(modifier.apply("onerous task"), "an onerous task");
+    }
+
+    #[test]
+    fn past_tense() {
+        let modifier = PastTenseModifier {};
+        assert_eq!(modifier.apply("walk"), "walked");
+        assert_eq!(modifier.apply("bake"), "baked");
+        assert_eq!(modifier.apply("carry"), "carried");
+        assert_eq!(modifier.apply("play"), "played");
+    }
+
+    #[test]
+    fn quote_and_comma() {
+        assert_eq!(QuoteModifier {}.apply("hi"), "\"hi\"");
+        assert_eq!(CommaModifier {}.apply("hi"), "hi,");
+        assert_eq!(CommaModifier {}.apply("hi!"), "hi!");
+    }
+
+    #[test]
+    fn acronym() {
+        let modifier = AcronymModifier {
+            keep_stop_words: false,
+        };
+        assert_eq!(modifier.apply("Galactic Trade Federation"), "GTF");
+        assert_eq!(
+            modifier.apply("the Order of the Trans-Lunar 'Star'"),
+            "OTLS"
+        );
+        assert_eq!(modifier.apply(""), "");
+
+        let modifier = AcronymModifier {
+            keep_stop_words: true,
+        };
+        assert_eq!(modifier.apply("Bank of the North"), "BOTN");
+    }
+
+    #[test]
+    fn apply_cow_borrows_when_unchanged() {
+        let unchanged = |modifier: &dyn Modifier, source: &str| {
+            matches!(modifier.apply_cow(Cow::Borrowed(source)), Cow::Borrowed(_))
+        };
+
+        assert!(unchanged(&CapitalizeModifier {}, "Already"));
+        assert!(unchanged(&UppercaseModifier {}, "LOUD"));
+        assert!(unchanged(&LowercaseModifier {}, "quiet"));
+        assert!(unchanged(&TrimModifier {}, "  padded  "));
+        assert!(unchanged(&CommaModifier {}, "done."));
+        assert!(!unchanged(&CapitalizeModifier {}, "lower"));
+    }
+
+    #[test]
+    fn apply_cow_default_implementation() {
+        let modifier = PluralizeModifier {};
+        assert_eq!(modifier.apply_cow(Cow::Borrowed("cat")), "cats");
+    }
+
+    #[test]
+    fn standard_modifier_names() {
+        let names: Vec<String> = standard_modifiers().into_iter().map(|(n, _)| n).collect();
+        assert!(names.contains(&"capitalize".to_string()));
+        assert!(names.contains(&"s".to_string()));
+        assert!(names.contains(&"pad".to_string()));
+        assert_eq!(names.len(), 13);
+    }
+
+    #[test]
+    fn capitalize_empty_string() {
+        let modifier = CapitalizeModifier {};
+        assert_eq!(modifier.apply(""), "");
+    }
+
+    #[test]
+    fn capitalize_valid_string() {
+        let modifier = CapitalizeModifier {};
+        assert_eq!(
+            modifier.apply("capitalize me please"),
+            "Capitalize me please"
+        );
+    }
+
+    #[test]
+    fn pluralize_empty_string() {
+        let modifier = PluralizeModifier {};
+        assert_eq!(modifier.apply(""), "");
+    }
+
+    #[test]
+    fn pluralize_s_ending() {
+        let modifier = PluralizeModifier {};
+        assert_eq!(modifier.apply("princess"), "princesses");
+    }
+
+    #[test]
+    fn pluralize_h_ending() {
+        let modifier = PluralizeModifier {};
+        assert_eq!(modifier.apply("bush"), "bushes");
+    }
+
+    #[test]
+    fn pluralize_x_ending() {
+        let modifier = PluralizeModifier {};
+        assert_eq!(modifier.apply("sphinx"), "sphinxes");
+    }
+
+    #[test]
+    fn pluralize_y_ending_after_vowel() {
+        let modifier = PluralizeModifier {};
+        assert_eq!(modifier.apply("matey"), "mateys");
+    }
+
+    #[test]
+    fn pluralize_y_ending_after_consonant() {
+        let modifier = PluralizeModifier {};
+        assert_eq!(modifier.apply("party"), "parties");
+    }
+
+    #[test]
+    fn pluralize_short_and_non_ascii_y_endings() {
+        let modifier = PluralizeModifier {};
+        assert_eq!(modifier.apply("y"), "ys");
+        assert_eq!(modifier.apply("éty"), "éties");
+    }
+
+    #[test]
+    fn pluralize_other() {
+        let modifier = PluralizeModifier {};
+        assert_eq!(modifier.apply("word"), "words");
+    }
+}
@@ -0,0 +1,86 @@
+use super::Modifier;
+use crate::ssml;
+
+/// Wraps its input in an SSML `emphasis` element, with the given level (`strong`, `moderate`, `reduced`)
+pub struct SsmlEmphasisModifier {
+    pub level: String,
+}
+
+impl Modifier for SsmlEmphasisModifier {
+    fn apply(&self, source: &str) -> String {
+        let (open, close) = ssml::element("emphasis", &[("level", &self.level)]);
+        format!("{}{}{}", open, source, close)
+    }
+}
+
+/// Wraps its input in an SSML `prosody` element, with the given rate, pitch and volume (omitted when `None`)
+#[derive(Default)]
+pub struct SsmlProsodyModifier {
+    pub rate: Option<String>,
+    pub pitch: Option<String>,
+    pub volume: Option<String>,
+}
+
+impl Modifier for SsmlProsodyModifier {
+    fn apply(&self, source: &str) -> String {
+        let attributes: Vec<(&str, &str)> = [
+            ("rate", &self.rate),
+            ("pitch", &self.pitch),
+            ("volume", &self.volume),
+        ]
+        .iter()
+        .filter_map(|(name, value)| value.as_deref().map(|value| (*name, value)))
+        .collect();
+
+        let (open, close) = ssml::element("prosody", &attributes);
+        format!("{}{}{}", open, source, close)
+    }
+}
+
+/// Appends an SSML `break` (pause) of the given duration (e.g. `500ms`) after its input
+pub struct SsmlBreakModifier {
+    pub time: String,
+}
+
+impl Modifier for SsmlBreakModifier {
+    fn apply(&self, source: &str) -> String {
+        format!("{}<break time=\"{}\"/>", source, ssml::escape(&self.time))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emphasis() {
+        let modifier = SsmlEmphasisModifier {
+            level: "strong".to_string(),
+        };
+        assert_eq!(
+            modifier.apply("now"),
+            "<emphasis level=\"strong\">now</emphasis>"
+        );
+    }
+
+    #[test]
+    fn prosody_with_some_attributes() {
+        let modifier = SsmlProsodyModifier {
+            rate: Some("slow".to_string()),
+            volume: Some("loud".to_string()),
+            ..SsmlProsodyModifier::default()
+        };
+        assert_eq!(
+            modifier.apply("boo"),
+            "<prosody rate=\"slow\" volume=\"loud\">boo</prosody>"
+        );
+    }
+
+    #[test]
+    fn break_after() {
+        let modifier = SsmlBreakModifier {
+            time: "1s".to_string(),
+        };
+        assert_eq!(modifier.apply("wait"), "wait<break time=\"1s\"/>");
+    }
+}
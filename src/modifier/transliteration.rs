@@ -0,0 +1,189 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use super::Modifier;
+
+/// Letters with diacritics, each one stripped into the letter at the same position of `UNACCENTED`
+const ACCENTED: &str =
+    "ÀÁÂÃÄÅàáâãäåĀāĂăĄąÇçĆćĈĉĊċČčĎďÈÉÊËèéêëĒēĔĕĖėĘęĚěĜĝĞğĠġĢģĤĥÌÍÎÏìíîïĨĩĪīĬĭĮįİıĴĵĶķĹĺĻļĽľ\
+ÑñŃńŅņŇňÒÓÔÕÖòóôõöŌōŎŏŐőŔŕŖŗŘřŚśŜŝŞşŠšŢţŤťÙÚÛÜùúûüŨũŪūŬŭŮůŰűŲųŴŵÝýÿŶŷŸŹźŻżŽž";
+/// The letters of `ACCENTED`, without their diacritics
+const UNACCENTED: &str =
+    "AAAAAAaaaaaaAaAaAaCcCcCcCcCcDdEEEEeeeeEeEeEeEeEeGgGgGgGgHhIIIIiiiiIiIiIiIiIiJjKkLlLlLl\
+NnNnNnNnOOOOOoooooOoOoOoRrRrRrSsSsSsSsTtTtUUUUuuuuUuUuUuUuUuUuWwYyyYyYZzZzZz";
+/// Letters which are not a base letter with a diacritic, but are spelled with several Latin letters
+const LIGATURES: [(char, &str); 13] = [
+    ('Æ', "AE"),
+    ('æ', "ae"),
+    ('Œ', "OE"),
+    ('œ', "oe"),
+    ('ß', "ss"),
+    ('Ø', "O"),
+    ('ø', "o"),
+    ('Ł', "L"),
+    ('ł', "l"),
+    ('Đ', "D"),
+    ('đ', "d"),
+    ('Þ', "Th"),
+    ('þ', "th"),
+];
+/// Combining diacritical marks, found in decomposed text (e.g. `e` followed by U+0301)
+const COMBINING_MARKS: std::ops::RangeInclusive<char> = '\u{0300}'..='\u{036F}';
+
+/// Replaces the sequences of its input listed in a transliteration table, e.g. to give a generated name
+/// a runic or Greek look. At each position, the longest matching sequence is replaced.
+///
+/// ```
+/// use vitrail::modifier::{Modifier, TransliterationModifier};
+///
+/// let modifier = TransliterationModifier::new(&[("th", "þ"), ("a", "ᚨ"), ("e", "ε")]);
+/// assert_eq!(modifier.apply("thane"), "þᚨnε");
+/// ```
+pub struct TransliterationModifier {
+    /// The replaced sequences and their replacement, longest sequences first
+    table: Vec<(String, String)>,
+}
+
+impl TransliterationModifier {
+    /// Create a modifier from `(sequence, replacement)` pairs. Empty sequences are ignored.
+    pub fn new(table: &[(&str, &str)]) -> Self {
+        Self::from_pairs(
+            table
+                .iter()
+                .map(|(from, to)| (from.to_string(), to.to_string())),
+        )
+    }
+
+    /// Create a modifier from a JSON data file, holding an object which maps each sequence to its replacement:
+    /// `{"th": "þ", "a": "ᚨ"}`
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+        let content = fs::read_to_string(path)
+            .map_err(|_| format!("could not read transliteration table at {}", path.display()))?;
+        let table: HashMap<String, String> = serde_json::from_str(&content).map_err(|_| {
+            format!(
+                "transliteration table {} must be an object of strings",
+                path.display()
+            )
+        })?;
+
+        Ok(Self::from_pairs(table))
+    }
+
+    fn from_pairs<I>(pairs: I) -> Self
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut table: Vec<(String, String)> = pairs
+            .into_iter()
+            .filter(|(from, _)| !from.is_empty())
+            .collect();
+        table.sort_by(|(a, _), (b, _)| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+
+        Self { table }
+    }
+}
+
+impl Modifier for TransliterationModifier {
+    fn apply(&self, source: &str) -> String {
+        let mut transliterated = String::with_capacity(source.len());
+        let mut rest = source;
+
+        while let Some(character) = rest.chars().next() {
+            match self
+                .table
+                .iter()
+                .find(|(from, _)| rest.starts_with(from.as_str()))
+            {
+                Some((from, to)) => {
+                    transliterated.push_str(to);
+                    rest = &rest[from.len()..];
+                }
+                None => {
+                    transliterated.push(character);
+                    rest = &rest[character.len_utf8()..];
+                }
+            }
+        }
+
+        transliterated
+    }
+}
+
+/// Removes the diacritics of Latin letters (`é` => `e`), and spells out the letters without a base letter
+/// (`æ` => `ae`, `ß` => `ss`), e.g. to turn a stylized name into a plain identifier
+///
+/// ```
+/// use vitrail::modifier::{Modifier, StripDiacriticsModifier};
+///
+/// assert_eq!(StripDiacriticsModifier {}.apply("Ærøskøbing Čáslav"), "AEroskobing Caslav");
+/// ```
+pub struct StripDiacriticsModifier {}
+impl Modifier for StripDiacriticsModifier {
+    fn apply(&self, source: &str) -> String {
+        let mut stripped = String::with_capacity(source.len());
+
+        for character in source.chars() {
+            if COMBINING_MARKS.contains(&character) {
+                continue;
+            }
+
+            if let Some(position) = ACCENTED.chars().position(|accented| accented == character) {
+                stripped.extend(UNACCENTED.chars().nth(position));
+            } else if let Some((_, spelled)) = LIGATURES
+                .iter()
+                .find(|(ligature, _)| *ligature == character)
+            {
+                stripped.push_str(spelled);
+            } else {
+                stripped.push(character);
+            }
+        }
+
+        stripped
+    }
+
+    fn apply_cow<'s>(&self, source: Cow<'s, str>) -> Cow<'s, str> {
+        if source.is_ascii() {
+            source
+        } else {
+            Cow::Owned(self.apply(&source))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn longest_sequence_wins() {
+        let modifier = TransliterationModifier::new(&[("o", "ο"), ("oo", "ω"), ("", "x")]);
+        assert_eq!(modifier.apply("moon"), "mωn");
+        assert_eq!(modifier.apply("pool of ooo"), "pωl οf ωο");
+    }
+
+    #[test]
+    fn table_from_file() {
+        let modifier = TransliterationModifier::from_file("tests/data/runes.json").unwrap();
+        assert_eq!(modifier.apply("thorn"), "ᚦᛟᚱᚾ");
+        assert!(TransliterationModifier::from_file("tests/data/missing.json").is_err());
+        assert!(TransliterationModifier::from_file("tests/data/names.txt").is_err());
+    }
+
+    #[test]
+    fn diacritics_tables_match() {
+        assert_eq!(ACCENTED.chars().count(), UNACCENTED.chars().count());
+    }
+
+    #[test]
+    fn strip_decomposed_diacritics() {
+        assert_eq!(StripDiacriticsModifier {}.apply("cafe\u{0301}"), "cafe");
+        assert!(matches!(
+            StripDiacriticsModifier {}.apply_cow(Cow::Borrowed("plain")),
+            Cow::Borrowed(_)
+        ));
+    }
+}
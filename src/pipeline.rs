@@ -0,0 +1,138 @@
+//! Layered generation, where each grammar expands with the outputs of the previous ones.
+
+use std::collections::HashMap;
+
+use crate::grammar::{CaptureValue, Grammar};
+
+/// A grammar of a pipeline, whose output is bound under a name for the following stages
+struct Stage<'a> {
+    binding: String,
+    grammar: Grammar<'a>,
+}
+
+/// A sequence of grammars sharing one seed, where each grammar is expanded with the output of every previous
+/// stage available as a captured variable named after its stage, along with the variables they captured.
+///
+/// ```
+/// use vitrail::{
+///     config::GrammarSyntax,
+///     grammar::Grammar,
+///     pipeline::Pipeline,
+/// };
+///
+/// let plot = Grammar::new("any", GrammarSyntax::default())
+///     .with_symbol("root".to_string(), vec!["[villain>villain]a heist".to_string()])
+///     .with_symbol("villain".to_string(), vec!["the Baron".to_string()]);
+/// let prose = Grammar::new("any", GrammarSyntax::default())
+///     .with_symbol("root".to_string(), vec!["It was {plot}, planned by {villain}.".to_string()]);
+///
+/// let mut pipeline = Pipeline::new("anyrandomseed")
+///     .with_stage("plot", plot)
+///     .with_stage("prose", prose);
+///
+/// assert_eq!(pipeline.run(), "It was a heist, planned by the Baron.");
+/// assert_eq!(pipeline.bindings()["plot"], "a heist");
+/// ```
+pub struct Pipeline<'a> {
+    seed: String,
+    stages: Vec<Stage<'a>>,
+    bindings: HashMap<String, CaptureValue>,
+}
+
+impl<'a> Pipeline<'a> {
+    /// Create an empty pipeline, whose grammars will be seeded from the given seed
+    pub fn new(seed: &str) -> Self {
+        Self {
+            seed: seed.to_string(),
+            stages: Vec::new(),
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Add a grammar at the end of the pipeline. Its output is bound under the given name for the following stages.
+    ///
+    /// The grammar is reseeded from the pipeline seed and the binding name, so every stage draws different values
+    /// while the whole pipeline stays reproducible.
+    ///
+    /// The method returns the Pipeline instance, so you can build upon it.
+    pub fn with_stage(mut self, binding: &str, mut grammar: Grammar<'a>) -> Self {
+        grammar.reseed(&format!("{}/{}", self.seed, binding));
+        self.stages.push(Stage {
+            binding: binding.to_string(),
+            grammar,
+        });
+        self
+    }
+
+    /// Expand every grammar from its default root symbol, in order, and return the output of the last one
+    pub fn run(&mut self) -> String {
+        self.bindings.clear();
+        let mut output = String::new();
+
+        for stage in self.stages.iter_mut() {
+            output = stage.grammar.flatten_with_captures(&self.bindings);
+            self.bindings.extend(stage.grammar.export_captures());
+            self.bindings
+                .insert(stage.binding.to_string(), CaptureValue::from_text(&output));
+        }
+
+        output
+    }
+
+    /// Returns the values bound by the last run: the output of each stage under its name,
+    /// and the variables captured by the stages
+    pub fn bindings(&self) -> &HashMap<String, CaptureValue> {
+        &self.bindings
+    }
+
+    /// Returns the grammar of the stage bound under the given name
+    pub fn stage(&mut self, binding: &str) -> Option<&mut Grammar<'a>> {
+        self.stages
+            .iter_mut()
+            .find(|stage| stage.binding == binding)
+            .map(|stage| &mut stage.grammar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::GrammarSyntax;
+
+    fn stage(rules: &[&str]) -> Grammar<'static> {
+        Grammar::new("stage", GrammarSyntax::default()).with_symbol(
+            "root".to_string(),
+            rules.iter().map(|rule| rule.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn outputs_feed_following_stages() {
+        let mut pipeline = Pipeline::new("seed")
+            .with_stage("first", stage(&["one"]))
+            .with_stage("second", stage(&["{first} two"]))
+            .with_stage("third", stage(&["{second} three"]));
+
+        assert_eq!(pipeline.run(), "one two three");
+        assert_eq!(pipeline.bindings()["second"], "one two");
+    }
+
+    #[test]
+    fn same_seed_same_output() {
+        let build = || {
+            Pipeline::new("seed")
+                .with_stage("a", stage(&["1", "2", "3", "4"]))
+                .with_stage("b", stage(&["{a}-x", "{a}-y", "{a}-z"]))
+        };
+
+        let outputs: Vec<String> = (0..5).map(|_| build().run()).collect();
+        assert!(outputs.iter().all(|output| *output == outputs[0]));
+    }
+
+    #[test]
+    fn stage_access() {
+        let mut pipeline = Pipeline::new("seed").with_stage("a", stage(&["1"]));
+        assert!(pipeline.stage("a").is_some());
+        assert!(pipeline.stage("b").is_none());
+    }
+}
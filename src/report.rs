@@ -0,0 +1,228 @@
+//! Reports describing the behavior of a grammar, serializable to JSON.
+
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Number of occurrences of each distinct value in a sample
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Histogram {
+    /// The occurrences of each value, sorted by decreasing count, then by value
+    pub counts: Vec<(String, usize)>,
+    /// The Shannon entropy of the distribution, in bits.
+    /// 0 means a single value was ever produced, and `log2(n)` means `n` values were evenly produced.
+    pub entropy: f64,
+}
+
+impl Histogram {
+    /// Build the histogram of the given values
+    ///
+    /// ```
+    /// use vitrail::report::Histogram;
+    ///
+    /// let histogram = Histogram::from_values(vec!["a", "b", "a", "b"]);
+    /// assert_eq!(histogram.counts, vec![("a".to_string(), 2), ("b".to_string(), 2)]);
+    /// assert_eq!(histogram.entropy, 1.0);
+    /// ```
+    pub fn from_values<I, S>(values: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let mut occurrences: HashMap<String, usize> = HashMap::new();
+        for value in values {
+            *occurrences.entry(value.into()).or_insert(0) += 1;
+        }
+
+        let total: usize = occurrences.values().sum();
+        let entropy = occurrences
+            .values()
+            .map(|count| {
+                let probability = *count as f64 / total as f64;
+                -probability * probability.log2()
+            })
+            .sum::<f64>()
+            .abs();
+
+        let mut counts: Vec<(String, usize)> = occurrences.into_iter().collect();
+        counts.sort_by(|(a, a_count), (b, b_count)| b_count.cmp(a_count).then(a.cmp(b)));
+
+        Self { counts, entropy }
+    }
+
+    /// Returns the number of occurrences of the given value
+    pub fn count(&self, value: &str) -> usize {
+        self.counts
+            .iter()
+            .find(|(candidate, _)| candidate == value)
+            .map_or(0, |(_, count)| *count)
+    }
+}
+
+/// Measures of the work performed by a single flatten, to monitor grammars behaving pathologically
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ExpansionMetrics {
+    /// The maximum nesting depth of the expansion, 1 being the rule of the root symbol
+    pub max_depth: usize,
+    /// The number of symbols derived, including captured variables and the root symbol
+    pub expansions: usize,
+    /// The length of the output, in graphemes
+    pub output_length: usize,
+}
+
+/// The output of a flatten, with metrics about its expansion, built by `Grammar::generate`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct GenerationResult {
+    /// The expanded text
+    pub output: String,
+    /// The metrics of the expansion
+    pub metrics: ExpansionMetrics,
+    /// Whether a limit was reached with partial output or a truncation marker enabled, so the output lacks
+    /// the symbols which were left unexpanded
+    pub partial: bool,
+}
+
+/// Number of times each rule was selected while expanding a grammar, enabled with `Grammar::with_counters`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SelectionCounters {
+    /// The selections of each symbol, expressed as a map of symbol => number of selections of each rule, by rule index
+    pub counts: HashMap<String, Vec<usize>>,
+}
+
+impl SelectionCounters {
+    /// Count a selection of the rule at `index` of the given symbol
+    pub fn record(&mut self, symbol: &str, index: usize) {
+        let counts = self.counts.entry(symbol.to_string()).or_default();
+        if counts.len() <= index {
+            counts.resize(index + 1, 0);
+        }
+        counts[index] += 1;
+    }
+
+    /// Returns the number of selections of the rule at `index` of the given symbol
+    pub fn count(&self, symbol: &str, index: usize) -> usize {
+        self.counts
+            .get(symbol)
+            .and_then(|counts| counts.get(index))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of selections of any rule of the given symbol
+    pub fn total(&self, symbol: &str) -> usize {
+        self.counts
+            .get(symbol)
+            .map_or(0, |counts| counts.iter().sum())
+    }
+
+    /// Set every counter back to zero
+    pub fn reset(&mut self) {
+        self.counts.clear();
+    }
+}
+
+/// Distribution of the outputs of a grammar over a number of flattens, built by `Grammar::sample_report`
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SampleReport {
+    /// The symbol the grammar was expanded from
+    pub root: String,
+    /// The number of flattens performed
+    pub samples: usize,
+    /// The distribution of the complete outputs
+    pub outputs: Histogram,
+    /// The distribution of the rules chosen for the root symbol, before their expansion
+    pub root_rules: Histogram,
+}
+
+impl SampleReport {
+    /// Returns the report serialized as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("A sample report is always serializable")
+    }
+}
+
+/// The size of the rules of a symbol, in a `SizeReport`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SymbolSize {
+    /// The name of the symbol
+    pub symbol: String,
+    /// The number of rules of the symbol
+    pub rules: usize,
+    /// The length of the name and rules of the symbol, in bytes
+    pub text_bytes: usize,
+    /// The approximate memory taken by the symbol, in bytes
+    pub estimated_memory: usize,
+}
+
+/// Approximate size of a grammar in memory, built by `Grammar::size_report`, to decide what to prune
+/// before targeting constrained platforms
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SizeReport {
+    /// The number of symbols with rules
+    pub symbols: usize,
+    /// The number of rules of all symbols
+    pub rules: usize,
+    /// The length of the names and rules of all symbols, in bytes
+    pub text_bytes: usize,
+    /// The approximate memory taken by the symbols, in bytes: their texts, the collections holding them,
+    /// the options of their rules, and the tokens the rules are parsed into when analyzed
+    pub estimated_memory: usize,
+    /// The symbols taking the most memory, by decreasing estimated memory
+    pub largest_symbols: Vec<SymbolSize>,
+}
+
+impl SizeReport {
+    /// Returns the report serialized as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("A size report is always serializable")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selection_counters() {
+        let mut counters = SelectionCounters::default();
+        counters.record("colour", 2);
+        counters.record("colour", 2);
+        counters.record("colour", 0);
+
+        assert_eq!(counters.counts["colour"], vec![1, 0, 2]);
+        assert_eq!(counters.count("colour", 2), 2);
+        assert_eq!(counters.count("shape", 0), 0);
+        assert_eq!(counters.total("colour"), 3);
+
+        counters.reset();
+        assert_eq!(counters.total("colour"), 0);
+    }
+
+    #[test]
+    fn histogram_single_value() {
+        let histogram = Histogram::from_values(vec!["a", "a"]);
+        assert_eq!(histogram.entropy, 0.0);
+        assert_eq!(histogram.count("a"), 2);
+        assert_eq!(histogram.count("b"), 0);
+    }
+
+    #[test]
+    fn histogram_sorted_by_count() {
+        let histogram = Histogram::from_values(vec!["b", "a", "b", "c"]);
+        let values: Vec<&str> = histogram.counts.iter().map(|(v, _)| v.as_str()).collect();
+        assert_eq!(values, vec!["b", "a", "c"]);
+    }
+
+    #[test]
+    fn report_to_json() {
+        let report = SampleReport {
+            root: "root".to_string(),
+            samples: 1,
+            outputs: Histogram::from_values(vec!["x"]),
+            root_rules: Histogram::from_values(vec!["{x}"]),
+        };
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["samples"], 1);
+        assert_eq!(json["outputs"]["counts"][0][0], "x");
+    }
+}
@@ -0,0 +1,170 @@
+//! The reproducibility contract: a grammar flattened with a given seed generates the same outputs on every
+//! supported target, 32-bit and WASM ones included, and in every version sharing the same `ALGORITHM_VERSION`,
+//! so seeds stored in save files keep giving the same texts.
+//!
+//! The contract covers rule selection (uniform, weighted and by strategy), captures, modifiers, arithmetic
+//! expressions, escaped delimiters and the random built-ins. It is checked by the golden vectors below, which the test suite runs
+//! on every target, and which applications can run as well with `verify_reproducibility`.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::config::GrammarSyntax;
+use crate::grammar::Grammar;
+
+/// Version of the expansion and selection algorithms. It only changes along with the golden vectors, when a
+/// release cannot avoid generating different outputs from the same seeds.
+pub const ALGORITHM_VERSION: u32 = 2;
+
+/// A JSON grammar and a seed, along with the outputs of their first flattens, which must never change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GoldenVector {
+    /// What the vector exercises
+    pub name: &'static str,
+    /// The JSON grammar, flattened with the standard modifiers
+    pub grammar: &'static str,
+    pub seed: &'static str,
+    /// The outputs of the successive flattens from the root symbol
+    pub outputs: &'static [&'static str],
+}
+
+/// The golden vectors of the current `ALGORITHM_VERSION`
+pub const GOLDEN_VECTORS: &[GoldenVector] = &[
+    GoldenVector {
+        name: "uniform selection, captures and modifiers",
+        grammar: r#"{
+            "root": "[hero>name]{name:capitalize} met {animal:a} and {count} {animal:s}. {name} {verb:ed}.",
+            "hero": ["ada", "bo", "cyrus", "dana"],
+            "animal": ["owl", "fox", "eagle", "cat", "otter"],
+            "count": ["two", "three", "seven"],
+            "verb": ["laugh", "jump", "wander"]
+        }"#,
+        seed: "golden",
+        outputs: &[
+            "Dana met a fox and two eagles. dana jumped.",
+            "Cyrus met an otter and three cats. cyrus jumped.",
+            "Dana met a fox and seven foxes. dana jumped.",
+            "Ada met a fox and three eagles. ada jumped.",
+            "Ada met an eagle and two otters. ada laughed.",
+            "Cyrus met an owl and two owls. cyrus laughed.",
+        ],
+    },
+    GoldenVector {
+        name: "weights and strategies",
+        grammar: r#"{
+            "root": "{loot}, {card}, {card}, {event}",
+            "loot": [{"rule": "gold", "weight": 5}, {"rule": "gem", "weight": 1.5}, {"rule": "relic", "weight": 0.25}],
+            "card": {"rules": ["ace", "king", "queen", "jack"], "strategy": "shuffleBag"},
+            "event": ["storm", "eclipse", {"rule": "calm", "weight": 0}]
+        }"#,
+        seed: "golden",
+        outputs: &[
+            "gold, jack, ace, storm",
+            "gold, queen, king, storm",
+            "gold, king, queen, eclipse",
+            "gold, jack, ace, storm",
+            "gem, ace, king, eclipse",
+            "gold, queen, jack, storm",
+        ],
+    },
+    GoldenVector {
+        name: "expressions and random built-ins",
+        grammar: r#"{
+            "root": "[level>lvl]Level {lvl}: {#lvl*10+5} gold, ticket {@base32:6}, agent {@hex:4}, id {@uuid}",
+            "level": ["1", "2", "3", "4", "5", "6", "7", "8", "9"]
+        }"#,
+        seed: "golden",
+        outputs: &[
+            "Level 2: 25 gold, ticket OVNJZJ, agent b244, id 40ba217a-dd3b-4c94-952f-b9d5777c07c2",
+            "Level 9: 95 gold, ticket XMMBJL, agent 0f29, id 413857ad-e1c9-440e-b1c6-698c7438a60d",
+            "Level 8: 85 gold, ticket PPU2HB, agent ef89, id 8b2bdad8-040c-44fe-9629-4b4c9142116d",
+            "Level 6: 65 gold, ticket ALWRML, agent a495, id 61b31c06-922f-4c9e-9022-c826897f1fc8",
+            "Level 4: 45 gold, ticket UC4NVZ, agent 8eb4, id 22a3e115-ea95-48db-bf97-fe6018afe1b1",
+            "Level 5: 55 gold, ticket ZJWEAR, agent 1228, id 5ee0d897-b2f9-4165-8e22-dcdd50fea6f7",
+        ],
+    },
+    GoldenVector {
+        name: "escaped delimiters",
+        grammar: r#"{
+            "root": "\\{{animal}\\} \\[{count}\\] \\\\{animal:s} \\x",
+            "animal": ["owl", "fox", "eagle"],
+            "count": ["two", "three"]
+        }"#,
+        seed: "golden",
+        outputs: &[
+            r"{owl} [two] \owls \x",
+            r"{fox} [two] \foxes \x",
+            r"{fox} [two] \eagles \x",
+            r"{eagle} [three] \eagles \x",
+            r"{fox} [two] \eagles \x",
+            r"{fox} [two] \foxes \x",
+        ],
+    },
+];
+
+/// An output of a golden vector which differs from the one expected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    /// The name of the golden vector
+    pub vector: &'static str,
+    /// The index of the flatten, from 0
+    pub flatten: usize,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Golden vector '{}' (algorithm version {}) generated '{}' instead of '{}' on flatten {}",
+            self.vector, ALGORITHM_VERSION, self.actual, self.expected, self.flatten
+        )
+    }
+}
+
+impl Error for Mismatch {}
+
+/// Check that this build generates the outputs of every golden vector, e.g. in the test suite of an application
+/// storing seeds, built for a target the library is not tested on. Returns the first output which differs.
+///
+/// ```
+/// assert_eq!(vitrail::reproducibility::verify_reproducibility(), Ok(()));
+/// ```
+pub fn verify_reproducibility() -> Result<(), Mismatch> {
+    GOLDEN_VECTORS.iter().try_for_each(verify_vector)
+}
+
+/// Check that this build generates the outputs of a golden vector
+fn verify_vector(vector: &GoldenVector) -> Result<(), Mismatch> {
+    let mut grammar =
+        Grammar::from_json_str_unchecked(vector.grammar, vector.seed, GrammarSyntax::default())
+            .with_standard_modifiers();
+
+    for (flatten, expected) in vector.outputs.iter().enumerate() {
+        let actual = grammar.flatten_unchecked();
+        if actual != *expected {
+            return Err(Mismatch {
+                vector: vector.name,
+                flatten,
+                expected: expected.to_string(),
+                actual,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn golden_vectors_are_reproduced() {
+        for vector in GOLDEN_VECTORS {
+            assert!(!vector.outputs.is_empty());
+        }
+        assert_eq!(verify_reproducibility(), Ok(()));
+    }
+}
@@ -0,0 +1,514 @@
+use rand::{Rng, RngCore, SeedableRng};
+use rand_seeder::Seeder;
+use serde::{Deserialize, Serialize};
+
+/// Lowercase hexadecimal digits, used to render hex identifiers
+const HEX_ALPHABET: &[u8] = b"0123456789abcdef";
+/// RFC 4648 base32 alphabet, used to render base32 codes
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// Random number generator seeded from a string, so that generation is reproducible.
+///
+/// It implements `RngCore`, so it can be used with any `rand` API, and provides helpers
+/// for the random values needed by grammars. It is serializable, so that a generator saved along with a `Session`
+/// goes on with the same sequence.
+///
+/// ```
+/// use vitrail::rng::SeededRng;
+///
+/// let mut rng = SeededRng::new("anyrandomseed");
+/// assert_eq!(rng.hex(8).len(), 8);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeededRng {
+    source: Source,
+}
+
+/// Where the values of a `SeededRng` come from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+enum Source {
+    Seeded(Xoshiro256PlusPlus),
+    Scripted(ScriptedRng),
+}
+
+/// The xoshiro256++ generator behind seeded generators. It is the algorithm of `rand`'s `SmallRng` on 64-bit
+/// targets, implemented here because `SmallRng` switches to another algorithm on 32-bit ones (WASM included),
+/// which would break the reproducibility of outputs across targets.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct Xoshiro256PlusPlus {
+    state: [u64; 4],
+}
+
+impl SeedableRng for Xoshiro256PlusPlus {
+    type Seed = [u8; 32];
+
+    fn from_seed(seed: [u8; 32]) -> Self {
+        let mut state = [0; 4];
+        for (word, bytes) in state.iter_mut().zip(seed.chunks_exact(8)) {
+            let mut le_bytes = [0; 8];
+            le_bytes.copy_from_slice(bytes);
+            *word = u64::from_le_bytes(le_bytes);
+        }
+        // An all-zero state would only ever generate zeros
+        if state == [0; 4] {
+            state = [
+                0x9e37_79b9_7f4a_7c15,
+                0xbf58_476d_1ce4_e5b9,
+                0x94d0_49bb_1331_11eb,
+                1,
+            ];
+        }
+
+        Self { state }
+    }
+}
+
+impl RngCore for Xoshiro256PlusPlus {
+    fn next_u32(&mut self) -> u32 {
+        // The lowest bits are the weakest ones
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let s = &mut self.state;
+        let result = s[0].wrapping_add(s[3]).rotate_left(23).wrapping_add(s[0]);
+        let t = s[1] << 17;
+
+        s[2] ^= s[0];
+        s[3] ^= s[1];
+        s[1] ^= s[2];
+        s[0] ^= s[3];
+        s[2] ^= t;
+        s[3] = s[3].rotate_left(45);
+
+        result
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        let mut chunks = dest.chunks_exact_mut(8);
+        for chunk in &mut chunks {
+            chunk.copy_from_slice(&self.next_u64().to_le_bytes());
+        }
+
+        let rest = chunks.into_remainder();
+        match rest.len() {
+            0 => {}
+            1..=4 => {
+                let len = rest.len();
+                rest.copy_from_slice(&self.next_u32().to_le_bytes()[..len]);
+            }
+            len => rest.copy_from_slice(&self.next_u64().to_le_bytes()[..len]),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Test double for `SeededRng`, returning a predefined sequence of choices instead of random ones,
+/// so the outputs of a grammar can be fully scripted in unit tests.
+///
+/// Every index drawn, weighted or not, is the next choice of the script: the weights are ignored.
+/// Other values are taken from the next choices as well, e.g. one choice per byte of a UUID.
+/// Drawing past the end of the script, or a choice out of the range drawn from, panics.
+///
+/// ```
+/// use vitrail::{
+///     config::GrammarSyntax,
+///     grammar::Grammar,
+///     rng::ScriptedRng,
+/// };
+///
+/// let mut grammar = Grammar::new("anyrandomseed", GrammarSyntax::default())
+///     .with_symbol("root".to_string(), vec!["{loot} x{count}".to_string()])
+///     .with_symbol("loot".to_string(), vec!["gold".to_string(), "gem".to_string()])
+///     .with_symbol("count".to_string(), vec!["1".to_string(), "2".to_string(), "3".to_string()])
+///     .with_rng(ScriptedRng::new(vec![0, 1, 2]));
+///
+/// assert_eq!(grammar.flatten().unwrap(), "gem x3");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScriptedRng {
+    choices: Vec<usize>,
+    /// Position of the next choice in the script
+    position: usize,
+}
+
+impl ScriptedRng {
+    /// Create a generator returning the given choices, in order
+    pub fn new(choices: Vec<usize>) -> Self {
+        Self {
+            choices,
+            position: 0,
+        }
+    }
+
+    /// Returns the number of choices left in the script
+    pub fn remaining(&self) -> usize {
+        self.choices.len() - self.position
+    }
+
+    fn next_choice(&mut self) -> usize {
+        let choice = *self.choices.get(self.position).unwrap_or_else(|| {
+            panic!(
+                "The scripted generator ran out of choices after {} draws",
+                self.position
+            )
+        });
+        self.position += 1;
+        choice
+    }
+
+    fn index(&mut self, len: usize) -> usize {
+        let choice = self.next_choice();
+        if choice >= len {
+            panic!(
+                "Scripted choice {} (draw {}) is out of range for {} options",
+                choice, self.position, len
+            );
+        }
+        choice
+    }
+}
+
+impl RngCore for ScriptedRng {
+    fn next_u32(&mut self) -> u32 {
+        self.next_choice() as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.next_choice() as u64
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for byte in dest {
+            *byte = self.next_choice() as u8;
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl From<ScriptedRng> for SeededRng {
+    fn from(rng: ScriptedRng) -> Self {
+        Self {
+            source: Source::Scripted(rng),
+        }
+    }
+}
+
+impl SeededRng {
+    /// Create a generator from a seed string. The same seed always yields the same sequence.
+    pub fn new(seed: &str) -> Self {
+        Self {
+            source: Source::Seeded(Seeder::from(seed).make_rng()),
+        }
+    }
+
+    /// Returns a random index in `0..len`.
+    ///
+    /// Panics if `len` is 0.
+    pub fn index(&mut self, len: usize) -> usize {
+        match &mut self.source {
+            // Drawn as a u64, as usize is 32 bits wide on some targets
+            Source::Seeded(rng) => rng.gen_range(0..len as u64) as usize,
+            Source::Scripted(rng) => rng.index(len),
+        }
+    }
+
+    /// Returns a random index in `0..weights.len()`, each index being drawn proportionally to its weight.
+    ///
+    /// Weights are normalized by the largest one before being summed, so huge weights cannot overflow the total,
+    /// and infinite weights share all the probability.
+    ///
+    /// Panics if `weights` is empty. Falls back to a uniform draw if no weight is positive.
+    pub fn weighted_index(&mut self, weights: &[f64]) -> usize {
+        let largest = weights
+            .iter()
+            .copied()
+            .filter(|weight| *weight > 0.0)
+            .fold(0.0, f64::max);
+        let rng = match &mut self.source {
+            Source::Seeded(rng) if largest > 0.0 => rng,
+            _ => return self.index(weights.len()),
+        };
+
+        let normalized: Vec<f64> = weights
+            .iter()
+            .map(|weight| match *weight {
+                // Infinite weights share all the probability
+                weight if largest.is_infinite() && weight == f64::INFINITY => 1.0,
+                weight if weight > 0.0 && largest.is_finite() => weight / largest,
+                _ => 0.0,
+            })
+            .collect();
+        let total: f64 = normalized.iter().sum();
+
+        let mut target = rng.gen_range(0.0..total);
+        for (index, weight) in normalized.iter().enumerate() {
+            if *weight <= 0.0 {
+                continue;
+            }
+            if target < *weight {
+                return index;
+            }
+            target -= weight;
+        }
+
+        normalized
+            .iter()
+            .rposition(|weight| *weight > 0.0)
+            .unwrap_or(0)
+    }
+
+    /// Returns a random entry of a list of `(entry, weight)` pairs, each entry being drawn proportionally
+    /// to its weight, or `None` if the list is empty.
+    ///
+    /// Falls back to a uniform draw if no weight is positive.
+    ///
+    /// ```
+    /// use vitrail::rng::SeededRng;
+    ///
+    /// let mut rng = SeededRng::new("anyrandomseed");
+    /// assert_eq!(rng.weighted_entry(&[("common", 0.0), ("rare", 1.0)]), Some(&"rare"));
+    /// assert_eq!(rng.weighted_entry::<&str>(&[]), None);
+    /// ```
+    pub fn weighted_entry<'e, T>(&mut self, entries: &'e [(T, f64)]) -> Option<&'e T> {
+        if entries.is_empty() {
+            return None;
+        }
+
+        let weights: Vec<f64> = entries.iter().map(|(_, weight)| *weight).collect();
+        Some(&entries[self.weighted_index(&weights)].0)
+    }
+
+    /// Shuffle the items in place, every permutation being equally likely (Fisher-Yates)
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for last in (1..items.len()).rev() {
+            let other = self.index(last + 1);
+            items.swap(last, other);
+        }
+    }
+
+    /// Returns `n` distinct items drawn at random (sampling without replacement), in the order they were drawn,
+    /// or all the items in a random order if there are fewer than `n`
+    ///
+    /// ```
+    /// use vitrail::rng::SeededRng;
+    ///
+    /// let mut rng = SeededRng::new("anyrandomseed");
+    /// let party = rng.sample_n(&["bard", "cleric", "rogue", "wizard"], 3);
+    /// assert_eq!(party.len(), 3);
+    /// ```
+    pub fn sample_n<'i, T>(&mut self, items: &'i [T], n: usize) -> Vec<&'i T> {
+        let mut indices: Vec<usize> = (0..items.len()).collect();
+        let n = n.min(items.len());
+
+        // Partial Fisher-Yates: the first n positions receive the drawn indices
+        for position in 0..n {
+            let other = position + self.index(indices.len() - position);
+            indices.swap(position, other);
+        }
+
+        indices[..n].iter().map(|index| &items[*index]).collect()
+    }
+
+    /// Returns a string of `len` random lowercase hexadecimal digits
+    pub fn hex(&mut self, len: usize) -> String {
+        self.string_from(HEX_ALPHABET, len)
+    }
+
+    /// Returns a string of `len` random base32 characters
+    pub fn base32(&mut self, len: usize) -> String {
+        self.string_from(BASE32_ALPHABET, len)
+    }
+
+    /// Returns a random version 4 UUID, formatted as `xxxxxxxx-xxxx-4xxx-yxxx-xxxxxxxxxxxx`
+    pub fn uuid(&mut self) -> String {
+        let mut bytes = [0u8; 16];
+        self.fill_bytes(&mut bytes);
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+        format!(
+            "{}-{}-{}-{}-{}",
+            &hex[0..8],
+            &hex[8..12],
+            &hex[12..16],
+            &hex[16..20],
+            &hex[20..32],
+        )
+    }
+
+    fn string_from(&mut self, alphabet: &[u8], len: usize) -> String {
+        (0..len)
+            .map(|_| alphabet[self.index(alphabet.len())] as char)
+            .collect()
+    }
+}
+
+impl RngCore for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        match &mut self.source {
+            Source::Seeded(rng) => rng.next_u32(),
+            Source::Scripted(rng) => rng.next_u32(),
+        }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        match &mut self.source {
+            Source::Seeded(rng) => rng.next_u64(),
+            Source::Scripted(rng) => rng.next_u64(),
+        }
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match &mut self.source {
+            Source::Seeded(rng) => rng.fill_bytes(dest),
+            Source::Scripted(rng) => rng.fill_bytes(dest),
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        match &mut self.source {
+            Source::Seeded(rng) => rng.try_fill_bytes(dest),
+            Source::Scripted(rng) => rng.try_fill_bytes(dest),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = SeededRng::new("seed");
+        let mut b = SeededRng::new("seed");
+        assert_eq!(a.hex(16), b.hex(16));
+    }
+
+    #[test]
+    fn weighted_index() {
+        let mut rng = SeededRng::new("seed");
+        for _ in 0..100 {
+            assert_eq!(rng.weighted_index(&[0.0, 2.0, 0.0]), 1);
+        }
+
+        let picks: Vec<usize> = (0..1000).map(|_| rng.weighted_index(&[1.0, 9.0])).collect();
+        let zeros = picks.iter().filter(|index| **index == 0).count();
+        assert!(zeros > 50 && zeros < 150);
+    }
+
+    #[test]
+    fn huge_weights_are_normalized() {
+        let mut rng = SeededRng::new("seed");
+        let picks: Vec<usize> = (0..1000)
+            .map(|_| rng.weighted_index(&[1e308, 0.0, 1e308]))
+            .collect();
+        assert!(picks.iter().all(|index| *index != 1));
+        let zeros = picks.iter().filter(|index| **index == 0).count();
+        assert!(zeros > 400 && zeros < 600);
+
+        for _ in 0..100 {
+            assert_eq!(rng.weighted_index(&[1e308, f64::INFINITY, f64::NAN]), 1);
+        }
+    }
+
+    #[test]
+    fn weighted_entry() {
+        let mut rng = SeededRng::new("seed");
+        let entries = [("never", 0.0), ("always", 3.0), ("negative", -1.0)];
+        for _ in 0..100 {
+            assert_eq!(rng.weighted_entry(&entries), Some(&"always"));
+        }
+        assert!(rng.weighted_entry(&[("a", 0.0), ("b", 0.0)]).is_some());
+    }
+
+    #[test]
+    fn shuffle_is_a_deterministic_permutation() {
+        let mut items: Vec<usize> = (0..20).collect();
+        SeededRng::new("seed").shuffle(&mut items);
+
+        let mut again: Vec<usize> = (0..20).collect();
+        SeededRng::new("seed").shuffle(&mut again);
+        assert_eq!(items, again);
+        assert_ne!(items, (0..20).collect::<Vec<usize>>());
+
+        items.sort_unstable();
+        assert_eq!(items, (0..20).collect::<Vec<usize>>());
+
+        SeededRng::new("seed").shuffle::<usize>(&mut []);
+    }
+
+    #[test]
+    fn sample_without_replacement() {
+        let mut rng = SeededRng::new("seed");
+        let items: Vec<usize> = (0..10).collect();
+
+        for n in 0..=12 {
+            let mut sample: Vec<usize> = rng.sample_n(&items, n).into_iter().copied().collect();
+            assert_eq!(sample.len(), n.min(10));
+            sample.sort_unstable();
+            sample.dedup();
+            assert_eq!(sample.len(), n.min(10));
+        }
+    }
+
+    #[test]
+    fn scripted_choices() {
+        let mut rng = SeededRng::from(ScriptedRng::new(vec![2, 0, 1, 7]));
+        assert_eq!(rng.index(3), 2);
+        assert_eq!(rng.weighted_index(&[0.0, 1.0]), 0);
+        assert_eq!(rng.sample_n(&["a", "b"], 1), vec![&"b"]);
+        assert_eq!(rng.next_u32(), 7);
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn scripted_choice_out_of_range() {
+        SeededRng::from(ScriptedRng::new(vec![3])).index(3);
+    }
+
+    #[test]
+    #[should_panic(expected = "ran out of choices")]
+    fn scripted_choices_exhausted() {
+        let mut rng = ScriptedRng::new(vec![0]);
+        assert_eq!(rng.remaining(), 1);
+        rng.index(1);
+        rng.index(1);
+    }
+
+    #[test]
+    fn hex_alphabet() {
+        let mut rng = SeededRng::new("seed");
+        assert!(rng.hex(64).chars().all(|c| c.is_ascii_hexdigit()));
+    }
+
+    #[test]
+    fn base32_alphabet() {
+        let mut rng = SeededRng::new("seed");
+        let code = rng.base32(64);
+        assert_eq!(code.len(), 64);
+        assert!(code
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || ('2'..='7').contains(&c)));
+    }
+
+    #[test]
+    fn uuid_format() {
+        let mut rng = SeededRng::new("seed");
+        let uuid = rng.uuid();
+        let groups: Vec<usize> = uuid.split('-').map(str::len).collect();
+
+        assert_eq!(groups, vec![8, 4, 4, 4, 12]);
+        assert_eq!(&uuid[14..15], "4");
+        assert!("89ab".contains(&uuid[19..20]));
+    }
+}
@@ -0,0 +1,114 @@
+//! Helpers to produce valid SSML (Speech Synthesis Markup Language) documents from generated text,
+//! for text-to-speech engines.
+
+/// Escape the characters reserved by XML, so plain text can be embedded in an SSML document
+///
+/// ```
+/// use vitrail::ssml;
+///
+/// assert_eq!(ssml::escape("Tom & <Jerry>"), "Tom &amp; &lt;Jerry&gt;");
+/// ```
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(character),
+        }
+    }
+
+    escaped
+}
+
+/// Check that every SSML tag of a document is properly closed, in the right order.
+///
+/// Returns the name of the first mismatched or unclosed tag as error.
+///
+/// ```
+/// use vitrail::ssml;
+///
+/// assert!(ssml::validate("<speak>Hi<break time=\"1s\"/><emphasis>there</emphasis></speak>").is_ok());
+/// assert!(ssml::validate("<speak><emphasis>there</speak>").is_err());
+/// ```
+pub fn validate(document: &str) -> Result<(), String> {
+    let mut open_tags: Vec<&str> = Vec::new();
+    let mut rest = document;
+
+    while let Some(start) = rest.find('<') {
+        let end = rest[start..]
+            .find('>')
+            .map(|i| start + i)
+            .ok_or_else(|| "unterminated tag".to_string())?;
+        let tag = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+
+        if tag.ends_with('/') {
+            continue;
+        }
+
+        match tag.strip_prefix('/') {
+            Some(name) => match open_tags.pop() {
+                Some(open) if open == name.trim() => (),
+                _ => return Err(name.trim().to_string()),
+            },
+            None => open_tags.push(tag.split_whitespace().next().unwrap_or_default()),
+        }
+    }
+
+    match open_tags.pop() {
+        Some(unclosed) => Err(unclosed.to_string()),
+        None => Ok(()),
+    }
+}
+
+/// Returns the opening and closing tags of an SSML element with the given attributes
+pub(crate) fn element(name: &str, attributes: &[(&str, &str)]) -> (String, String) {
+    let attributes: String = attributes
+        .iter()
+        .map(|(key, value)| format!(" {}=\"{}\"", key, escape(value)))
+        .collect();
+
+    (format!("<{}{}>", name, attributes), format!("</{}>", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_reserved_characters() {
+        assert_eq!(escape("\"a\" & 'b'"), "&quot;a&quot; &amp; &apos;b&apos;");
+    }
+
+    #[test]
+    fn validate_nested_tags() {
+        assert!(
+            validate("<speak><prosody rate=\"slow\"><emphasis>a</emphasis></prosody></speak>")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn validate_unclosed_tag() {
+        assert_eq!(
+            validate("<speak><emphasis>a</speak>"),
+            Err("speak".to_string())
+        );
+        assert_eq!(validate("<speak>a"), Err("speak".to_string()));
+    }
+
+    #[test]
+    fn element_escapes_attributes() {
+        assert_eq!(
+            element("say-as", &[("interpret-as", "\"date\"")]),
+            (
+                "<say-as interpret-as=\"&quot;date&quot;\">".to_string(),
+                "</say-as>".to_string()
+            )
+        );
+    }
+}
@@ -0,0 +1,275 @@
+//! Text utilities shared by output profiles and modifiers, counting user-perceived characters
+//! (grapheme clusters) rather than bytes or code points.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Punctuation marks ending a sentence
+const SENTENCE_ENDS: [char; 4] = ['.', '!', '?', '…'];
+/// Punctuation marks written right after the preceding word, without a space
+const ATTACHED_PUNCTUATION: [char; 7] = [',', '.', ';', ':', '!', '?', '…'];
+/// Closing quotes and brackets, which may follow the punctuation ending a text
+const CLOSING_MARKS: [char; 7] = ['"', '\'', ')', ']', '”', '’', '»'];
+
+/// Returns the number of grapheme clusters in the text
+///
+/// ```
+/// use vitrail::text;
+///
+/// assert_eq!(text::grapheme_len("café"), 4);
+/// assert_eq!(text::grapheme_len("👍🏽"), 1);
+/// ```
+pub fn grapheme_len(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// Truncate the text to at most `max` grapheme clusters, ellipsis included.
+/// The text is cut at the last word boundary when there is one, and returned unchanged if it already fits.
+/// The ellipsis is left out if it alone is longer than `max`.
+///
+/// ```
+/// use vitrail::text;
+///
+/// assert_eq!(text::truncate("The quick brown fox", 12, "…"), "The quick…");
+/// assert_eq!(text::truncate("Short", 12, "…"), "Short");
+/// ```
+pub fn truncate(text: &str, max: usize, ellipsis: &str) -> String {
+    if grapheme_len(text) <= max {
+        return text.to_string();
+    }
+    if grapheme_len(ellipsis) > max {
+        return truncate(text, max, "");
+    }
+
+    let budget = max.saturating_sub(grapheme_len(ellipsis));
+    let cut: String = text.graphemes(true).take(budget).collect();
+    let next_is_boundary = text
+        .graphemes(true)
+        .nth(budget)
+        .is_none_or(|grapheme| grapheme.trim().is_empty());
+
+    let kept = match cut.rfind(char::is_whitespace) {
+        Some(idx) if !next_is_boundary => &cut[..idx],
+        _ => cut.as_str(),
+    };
+
+    format!("{}{}", kept.trim_end(), ellipsis)
+}
+
+/// Returns the Levenshtein distance between two texts: the minimal number of character insertions,
+/// deletions and substitutions turning one into the other
+///
+/// ```
+/// use vitrail::text;
+///
+/// assert_eq!(text::edit_distance("kitten", "sitting"), 3);
+/// ```
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+/// Tidy a generated text into sentences: spaces left by empty expansions are collapsed and removed before
+/// punctuation, the first letter of each sentence is capitalized, and the text ends with punctuation.
+///
+/// ```
+/// use vitrail::text;
+///
+/// assert_eq!(
+///     text::finish_sentences("the door opens  , slowly .  a cat enters"),
+///     "The door opens, slowly. A cat enters."
+/// );
+/// ```
+pub fn finish_sentences(text: &str) -> String {
+    let mut finished = String::with_capacity(text.len() + 1);
+    let mut sentence_start = true;
+    let mut after_end = false;
+
+    for character in text.trim().chars() {
+        if character == ' ' && finished.ends_with(' ') {
+            continue;
+        }
+        if ATTACHED_PUNCTUATION.contains(&character) {
+            let spaced = finished.trim_end_matches(' ').len();
+            finished.truncate(spaced);
+        }
+
+        if after_end && !CLOSING_MARKS.contains(&character) {
+            sentence_start = character.is_whitespace();
+            after_end = false;
+        }
+
+        if sentence_start && character.is_alphanumeric() {
+            finished.extend(character.to_uppercase());
+            sentence_start = false;
+        } else {
+            finished.push(character);
+            if SENTENCE_ENDS.contains(&character) {
+                after_end = true;
+            } else if character.is_alphanumeric() {
+                sentence_start = false;
+            }
+        }
+    }
+
+    let last = finished
+        .chars()
+        .rev()
+        .find(|character| !CLOSING_MARKS.contains(character));
+    match last {
+        None => {}
+        Some(last) if SENTENCE_ENDS.contains(&last) => {}
+        Some(',') | Some(';') | Some(':') if finished.ends_with([',', ';', ':']) => {
+            finished.pop();
+            finished.push('.');
+        }
+        Some(_) => finished.push('.'),
+    }
+
+    finished
+}
+
+/// Wrap a text to lines of at most `width` grapheme clusters, breaking lines between words. Each line break
+/// of the text starts a new paragraph, whose following lines start with `indent` (a hanging indent, counted
+/// in the width). Spaces between words are collapsed, and a word longer than a line is left on a line of its own.
+///
+/// ```
+/// use vitrail::text;
+///
+/// assert_eq!(
+///     text::wrap("The old lighthouse keeper climbs the stairs", 16, "  "),
+///     "The old\n  lighthouse\n  keeper climbs\n  the stairs"
+/// );
+/// ```
+pub fn wrap(text: &str, width: usize, indent: &str) -> String {
+    let indent_len = grapheme_len(indent);
+    let mut wrapped = String::with_capacity(text.len());
+
+    for (i, paragraph) in text.lines().enumerate() {
+        if i > 0 {
+            wrapped.push('\n');
+        }
+
+        let mut line_len = 0;
+        for word in paragraph.split_whitespace() {
+            let word_len = grapheme_len(word);
+            if line_len == 0 {
+                wrapped.push_str(word);
+                line_len = word_len;
+            } else if line_len + 1 + word_len <= width {
+                wrapped.push(' ');
+                wrapped.push_str(word);
+                line_len += 1 + word_len;
+            } else {
+                wrapped.push('\n');
+                wrapped.push_str(indent);
+                wrapped.push_str(word);
+                line_len = indent_len + word_len;
+            }
+        }
+    }
+
+    wrapped
+}
+
+/// Returns the proportion of distinct words shared by two texts (Jaccard index), from 0.0 (no common word)
+/// to 1.0 (same words). Words are compared case-insensitively, ignoring punctuation.
+///
+/// ```
+/// use vitrail::text;
+///
+/// assert_eq!(text::token_overlap("The red door", "the blue door"), 0.5);
+/// ```
+pub fn token_overlap(a: &str, b: &str) -> f64 {
+    let words = |text: &str| -> std::collections::HashSet<String> {
+        text.unicode_words().map(str::to_lowercase).collect()
+    };
+    let (a, b) = (words(a), words(b));
+
+    let union = a.union(&b).count();
+    if union == 0 {
+        return 1.0;
+    }
+
+    a.intersection(&b).count() as f64 / union as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn edit_distance_identical_and_empty() {
+        assert_eq!(edit_distance("same", "same"), 0);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("abc", ""), 3);
+    }
+
+    #[test]
+    fn token_overlap_disjoint_and_identical() {
+        assert_eq!(token_overlap("red door", "blue window"), 0.0);
+        assert_eq!(token_overlap("Red door!", "red DOOR"), 1.0);
+    }
+
+    #[test]
+    fn finish_sentences_cleanup() {
+        assert_eq!(
+            finish_sentences("what ?  \"run!\" she said ;"),
+            "What? \"Run!\" She said."
+        );
+        assert_eq!(
+            finish_sentences("it costs 3.50 at example.com"),
+            "It costs 3.50 at example.com."
+        );
+        assert_eq!(finish_sentences("\"quoted.\""), "\"Quoted.\"");
+        assert_eq!(finish_sentences("  "), "");
+    }
+
+    #[test]
+    fn wrap_paragraphs_and_long_words() {
+        assert_eq!(
+            wrap("un café crème\n\nantidisestablishment  day", 9, ""),
+            "un café\ncrème\n\nantidisestablishment\nday"
+        );
+        assert_eq!(wrap("a b c", 3, "-"), "a b\n-c");
+        assert_eq!(wrap("", 10, "  "), "");
+    }
+
+    #[test]
+    fn truncate_at_word_boundary() {
+        assert_eq!(truncate("Hello wonderful world", 16, "..."), "Hello...");
+    }
+
+    #[test]
+    fn truncate_when_cut_ends_a_word() {
+        assert_eq!(truncate("Hello wonderful world", 9, "…"), "Hello…");
+        assert_eq!(truncate("Hello world", 6, "…"), "Hello…");
+    }
+
+    #[test]
+    fn truncate_single_long_word() {
+        assert_eq!(truncate("Supercalifragilistic", 6, "…"), "Super…");
+    }
+
+    #[test]
+    fn truncate_counts_graphemes() {
+        assert_eq!(truncate("👍🏽👍🏽👍🏽", 2, "…"), "👍🏽…");
+    }
+
+    #[test]
+    fn truncate_never_exceeds_max() {
+        assert_eq!(truncate("Hello world", 2, "..."), "He");
+        assert_eq!(truncate("Hello world", 3, "..."), "...");
+        assert_eq!(truncate("Hello world", 0, "..."), "");
+    }
+}